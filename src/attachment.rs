@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::Path;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use crate::config::AttachmentsConfig;
+use crate::error::BotError;
+
+// Leading bytes of formats whose contents identify them.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"%PDF-", "application/pdf"),
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+];
+
+// ZIP and OLE2 files hold many formats (.docx, .xlsx, .doc, ...), which only
+// the extension tells apart.
+const CONTAINERS: &[(&[u8], &str)] = &[(b"PK\x03\x04", "application/zip"), (b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", "application/x-ole-storage")];
+
+// The type a file's contents show, falling back to its extension. A file named
+// like a recognizable format that doesn't contain it, e.g. an HTML error page
+// saved as brochure.pdf, is refused rather than sent mislabeled.
+pub fn detect_content_type(filename: &str, data: &[u8]) -> Result<String, BotError> {
+    let guessed = mime_guess::from_path(filename).first_raw();
+    let sniffed = SIGNATURES.iter().find(|(signature, _)| data.starts_with(signature)).map(|(_, mime)| *mime);
+    if let Some(guessed) = guessed {
+        if SIGNATURES.iter().any(|(_, mime)| *mime == guessed) && sniffed != Some(guessed) {
+            return Err(BotError::InvalidData(format!(
+                "{} is named like {} but its contents are {}",
+                filename,
+                guessed,
+                sniffed.unwrap_or("something else")
+            )));
+        }
+    }
+    if let Some(sniffed) = sniffed {
+        return Ok(sniffed.to_string());
+    }
+    let container = CONTAINERS.iter().find(|(signature, _)| data.starts_with(signature)).map(|(_, mime)| *mime);
+    Ok(guessed.or(container).unwrap_or("application/octet-stream").to_string())
+}
+
+// A file sent along with an email. One with a content id is an inline image
+// the HTML shows with <img src="cid:...">, rather than a download.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+    pub content_id: Option<String>,
+}
+
+impl Attachment {
+    pub fn load(path: &str) -> Result<Self, BotError> {
+        let data = fs::read(path).map_err(|e| BotError::InvalidData(format!("cannot read {}: {}", path, e)))?;
+        let filename = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| BotError::InvalidData(format!("attachment {} is not a file", path)))?;
+        if data.is_empty() {
+            return Err(BotError::InvalidData(format!("attachment {} is empty", path)));
+        }
+        let content_type = detect_content_type(&filename, &data)?;
+        Ok(Attachment { filename, content_type, data, content_id: None })
+    }
+
+    pub fn inline_image(path: &str, content_id: &str) -> Result<Self, BotError> {
+        let mut attachment = Attachment::load(path)?;
+        if !attachment.content_type.starts_with("image/") {
+            return Err(BotError::InvalidData(format!("{} is {}, not an image", path, attachment.content_type)));
+        }
+        attachment.content_id = Some(content_id.to_string());
+        Ok(attachment)
+    }
+
+    // For APIs that take the contents as JSON or form fields.
+    pub fn base64(&self) -> String {
+        STANDARD.encode(&self.data)
+    }
+}
+
+// Refuses files that together exceed attachments.max_total_kb; `what` names
+// their owner in the error.
+pub fn check_total_size<'a>(config: &AttachmentsConfig, what: &str, attachments: impl Iterator<Item = &'a Attachment>) -> Result<(), BotError> {
+    let total: usize = attachments.map(|attachment| attachment.data.len()).sum();
+    if total as u64 > config.max_total_kb * 1024 {
+        return Err(BotError::InvalidData(format!(
+            "{} attaches {} KiB, more than attachments.max_total_kb ({} KiB)",
+            what,
+            total.div_ceil(1024),
+            config.max_total_kb
+        )));
+    }
+    Ok(())
+}
+
+// The files attached to a campaign's emails, read and checked up front so
+// a missing or oversized file stops the run before anything is sent.
+pub fn load_campaign_attachments(config: &AttachmentsConfig, campaign: &str) -> Result<Vec<Attachment>, BotError> {
+    let Some(paths) = config.campaigns.get(campaign) else {
+        return Ok(Vec::new());
+    };
+    let attachments = paths.iter().map(|path| Attachment::load(path)).collect::<Result<Vec<_>, _>>()?;
+    check_total_size(config, &format!("campaign \"{}\"", campaign), attachments.iter())?;
+    Ok(attachments)
+}
@@ -0,0 +1,263 @@
+use chrono::Utc;
+use rand::distributions::{Distribution, WeightedIndex};
+use crate::error::BotError;
+use crate::scrape::Business;
+use crate::storage::LeadStore;
+use crate::template::CampaignTemplate;
+
+pub const DEFAULT_TEMPLATE: &str = "default";
+
+// Which stored leads a campaign goes out to. Every set field must match,
+// case-insensitively; an empty filter matches every lead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LeadFilter {
+    // Substring of the location the lead was scraped for, e.g. "Columbus".
+    pub location: Option<String>,
+    // One of the lead's categories, e.g. "Plumbers".
+    pub category: Option<String>,
+    // Substring of the page the lead came from, e.g. "yelp.com".
+    pub source: Option<String>,
+}
+
+impl LeadFilter {
+    pub fn is_empty(&self) -> bool {
+        self.location.is_none() && self.category.is_none() && self.source.is_none()
+    }
+
+    pub fn matches(&self, business: &Business) -> bool {
+        let contains = |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+        self.location
+            .as_deref()
+            .is_none_or(|location| business.location.as_deref().is_some_and(|found| contains(found, location)))
+            && self
+                .category
+                .as_deref()
+                .is_none_or(|category| business.categories.iter().any(|found| found.eq_ignore_ascii_case(category)))
+            && self.source.as_deref().is_none_or(|source| contains(&business.url, source))
+    }
+}
+
+// A named outreach effort: what is sent, to whom, and how much. Every send is
+// recorded against its campaign.
+#[derive(Debug, Clone)]
+pub struct Campaign {
+    pub id: i64,
+    pub name: String,
+    pub subject: String,
+    // "default" for templates/email_template.html, or the path of an HTML
+    // template file.
+    pub template: String,
+    pub filter: LeadFilter,
+    // Sends per day for this campaign, on top of the global daily limit.
+    pub max_per_day: Option<i64>,
+    // Sends over the campaign's whole lifetime.
+    pub max_sends: Option<i64>,
+    pub created_at: String,
+}
+
+impl Campaign {
+    // An unsaved campaign with the default template and no filter or limits.
+    pub fn new(name: &str, subject: &str) -> Self {
+        Campaign {
+            id: 0,
+            name: name.to_string(),
+            subject: subject.to_string(),
+            template: DEFAULT_TEMPLATE.to_string(),
+            filter: LeadFilter::default(),
+            max_per_day: None,
+            max_sends: None,
+            created_at: String::new(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), BotError> {
+        if self.name.trim().is_empty() {
+            return Err(BotError::InvalidData("campaign name must not be empty".to_string()));
+        }
+        if self.subject.trim().is_empty() {
+            return Err(BotError::InvalidData("campaign subject must not be empty".to_string()));
+        }
+        if self.template.trim().is_empty() {
+            return Err(BotError::InvalidData("campaign template must not be empty".to_string()));
+        }
+        Ok(())
+    }
+
+    // Template files are stored by absolute path so later runs find them
+    // from any directory.
+    pub fn absolutize_template(&mut self) -> Result<(), BotError> {
+        if self.template != DEFAULT_TEMPLATE {
+            self.template = std::fs::canonicalize(&self.template).map_err(BotError::IOError)?.display().to_string();
+        }
+        Ok(())
+    }
+
+    // Reads the campaign's template and checks it renders.
+    pub fn load_template(&self) -> Result<CampaignTemplate, BotError> {
+        CampaignTemplate::load(&self.template)
+    }
+
+    // How many more emails the campaign's own limits allow right now, or None
+    // when it has no limits. Today starts at midnight UTC.
+    pub async fn remaining_sends(&self, store: &dyn LeadStore) -> Result<Option<usize>, BotError> {
+        let mut remaining: Option<i64> = None;
+        if let Some(max_per_day) = self.max_per_day {
+            let midnight = Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+            let sent_today = store.campaign_sent_count(self.id, Some(midnight)).await?;
+            remaining = Some(max_per_day - sent_today);
+        }
+        if let Some(max_sends) = self.max_sends {
+            let sent = store.campaign_sent_count(self.id, None).await?;
+            remaining = Some(remaining.map_or(max_sends - sent, |left| left.min(max_sends - sent)));
+        }
+        Ok(remaining.map(|left| left.max(0) as usize))
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("template {}", self.template)];
+        if let Some(location) = &self.filter.location {
+            parts.push(format!("location \"{}\"", location));
+        }
+        if let Some(category) = &self.filter.category {
+            parts.push(format!("category \"{}\"", category));
+        }
+        if let Some(source) = &self.filter.source {
+            parts.push(format!("source \"{}\"", source));
+        }
+        if let Some(max_per_day) = self.max_per_day {
+            parts.push(format!("{}/day", max_per_day));
+        }
+        if let Some(max_sends) = self.max_sends {
+            parts.push(format!("{} total", max_sends));
+        }
+        parts.join(", ")
+    }
+}
+
+// One arm of a campaign's A/B test. Each recipient gets a variant at random,
+// in proportion to the weights: weights 3 and 1 send 75% of the emails with
+// the first variant. The variant used is recorded with the send.
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub name: String,
+    pub subject: String,
+    // Like Campaign::template.
+    pub template: String,
+    pub weight: u32,
+}
+
+impl Variant {
+    pub fn validate(&self) -> Result<(), BotError> {
+        if self.name.trim().is_empty() {
+            return Err(BotError::InvalidData("variant name must not be empty".to_string()));
+        }
+        if self.subject.trim().is_empty() {
+            return Err(BotError::InvalidData(format!("variant \"{}\" subject must not be empty", self.name)));
+        }
+        if self.weight == 0 {
+            return Err(BotError::InvalidData(format!("variant \"{}\" weight must be at least 1", self.name)));
+        }
+        Ok(())
+    }
+
+    pub fn absolutize_template(&mut self) -> Result<(), BotError> {
+        if self.template != DEFAULT_TEMPLATE {
+            self.template = std::fs::canonicalize(&self.template).map_err(BotError::IOError)?.display().to_string();
+        }
+        Ok(())
+    }
+}
+
+// What a send run renders from: the campaign's variants with their templates
+// loaded, or the campaign itself as a single unnamed variant.
+pub struct VariantSplit {
+    variants: Vec<(Option<String>, String, CampaignTemplate)>,
+    weights: Option<WeightedIndex<u32>>,
+}
+
+impl VariantSplit {
+    // The campaign's own subject and template, for campaigns without variants.
+    pub fn single(campaign: &Campaign, template: CampaignTemplate) -> Self {
+        VariantSplit { variants: vec![(None, campaign.subject.clone(), template)], weights: None }
+    }
+
+    // Loads every variant's template up front so a broken one stops the run
+    // before anything is sent.
+    pub fn load(variants: &[Variant]) -> Result<Self, BotError> {
+        if variants.len() < 2 {
+            return Err(BotError::InvalidData("an A/B test needs at least two variants".to_string()));
+        }
+        let mut loaded = Vec::new();
+        for variant in variants {
+            variant.validate()?;
+            loaded.push((Some(variant.name.clone()), variant.subject.clone(), CampaignTemplate::load(&variant.template)?));
+        }
+        let weights = WeightedIndex::new(variants.iter().map(|variant| variant.weight))
+            .map_err(|e| BotError::InvalidData(format!("invalid variant weights: {}", e)))?;
+        Ok(VariantSplit { variants: loaded, weights: Some(weights) })
+    }
+
+    // A variant for the next recipient: its name (None without variants),
+    // subject and template.
+    pub fn pick(&self) -> (Option<&str>, &str, &CampaignTemplate) {
+        let index = self.weights.as_ref().map_or(0, |weights| weights.sample(&mut rand::thread_rng()));
+        let (name, subject, template) = &self.variants[index];
+        (name.as_deref(), subject, template)
+    }
+
+    // The named variant's template, e.g. to send an email again; the first
+    // variant's when the name is unknown.
+    pub fn template(&self, name: Option<&str>) -> &CampaignTemplate {
+        let found = self.variants.iter().find(|(variant, _, _)| variant.as_deref() == name);
+        &found.unwrap_or(&self.variants[0]).2
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Option<&str>, &str, &CampaignTemplate)> {
+        self.variants.iter().map(|(name, subject, template)| (name.as_deref(), subject.as_str(), template))
+    }
+}
+
+// A step of a campaign's drip sequence: sent `delay_days` after the email
+// before it to recipients who haven't replied, unsubscribed or bounced, as a
+// reply in the same conversation. Steps are numbered from 1, in the order
+// they were added; the campaign's own email is step 0.
+#[derive(Debug, Clone)]
+pub struct FollowUp {
+    pub step: u32,
+    pub delay_days: u32,
+    // None replies with "Re: " and the subject of the first email.
+    pub subject: Option<String>,
+    // Like Campaign::template.
+    pub template: String,
+}
+
+impl FollowUp {
+    pub fn validate(&self) -> Result<(), BotError> {
+        if self.delay_days == 0 {
+            return Err(BotError::InvalidData(format!("follow-up {} must wait at least one day", self.step)));
+        }
+        if self.subject.as_deref().is_some_and(|subject| subject.trim().is_empty()) {
+            return Err(BotError::InvalidData(format!("follow-up {} subject must not be empty", self.step)));
+        }
+        if self.template.trim().is_empty() {
+            return Err(BotError::InvalidData(format!("follow-up {} template must not be empty", self.step)));
+        }
+        Ok(())
+    }
+
+    pub fn absolutize_template(&mut self) -> Result<(), BotError> {
+        if self.template != DEFAULT_TEMPLATE {
+            self.template = std::fs::canonicalize(&self.template).map_err(BotError::IOError)?.display().to_string();
+        }
+        Ok(())
+    }
+
+    // The subject of the follow-up to an email sent with `subject`.
+    pub fn reply_subject(&self, subject: &str) -> String {
+        match &self.subject {
+            Some(own) => own.clone(),
+            None if subject.to_lowercase().starts_with("re:") => subject.to_string(),
+            None => format!("Re: {}", subject),
+        }
+    }
+}
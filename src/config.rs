@@ -0,0 +1,1500 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+use lettre::message::Mailbox;
+use serde::Deserialize;
+use crate::error::BotError;
+use crate::filter::FilterConfig;
+use crate::ratelimit::{Limits, DEFAULT_LIMIT_FILE, DEFAULT_MAX_EMAILS_PER_DAY, DEFAULT_REDIS_POOL_SIZE, DEFAULT_REDIS_URL, DEFAULT_SENTINEL_MASTER};
+use crate::schedule::SendWindow;
+use crate::scrape::selectors::SelectorConfig;
+use crate::scrape::{source_by_name, SOURCE_NAMES};
+
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+pub const DEFAULT_SMTP_HOST: &str = "smtp.gmail.com";
+pub const DEFAULT_SENDER: &str = "coffeecodestudio.dev@gmail.com";
+// Sends a few minutes apart at random, as a person working through a list
+// would, rather than on a steady beat.
+pub const DEFAULT_SEND_DELAY_MS: u64 = 45_000;
+pub const DEFAULT_SEND_MAX_DELAY_MS: u64 = 180_000;
+pub const DEFAULT_WARMUP_START: usize = 20;
+pub const DEFAULT_WARMUP_INCREASE_PERCENT: u32 = 20;
+pub const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 5;
+pub const DEFAULT_RETRY_BACKOFF_MINUTES: u32 = 30;
+pub const DEFAULT_SES_REGION: &str = "us-east-1";
+pub const DEFAULT_POSTMARK_STREAM: &str = "broadcast";
+// Attachments grow by a third when base64-encoded, and this keeps an email
+// under Postmark's 10 MB limit, the lowest of the supported transports.
+pub const DEFAULT_MAX_ATTACHMENTS_KB: u64 = 5 * 1024;
+pub const DEFAULT_UTM_SOURCE: &str = "outreach";
+pub const DEFAULT_UTM_MEDIUM: &str = "email";
+pub const DEFAULT_UTM_CAMPAIGN: &str = "{campaign}";
+pub const DEFAULT_IMAP_MAILBOX: &str = "INBOX";
+pub const DEFAULT_SPAMD_PORT: u16 = 783;
+// Where MailHog and Mailpit listen for SMTP out of the box.
+pub const DEFAULT_SANDBOX_HOST: &str = "localhost";
+pub const DEFAULT_SANDBOX_PORT: u16 = 1025;
+// SpamAssassin's own default for calling a message spam.
+pub const DEFAULT_SPAM_THRESHOLD: f64 = 5.0;
+pub const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+
+// Environment variables that override a config.toml key, applied after the
+// file is read (and .env is loaded), so secrets can stay out of the file.
+const ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("REDIS_URL", "redis.url"),
+    ("REDIS_USERNAME", "redis.username"),
+    ("REDIS_PASSWORD", "redis.password"),
+    ("REDIS_DB", "redis.db"),
+    ("REDIS_TLS", "redis.tls"),
+    ("REDIS_TLS_INSECURE", "redis.tls_insecure"),
+    ("REDIS_POOL_SIZE", "redis.pool_size"),
+    ("REDIS_MODE", "redis.mode"),
+    ("REDIS_NODES", "redis.nodes"),
+    ("REDIS_SENTINEL_MASTER", "redis.sentinel_master"),
+    ("SMTP_HOST", "smtp.host"),
+    ("SMTP_PORT", "smtp.port"),
+    ("SMTP_TLS", "smtp.tls"),
+    ("SMTP_USERNAME", "smtp.username"),
+    ("EMAIL_PASSWORD", "smtp.password"),
+    ("SMTP_OAUTH2_CLIENT_ID", "smtp.oauth2.client_id"),
+    ("SMTP_OAUTH2_CLIENT_SECRET", "smtp.oauth2.client_secret"),
+    ("SMTP_OAUTH2_REFRESH_TOKEN", "smtp.oauth2.refresh_token"),
+    ("SMTP_OAUTH2_TOKEN_URL", "smtp.oauth2.token_url"),
+    ("DKIM_SELECTOR", "dkim.selector"),
+    ("DKIM_DOMAIN", "dkim.domain"),
+    ("DKIM_ALGORITHM", "dkim.algorithm"),
+    ("DKIM_PRIVATE_KEY", "dkim.private_key"),
+    ("DKIM_PRIVATE_KEY_FILE", "dkim.private_key_file"),
+    ("EMAIL_TRANSPORT", "send.transport"),
+    ("SENDGRID_API_KEY", "sendgrid.api_key"),
+    ("MAILGUN_API_KEY", "mailgun.api_key"),
+    ("MAILGUN_DOMAIN", "mailgun.domain"),
+    ("MAILGUN_REGION", "mailgun.region"),
+    ("MAILGUN_SANDBOX_DOMAIN", "mailgun.sandbox_domain"),
+    ("MAILGUN_SANDBOX", "mailgun.sandbox"),
+    ("AWS_REGION", "ses.region"),
+    ("AWS_ACCESS_KEY_ID", "ses.access_key_id"),
+    ("AWS_SECRET_ACCESS_KEY", "ses.secret_access_key"),
+    ("AWS_SESSION_TOKEN", "ses.session_token"),
+    ("SES_CONFIGURATION_SET", "ses.configuration_set"),
+    ("POSTMARK_SERVER_TOKEN", "postmark.server_token"),
+    ("POSTMARK_MESSAGE_STREAM", "postmark.message_stream"),
+    ("COMPANY_NAME", "company.name"),
+    ("COMPANY_POSTAL_ADDRESS", "company.postal_address"),
+    ("UNSUBSCRIBE_URL", "unsubscribe.url"),
+    ("UNSUBSCRIBE_MAILTO", "unsubscribe.mailto"),
+    ("UNSUBSCRIBE_SECRET", "unsubscribe.secret"),
+    ("TRACKING_URL", "tracking.url"),
+    ("TRACKING_SECRET", "tracking.secret"),
+    ("TRACKING_CLICKS", "tracking.clicks"),
+    ("IMAP_HOST", "imap.host"),
+    ("IMAP_PORT", "imap.port"),
+    ("IMAP_USERNAME", "imap.username"),
+    ("IMAP_PASSWORD", "imap.password"),
+    ("IMAP_MAILBOX", "imap.mailbox"),
+    ("SANDBOX_HOST", "sandbox.host"),
+    ("SANDBOX_PORT", "sandbox.port"),
+    ("SPAMD_HOST", "spamassassin.host"),
+    ("SPAMD_PORT", "spamassassin.port"),
+    ("SPAM_THRESHOLD", "spamassassin.threshold"),
+    ("EMAIL_SENDER", "send.sender"),
+    ("EMAIL_SENDER_NAME", "send.sender_name"),
+    ("MAX_EMAILS_PER_DAY", "send.max_per_day"),
+    ("MAX_EMAILS_PER_HOUR", "send.max_per_hour"),
+    ("MAX_EMAILS_PER_MINUTE", "send.max_per_minute"),
+    ("MAX_EMAILS_PER_DOMAIN", "send.max_per_domain"),
+    ("LIMIT_STRATEGY", "send.limit_strategy"),
+    ("LIMIT_TIMEZONE", "send.limit_timezone"),
+    ("LIMIT_FALLBACK", "send.limit_fallback"),
+    ("LIMIT_FILE", "send.limit_file"),
+    ("SEND_DELAY_MS", "send.delay_ms"),
+    ("SEND_MAX_DELAY_MS", "send.max_delay_ms"),
+    ("WARMUP", "warmup.enabled"),
+    ("WARMUP_START", "warmup.start"),
+    ("WARMUP_INCREASE_PERCENT", "warmup.increase_percent"),
+    ("RETRY_MAX_ATTEMPTS", "retry.max_attempts"),
+    ("RETRY_BACKOFF_MINUTES", "retry.backoff_minutes"),
+    ("SEND_DAYS", "schedule.days"),
+    ("SEND_START", "schedule.start"),
+    ("SEND_END", "schedule.end"),
+    ("SEND_TIMEZONE", "schedule.timezone"),
+    ("SEND_IN_RECIPIENT_TIMEZONE", "schedule.recipient_timezone"),
+];
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub sources: HashMap<String, SourceConfig>,
+    pub filter: FilterConfig,
+    pub redis: RedisConfig,
+    pub smtp: SmtpConfig,
+    pub sandbox: SandboxConfig,
+    pub dkim: DkimConfig,
+    pub send: SendConfig,
+    pub schedule: ScheduleConfig,
+    pub warmup: WarmupConfig,
+    pub retry: RetryConfig,
+    pub sendgrid: SendgridConfig,
+    pub mailgun: MailgunConfig,
+    pub ses: SesConfig,
+    pub postmark: PostmarkConfig,
+    pub company: CompanyConfig,
+    pub attachments: AttachmentsConfig,
+    pub unsubscribe: UnsubscribeConfig,
+    pub tracking: TrackingConfig,
+    pub utm: UtmConfig,
+    pub imap: ImapConfig,
+    pub spamassassin: SpamAssassinConfig,
+    // Mailboxes to rotate sends across; when empty, [send] sender goes out
+    // through send.transport.
+    pub accounts: Vec<AccountConfig>,
+}
+
+// One mailbox in the sender rotation, sending over SMTP. Connection settings
+// left out are taken from [smtp].
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountConfig {
+    // Identifies the account in logs and its Redis counter.
+    pub name: String,
+    pub sender: String,
+    pub sender_name: Option<String>,
+    // Emails this account may send per day.
+    pub max_per_day: Option<usize>,
+    // Overrides warmup.enabled, e.g. false for a mailbox that has sent for years.
+    pub warmup: Option<bool>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub tls: Option<SmtpTls>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Environment variable holding the password, to keep it out of the file.
+    pub password_env: Option<String>,
+    pub oauth2: Option<OAuth2Config>,
+}
+
+impl AccountConfig {
+    pub fn sender_mailbox(&self) -> Result<Mailbox, BotError> {
+        let send = SendConfig {
+            sender: self.sender.clone(),
+            sender_name: self.sender_name.clone(),
+            ..SendConfig::default()
+        };
+        send.sender_mailbox()
+            .map_err(|_| BotError::ConfigError(format!("accounts.{}.sender: \"{}\" is not an email address", self.name, self.sender)))
+    }
+
+    // The [smtp] settings with this account's overrides applied.
+    pub fn smtp(&self, base: &SmtpConfig) -> Result<SmtpConfig, BotError> {
+        let mut smtp = base.clone();
+        if let Some(host) = &self.host {
+            smtp.host = host.clone();
+        }
+        if self.port.is_some() {
+            smtp.port = self.port;
+        }
+        if let Some(tls) = self.tls {
+            smtp.tls = tls;
+        }
+        smtp.username = self.username.clone();
+        smtp.oauth2 = self.oauth2.clone();
+        smtp.password = match &self.password_env {
+            Some(var) => Some(env::var(var).map_err(|_| {
+                BotError::ConfigError(format!("accounts.{}.password_env: {} is not set", self.name, var))
+            })?),
+            None => self.password.clone(),
+        };
+        if smtp.auth && smtp.oauth2.is_none() && smtp.password.as_deref().is_none_or(str::is_empty) {
+            return Err(BotError::ConfigError(format!(
+                "accounts.{} has no credentials; set password_env, password or [accounts.oauth2]",
+                self.name
+            )));
+        }
+        Ok(smtp)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisConfig {
+    // redis://[user:password@]host[:port][/db], or rediss:// for TLS.
+    pub url: String,
+    // Replace the ones in the URL; an ACL user needs its password too.
+    // Better set through REDIS_PASSWORD than written into the file.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub db: Option<i64>,
+    // TLS even with a redis:// URL, as managed services often require.
+    pub tls: bool,
+    // Accepts any certificate, for services with self-signed ones.
+    pub tls_insecure: bool,
+    // Connections kept open and shared by everything a command does at once.
+    pub pool_size: usize,
+    pub mode: RedisMode,
+    // The sentinels' addresses in sentinel mode; some of the cluster's nodes
+    // in cluster mode, which finds the rest itself, or just `url` when empty.
+    pub nodes: Vec<String>,
+    // The name the sentinels know the master by.
+    pub sentinel_master: String,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        RedisConfig {
+            url: DEFAULT_REDIS_URL.to_string(),
+            username: None,
+            password: None,
+            db: None,
+            tls: false,
+            tls_insecure: false,
+            pool_size: DEFAULT_REDIS_POOL_SIZE,
+            mode: RedisMode::default(),
+            nodes: Vec::new(),
+            sentinel_master: DEFAULT_SENTINEL_MASTER.to_string(),
+        }
+    }
+}
+
+impl RedisConfig {
+    // Where commands go in standalone mode, and the credentials, database
+    // and TLS of the master or cluster nodes in the others.
+    pub fn connection_info(&self) -> Result<redis::ConnectionInfo, BotError> {
+        let mut info = self.address("redis.url", &self.url)?;
+        if let Some(username) = &self.username {
+            info.redis.username = Some(username.clone());
+        }
+        if let Some(password) = &self.password {
+            info.redis.password = Some(password.clone());
+        }
+        if let Some(db) = self.db {
+            info.redis.db = db;
+        }
+        Ok(info)
+    }
+
+    // redis.nodes with the same TLS as `url`. The sentinels' own credentials,
+    // if they need any, go in their URLs.
+    pub fn node_infos(&self) -> Result<Vec<redis::ConnectionInfo>, BotError> {
+        self.nodes.iter().map(|node| self.address("redis.nodes", node)).collect()
+    }
+
+    fn address(&self, key: &str, url: &str) -> Result<redis::ConnectionInfo, BotError> {
+        let mut info: redis::ConnectionInfo =
+            url.trim().parse().map_err(|e| BotError::ConfigError(format!("{}: \"{}\" is not a Redis URL: {}", key, url, e)))?;
+        info.addr = match info.addr {
+            redis::ConnectionAddr::Tcp(host, port) if self.tls || self.tls_insecure => {
+                redis::ConnectionAddr::TcpTls { host, port, insecure: self.tls_insecure, tls_params: None }
+            }
+            redis::ConnectionAddr::TcpTls { host, port, insecure, tls_params } => {
+                redis::ConnectionAddr::TcpTls { host, port, insecure: insecure || self.tls_insecure, tls_params }
+            }
+            addr => addr,
+        };
+        Ok(info)
+    }
+}
+
+// How Redis is deployed.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RedisMode {
+    // The one server at redis.url.
+    #[default]
+    Standalone,
+    // The master the sentinels at redis.nodes name, asked again for every
+    // new connection so a failover only costs the connections to the old one.
+    Sentinel,
+    // A Redis Cluster, each key sent to the node holding it.
+    Cluster,
+}
+
+impl RedisMode {
+    pub const NAMES: &'static [&'static str] = &["standalone", "sentinel", "cluster"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "standalone" => Some(RedisMode::Standalone),
+            "sentinel" => Some(RedisMode::Sentinel),
+            "cluster" => Some(RedisMode::Cluster),
+            _ => None,
+        }
+    }
+}
+
+// How the SMTP connection is secured.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    // TLS from the first byte, usually port 465.
+    #[default]
+    Tls,
+    // Plain connection upgraded with STARTTLS, usually port 587.
+    Starttls,
+    // Unencrypted, for relays on a trusted network; usually port 25.
+    None,
+}
+
+impl SmtpTls {
+    pub const NAMES: &'static [&'static str] = &["tls", "starttls", "none"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "tls" => Some(SmtpTls::Tls),
+            "starttls" => Some(SmtpTls::Starttls),
+            "none" => Some(SmtpTls::None),
+            _ => None,
+        }
+    }
+
+    pub fn default_port(self) -> u16 {
+        match self {
+            SmtpTls::Tls => 465,
+            SmtpTls::Starttls => 587,
+            SmtpTls::None => 25,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub host: String,
+    // Defaults to the usual port for the TLS mode.
+    pub port: Option<u16>,
+    pub tls: SmtpTls,
+    // Internal relays that accept mail without logging in set this to false.
+    pub auth: bool,
+    // Defaults to the sender's address.
+    pub username: Option<String>,
+    // Better set through EMAIL_PASSWORD than written into the file.
+    pub password: Option<String>,
+    // Log in with XOAUTH2 instead of a password.
+    pub oauth2: Option<OAuth2Config>,
+}
+
+// An OAuth2 client allowed to send mail for the account, and a refresh token
+// granted to it (scope https://mail.google.com/ for Gmail,
+// https://outlook.office.com/SMTP.Send offline_access for Microsoft 365).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct OAuth2Config {
+    pub client_id: String,
+    pub client_secret: String,
+    pub refresh_token: String,
+    // Google's by default; Microsoft 365 uses
+    // https://login.microsoftonline.com/<tenant>/oauth2/v2.0/token.
+    pub token_url: String,
+}
+
+impl Default for OAuth2Config {
+    fn default() -> Self {
+        OAuth2Config {
+            client_id: String::new(),
+            client_secret: String::new(),
+            refresh_token: String::new(),
+            token_url: GOOGLE_TOKEN_URL.to_string(),
+        }
+    }
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        SmtpConfig {
+            host: DEFAULT_SMTP_HOST.to_string(),
+            port: None,
+            tls: SmtpTls::default(),
+            auth: true,
+            username: None,
+            password: None,
+            oauth2: None,
+        }
+    }
+}
+
+// A local MailHog or Mailpit instance that send.transport = "sandbox" hands
+// every email to instead of a real server, over plain SMTP without logging
+// in, so the whole pipeline can be tried out with nothing reaching a real
+// mailbox.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        SandboxConfig { host: DEFAULT_SANDBOX_HOST.to_string(), port: DEFAULT_SANDBOX_PORT }
+    }
+}
+
+impl SandboxConfig {
+    pub fn smtp(&self) -> SmtpConfig {
+        SmtpConfig { host: self.host.clone(), port: Some(self.port), tls: SmtpTls::None, auth: false, ..SmtpConfig::default() }
+    }
+}
+
+// DKIM signing of what goes out over SMTP, for relays and own servers that
+// don't sign mail themselves. The public key is published as a TXT record at
+// <selector>._domainkey.<domain>. The API transports sign with the keys set
+// up in the provider's dashboard instead.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DkimConfig {
+    // Signing is on when this is set, e.g. "mail" for mail._domainkey.
+    pub selector: Option<String>,
+    // Defaults to the domain of each From address, which DMARC needs the
+    // signature to match.
+    pub domain: Option<String>,
+    pub algorithm: DkimAlgorithm,
+    // The PEM of an RSA key in PKCS#1 form ("BEGIN RSA PRIVATE KEY"), or the
+    // base64 of a 32-byte Ed25519 private key. Better set through DKIM_PRIVATE_KEY, or
+    // kept in a file named by private_key_file.
+    pub private_key: Option<String>,
+    pub private_key_file: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DkimAlgorithm {
+    // rsa-sha256, which every receiver checks; use a 2048-bit key.
+    #[default]
+    Rsa,
+    // ed25519-sha256 (RFC 8463), which not every receiver checks yet.
+    Ed25519,
+}
+
+impl DkimAlgorithm {
+    pub const NAMES: &'static [&'static str] = &["rsa", "ed25519"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "rsa" => Some(DkimAlgorithm::Rsa),
+            "ed25519" => Some(DkimAlgorithm::Ed25519),
+            _ => None,
+        }
+    }
+}
+
+impl DkimConfig {
+    // The private key, read from private_key_file when it isn't given inline.
+    pub fn private_key(&self) -> Result<String, BotError> {
+        if let Some(key) = &self.private_key {
+            return Ok(key.clone());
+        }
+        match &self.private_key_file {
+            Some(path) => fs::read_to_string(path).map_err(|e| BotError::ConfigError(format!("dkim.private_key_file: {}: {}", path, e))),
+            None => Err(BotError::ConfigError(
+                "dkim.private_key is not set; set DKIM_PRIVATE_KEY, [dkim] private_key or private_key_file".to_string(),
+            )),
+        }
+    }
+}
+
+// What delivers the emails.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    // The [smtp] server.
+    #[default]
+    Smtp,
+    // SendGrid's HTTP API, with the key from [sendgrid].
+    Sendgrid,
+    // Mailgun's HTTP API, with the key and domain from [mailgun].
+    Mailgun,
+    // Amazon SES's v2 API, with the credentials from [ses].
+    Ses,
+    // Postmark's API, with the server token from [postmark].
+    Postmark,
+    // The MailHog or Mailpit instance in [sandbox], for development.
+    Sandbox,
+}
+
+impl TransportKind {
+    pub const NAMES: &'static [&'static str] = &["smtp", "sendgrid", "mailgun", "ses", "postmark", "sandbox"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "smtp" => Some(TransportKind::Smtp),
+            "sendgrid" => Some(TransportKind::Sendgrid),
+            "mailgun" => Some(TransportKind::Mailgun),
+            "ses" => Some(TransportKind::Ses),
+            "postmark" => Some(TransportKind::Postmark),
+            "sandbox" => Some(TransportKind::Sandbox),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SendgridConfig {
+    // Better set through SENDGRID_API_KEY than written into the file.
+    pub api_key: Option<String>,
+}
+
+impl SendgridConfig {
+    pub fn api_key(&self) -> Result<&str, BotError> {
+        self.api_key.as_deref().filter(|key| !key.is_empty()).ok_or_else(|| {
+            BotError::ConfigError("sendgrid.api_key is not set; set SENDGRID_API_KEY or [sendgrid] api_key in config.toml".to_string())
+        })
+    }
+}
+
+// Where the Mailgun account's data lives; EU accounts have their own API host.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailgunRegion {
+    #[default]
+    Us,
+    Eu,
+}
+
+impl MailgunRegion {
+    pub const NAMES: &'static [&'static str] = &["us", "eu"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "us" => Some(MailgunRegion::Us),
+            "eu" => Some(MailgunRegion::Eu),
+            _ => None,
+        }
+    }
+
+    pub fn api_url(self) -> &'static str {
+        match self {
+            MailgunRegion::Us => "https://api.mailgun.net",
+            MailgunRegion::Eu => "https://api.eu.mailgun.net",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailgunConfig {
+    // Better set through MAILGUN_API_KEY than written into the file.
+    pub api_key: Option<String>,
+    // The verified sending domain, e.g. "mg.example.com".
+    pub domain: Option<String>,
+    pub region: MailgunRegion,
+    // The account's sandboxXXXX.mailgun.org domain, which only delivers to
+    // the recipients authorized on it.
+    pub sandbox_domain: Option<String>,
+    // Send through sandbox_domain instead of domain, for testing.
+    pub sandbox: bool,
+}
+
+impl MailgunConfig {
+    pub fn api_key(&self) -> Result<&str, BotError> {
+        self.api_key.as_deref().filter(|key| !key.is_empty()).ok_or_else(|| {
+            BotError::ConfigError("mailgun.api_key is not set; set MAILGUN_API_KEY or [mailgun] api_key in config.toml".to_string())
+        })
+    }
+
+    pub fn sending_domain(&self) -> Result<&str, BotError> {
+        let (key, domain) = if self.sandbox {
+            ("mailgun.sandbox_domain", &self.sandbox_domain)
+        } else {
+            ("mailgun.domain", &self.domain)
+        };
+        domain
+            .as_deref()
+            .map(str::trim)
+            .filter(|domain| !domain.is_empty())
+            .ok_or_else(|| BotError::ConfigError(format!("{} is not set", key)))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SesConfig {
+    pub region: String,
+    // An IAM user or role allowed ses:SendEmail and ses:GetAccount. Better set
+    // through the usual AWS_* environment variables than written into the file.
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    // Only for temporary credentials.
+    pub session_token: Option<String>,
+    // Configuration set for event publishing (bounces, opens, clicks).
+    pub configuration_set: Option<String>,
+}
+
+impl Default for SesConfig {
+    fn default() -> Self {
+        SesConfig {
+            region: DEFAULT_SES_REGION.to_string(),
+            access_key_id: None,
+            secret_access_key: None,
+            session_token: None,
+            configuration_set: None,
+        }
+    }
+}
+
+impl SesConfig {
+    pub fn access_key_id(&self) -> Result<&str, BotError> {
+        self.access_key_id.as_deref().filter(|key| !key.is_empty()).ok_or_else(|| {
+            BotError::ConfigError("ses.access_key_id is not set; set AWS_ACCESS_KEY_ID or [ses] access_key_id in config.toml".to_string())
+        })
+    }
+
+    pub fn secret_access_key(&self) -> Result<&str, BotError> {
+        self.secret_access_key.as_deref().filter(|key| !key.is_empty()).ok_or_else(|| {
+            BotError::ConfigError(
+                "ses.secret_access_key is not set; set AWS_SECRET_ACCESS_KEY or [ses] secret_access_key in config.toml".to_string(),
+            )
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostmarkConfig {
+    // Better set through POSTMARK_SERVER_TOKEN than written into the file.
+    pub server_token: Option<String>,
+    // Stream for campaigns not listed in `streams`.
+    pub message_stream: String,
+    // Campaign name to message stream id, e.g. "spring-promo" = "promotions".
+    pub streams: HashMap<String, String>,
+}
+
+impl Default for PostmarkConfig {
+    fn default() -> Self {
+        PostmarkConfig {
+            server_token: None,
+            message_stream: DEFAULT_POSTMARK_STREAM.to_string(),
+            streams: HashMap::new(),
+        }
+    }
+}
+
+impl PostmarkConfig {
+    pub fn server_token(&self) -> Result<&str, BotError> {
+        self.server_token.as_deref().filter(|token| !token.is_empty()).ok_or_else(|| {
+            BotError::ConfigError(
+                "postmark.server_token is not set; set POSTMARK_SERVER_TOKEN or [postmark] server_token in config.toml".to_string(),
+            )
+        })
+    }
+}
+
+// Who the emails are from, printed in every footer: CAN-SPAM requires
+// commercial email to carry the sender's valid physical postal address.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompanyConfig {
+    pub name: Option<String>,
+    // A street address, PO box or registered private mailbox, e.g.
+    // "123 Main St, Columbus, OH 43215".
+    pub postal_address: Option<String>,
+}
+
+impl CompanyConfig {
+    // Sending refuses to start without both.
+    pub fn footer_identity(&self) -> Result<(&str, &str), BotError> {
+        let name = self.name.as_deref().map(str::trim).filter(|name| !name.is_empty()).ok_or_else(|| {
+            BotError::ConfigError("company.name is not set; set COMPANY_NAME or [company] name for the email footer".to_string())
+        })?;
+        let address = self.postal_address.as_deref().map(str::trim).filter(|address| !address.is_empty()).ok_or_else(|| {
+            BotError::ConfigError(
+                "company.postal_address is not set; CAN-SPAM requires a postal address in every email, set COMPANY_POSTAL_ADDRESS or [company] postal_address".to_string(),
+            )
+        })?;
+        Ok((name, address))
+    }
+}
+
+// Files sent along with a campaign's emails, e.g. a PDF brochure.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttachmentsConfig {
+    // Combined size of one email's attachments, in KiB.
+    pub max_total_kb: u64,
+    // Campaign name to the files it attaches, e.g.
+    // "spring-promo" = ["files/brochure.pdf"]. Relative paths are resolved
+    // from the working directory.
+    pub campaigns: HashMap<String, Vec<String>>,
+}
+
+impl Default for AttachmentsConfig {
+    fn default() -> Self {
+        AttachmentsConfig { max_total_kb: DEFAULT_MAX_ATTACHMENTS_KB, campaigns: HashMap::new() }
+    }
+}
+
+// How recipients opt out. Every email carries a List-Unsubscribe header and a
+// footer link; without a url they point at a mailto address.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnsubscribeConfig {
+    // Page that unsubscribes whoever opens it, with {token} standing for the
+    // recipient's signed token, e.g. "https://example.com/unsubscribe?t={token}".
+    // An https URL also enables one-click unsubscribes from the inbox.
+    pub url: Option<String>,
+    // Address unsubscribe requests are mailed to; defaults to the sender.
+    pub mailto: Option<String>,
+    // Key the tokens are signed with. Better set through UNSUBSCRIBE_SECRET
+    // than written into the file.
+    pub secret: Option<String>,
+}
+
+// Open and click tracking: with a url, every email carries a 1x1 image whose
+// address identifies the campaign and recipient, and its links go through
+// `serve`, which records opens and clicks. Mail clients that block images,
+// or load them through a proxy up front, make open counts approximate.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackingConfig {
+    // Where `serve` is reachable from the internet, e.g.
+    // "https://track.example.com"; pixels load from <url>/open/<token> and
+    // links redirect from <url>/click/<token>.
+    pub url: Option<String>,
+    // Key the tokens are signed with. Better set through TRACKING_SECRET than
+    // written into the file.
+    pub secret: Option<String>,
+    // Off leaves links pointing straight at their targets.
+    pub clicks: bool,
+}
+
+impl Default for TrackingConfig {
+    fn default() -> Self {
+        TrackingConfig { url: None, secret: None, clicks: true }
+    }
+}
+
+// UTM parameters appended to every http(s) link, so website analytics
+// attribute the visits to the campaign. "{campaign}" stands for the campaign
+// name. Links that already carry a utm_ parameter are left alone.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct UtmConfig {
+    pub enabled: bool,
+    pub source: String,
+    pub medium: String,
+    pub campaign: String,
+}
+
+impl Default for UtmConfig {
+    fn default() -> Self {
+        UtmConfig {
+            enabled: true,
+            source: DEFAULT_UTM_SOURCE.to_string(),
+            medium: DEFAULT_UTM_MEDIUM.to_string(),
+            campaign: DEFAULT_UTM_CAMPAIGN.to_string(),
+        }
+    }
+}
+
+// The mailbox replies to the campaigns arrive in, which `inbox` reads.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImapConfig {
+    // e.g. imap.gmail.com; `inbox` can't run without it.
+    pub host: Option<String>,
+    // Defaults to 993, or 143 without TLS.
+    pub port: Option<u16>,
+    // Only local test servers should turn this off.
+    pub tls: bool,
+    // Default to the sender's address and smtp.password.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub mailbox: String,
+}
+
+impl Default for ImapConfig {
+    fn default() -> Self {
+        ImapConfig { host: None, port: None, tls: true, username: None, password: None, mailbox: DEFAULT_IMAP_MAILBOX.to_string() }
+    }
+}
+
+impl ImapConfig {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(if self.tls { 993 } else { 143 })
+    }
+}
+
+// A SpamAssassin daemon (spamd) that `send` has score the rendered email
+// before asking to go ahead, warning when it scores above the threshold.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpamAssassinConfig {
+    // The check is skipped when this isn't set.
+    pub host: Option<String>,
+    pub port: u16,
+    pub threshold: f64,
+}
+
+impl Default for SpamAssassinConfig {
+    fn default() -> Self {
+        SpamAssassinConfig { host: None, port: DEFAULT_SPAMD_PORT, threshold: DEFAULT_SPAM_THRESHOLD }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SendConfig {
+    // Fills in the limits and connection settings this file leaves out.
+    pub provider: Option<ProviderPreset>,
+    pub transport: TransportKind,
+    // From address, optionally with a display name: "Coffee Code Studio <hi@example.com>".
+    pub sender: String,
+    // Display name shown with the sender address; replaces one given in `sender`.
+    pub sender_name: Option<String>,
+    pub max_per_day: usize,
+    // Shorter windows on top of the day; a send that would go over one waits
+    // for the next window instead of ending the run.
+    pub max_per_hour: Option<usize>,
+    pub max_per_minute: Option<usize>,
+    // Emails per recipient domain per day; the rest wait for a later run.
+    // Free mail providers like gmail.com aren't capped.
+    pub max_per_domain: Option<usize>,
+    // Whether the limits above count calendar days, hours and minutes or
+    // the last ones.
+    pub limit_strategy: LimitStrategy,
+    // Whose midnight starts a new day for the daily limits, e.g.
+    // "America/Los_Angeles" for a provider that resets its caps on Pacific time.
+    pub limit_timezone: String,
+    // Where the limits are counted when Redis fails during a run.
+    pub limit_fallback: LimitFallback,
+    pub limit_file: String,
+    // The pause between two emails is picked at random from this range.
+    pub delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for SendConfig {
+    fn default() -> Self {
+        SendConfig {
+            provider: None,
+            transport: TransportKind::default(),
+            sender: DEFAULT_SENDER.to_string(),
+            sender_name: None,
+            max_per_day: DEFAULT_MAX_EMAILS_PER_DAY,
+            max_per_hour: None,
+            max_per_minute: None,
+            max_per_domain: None,
+            limit_strategy: LimitStrategy::default(),
+            limit_timezone: "UTC".to_string(),
+            limit_fallback: LimitFallback::default(),
+            limit_file: DEFAULT_LIMIT_FILE.to_string(),
+            delay_ms: DEFAULT_SEND_DELAY_MS,
+            max_delay_ms: DEFAULT_SEND_MAX_DELAY_MS,
+        }
+    }
+}
+
+// How the daily, hourly and per-minute limits count their sends.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitStrategy {
+    // A counter per calendar day in send.limit_timezone, and per hour and
+    // minute, so up to twice a limit can go out around the moment one ends
+    // and the next starts.
+    #[default]
+    Fixed,
+    // The sends of the last 24 hours, hour or minute, kept in a sorted set,
+    // so no stretch of that length ever sees more than the limit.
+    Sliding,
+}
+
+impl LimitStrategy {
+    pub const NAMES: &'static [&'static str] = &["fixed", "sliding"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "fixed" => Some(LimitStrategy::Fixed),
+            "sliding" => Some(LimitStrategy::Sliding),
+            _ => None,
+        }
+    }
+}
+
+// What counts the limits for the rest of a run once Redis fails, after its
+// retries, instead of the run ending with the error.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitFallback {
+    // Nothing; the run ends.
+    None,
+    // The process, knowing only this run's sends.
+    #[default]
+    Memory,
+    // send.limit_file, knowing every run's sends on this machine.
+    File,
+}
+
+impl LimitFallback {
+    pub const NAMES: &'static [&'static str] = &["none", "memory", "file"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "none" => Some(LimitFallback::None),
+            "memory" => Some(LimitFallback::Memory),
+            "file" => Some(LimitFallback::File),
+            _ => None,
+        }
+    }
+}
+
+// Ramps up what each sender account may send per day, from `start` on the
+// day it first sends by increase_percent a day, so a fresh mailbox builds a
+// reputation before it reaches its daily cap.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WarmupConfig {
+    pub enabled: bool,
+    pub start: usize,
+    pub increase_percent: u32,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        WarmupConfig { enabled: false, start: DEFAULT_WARMUP_START, increase_percent: DEFAULT_WARMUP_INCREASE_PERCENT }
+    }
+}
+
+impl WarmupConfig {
+    // The emails allowed on a day of the warm-up, the first being day 0.
+    pub fn limit(&self, day: u32) -> usize {
+        let growth = 1.0 + f64::from(self.increase_percent) / 100.0;
+        (self.start as f64 * growth.powi(day.min(i32::MAX as u32) as i32)).round() as usize
+    }
+}
+
+// How failed sends are tried again: backoff_minutes after the first failure,
+// twice as long after each one since, until max_attempts have failed and the
+// send moves to the dead letters.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub backoff_minutes: u32,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig { max_attempts: DEFAULT_RETRY_MAX_ATTEMPTS, backoff_minutes: DEFAULT_RETRY_BACKOFF_MINUTES }
+    }
+}
+
+impl RetryConfig {
+    // The wait before trying again after `attempts` failures.
+    pub fn backoff(&self, attempts: u32) -> chrono::Duration {
+        let minutes = i64::from(self.backoff_minutes).saturating_mul(1 << attempts.saturating_sub(1).min(16));
+        chrono::Duration::minutes(minutes)
+    }
+}
+
+// Sending limits and connection settings matching what a provider documents,
+// picked with send.provider or --provider. They fill in what config.toml
+// leaves out, and environment variables still override them.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProviderPreset {
+    // A free Gmail account: 500 recipients a day.
+    Gmail,
+    // A paid Google Workspace mailbox: 2,000 messages a day.
+    GoogleWorkspace,
+    // Outlook.com and Hotmail: 300 recipients a day, 30 messages a minute.
+    Outlook,
+    // An SES account still in the sandbox: 200 messages a day, one a second,
+    // and only to verified addresses.
+    SesSandbox,
+}
+
+impl ProviderPreset {
+    pub const NAMES: &'static [&'static str] = &["gmail", "google-workspace", "outlook", "ses-sandbox"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "gmail" => Some(ProviderPreset::Gmail),
+            "google-workspace" => Some(ProviderPreset::GoogleWorkspace),
+            "outlook" => Some(ProviderPreset::Outlook),
+            "ses-sandbox" => Some(ProviderPreset::SesSandbox),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProviderPreset::Gmail => "gmail",
+            ProviderPreset::GoogleWorkspace => "google-workspace",
+            ProviderPreset::Outlook => "outlook",
+            ProviderPreset::SesSandbox => "ses-sandbox",
+        }
+    }
+
+    // The preset as config.toml would write it. Google publishes no
+    // per-minute rates, so theirs are kept well clear of its burst throttling.
+    fn settings(self) -> &'static str {
+        match self {
+            ProviderPreset::Gmail => {
+                r#"
+                [smtp]
+                host = "smtp.gmail.com"
+                tls = "tls"
+                [send]
+                transport = "smtp"
+                max_per_day = 500
+                max_per_minute = 20
+                "#
+            }
+            ProviderPreset::GoogleWorkspace => {
+                r#"
+                [smtp]
+                host = "smtp.gmail.com"
+                tls = "tls"
+                [send]
+                transport = "smtp"
+                max_per_day = 2000
+                max_per_minute = 30
+                "#
+            }
+            ProviderPreset::Outlook => {
+                r#"
+                [smtp]
+                host = "smtp-mail.outlook.com"
+                tls = "starttls"
+                [send]
+                transport = "smtp"
+                max_per_day = 300
+                max_per_minute = 30
+                "#
+            }
+            ProviderPreset::SesSandbox => {
+                r#"
+                [send]
+                transport = "ses"
+                max_per_day = 200
+                max_per_minute = 60
+                "#
+            }
+        }
+    }
+}
+
+// Adds the keys of `defaults` that `table` doesn't have, table by table.
+fn merge_defaults(table: &mut toml::Table, defaults: toml::Table) {
+    for (key, value) in defaults {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(value)) => merge_defaults(existing, value),
+            (Some(_), _) => {}
+            (None, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+// When `send` and `follow-up` may send; outside it they wait for it to open
+// again. With recipient_timezone the hours are the lead's own, from the US
+// state in its address or search location, and leads outside theirs wait
+// while the others go out.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    // "mon", "tue", ...; every day when empty.
+    pub days: Vec<String>,
+    // "09:00"; from midnight and to midnight when not set.
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub timezone: String,
+    pub recipient_timezone: bool,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        ScheduleConfig { days: Vec::new(), start: None, end: None, timezone: "UTC".to_string(), recipient_timezone: false }
+    }
+}
+
+impl SendConfig {
+    // Checked by validate, so this only fails on a hand-built config.
+    pub fn sender_mailbox(&self) -> Result<Mailbox, BotError> {
+        let mut mailbox: Mailbox = self
+            .sender
+            .parse()
+            .map_err(|e| BotError::ConfigError(format!("send.sender: \"{}\" is not an email address: {}", self.sender, e)))?;
+        if let Some(name) = &self.sender_name {
+            mailbox.name = Some(name.clone()).filter(|name| !name.trim().is_empty());
+        }
+        Ok(mailbox)
+    }
+}
+
+impl SmtpConfig {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or_else(|| self.tls.default_port())
+    }
+
+    pub fn password(&self) -> Result<&str, BotError> {
+        self.password.as_deref().filter(|password| !password.is_empty()).ok_or_else(|| {
+            BotError::ConfigError("smtp.password is not set; set EMAIL_PASSWORD or [smtp] password in config.toml".to_string())
+        })
+    }
+}
+
+fn parse_choice<T>(key: &str, var: &str, value: &str, parse: fn(&str) -> Option<T>, names: &[&str]) -> Result<T, BotError> {
+    parse(value).ok_or_else(|| {
+        BotError::ConfigError(format!("{} (from {}): expected one of {}, got \"{}\"", key, var, names.join(", "), value))
+    })
+}
+
+fn parse_bool(key: &str, var: &str, value: &str) -> Result<bool, BotError> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" | "" => Ok(false),
+        _ => Err(BotError::ConfigError(format!("{} (from {}): expected true or false, got \"{}\"", key, var, value))),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(key: &str, var: &str, value: &str) -> Result<T, BotError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| BotError::ConfigError(format!("{} (from {}): expected a whole number, got \"{}\"", key, var, value)))
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceConfig {
+    pub selectors: SelectorConfig,
+}
+
+impl Config {
+    // A missing file at the default location just means "use the defaults";
+    // an explicitly requested file has to exist.
+    // `provider` is --provider, which beats send.provider.
+    pub fn load(path: Option<&str>, provider: Option<&str>) -> Result<Self, BotError> {
+        let (path, required) = match path {
+            Some(path) => (path, true),
+            None => (DEFAULT_CONFIG_PATH, false),
+        };
+
+        let contents = if !required && !Path::new(path).exists() {
+            String::new()
+        } else {
+            fs::read_to_string(path).map_err(BotError::IOError)?
+        };
+        let mut config: Config = toml::from_str(&contents).map_err(|e| BotError::ConfigError(format!("{}: {}", path, e)))?;
+        let preset = match provider {
+            Some(name) => Some(ProviderPreset::parse(name).ok_or_else(|| {
+                BotError::ConfigError(format!("--provider: expected one of {}, got \"{}\"", ProviderPreset::NAMES.join(", "), name))
+            })?),
+            None => config.send.provider,
+        };
+        // Read a second time with the preset under the file, which was
+        // checked with its own line numbers above.
+        if let Some(preset) = preset {
+            let mut table: toml::Table = toml::from_str(&contents).map_err(|e| BotError::ConfigError(format!("{}: {}", path, e)))?;
+            merge_defaults(&mut table, toml::from_str(preset.settings()).expect("valid preset"));
+            config = table.try_into().map_err(|e| BotError::ConfigError(format!("{}: {}", path, e)))?;
+            config.send.provider = Some(preset);
+        }
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<(), BotError> {
+        for (var, key) in ENV_OVERRIDES {
+            let Ok(value) = env::var(var) else {
+                continue;
+            };
+            match *key {
+                "redis.url" => self.redis.url = value,
+                "redis.username" => self.redis.username = Some(value),
+                "redis.password" => self.redis.password = Some(value),
+                "redis.db" => self.redis.db = Some(parse_number(key, var, &value)?),
+                "redis.tls" => self.redis.tls = parse_bool(key, var, &value)?,
+                "redis.tls_insecure" => self.redis.tls_insecure = parse_bool(key, var, &value)?,
+                "redis.pool_size" => self.redis.pool_size = parse_number(key, var, &value)?,
+                "redis.mode" => self.redis.mode = parse_choice(key, var, &value, RedisMode::parse, RedisMode::NAMES)?,
+                "redis.nodes" => {
+                    self.redis.nodes = value.split(',').map(str::trim).filter(|node| !node.is_empty()).map(str::to_string).collect()
+                }
+                "redis.sentinel_master" => self.redis.sentinel_master = value,
+                "smtp.host" => self.smtp.host = value,
+                "smtp.port" => self.smtp.port = Some(parse_number(key, var, &value)?),
+                "smtp.tls" => self.smtp.tls = parse_choice(key, var, &value, SmtpTls::parse, SmtpTls::NAMES)?,
+                "send.transport" => self.send.transport = parse_choice(key, var, &value, TransportKind::parse, TransportKind::NAMES)?,
+                "sendgrid.api_key" => self.sendgrid.api_key = Some(value),
+                "mailgun.api_key" => self.mailgun.api_key = Some(value),
+                "mailgun.domain" => self.mailgun.domain = Some(value),
+                "mailgun.region" => self.mailgun.region = parse_choice(key, var, &value, MailgunRegion::parse, MailgunRegion::NAMES)?,
+                "mailgun.sandbox_domain" => self.mailgun.sandbox_domain = Some(value),
+                "mailgun.sandbox" => self.mailgun.sandbox = parse_bool(key, var, &value)?,
+                "ses.region" => self.ses.region = value,
+                "ses.access_key_id" => self.ses.access_key_id = Some(value),
+                "ses.secret_access_key" => self.ses.secret_access_key = Some(value),
+                "ses.session_token" => self.ses.session_token = Some(value),
+                "ses.configuration_set" => self.ses.configuration_set = Some(value),
+                "postmark.server_token" => self.postmark.server_token = Some(value),
+                "postmark.message_stream" => self.postmark.message_stream = value,
+                "smtp.username" => self.smtp.username = Some(value),
+                "smtp.password" => self.smtp.password = Some(value),
+                "smtp.oauth2.client_id" => self.smtp.oauth2.get_or_insert_with(Default::default).client_id = value,
+                "smtp.oauth2.client_secret" => self.smtp.oauth2.get_or_insert_with(Default::default).client_secret = value,
+                "smtp.oauth2.refresh_token" => self.smtp.oauth2.get_or_insert_with(Default::default).refresh_token = value,
+                "smtp.oauth2.token_url" => self.smtp.oauth2.get_or_insert_with(Default::default).token_url = value,
+                "dkim.selector" => self.dkim.selector = Some(value),
+                "dkim.domain" => self.dkim.domain = Some(value),
+                "dkim.algorithm" => self.dkim.algorithm = parse_choice(key, var, &value, DkimAlgorithm::parse, DkimAlgorithm::NAMES)?,
+                "dkim.private_key" => self.dkim.private_key = Some(value),
+                "dkim.private_key_file" => self.dkim.private_key_file = Some(value),
+                "company.name" => self.company.name = Some(value),
+                "company.postal_address" => self.company.postal_address = Some(value),
+                "unsubscribe.url" => self.unsubscribe.url = Some(value),
+                "unsubscribe.mailto" => self.unsubscribe.mailto = Some(value),
+                "unsubscribe.secret" => self.unsubscribe.secret = Some(value),
+                "tracking.url" => self.tracking.url = Some(value),
+                "tracking.secret" => self.tracking.secret = Some(value),
+                "tracking.clicks" => self.tracking.clicks = parse_bool(key, var, &value)?,
+                "imap.host" => self.imap.host = Some(value),
+                "imap.port" => self.imap.port = Some(parse_number(key, var, &value)?),
+                "imap.username" => self.imap.username = Some(value),
+                "imap.password" => self.imap.password = Some(value),
+                "imap.mailbox" => self.imap.mailbox = value,
+                "sandbox.host" => self.sandbox.host = value,
+                "sandbox.port" => self.sandbox.port = parse_number(key, var, &value)?,
+                "spamassassin.host" => self.spamassassin.host = Some(value),
+                "spamassassin.port" => self.spamassassin.port = parse_number(key, var, &value)?,
+                "spamassassin.threshold" => {
+                    self.spamassassin.threshold = value.trim().parse().map_err(|_| {
+                        BotError::ConfigError(format!("{} (from {}): expected a number like 5.0, got \"{}\"", key, var, value))
+                    })?
+                }
+                "send.sender" => self.send.sender = value,
+                "send.sender_name" => self.send.sender_name = Some(value),
+                "send.max_per_day" => self.send.max_per_day = parse_number(key, var, &value)?,
+                "send.max_per_hour" => self.send.max_per_hour = Some(parse_number(key, var, &value)?),
+                "send.max_per_minute" => self.send.max_per_minute = Some(parse_number(key, var, &value)?),
+                "send.max_per_domain" => self.send.max_per_domain = Some(parse_number(key, var, &value)?),
+                "send.limit_strategy" => self.send.limit_strategy = parse_choice(key, var, &value, LimitStrategy::parse, LimitStrategy::NAMES)?,
+                "send.limit_timezone" => self.send.limit_timezone = value,
+                "send.limit_fallback" => self.send.limit_fallback = parse_choice(key, var, &value, LimitFallback::parse, LimitFallback::NAMES)?,
+                "send.limit_file" => self.send.limit_file = value,
+                "send.delay_ms" => self.send.delay_ms = parse_number(key, var, &value)?,
+                "send.max_delay_ms" => self.send.max_delay_ms = parse_number(key, var, &value)?,
+                "warmup.enabled" => self.warmup.enabled = parse_bool(key, var, &value)?,
+                "warmup.start" => self.warmup.start = parse_number(key, var, &value)?,
+                "warmup.increase_percent" => self.warmup.increase_percent = parse_number(key, var, &value)?,
+                "retry.max_attempts" => self.retry.max_attempts = parse_number(key, var, &value)?,
+                "retry.backoff_minutes" => self.retry.backoff_minutes = parse_number(key, var, &value)?,
+                "schedule.days" => {
+                    self.schedule.days = value.split(',').map(str::trim).filter(|day| !day.is_empty()).map(str::to_string).collect()
+                }
+                "schedule.start" => self.schedule.start = Some(value),
+                "schedule.end" => self.schedule.end = Some(value),
+                "schedule.timezone" => self.schedule.timezone = value,
+                "schedule.recipient_timezone" => self.schedule.recipient_timezone = parse_bool(key, var, &value)?,
+                _ => unreachable!("no override for {}", key),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), BotError> {
+        self.redis.connection_info()?;
+        if self.redis.db.is_some_and(|db| db < 0) {
+            return Err(BotError::ConfigError("redis.db must not be negative".to_string()));
+        }
+        if self.redis.pool_size == 0 {
+            return Err(BotError::ConfigError("redis.pool_size must be at least 1".to_string()));
+        }
+        self.redis.node_infos()?;
+        match self.redis.mode {
+            RedisMode::Standalone => {}
+            RedisMode::Sentinel => {
+                if self.redis.nodes.is_empty() {
+                    return Err(BotError::ConfigError("redis.nodes must list the sentinels in sentinel mode".to_string()));
+                }
+                if self.redis.sentinel_master.trim().is_empty() {
+                    return Err(BotError::ConfigError("redis.sentinel_master must not be empty in sentinel mode".to_string()));
+                }
+            }
+            RedisMode::Cluster => {
+                if self.redis.connection_info()?.redis.db != 0 {
+                    return Err(BotError::ConfigError("redis.db must be 0 in cluster mode, which has no other databases".to_string()));
+                }
+            }
+        }
+        if self.smtp.host.trim().is_empty() {
+            return Err(BotError::ConfigError("smtp.host must not be empty".to_string()));
+        }
+        if self.smtp.port == Some(0) {
+            return Err(BotError::ConfigError("smtp.port must be between 1 and 65535".to_string()));
+        }
+        if self.smtp.username.as_deref().is_some_and(|username| username.trim().is_empty()) {
+            return Err(BotError::ConfigError("smtp.username must not be empty; leave it out to log in as the sender".to_string()));
+        }
+        if let Some(oauth2) = &self.smtp.oauth2 {
+            let fields = [
+                ("client_id", &oauth2.client_id),
+                ("client_secret", &oauth2.client_secret),
+                ("refresh_token", &oauth2.refresh_token),
+                ("token_url", &oauth2.token_url),
+            ];
+            for (field, value) in fields {
+                if value.trim().is_empty() {
+                    return Err(BotError::ConfigError(format!("smtp.oauth2.{} is not set", field)));
+                }
+            }
+            if !self.smtp.auth {
+                return Err(BotError::ConfigError("smtp.oauth2 needs smtp.auth = true".to_string()));
+            }
+        }
+        if let Some(selector) = &self.dkim.selector {
+            if selector.trim().is_empty() || !selector.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') {
+                return Err(BotError::ConfigError(format!("dkim.selector: \"{}\" is not a DNS label like \"mail\"", selector)));
+            }
+            if self.dkim.private_key.is_none() && self.dkim.private_key_file.is_none() {
+                return Err(BotError::ConfigError(
+                    "dkim.selector is set without a key; set DKIM_PRIVATE_KEY, [dkim] private_key or private_key_file".to_string(),
+                ));
+            }
+        } else if self.dkim.private_key.is_some() || self.dkim.private_key_file.is_some() {
+            return Err(BotError::ConfigError("dkim.selector is not set; set DKIM_SELECTOR or [dkim] selector to sign with the key".to_string()));
+        }
+        if self.dkim.domain.as_deref().is_some_and(|domain| domain.trim().is_empty()) {
+            return Err(BotError::ConfigError("dkim.domain must not be empty; leave it out to sign for the sender's domain".to_string()));
+        }
+        if self.ses.region.trim().is_empty() || !self.ses.region.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(BotError::ConfigError(format!("ses.region: \"{}\" is not an AWS region like us-east-1", self.ses.region)));
+        }
+        if self.postmark.message_stream.trim().is_empty() {
+            return Err(BotError::ConfigError("postmark.message_stream must not be empty".to_string()));
+        }
+        for (campaign, stream) in &self.postmark.streams {
+            if stream.trim().is_empty() {
+                return Err(BotError::ConfigError(format!("postmark.streams.{} must not be empty", campaign)));
+            }
+        }
+        if self.attachments.max_total_kb == 0 {
+            return Err(BotError::ConfigError("attachments.max_total_kb must be at least 1".to_string()));
+        }
+        for (campaign, files) in &self.attachments.campaigns {
+            if files.iter().any(|file| file.trim().is_empty()) {
+                return Err(BotError::ConfigError(format!("attachments.campaigns.{} lists an empty path", campaign)));
+            }
+        }
+        if let Some(url) = &self.unsubscribe.url {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err(BotError::ConfigError(format!("unsubscribe.url: \"{}\" is not an http(s) URL", url)));
+            }
+            if !url.contains("{token}") {
+                return Err(BotError::ConfigError("unsubscribe.url must contain {token} to identify the recipient".to_string()));
+            }
+            if self.unsubscribe.secret.as_deref().is_none_or(|secret| secret.trim().is_empty()) {
+                return Err(BotError::ConfigError(
+                    "unsubscribe.secret is not set; set UNSUBSCRIBE_SECRET or [unsubscribe] secret to sign unsubscribe links".to_string(),
+                ));
+            }
+        }
+        if let Some(mailto) = &self.unsubscribe.mailto {
+            if mailto.parse::<lettre::Address>().is_err() {
+                return Err(BotError::ConfigError(format!("unsubscribe.mailto: \"{}\" is not an email address", mailto)));
+            }
+        }
+        if let Some(url) = &self.tracking.url {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err(BotError::ConfigError(format!("tracking.url: \"{}\" is not an http(s) URL", url)));
+            }
+            if self.tracking.secret.as_deref().is_none_or(|secret| secret.trim().is_empty()) {
+                return Err(BotError::ConfigError(
+                    "tracking.secret is not set; set TRACKING_SECRET or [tracking] secret to sign tracking links".to_string(),
+                ));
+            }
+        }
+        if self.utm.enabled {
+            for (field, value) in [("source", &self.utm.source), ("medium", &self.utm.medium), ("campaign", &self.utm.campaign)] {
+                if value.trim().is_empty() {
+                    return Err(BotError::ConfigError(format!("utm.{} must not be empty; set utm.enabled = false to turn UTM tags off", field)));
+                }
+            }
+        }
+        if self.imap.host.as_deref().is_some_and(|host| host.trim().is_empty()) {
+            return Err(BotError::ConfigError("imap.host must not be empty".to_string()));
+        }
+        if self.imap.port == Some(0) {
+            return Err(BotError::ConfigError("imap.port must be between 1 and 65535".to_string()));
+        }
+        if self.imap.mailbox.trim().is_empty() {
+            return Err(BotError::ConfigError("imap.mailbox must not be empty".to_string()));
+        }
+        if self.sandbox.host.trim().is_empty() {
+            return Err(BotError::ConfigError("sandbox.host must not be empty".to_string()));
+        }
+        if self.sandbox.port == 0 {
+            return Err(BotError::ConfigError("sandbox.port must be between 1 and 65535".to_string()));
+        }
+        if self.spamassassin.host.as_deref().is_some_and(|host| host.trim().is_empty()) {
+            return Err(BotError::ConfigError("spamassassin.host must not be empty".to_string()));
+        }
+        if self.spamassassin.port == 0 {
+            return Err(BotError::ConfigError("spamassassin.port must be between 1 and 65535".to_string()));
+        }
+        if !self.spamassassin.threshold.is_finite() {
+            return Err(BotError::ConfigError("spamassassin.threshold must be a number like 5.0".to_string()));
+        }
+        let mut account_names = HashSet::new();
+        for account in &self.accounts {
+            if account.name.trim().is_empty() {
+                return Err(BotError::ConfigError("every [[accounts]] entry needs a name".to_string()));
+            }
+            if !account_names.insert(account.name.as_str()) {
+                return Err(BotError::ConfigError(format!("accounts.{} is listed twice", account.name)));
+            }
+            account.sender_mailbox()?;
+            if account.max_per_day == Some(0) {
+                return Err(BotError::ConfigError(format!("accounts.{}.max_per_day must be at least 1", account.name)));
+            }
+        }
+        if !self.accounts.is_empty() && !matches!(self.send.transport, TransportKind::Smtp | TransportKind::Sandbox) {
+            return Err(BotError::ConfigError("[[accounts]] send over SMTP; set send.transport = \"smtp\" or \"sandbox\"".to_string()));
+        }
+        self.send.sender_mailbox()?;
+        if self.send.max_per_day == 0 {
+            return Err(BotError::ConfigError("send.max_per_day must be at least 1".to_string()));
+        }
+        if self.send.max_delay_ms < self.send.delay_ms {
+            return Err(BotError::ConfigError(format!(
+                "send.max_delay_ms ({}) must be at least send.delay_ms ({})",
+                self.send.max_delay_ms, self.send.delay_ms
+            )));
+        }
+        SendWindow::from_config(&self.schedule)?;
+        Limits::from_config(&self.send)?;
+        if self.warmup.start == 0 {
+            return Err(BotError::ConfigError("warmup.start must be at least 1".to_string()));
+        }
+        if self.retry.max_attempts == 0 {
+            return Err(BotError::ConfigError("retry.max_attempts must be at least 1".to_string()));
+        }
+        if self.send.max_per_hour == Some(0) {
+            return Err(BotError::ConfigError("send.max_per_hour must be at least 1".to_string()));
+        }
+        if self.send.max_per_minute == Some(0) {
+            return Err(BotError::ConfigError("send.max_per_minute must be at least 1".to_string()));
+        }
+        if self.send.max_per_domain == Some(0) {
+            return Err(BotError::ConfigError("send.max_per_domain must be at least 1".to_string()));
+        }
+        for name in self.sources.keys() {
+            if !SOURCE_NAMES.contains(&name.as_str()) {
+                return Err(BotError::ConfigError(format!(
+                    "[sources.{}] is not a known source, expected one of: {}",
+                    name,
+                    SOURCE_NAMES.join(", ")
+                )));
+            }
+            source_by_name(name, self)?;
+        }
+        Ok(())
+    }
+
+    pub fn source(&self, name: &str) -> SourceConfig {
+        self.sources.get(name).cloned().unwrap_or_default()
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use html5ever::{local_name, namespace_url, ns, QualName};
+use scraper::{Html, Node, Selector, StrTendril};
+
+// One `property: value` of a rule or style attribute, with what it takes to
+// win the cascade: !important, then inline over stylesheet, then
+// specificity, then source order.
+#[derive(Debug, Clone)]
+struct Declaration {
+    property: String,
+    value: String,
+    important: bool,
+    inline: bool,
+    specificity: (usize, usize, usize),
+    order: usize,
+}
+
+impl Declaration {
+    fn rank(&self) -> (bool, bool, (usize, usize, usize), usize) {
+        (self.important, self.inline, self.specificity, self.order)
+    }
+}
+
+enum Block<'a> {
+    Rule { selectors: &'a str, body: &'a str },
+    // @media, @font-face and the like, kept as written.
+    AtRule(&'a str),
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        output.push_str(&rest[..start]);
+        rest = rest[start + 2..].find("*/").map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    output.push_str(rest);
+    output
+}
+
+// The length of the {...} block `css` starts with, nested blocks included.
+fn block_len(css: &str) -> usize {
+    let mut depth = 0;
+    for (i, c) in css.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    css.len()
+}
+
+fn parse_stylesheet(css: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut rest = css.trim_start();
+    while !rest.is_empty() {
+        let Some(open) = rest.find('{') else {
+            break;
+        };
+        if rest.starts_with('@') {
+            // Statements like @import end at a ';' before any block.
+            let len = match rest.find(';') {
+                Some(semicolon) if semicolon < open => semicolon + 1,
+                _ => open + block_len(&rest[open..]),
+            };
+            blocks.push(Block::AtRule(rest[..len].trim()));
+            rest = rest[len..].trim_start();
+            continue;
+        }
+        let close = rest[open..].find('}').map_or(rest.len(), |close| open + close);
+        blocks.push(Block::Rule { selectors: rest[..open].trim(), body: rest[open + 1..close].trim() });
+        rest = rest.get(close + 1..).unwrap_or("").trim_start();
+    }
+    blocks
+}
+
+fn parse_declarations(body: &str) -> Vec<(String, String, bool)> {
+    body.split(';')
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let (property, value) = (property.trim().to_lowercase(), value.trim());
+            if property.is_empty() || value.is_empty() {
+                return None;
+            }
+            let important = value.to_lowercase().ends_with("!important");
+            Some((property, value.to_string(), important))
+        })
+        .collect()
+}
+
+// (ids, classes and attributes, elements), close enough for the selectors
+// email templates use.
+fn specificity(selector: &str) -> (usize, usize, usize) {
+    let ids = selector.matches('#').count();
+    let classes = selector.matches('.').count() + selector.matches('[').count();
+    let elements = selector
+        .split(|c: char| c.is_whitespace() || "+>~".contains(c))
+        .filter(|part| part.starts_with(|c: char| c.is_ascii_alphabetic()))
+        .count();
+    (ids, classes, elements)
+}
+
+// Pseudo-classes like :hover can't be written into a style attribute, so
+// rules using them stay in the <style> block.
+fn is_inlinable(selector: &str) -> bool {
+    !selector.is_empty() && !selector.contains(':')
+}
+
+fn format_style(declarations: &[Declaration]) -> String {
+    let mut sorted = declarations.to_vec();
+    sorted.sort_by_key(Declaration::rank);
+    // The winner of each property, in the order they won.
+    let mut winners: Vec<&Declaration> = Vec::new();
+    for declaration in &sorted {
+        winners.retain(|winner| winner.property != declaration.property);
+        winners.push(declaration);
+    }
+    winners.iter().map(|declaration| format!("{}: {}", declaration.property, declaration.value)).collect::<Vec<_>>().join("; ")
+}
+
+// Gmail and Outlook drop or ignore <style> blocks in many cases, so the
+// rules are written into the style attribute of every element they match.
+// What can't be inlined (pseudo-classes, @media queries) stays behind in the
+// <style> block, which is removed when nothing is left.
+pub fn inline_css(html: &str) -> String {
+    let mut document = Html::parse_document(html);
+    let style_selector = Selector::parse("style").expect("valid selector");
+    let mut styles = Vec::new();
+    let mut matches: HashMap<_, Vec<Declaration>> = HashMap::new();
+    let mut order = 0;
+    for style in document.select(&style_selector) {
+        let css = strip_comments(&style.text().collect::<String>());
+        let mut kept = Vec::new();
+        for block in parse_stylesheet(&css) {
+            let (selectors, body) = match block {
+                Block::Rule { selectors, body } => (selectors, body),
+                Block::AtRule(rule) => {
+                    kept.push(rule.to_string());
+                    continue;
+                }
+            };
+            let declarations = parse_declarations(body);
+            for selector in selectors.split(',').map(str::trim) {
+                let parsed = Selector::parse(selector).ok().filter(|_| is_inlinable(selector));
+                let Some(parsed) = parsed else {
+                    kept.push(format!("{} {{ {} }}", selector, body));
+                    continue;
+                };
+                for element in document.select(&parsed) {
+                    let found = matches.entry(element.id()).or_default();
+                    for (property, value, important) in &declarations {
+                        order += 1;
+                        found.push(Declaration {
+                            property: property.clone(),
+                            value: value.clone(),
+                            important: *important,
+                            inline: false,
+                            specificity: specificity(selector),
+                            order,
+                        });
+                    }
+                }
+            }
+        }
+        styles.push((style.id(), kept.join("\n")));
+    }
+
+    for (id, mut declarations) in matches {
+        let Some(mut node) = document.tree.get_mut(id) else {
+            continue;
+        };
+        let Node::Element(element) = node.value() else {
+            continue;
+        };
+        // What the template wrote in the attribute itself beats the stylesheet.
+        for (property, value, important) in parse_declarations(element.attr("style").unwrap_or("")) {
+            order += 1;
+            declarations.push(Declaration { property, value, important, inline: true, specificity: (0, 0, 0), order });
+        }
+        let name = QualName::new(None, ns!(), local_name!("style"));
+        element.attrs.insert(name, StrTendril::from(format_style(&declarations)));
+    }
+    for (id, kept) in styles {
+        let Some(mut node) = document.tree.get_mut(id) else {
+            continue;
+        };
+        if kept.is_empty() {
+            node.detach();
+        } else if let Some(mut text) = node.first_child() {
+            if let Node::Text(text) = text.value() {
+                text.text = StrTendril::from(format!("\n{}\n", kept));
+            }
+        }
+    }
+    document.html()
+}
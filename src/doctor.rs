@@ -0,0 +1,243 @@
+use hickory_resolver::TokioAsyncResolver;
+use crate::config::DkimAlgorithm;
+use crate::validation::{is_no_records, system_resolver, MxStatus, MxValidator};
+
+// SPF evaluation fails (permerror) past this many DNS-querying terms (RFC 7208).
+const SPF_MAX_LOOKUPS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    Warning,
+    // Likely to get mail rejected or sent to spam in bulk.
+    Problem,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Problem => "problem",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    // "SPF", "DKIM mail", "DMARC" or "MX".
+    pub check: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(check: &str, severity: Severity, message: impl Into<String>) -> Self {
+        Finding { check: check.to_string(), severity, message: message.into() }
+    }
+}
+
+// "k=v; k2=v2" records, as DKIM and DMARC use.
+fn tags(record: &str) -> Vec<(String, String)> {
+    record
+        .split(';')
+        .filter_map(|tag| {
+            let (name, value) = tag.split_once('=')?;
+            Some((name.trim().to_lowercase(), value.split_whitespace().collect()))
+        })
+        .collect()
+}
+
+fn tag<'a>(tags: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+}
+
+// The domain's TXT records starting with `version`, e.g. "v=spf1".
+fn versioned<'a>(records: &'a [String], version: &str) -> Vec<&'a str> {
+    records
+        .iter()
+        .map(|record| record.trim())
+        .filter(|record| {
+            record.len() >= version.len()
+                && record[..version.len()].eq_ignore_ascii_case(version)
+                && record[version.len()..].chars().next().is_none_or(|c| c == ' ' || c == ';')
+        })
+        .collect()
+}
+
+pub fn check_spf(records: &[String]) -> Finding {
+    let spf = versioned(records, "v=spf1");
+    let record = match spf.as_slice() {
+        [] => return Finding::new("SPF", Severity::Problem, "no SPF record; Gmail and Yahoo reject bulk mail from domains without one"),
+        [record] => *record,
+        _ => return Finding::new("SPF", Severity::Problem, format!("{} SPF records, which makes every check fail; merge them into one", spf.len())),
+    };
+    let terms: Vec<String> = record.split_whitespace().skip(1).map(|term| term.to_lowercase()).collect();
+    let lookups = terms
+        .iter()
+        .map(|term| term.trim_start_matches(['+', '-', '~', '?']))
+        .filter(|term| {
+            ["include:", "a:", "a/", "mx:", "mx/", "ptr", "exists:", "redirect="].iter().any(|prefix| term.starts_with(prefix))
+                || *term == "a"
+                || *term == "mx"
+        })
+        .count();
+    if lookups > SPF_MAX_LOOKUPS {
+        return Finding::new(
+            "SPF",
+            Severity::Problem,
+            format!("{} DNS lookups, more than the {} SPF allows, so checks fail: {}", lookups, SPF_MAX_LOOKUPS, record),
+        );
+    }
+    match terms.iter().find(|term| term.trim_start_matches(['+', '-', '~', '?']) == "all").map(String::as_str) {
+        Some("all" | "+all") => Finding::new("SPF", Severity::Problem, format!("\"+all\" lets anyone send as the domain: {}", record)),
+        Some("?all") => Finding::new("SPF", Severity::Warning, format!("\"?all\" says nothing about other senders; use ~all or -all: {}", record)),
+        None if !terms.iter().any(|term| term.starts_with("redirect=")) => {
+            Finding::new("SPF", Severity::Warning, format!("no \"all\" term, so other senders are neutral; end it with ~all or -all: {}", record))
+        }
+        _ => Finding::new("SPF", Severity::Ok, record),
+    }
+}
+
+// `algorithm` is what [dkim] signs with, when this is its selector.
+pub fn check_dkim(selector: &str, records: &[String], algorithm: Option<DkimAlgorithm>) -> Finding {
+    let check = format!("DKIM {}", selector);
+    // "v=DKIM1" is optional, so any record with a p= tag counts.
+    let Some(record) = records.iter().find(|record| tag(&tags(record), "p").is_some()) else {
+        return Finding::new(&check, Severity::Problem, format!("no key published at {}._domainkey", selector));
+    };
+    let tags = tags(record);
+    if tag(&tags, "p").is_some_and(str::is_empty) {
+        return Finding::new(&check, Severity::Problem, "the key is revoked (empty p=)");
+    }
+    let published = tag(&tags, "k").unwrap_or("rsa");
+    let expected = match algorithm {
+        Some(DkimAlgorithm::Rsa) => Some("rsa"),
+        Some(DkimAlgorithm::Ed25519) => Some("ed25519"),
+        None => None,
+    };
+    match expected {
+        Some(expected) if !published.eq_ignore_ascii_case(expected) => Finding::new(
+            &check,
+            Severity::Problem,
+            format!("the published key is {} but dkim.algorithm is {}", published, expected),
+        ),
+        _ => Finding::new(&check, Severity::Ok, format!("{} key published", published)),
+    }
+}
+
+pub fn check_dmarc(records: &[String]) -> Finding {
+    let dmarc = versioned(records, "v=DMARC1");
+    let record = match dmarc.as_slice() {
+        [] => return Finding::new("DMARC", Severity::Problem, "no DMARC record at _dmarc; Gmail and Yahoo require one from bulk senders"),
+        [record] => *record,
+        _ => return Finding::new("DMARC", Severity::Problem, "more than one DMARC record, so receivers ignore them all"),
+    };
+    match tag(&tags(record), "p").map(str::to_lowercase).as_deref() {
+        Some("none") => Finding::new("DMARC", Severity::Ok, format!("monitoring only (p=none): {}", record)),
+        Some("quarantine" | "reject") => Finding::new("DMARC", Severity::Ok, record),
+        Some(policy) => Finding::new("DMARC", Severity::Problem, format!("unknown policy p={}: {}", policy, record)),
+        None => Finding::new("DMARC", Severity::Problem, format!("no p= policy, so the record is invalid: {}", record)),
+    }
+}
+
+// Whether a DKIM signature for `signing` passes DMARC for mail from `from`:
+// the same domain, or one a subdomain of the other (relaxed alignment,
+// approximated without the public suffix list).
+pub fn is_aligned(from: &str, signing: &str) -> bool {
+    let (from, signing) = (from.to_lowercase(), signing.to_lowercase());
+    from == signing || from.ends_with(&format!(".{}", signing)) || signing.ends_with(&format!(".{}", from))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Signing<'a> {
+    pub selector: &'a str,
+    // dkim.domain, or the From domain.
+    pub domain: &'a str,
+    pub algorithm: DkimAlgorithm,
+}
+
+// What to check for one From domain.
+pub struct DomainChecks<'a> {
+    pub domain: &'a str,
+    // When mail is signed with [dkim].
+    pub signing: Option<Signing<'a>>,
+    // Selectors a provider signs with, e.g. "s1" and "s2" for SendGrid.
+    pub selectors: &'a [String],
+}
+
+// Looks up the SPF, DKIM and DMARC records of sending domains.
+pub struct Doctor {
+    resolver: TokioAsyncResolver,
+    mx: MxValidator,
+}
+
+impl Doctor {
+    pub fn new() -> Self {
+        Doctor { resolver: system_resolver(), mx: MxValidator::new() }
+    }
+
+    // The TXT records at `name`; empty when there are none. Each record's
+    // strings are joined, since long keys are split across several.
+    async fn txt(&self, name: &str) -> Result<Vec<String>, String> {
+        match self.resolver.txt_lookup(format!("{}.", name)).await {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .map(|txt| txt.iter().map(|part| String::from_utf8_lossy(part)).collect::<String>())
+                .collect()),
+            Err(e) if is_no_records(&e) => Ok(Vec::new()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    async fn check_txt(&self, check: &str, name: &str, parse: impl FnOnce(&[String]) -> Finding) -> Finding {
+        match self.txt(name).await {
+            Ok(records) => parse(&records),
+            Err(e) => Finding::new(check, Severity::Warning, format!("couldn't look up {}: {}", name, e)),
+        }
+    }
+
+    pub async fn check(&self, checks: &DomainChecks<'_>) -> Vec<Finding> {
+        let domain = checks.domain;
+        let mut findings = vec![self.check_txt("SPF", domain, check_spf).await];
+        let mut selectors: Vec<(&str, &str, Option<DkimAlgorithm>)> =
+            checks.selectors.iter().map(|selector| (selector.as_str(), domain, None)).collect();
+        if let Some(signing) = checks.signing {
+            selectors.retain(|(other, _, _)| *other != signing.selector);
+            selectors.insert(0, (signing.selector, signing.domain, Some(signing.algorithm)));
+            if !is_aligned(domain, signing.domain) {
+                findings.push(Finding::new(
+                    "DKIM",
+                    Severity::Problem,
+                    format!("mail is signed for {}, which doesn't align with the From domain, so DMARC can't pass on DKIM", signing.domain),
+                ));
+            }
+        }
+        if selectors.is_empty() {
+            findings.push(Finding::new(
+                "DKIM",
+                Severity::Warning,
+                "no selector to check; set up [dkim], or pass --selector with the one your provider signs with",
+            ));
+        }
+        for (selector, signing_domain, algorithm) in selectors {
+            let name = format!("{}._domainkey.{}", selector, signing_domain);
+            let check = format!("DKIM {}", selector);
+            findings.push(self.check_txt(&check, &name, |records| check_dkim(selector, records, algorithm)).await);
+        }
+        findings.push(self.check_txt("DMARC", &format!("_dmarc.{}", domain), check_dmarc).await);
+        // Replies and bounces come back to the From domain.
+        findings.push(match self.mx.check_domain(domain).await {
+            MxStatus::NoMail => Finding::new("MX", Severity::Problem, "the domain can't receive mail, so replies and bounces are lost"),
+            MxStatus::Unknown(e) => Finding::new("MX", Severity::Warning, format!("couldn't look up the domain's mail servers: {}", e)),
+            status => Finding::new("MX", Severity::Ok, status.primary_host().unwrap_or(domain).to_string()),
+        });
+        findings
+    }
+}
+
+impl Default for Doctor {
+    fn default() -> Self {
+        Doctor::new()
+    }
+}
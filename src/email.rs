@@ -0,0 +1,992 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use lettre::Address;
+use rand::Rng;
+use redis::AsyncCommands;
+use crate::attachment::Attachment;
+use crate::campaign::{Campaign, VariantSplit};
+use crate::followup::{DueEmail, RetryCause};
+use crate::config::{CompanyConfig, SendConfig, TrackingConfig, UnsubscribeConfig, UtmConfig};
+use crate::error::BotError;
+use crate::filter::EmailFilter;
+use crate::retry::{FailedSend, RetryQueue};
+use crate::ratelimit::{
+    check_update_domain_count, check_update_email_count, check_update_window_count, connection, domain_sent_today, emails_sent_today, release_domain_count,
+    release_account_count, release_email_count, release_window_count, window_reopens, RateLimiter, RedisPool, Window,
+};
+use crate::schedule::SendWindow;
+use crate::shutdown::Shutdown;
+use crate::scrape::{Business, ReviewStatus};
+use crate::storage::{LeadStore, SentEmail, CONTACTED_STATUSES, SEND_FAILED, SEND_REPLIED, SEND_SKIPPED};
+use crate::suppression::SuppressionList;
+use crate::template::{CampaignTemplate, FooterDetails};
+use crate::transport::rotation::SenderPool;
+use crate::tracking::{add_utm_parameters, TrackingLinks};
+use crate::transport::{new_message_id, OutgoingEmail};
+use crate::unsubscribe::UnsubscribeLinks;
+use crate::validation::{email_domain, SmtpStatus};
+
+pub const DEFAULT_SUBJECT: &str = "Grow Your Business with Coffee Code Studio - Special Offer Inside!";
+
+// Fallbacks keep sentences like "I noticed your {{ category }} business in
+// {{ city }}" readable for leads that lack the field.
+const FALLBACK_BUSINESS_NAME: &str = "there";
+const FALLBACK_CATEGORY: &str = "local";
+const FALLBACK_CITY: &str = "your area";
+
+// Up to this much is added to a wait for the next minute or hour window.
+const WINDOW_JITTER_MS: u64 = 5000;
+
+// The wait after the first temporary failure, doubling with each one after.
+const THROTTLE_BACKOFF_SECS: u64 = 60;
+const MAX_THROTTLE_BACKOFF_SECS: u64 = 15 * 60;
+// The most the pause between emails is stretched by.
+const MAX_THROTTLE_SLOWDOWN: u64 = 8;
+// Temporary failures of one email before it's recorded as failed.
+const MAX_THROTTLED_ATTEMPTS: u32 = 3;
+// Leads in a row whose checks or email end in an error that isn't the lead's
+// own before the run gives up; that many usually means Redis, the database or
+// the transport is down.
+const MAX_FAILED_LEADS_IN_A_ROW: usize = 5;
+
+// The city from a "123 Main St, Columbus, OH 43215" style address, or else
+// from the search location, e.g. "Columbus, OH".
+pub fn business_city(business: &Business) -> Option<String> {
+    let from_address = business.address.as_deref().and_then(|address| {
+        let parts: Vec<&str> = address.split(',').map(str::trim).filter(|part| !part.is_empty()).collect();
+        (parts.len() >= 2).then(|| parts[parts.len() - 2].to_string())
+    });
+    from_address.or_else(|| {
+        let location = business.location.as_deref()?.split(',').next()?.trim();
+        (!location.is_empty()).then(|| location.to_string())
+    })
+}
+
+// The variables every template can use besides the full `business` record.
+pub(crate) struct Personalization {
+    pub business_name: String,
+    pub city: String,
+    pub category: String,
+}
+
+impl Personalization {
+    pub fn new(business: &Business) -> Self {
+        Personalization {
+            business_name: business
+                .name
+                .as_deref()
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .unwrap_or(FALLBACK_BUSINESS_NAME)
+                .to_string(),
+            city: business_city(business).unwrap_or_else(|| FALLBACK_CITY.to_string()),
+            category: business
+                .categories
+                .first()
+                .map(|category| category.to_lowercase())
+                .unwrap_or_else(|| FALLBACK_CATEGORY.to_string()),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "email_template.html")]
+pub struct EmailTemplate<'a> {
+    pub subject: &'a str,
+    pub business: &'a Business,
+    pub business_name: &'a str,
+    pub city: &'a str,
+    pub category: &'a str,
+    pub company_name: &'a str,
+    pub postal_address: &'a str,
+    pub unsubscribe_url: &'a str,
+}
+
+pub fn render_email(subject: &str, business: &Business, footer: &FooterDetails) -> Result<String, BotError> {
+    let vars = Personalization::new(business);
+    let email_template = EmailTemplate {
+        subject,
+        business,
+        business_name: &vars.business_name,
+        city: &vars.city,
+        category: &vars.category,
+        company_name: footer.company_name,
+        postal_address: footer.postal_address,
+        unsubscribe_url: footer.unsubscribe_url,
+    };
+    email_template.render().map_err(BotError::TemplateError)
+}
+
+// The plain-text alternative sent alongside the HTML body.
+#[derive(Template)]
+#[template(path = "email_template.txt")]
+pub struct EmailTextTemplate<'a> {
+    pub subject: &'a str,
+    pub business: &'a Business,
+    pub business_name: &'a str,
+    pub city: &'a str,
+    pub category: &'a str,
+    pub company_name: &'a str,
+    pub postal_address: &'a str,
+    pub unsubscribe_url: &'a str,
+}
+
+pub fn render_text_email(subject: &str, business: &Business, footer: &FooterDetails) -> Result<String, BotError> {
+    let vars = Personalization::new(business);
+    let text_template = EmailTextTemplate {
+        subject,
+        business,
+        business_name: &vars.business_name,
+        city: &vars.city,
+        category: &vars.category,
+        company_name: footer.company_name,
+        postal_address: footer.postal_address,
+        unsubscribe_url: footer.unsubscribe_url,
+    };
+    text_template.render().map_err(BotError::TemplateError)
+}
+
+const MAX_EMAIL_LEN: usize = 254;
+const MAX_LOCAL_PART_LEN: usize = 64;
+
+// An RFC 5321 addr-spec that can actually receive mail: lettre's parser plus
+// length limits and a dotted domain, so "foo@bar" and "a b@c.com" are rejected.
+pub fn is_valid_email(email: &str) -> bool {
+    if email.len() > MAX_EMAIL_LEN || email.chars().any(char::is_whitespace) {
+        return false;
+    }
+    let Ok(address) = email.parse::<Address>() else {
+        return false;
+    };
+    let domain = address.domain();
+    address.user().len() <= MAX_LOCAL_PART_LEN
+        && domain.contains('.')
+        && !domain.starts_with('[')
+        && !domain.split('.').any(|label| label.is_empty() || label.starts_with('-') || label.ends_with('-'))
+}
+
+const CONTACTED_KEY: &str = "outreach:contacted";
+
+// Trimmed and lowercased; this is the form stored in the leads file.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().trim_end_matches('.').to_lowercase()
+}
+
+// Identifies the mailbox behind an address for deduplication: plus-tags are
+// dropped everywhere, and Gmail also ignores dots and accepts googlemail.com.
+pub fn dedup_key(email: &str) -> String {
+    let email = normalize_email(email);
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return email;
+    };
+    let local = local.split('+').next().unwrap_or(local);
+    match domain {
+        "gmail.com" | "googlemail.com" => format!("{}@gmail.com", local.replace('.', "")),
+        _ => format!("{}@{}", local, domain),
+    }
+}
+
+// Mailboxes that already got this outreach, across every leads file and run.
+pub async fn already_contacted(pool: &RedisPool, email: &str) -> Result<bool, BotError> {
+    let mut con = connection(pool).await?;
+    con.sismember(CONTACTED_KEY, dedup_key(email)).await.map_err(BotError::RedisError)
+}
+
+pub async fn mark_contacted(pool: &RedisPool, email: &str) -> Result<(), BotError> {
+    let mut con = connection(pool).await?;
+    let _: () = con.sadd(CONTACTED_KEY, dedup_key(email)).await.map_err(BotError::RedisError)?;
+    Ok(())
+}
+
+// What a run does with the emails it renders.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SendMode {
+    #[default]
+    Live,
+    // Every check and render happens, but nothing is sent, recorded or
+    // counted against the limits.
+    DryRun,
+    // Every email goes to this address instead, its subject naming the lead
+    // it was rendered for. The sends count against the limits, but nothing
+    // is recorded against the leads.
+    TestTo(String),
+}
+
+impl SendMode {
+    // Whether sends and skips are recorded in send_events and Redis.
+    pub fn records(&self) -> bool {
+        *self == SendMode::Live
+    }
+}
+
+// How a run slows down once the relay or provider pushes back with temporary
+// failures: it waits, longer each time, and pauses longer between emails for
+// the rest of the run. The email is tried again after the others.
+#[derive(Debug, Default)]
+pub struct Throttle {
+    hits: u32,
+    // Temporary failures per lead.
+    attempts: HashMap<String, u32>,
+}
+
+impl Throttle {
+    fn backoff(&self) -> Duration {
+        let secs = THROTTLE_BACKOFF_SECS.saturating_mul(1 << self.hits.saturating_sub(1).min(16));
+        Duration::from_secs(secs.min(MAX_THROTTLE_BACKOFF_SECS))
+    }
+
+    fn slowdown(&self) -> u64 {
+        (1u64 << self.hits.min(16)).min(MAX_THROTTLE_SLOWDOWN)
+    }
+}
+
+// Everything a campaign run needs besides the leads themselves.
+pub struct SendContext<'a> {
+    pub senders: &'a mut SenderPool,
+    pub settings: &'a SendConfig,
+    pub limiter: &'a mut dyn RateLimiter,
+    pub company: &'a CompanyConfig,
+    pub unsubscribe: &'a UnsubscribeConfig,
+    pub tracking: &'a TrackingConfig,
+    pub utm: &'a UtmConfig,
+    pub redis: &'a RedisPool,
+    pub suppression: &'a SuppressionList,
+    pub retries: &'a RetryQueue,
+    pub filter: &'a EmailFilter,
+    pub store: &'a dyn LeadStore,
+    pub campaign: &'a Campaign,
+    pub attachments: Arc<[Attachment]>,
+    // How long before a contacted address may be emailed again; None means never.
+    pub contact_cooldown: Option<chrono::Duration>,
+    pub mode: SendMode,
+    // Who this run emailed, when the mode doesn't record it, so each address
+    // still gets one email as it would in a live run.
+    pub emailed: HashSet<String>,
+    // Who earlier runs of the campaign already emailed or failed to; see
+    // `resumed_recipients`.
+    pub resumed: Resumed,
+    // None sends at any hour.
+    pub schedule: Option<&'a SendWindow>,
+    pub throttle: Throttle,
+    // Whether an email was tried already, so the next one waits for the
+    // pause first; carried over when one run sends several batches.
+    pub attempted: bool,
+    // Asked for, the run stops before its next email.
+    pub shutdown: Shutdown,
+}
+
+// Reasons never to email the lead, whatever it was sent before.
+async fn delivery_problem(context: &SendContext<'_>, business: &Business) -> Result<Option<String>, BotError> {
+    if !is_valid_email(&business.email) {
+        return Ok(Some("invalid email".to_string()));
+    }
+    if business.mx_valid == Some(false) {
+        return Ok(Some("domain does not accept mail".to_string()));
+    }
+    if business.smtp_status == Some(SmtpStatus::Undeliverable) {
+        return Ok(Some("mailbox rejected during verification".to_string()));
+    }
+    if business.review_status == Some(ReviewStatus::Rejected) {
+        return Ok(Some("rejected in review".to_string()));
+    }
+    if let Some(reason) = context.filter.domain_rejection(&business.email) {
+        return Ok(Some(reason.to_string()));
+    }
+    // Only Redis has the whole list, so when it can't be checked the error
+    // stands and the run stops once a few leads in a row hit it.
+    if let Some(reason) = context.suppression.reason(&business.email).await? {
+        return Ok(Some(format!("suppressed ({})", reason)));
+    }
+    // Whoever answered any campaign is talking to a person now.
+    if context.store.send_history(&business.email).await?.iter().any(|event| event.status == SEND_REPLIED) {
+        return Ok(Some("replied to an earlier email".to_string()));
+    }
+    Ok(None)
+}
+
+// With Redis down the send history alone says who was contacted, as it does
+// with a cooldown, so the run goes on with the limits' fallback.
+async fn contacted_in_redis(pool: &RedisPool, email: &str) -> bool {
+    already_contacted(pool, email).await.unwrap_or_else(|e| {
+        eprintln!("Could not check Redis for an earlier email to {}: {}; going by the send history", email, e);
+        false
+    })
+}
+
+async fn skip_reason(context: &mut SendContext<'_>, business: &Business) -> Result<Option<String>, BotError> {
+    if let Some(reason) = delivery_problem(context, business).await? {
+        return Ok(Some(reason));
+    }
+    // The Redis set has no timestamps, so with a cooldown only the send history counts.
+    match context.contact_cooldown {
+        Some(cooldown) => {
+            if context.store.was_contacted(&business.email, Some(Utc::now() - cooldown)).await? {
+                return Ok(Some(format!("contacted within the last {} days", cooldown.num_days())));
+            }
+        }
+        None => {
+            if context.store.was_contacted(&business.email, None).await? || contacted_in_redis(context.redis, &business.email).await {
+                return Ok(Some("already contacted in an earlier campaign".to_string()));
+            }
+        }
+    }
+    if context.emailed.contains(&dedup_key(&business.email)) {
+        return Ok(Some("already emailed in this run".to_string()));
+    }
+    Ok(None)
+}
+
+// One email of a campaign's sequence: its step (0 for the first email), the
+// A/B variant, subject and template it's rendered from, and the Message-IDs
+// of the emails it follows up on.
+struct Message<'a> {
+    step: u32,
+    variant: Option<&'a str>,
+    subject: &'a str,
+    template: &'a CampaignTemplate,
+    references: Vec<String>,
+}
+
+enum Outcome {
+    Sent,
+    Failed,
+    // A daily limit or quota was reached, so nothing more can be sent today,
+    // or the run is shutting down.
+    Stopped,
+    // The recipient's domain had its emails for today; the lead stays queued.
+    Deferred,
+    // Turned away for now; the lead is tried again after the others.
+    Throttled,
+}
+
+// A random pause from the send.delay_ms to max_delay_ms range, since emails
+// going out on a steady beat look automated to providers' filters. It is
+// stretched while the run is throttled.
+fn send_pause(context: &SendContext<'_>) -> Duration {
+    let settings = context.settings;
+    let pause = rand::thread_rng().gen_range(settings.delay_ms..=settings.max_delay_ms);
+    Duration::from_millis(pause.saturating_mul(context.throttle.slowdown()))
+}
+
+pub fn describe_send_pause(settings: &SendConfig) -> String {
+    let seconds = |ms: u64| ms as f64 / 1000.0;
+    if settings.delay_ms == settings.max_delay_ms {
+        format!("{}s", seconds(settings.delay_ms))
+    } else {
+        format!("{}-{}s", seconds(settings.delay_ms), seconds(settings.max_delay_ms))
+    }
+}
+
+// Takes a send from each of send.max_per_minute and max_per_hour, waiting for
+// the next window when one is used up. The wait ends a few seconds past the
+// window's start, at random, so the sends after it don't all go out on the
+// minute. None when the run is shut down during a wait.
+async fn take_window_sends(context: &mut SendContext<'_>) -> Result<Option<Vec<Window>>, BotError> {
+    let limits: Vec<(Window, usize)> = [(Window::Minute, context.settings.max_per_minute), (Window::Hour, context.settings.max_per_hour)]
+        .into_iter()
+        .filter_map(|(window, max)| Some((window, max?)))
+        .collect();
+    'windows: loop {
+        let mut taken = Vec::new();
+        for &(window, max) in &limits {
+            if check_update_window_count(context.limiter, window, max).await? {
+                taken.push(window);
+                continue;
+            }
+            release_window_sends(context.limiter, &taken).await?;
+            let wait = window_reopens(context.limiter, window).await? + Duration::from_millis(rand::thread_rng().gen_range(0..=WINDOW_JITTER_MS));
+            println!("Reached the limit of {} emails per {}; waiting {}s for the next one", max, window.name(), wait.as_secs());
+            context.shutdown.sleep(wait).await;
+            if context.shutdown.requested() {
+                return Ok(None);
+            }
+            continue 'windows;
+        }
+        return Ok(Some(taken));
+    }
+}
+
+async fn release_window_sends(limiter: &mut dyn RateLimiter, taken: &[Window]) -> Result<(), BotError> {
+    for &window in taken {
+        release_window_count(limiter, window).await?;
+    }
+    Ok(())
+}
+
+// The lead's domain with send.max_per_domain when that caps it. Test sends
+// go to the tester, so their leads' domains don't count.
+fn capped_domain(context: &SendContext<'_>, business: &Business) -> Option<(String, usize)> {
+    let max = context.settings.max_per_domain?;
+    let domain = email_domain(&business.email).filter(|domain| !context.filter.is_free_mail(domain))?;
+    (!matches!(context.mode, SendMode::TestTo(_))).then_some((domain, max))
+}
+
+async fn release_domain_send(limiter: &mut dyn RateLimiter, domain: &Option<(String, usize)>) -> Result<(), BotError> {
+    match domain {
+        Some((domain, _)) => release_domain_count(limiter, domain).await,
+        None => Ok(()),
+    }
+}
+
+// Gives back every send a live email took from the limits, for when it didn't go out.
+async fn release_sends(limiter: &mut dyn RateLimiter, account: &str, windows: &[Window], domain: &Option<(String, usize)>) -> Result<(), BotError> {
+    release_account_count(limiter, account).await?;
+    release_email_count(limiter).await?;
+    release_window_sends(limiter, windows).await?;
+    release_domain_send(limiter, domain).await
+}
+
+// The lead's error as a skip reason, so one bad lead doesn't end the run.
+// Errors in the lead's own data always are; others only while few enough
+// leads failed with them in a row, and past that, the error itself.
+fn tolerate(failures: &mut usize, error: BotError) -> Result<String, BotError> {
+    if error.concerns_lead() {
+        return Ok(format!("error: {}", error));
+    }
+    *failures += 1;
+    if *failures >= MAX_FAILED_LEADS_IN_A_ROW {
+        eprintln!("{} leads in a row failed; stopping the run", failures);
+        return Err(error);
+    }
+    Ok(format!("error: {}", error))
+}
+
+async fn skip_failed(context: &mut SendContext<'_>, business: &Business, error: BotError, failures: &mut usize) -> Result<(), BotError> {
+    let reason = tolerate(failures, error)?;
+    eprintln!("Skipped ({}): {}", reason, business.email);
+    if context.mode.records() {
+        context.store.record_send(context.campaign.id, &business.email, SEND_SKIPPED, Some(&reason), None).await?;
+    }
+    Ok(())
+}
+
+async fn deliver(context: &mut SendContext<'_>, business: &Business, message: Message<'_>) -> Result<Outcome, BotError> {
+    let campaign_id = context.campaign.id;
+    let (company_name, postal_address) = context.company.footer_identity()?;
+    let domain = capped_domain(context, business);
+    let mut windows = Vec::new();
+    let account = match context.mode {
+        SendMode::DryRun => {
+            if let Some((domain, max)) = &domain {
+                let rehearsed = context.emailed.iter().filter(|email| email_domain(email).as_ref() == Some(domain)).count();
+                if domain_sent_today(context.limiter, domain).await? + rehearsed >= *max {
+                    println!("Would defer ({} emails to {} today): {}", max, domain, business.email);
+                    return Ok(Outcome::Deferred);
+                }
+            }
+            if emails_sent_today(context.limiter).await? + context.emailed.len() >= context.settings.max_per_day {
+                println!("Reached the daily limit of max emails sent.");
+                return Ok(Outcome::Stopped);
+            }
+            context.senders.rehearse_next(context.limiter).await?
+        }
+        SendMode::Live | SendMode::TestTo(_) => {
+            if let Some((domain, max)) = &domain {
+                if !check_update_domain_count(context.limiter, domain, *max).await? {
+                    println!("Deferred ({} emails to {} today): {}", max, domain, business.email);
+                    return Ok(Outcome::Deferred);
+                }
+            }
+            let Some(taken) = take_window_sends(context).await? else {
+                release_domain_send(context.limiter, &domain).await?;
+                return Ok(Outcome::Stopped);
+            };
+            windows = taken;
+            if !check_update_email_count(context.limiter, context.settings.max_per_day).await? {
+                release_window_sends(context.limiter, &windows).await?;
+                release_domain_send(context.limiter, &domain).await?;
+                println!("Reached the daily limit of max emails sent.");
+                return Ok(Outcome::Stopped);
+            }
+            let account = context.senders.next_available(context.limiter).await?;
+            if account.is_none() {
+                release_email_count(context.limiter).await?;
+                release_window_sends(context.limiter, &windows).await?;
+                release_domain_send(context.limiter, &domain).await?;
+            }
+            account
+        }
+    };
+    let Some(account) = account else {
+        println!("Every sender account reached its daily limit or sending quota.");
+        return Ok(Outcome::Stopped);
+    };
+    // A test email's unsubscribe link is the tester's own, and it isn't
+    // tracked, so clicking around in it doesn't count against the lead.
+    let (recipient, tracked) = match &context.mode {
+        SendMode::TestTo(address) => (address.as_str(), false),
+        SendMode::Live | SendMode::DryRun => (business.email.as_str(), true),
+    };
+    let unsubscribe = UnsubscribeLinks::new(context.unsubscribe, recipient, &account.mailbox.email);
+    let footer = FooterDetails { company_name, postal_address, unsubscribe_url: unsubscribe.footer_url() };
+    let rendered = match message.template.render(message.subject, business, &footer) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            if context.mode != SendMode::DryRun {
+                release_sends(context.limiter, &account.name, &windows, &domain).await?;
+            }
+            return Err(e);
+        }
+    };
+    let keep = [unsubscribe.footer_url()];
+    let (mut html, text) = add_utm_parameters(&rendered.html, &rendered.text, context.utm, &context.campaign.name, &keep);
+    if let Some(tracking) = TrackingLinks::new(context.tracking, campaign_id, &business.email).filter(|_| tracked) {
+        html = tracking.track_clicks(&html, &keep);
+        tracking.add_open_pixel(&mut html);
+    }
+    let subject = match &context.mode {
+        SendMode::TestTo(_) => format!("[{}] {}", business.email, rendered.subject),
+        SendMode::Live | SendMode::DryRun => rendered.subject,
+    };
+    let email = OutgoingEmail {
+        from: account.mailbox.clone(),
+        to: recipient.to_string(),
+        subject,
+        html,
+        text,
+        campaign: context.campaign.name.clone(),
+        // The second tag lets provider dashboards split the A/B test too.
+        tags: std::iter::once(context.campaign.name.clone())
+            .chain(message.variant.map(|variant| format!("{}:{}", context.campaign.name, variant)))
+            .collect(),
+        list_unsubscribe: Some(unsubscribe.header()),
+        attachments: context.attachments.clone(),
+        inline_images: match message.template {
+            CampaignTemplate::File { images, .. } => images.clone(),
+            CampaignTemplate::Builtin => Arc::from([]),
+        },
+        message_id: new_message_id(account.mailbox.email.domain()),
+        references: message.references,
+    };
+
+    // No transport can take an address that doesn't parse, however often it's retried.
+    if let Err(e) = email.recipient() {
+        if context.mode != SendMode::DryRun {
+            release_sends(context.limiter, &account.name, &windows, &domain).await?;
+        }
+        return Err(e);
+    }
+    if context.mode == SendMode::DryRun {
+        match (message.step, message.variant) {
+            (0, Some(variant)) => println!("Would send to: {} (from {}, variant {}): {}", business.email, account.name, variant, email.subject),
+            (0, None) => println!("Would send to: {} (from {}): {}", business.email, account.name, email.subject),
+            (step, _) => println!("Would send follow-up {} to: {} (from {}): {}", step, business.email, account.name, email.subject),
+        }
+        context.emailed.insert(dedup_key(&business.email));
+        return Ok(Outcome::Sent);
+    }
+    if let Err(e) = account.transport.prepare().await {
+        release_sends(context.limiter, &account.name, &windows, &domain).await?;
+        return Err(e);
+    }
+    let result = account.transport.send(&email).await;
+    if let Err(e) = &result {
+        let attempts = context.throttle.attempts.entry(dedup_key(&business.email)).or_default();
+        if e.is_throttling() && *attempts + 1 < MAX_THROTTLED_ATTEMPTS {
+            *attempts += 1;
+            // Nothing went out, so the limits get their sends back.
+            release_sends(context.limiter, &account.name, &windows, &domain).await?;
+            context.throttle.hits += 1;
+            let backoff = context.throttle.backoff();
+            eprintln!(
+                "The email to {} was turned away for now by {}: {}; waiting {}s and sending {}x slower for the rest of the run",
+                business.email,
+                account.transport.name(),
+                e,
+                backoff.as_secs(),
+                context.throttle.slowdown()
+            );
+            context.shutdown.sleep(backoff).await;
+            return Ok(Outcome::Throttled);
+        }
+    }
+    if let SendMode::TestTo(address) = &context.mode {
+        context.emailed.insert(dedup_key(&business.email));
+        return Ok(match result {
+            Ok(_) => {
+                println!("Test email for {} sent to {}", business.email, address);
+                Outcome::Sent
+            }
+            Err(e) => {
+                eprintln!("Could not send the test email for {} to {}: {:?}", business.email, address, e);
+                Outcome::Failed
+            }
+        });
+    }
+    match result {
+        Ok(_) => {
+            match (message.step, message.variant) {
+                (0, Some(variant)) => println!("Email sent successfully to: {} (from {}, variant {})", business.email, account.name, variant),
+                (0, None) => println!("Email sent successfully to: {} (from {})", business.email, account.name),
+                (step, _) => println!("Follow-up {} sent successfully to: {} (from {})", step, business.email, account.name),
+            }
+            // The email is out either way, so what fails to be recorded here
+            // doesn't make it a failed send.
+            if let Err(e) = mark_contacted(context.redis, &business.email).await {
+                eprintln!("Could not mark {} as contacted in Redis: {}", business.email, e);
+            }
+            if let Err(e) = context.retries.resolve(campaign_id, message.step, &business.email).await {
+                eprintln!("Could not clear the retry for {}: {}", business.email, e);
+            }
+            let sent = SentEmail { variant: message.variant, step: message.step, message_id: &email.message_id, subject: &email.subject };
+            if let Err(e) = context.store.record_sent(campaign_id, &business.email, &sent).await {
+                eprintln!("Could not record the email to {} as sent: {}", business.email, e);
+            }
+            Ok(Outcome::Sent)
+        }
+        Err(e) => {
+            let error = e.to_string();
+            context.store.record_send(campaign_id, &business.email, SEND_FAILED, Some(&error), message.variant).await?;
+            let failed = FailedSend {
+                campaign_id,
+                email: business.email.clone(),
+                step: message.step,
+                variant: message.variant.map(str::to_string),
+                subject: message.subject.to_string(),
+                references: email.references,
+                attempts: 0,
+                error,
+                failed_at: 0,
+                retry_at: 0,
+            };
+            // The failure is in the send history either way.
+            match context.retries.record_failure(failed, e.is_permanent()).await {
+                Ok(Some(retry_at)) => eprintln!(
+                    "Could not send email to: {}: {:?}; `follow-up` tries again from {} UTC",
+                    business.email,
+                    e,
+                    retry_at.format("%Y-%m-%d %H:%M")
+                ),
+                Ok(None) => eprintln!("Could not send email to: {}: {:?}; moved to the dead letters", business.email, e),
+                Err(retry_error) => eprintln!("Could not send email to: {}: {:?}; nor queue it for a retry: {}", business.email, e, retry_error),
+            }
+            Ok(Outcome::Failed)
+        }
+    }
+}
+
+// Whether the lead may be emailed now under [schedule]. Outside the window
+// this waits for it to open, unless the window is the lead's own: then it
+// gives back when it opens, for the lead to be tried again after the others.
+// Dry runs only say when a live run would wait, and test sends, which go to
+// the tester, go out whenever.
+async fn wait_for_window(context: &SendContext<'_>, business: &Business) -> Option<DateTime<Utc>> {
+    let window = context.schedule?;
+    let timezone = window.timezone_for(business);
+    let opens = window.next_opening(Utc::now(), timezone)?;
+    let local = opens.with_timezone(&timezone).format("%a %Y-%m-%d %H:%M %Z");
+    match context.mode {
+        SendMode::TestTo(_) => None,
+        SendMode::DryRun => {
+            println!("Outside the sending window for {}; a live run would wait until {}", business.email, local);
+            None
+        }
+        SendMode::Live if window.per_recipient() => Some(opens),
+        SendMode::Live => {
+            println!("Outside the sending window ({}); waiting until {}", window.describe(), local);
+            sleep_until(&context.shutdown, opens).await;
+            None
+        }
+    }
+}
+
+async fn sleep_until(shutdown: &Shutdown, time: DateTime<Utc>) {
+    if let Ok(wait) = (time - Utc::now()).to_std() {
+        shutdown.sleep(wait).await;
+    }
+}
+
+// Gives back the leads put off in a pass, throttled ones and those outside
+// their sending window, for another pass once the first of them can go.
+async fn wait_for_deferred<T>(context: &SendContext<'_>, deferred: Vec<(DateTime<Utc>, T)>) -> Vec<T> {
+    let Some(opens) = deferred.iter().map(|(opens, _)| *opens).min() else {
+        return Vec::new();
+    };
+    if let Some(window) = context.schedule.filter(|_| opens > Utc::now()) {
+        println!(
+            "{} leads are outside their sending window ({}); waiting until {} UTC",
+            deferred.len(),
+            window.describe(),
+            opens.format("%a %Y-%m-%d %H:%M")
+        );
+        sleep_until(&context.shutdown, opens).await;
+    }
+    deferred.into_iter().map(|(_, item)| item).collect()
+}
+
+// The recipients earlier runs of the campaign reached, by dedup key, from its
+// send_events. A run after an interrupted one leaves them out and carries on
+// with the rest, however long the contact cooldown.
+#[derive(Debug, Default)]
+pub struct Resumed {
+    pub emailed: HashSet<String>,
+    // Sends that failed and weren't sent since; the retry queue has them,
+    // and `follow-up` tries them again or they're in the dead letters.
+    pub failed: HashSet<String>,
+}
+
+impl Resumed {
+    fn contains(&self, key: &str) -> bool {
+        self.emailed.contains(key) || self.failed.contains(key)
+    }
+}
+
+pub async fn resumed_recipients(store: &dyn LeadStore, campaign_id: i64) -> Result<Resumed, BotError> {
+    let mut resumed = Resumed::default();
+    for event in store.campaign_events(campaign_id).await? {
+        if CONTACTED_STATUSES.contains(&event.status.as_str()) {
+            resumed.emailed.insert(dedup_key(&event.email));
+        } else if event.status == SEND_FAILED {
+            resumed.failed.insert(dedup_key(&event.email));
+        }
+    }
+    resumed.failed.retain(|key| !resumed.emailed.contains(key));
+    Ok(resumed)
+}
+
+// The leads a campaign run left queued.
+#[derive(Debug, Default)]
+pub struct Unsent<'b> {
+    // Not reached before a limit or the shutdown stopped the run.
+    pub unreached: Vec<&'b Business>,
+    // Held back by their domain's daily cap, for a later day.
+    pub capped: Vec<&'b Business>,
+}
+
+// Every recipient is queued in send_events up front, then marked sent, failed
+// or skipped (with the reason); those not reached before a limit stay queued,
+// and are returned with those their domain's cap held back. `send_limit` caps
+// the successful sends of this run, e.g. what is left of the campaign's own
+// limits.
+pub async fn send_campaign<'b>(
+    context: &mut SendContext<'_>,
+    variants: &VariantSplit,
+    businesses: &'b [Business],
+    send_limit: Option<usize>,
+) -> Result<Unsent<'b>, BotError> {
+    let campaign_id = context.campaign.id;
+    let mut sent = 0;
+    let mut capped = Vec::new();
+    let (resumed, mut pending): (Vec<&Business>, Vec<&Business>) =
+        businesses.iter().partition(|business| context.resumed.contains(&dedup_key(&business.email)));
+    if !resumed.is_empty() {
+        let failed = resumed.iter().filter(|business| context.resumed.failed.contains(&dedup_key(&business.email))).count();
+        println!(
+            "Resuming: leaving out {} leads earlier runs of the campaign already emailed and {} whose send failed, which `follow-up` retries",
+            resumed.len() - failed,
+            failed
+        );
+    }
+    let emails: Vec<&str> = pending.iter().map(|business| business.email.as_str()).collect();
+    if context.mode.records() {
+        context.store.record_queued(campaign_id, &emails).await?;
+    }
+    let mut failures = 0;
+    while !pending.is_empty() {
+        let mut deferred = Vec::new();
+        let mut leads = pending.into_iter();
+        while let Some(business) = leads.next() {
+            if send_limit.is_some_and(|limit| sent >= limit) {
+                println!("Reached the campaign's send limit.");
+                let unreached = std::iter::once(business).chain(leads).chain(deferred.into_iter().map(|(_, lead)| lead)).collect();
+                return Ok(Unsent { unreached, capped });
+            }
+            let reason = match skip_reason(context, business).await {
+                Ok(reason) => reason,
+                Err(e) => {
+                    skip_failed(context, business, e, &mut failures).await?;
+                    continue;
+                }
+            };
+            if let Some(reason) = reason {
+                println!("Skipped ({}): {}", reason, business.email);
+                if context.mode.records() {
+                    context.store.record_send(campaign_id, &business.email, SEND_SKIPPED, Some(&reason), None).await?;
+                }
+                continue;
+            }
+            if let Some(opens) = wait_for_window(context, business).await {
+                deferred.push((opens, business));
+                continue;
+            }
+
+            // The pause comes between two emails, so the run ends with the last one.
+            if context.attempted && context.mode != SendMode::DryRun {
+                context.shutdown.sleep(send_pause(context)).await;
+            }
+            if context.shutdown.requested() {
+                println!("Shutting down; the leads not reached stay queued for the next run");
+                let unreached = std::iter::once(business).chain(leads).chain(deferred.into_iter().map(|(_, lead)| lead)).collect();
+                return Ok(Unsent { unreached, capped });
+            }
+            context.attempted = true;
+            let (variant, subject, template) = variants.pick();
+            let message = Message { step: 0, variant, subject, template, references: Vec::new() };
+            let outcome = match deliver(context, business, message).await {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    skip_failed(context, business, e, &mut failures).await?;
+                    continue;
+                }
+            };
+            failures = 0;
+            match outcome {
+                Outcome::Sent => sent += 1,
+                Outcome::Failed => {}
+                Outcome::Deferred => capped.push(business),
+                Outcome::Throttled => deferred.push((Utc::now(), business)),
+                Outcome::Stopped => {
+                    let unreached = std::iter::once(business).chain(leads).chain(deferred.into_iter().map(|(_, lead)| lead)).collect();
+                    return Ok(Unsent { unreached, capped });
+                }
+            }
+        }
+        pending = wait_for_deferred(context, deferred).await;
+    }
+
+    Ok(Unsent { unreached: Vec::new(), capped })
+}
+
+// Sends each due follow-up as a reply to the emails before it, and each due
+// retry as it was sent the first time, rendered for the stored lead with that
+// address, or for the bare address when the first email went to a leads file.
+// `templates` holds every follow-up's template by step, and `first` the
+// campaign's own. Leads that can no longer be emailed are skipped without a
+// record, so the next run checks them again.
+pub async fn send_follow_ups(
+    context: &mut SendContext<'_>,
+    due: &[DueEmail<'_>],
+    templates: &HashMap<u32, CampaignTemplate>,
+    first: &VariantSplit,
+    leads: &[Business],
+    send_limit: Option<usize>,
+) -> Result<(), BotError> {
+    let leads: HashMap<String, &Business> = leads.iter().map(|lead| (dedup_key(&lead.email), lead)).collect();
+    let mut sent = 0;
+    let mut failures = 0;
+    let mut pending: Vec<&DueEmail> = due.iter().collect();
+    'run: while !pending.is_empty() {
+        let mut deferred = Vec::new();
+        for email in pending {
+            if send_limit.is_some_and(|limit| sent >= limit) {
+                println!("Reached the campaign's send limit.");
+                break 'run;
+            }
+            let business = leads
+                .get(&dedup_key(&email.email))
+                .map(|lead| (*lead).clone())
+                .unwrap_or_else(|| Business { email: email.email.clone(), ..Business::default() });
+            let problem = match delivery_problem(context, &business).await {
+                Ok(problem) => problem,
+                Err(e) => {
+                    eprintln!("Skipped step {} ({}): {}", email.step, tolerate(&mut failures, e)?, business.email);
+                    continue;
+                }
+            };
+            if let Some(reason) = problem {
+                println!("Skipped step {} ({}): {}", email.step, reason, business.email);
+                continue;
+            }
+            let template = match email.step {
+                0 => first.template(email.variant.as_deref()),
+                step => match templates.get(&step) {
+                    Some(template) => template,
+                    None => continue,
+                },
+            };
+            if let Some(opens) = wait_for_window(context, &business).await {
+                deferred.push((opens, email));
+                continue;
+            }
+            if context.attempted {
+                context.shutdown.sleep(send_pause(context)).await;
+            }
+            if context.shutdown.requested() {
+                println!("Shutting down; the emails not sent are still due on the next run");
+                break 'run;
+            }
+            context.attempted = true;
+            match email.retry {
+                Some(RetryCause::SoftBounce) => println!("Retrying step {} after a soft bounce: {}", email.step, business.email),
+                Some(RetryCause::Failed { attempts }) => println!("Retrying step {} (attempt {}): {}", email.step, attempts + 1, business.email),
+                None => {}
+            }
+            let message = Message {
+                step: email.step,
+                variant: email.variant.as_deref(),
+                subject: &email.subject,
+                template,
+                references: email.references.clone(),
+            };
+            let outcome = match deliver(context, &business, message).await {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    eprintln!("Skipped step {} ({}): {}", email.step, tolerate(&mut failures, e)?, business.email);
+                    continue;
+                }
+            };
+            failures = 0;
+            match outcome {
+                Outcome::Sent => sent += 1,
+                Outcome::Failed | Outcome::Deferred => {}
+                Outcome::Throttled => deferred.push((Utc::now(), email)),
+                Outcome::Stopped => break 'run,
+            }
+        }
+        pending = wait_for_deferred(context, deferred).await;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{open_store, SEND_SENT};
+
+    #[test]
+    fn is_valid_email_rejects_what_cannot_receive_mail() {
+        assert!(is_valid_email("jane@plumbing.com"));
+        assert!(is_valid_email("jane.doe+quotes@mail.plumbing.co.uk"));
+        assert!(!is_valid_email("jane@plumbing"));
+        assert!(!is_valid_email("jane doe@plumbing.com"));
+        assert!(!is_valid_email("jane@plumbing..com"));
+        assert!(!is_valid_email("jane@-plumbing.com"));
+        assert!(!is_valid_email("jane@[127.0.0.1]"));
+        assert!(!is_valid_email("@plumbing.com"));
+        assert!(!is_valid_email(&format!("{}@plumbing.com", "a".repeat(65))));
+    }
+
+    #[test]
+    fn normalize_email_trims_and_lowercases() {
+        assert_eq!(normalize_email("  Jane@Plumbing.COM. "), "jane@plumbing.com");
+        assert_eq!(normalize_email("jane+quotes@plumbing.com"), "jane+quotes@plumbing.com");
+    }
+
+    #[test]
+    fn dedup_key_finds_the_mailbox_behind_an_address() {
+        assert_eq!(dedup_key("Jane+Quotes@Plumbing.com"), "jane@plumbing.com");
+        assert_eq!(dedup_key("j.a.n.e+x@googlemail.com"), "jane@gmail.com");
+        assert_eq!(dedup_key("J.Doe@Gmail.com"), "jdoe@gmail.com");
+        assert_eq!(dedup_key("j.doe@plumbing.com"), "j.doe@plumbing.com");
+        assert_eq!(dedup_key("not an address"), "not an address");
+    }
+
+    #[tokio::test]
+    async fn resumed_recipients_keeps_failed_sends_apart() {
+        let store = open_store("sqlite::memory:").await.unwrap();
+        let id = store.create_campaign(&Campaign::new("spring", "Hello")).await.unwrap();
+        store.record_send(id, "Jane+promo@Acme.com", SEND_SENT, None, None).await.unwrap();
+        store.record_send(id, "bob@acme.com", SEND_FAILED, Some("550 no such user"), None).await.unwrap();
+        store.record_send(id, "amy@acme.com", SEND_FAILED, Some("421 try later"), None).await.unwrap();
+        store.record_send(id, "amy@acme.com", SEND_SENT, None, None).await.unwrap();
+        store.record_send(id, "zed@acme.com", SEND_SKIPPED, Some("suppressed (manual)"), None).await.unwrap();
+        let resumed = resumed_recipients(store.as_ref(), id).await.unwrap();
+        assert_eq!(resumed.emailed, HashSet::from(["jane@acme.com".to_string(), "amy@acme.com".to_string()]));
+        assert_eq!(resumed.failed, HashSet::from(["bob@acme.com".to_string()]));
+        assert!(!resumed.contains("zed@acme.com"));
+    }
+}
@@ -0,0 +1,101 @@
+use std::io;
+use thiserror::Error;
+use lettre::error::Error as LettreError;
+use redis::RedisError;
+use askama::Error as AskamaError;
+
+#[derive(Error, Debug)]
+pub enum BotError {
+    #[error("Network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+
+    #[error("Data parsing error: {0}")]
+    DataParseError(#[from] serde_json::Error),
+
+    #[error("I/O error: {0}")]
+    IOError(#[from] io::Error),
+
+    #[error("Email sending error: {0}")]
+    EmailError(#[from] LettreError),
+
+    #[error("SMTP transport error: {0}")]
+    SmtpTransportError(#[from] lettre::transport::smtp::Error),
+
+    #[error("Redis operation error: {0}")]
+    RedisError(#[from] RedisError),
+
+    #[error("Redis connection pool error: {0}")]
+    RedisPoolError(#[from] deadpool::managed::PoolError<redis::RedisError>),
+    
+    #[error("Template rendering error: {0}")]
+    TemplateError(#[from] AskamaError),
+
+    #[error("CSV error: {0}")]
+    CsvError(#[from] csv::Error),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+    
+    #[error("HTTP {status} from {url}")]
+    HttpStatus { url: String, status: u16 },
+
+    #[error("{provider} rejected the email (HTTP {status}): {message}")]
+    ProviderError { provider: String, status: u16, message: String },
+
+    #[error("IMAP error: {0}")]
+    ImapError(#[from] imap::Error),
+
+    #[error("HTTP server error: {0}")]
+    ServerError(#[from] hyper::Error),
+
+    #[error("Disallowed by robots.txt: {0}")]
+    RobotsDisallowed(String),
+
+    #[error("Blocked by {url}: {reason}")]
+    Blocked { url: String, reason: String },
+
+    #[error("Headless browser error: {0}")]
+    BrowserError(String),
+
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+
+    #[error("Invalid data: {0}")]
+    InvalidData(String),
+
+    #[error("Stopped: {0}")]
+    Cancelled(String),
+
+    #[error("Pipeline stages failed: {0}")]
+    StageFailed(String),
+
+    #[error("Already running: {0}")]
+    AlreadyRunning(String),
+}
+
+impl BotError {
+    // A relay or provider turning an email away for now, usually for sending
+    // too fast (421, 450, 4.7.0 and the like, or HTTP 429), rather than for good.
+    pub fn is_throttling(&self) -> bool {
+        match self {
+            BotError::SmtpTransportError(error) => error.is_transient(),
+            BotError::ProviderError { status, .. } | BotError::HttpStatus { status, .. } => *status == 429,
+            _ => false,
+        }
+    }
+
+    // A relay or provider refusing the email itself (a 5xx like 550 for an
+    // unknown mailbox, or HTTP 400 or 422), which trying again won't change.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            BotError::SmtpTransportError(error) => error.is_permanent(),
+            BotError::ProviderError { status, .. } => matches!(status, 400 | 422),
+            _ => false,
+        }
+    }
+    // Something wrong with one lead, like an address that doesn't parse or
+    // fields its template doesn't render with, which the next lead won't share.
+    pub fn concerns_lead(&self) -> bool {
+        matches!(self, BotError::InvalidData(_) | BotError::TemplateError(_))
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashSet;
+use serde::Deserialize;
+use crate::error::BotError;
+use crate::storage;
+use crate::validation::email_domain;
+
+const BUNDLED_DISPOSABLE_DOMAINS: &str = include_str!("../data/disposable_domains.txt");
+
+// Consumer mailbox providers; fine for a local plumber, but not a business domain.
+pub const FREE_MAIL_DOMAINS: &[&str] = &[
+    "aol.com",
+    "att.net",
+    "comcast.net",
+    "gmail.com",
+    "gmx.com",
+    "googlemail.com",
+    "hotmail.com",
+    "icloud.com",
+    "live.com",
+    "mac.com",
+    "mail.com",
+    "me.com",
+    "msn.com",
+    "outlook.com",
+    "proton.me",
+    "protonmail.com",
+    "sbcglobal.net",
+    "verizon.net",
+    "yahoo.com",
+    "yandex.com",
+    "ymail.com",
+    "zoho.com",
+];
+
+// Shared mailboxes that nobody answers or that route to abuse desks; mailing
+// them buys complaints, not replies.
+pub const DEFAULT_ROLE_ACCOUNTS: &[&str] = &[
+    "abuse",
+    "admin",
+    "billing",
+    "bounce",
+    "bounces",
+    "contact",
+    "do-not-reply",
+    "donotreply",
+    "hello",
+    "hostmaster",
+    "info",
+    "legal",
+    "mailer-daemon",
+    "marketing",
+    "no-reply",
+    "noreply",
+    "office",
+    "postmaster",
+    "privacy",
+    "root",
+    "sales",
+    "security",
+    "spam",
+    "support",
+    "unsubscribe",
+    "webmaster",
+];
+
+// Role accounts that small businesses actually read, kept unless the config says otherwise.
+pub const DEFAULT_ALLOWED_ROLE_ACCOUNTS: &[&str] = &["info", "sales", "contact", "office", "hello"];
+
+// Government, military and school addresses are off limits by default.
+pub const DEFAULT_BLOCKED_DOMAINS: &[&str] = &["gov", "mil", "edu"];
+
+// The `[filter]` table of config.toml.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    pub skip_role_accounts: bool,
+    pub role_accounts: Vec<String>,
+    pub allowed_role_accounts: Vec<String>,
+    // Domains or suffixes never to email: "gov" covers every .gov address,
+    // "example.com" covers example.com and its subdomains.
+    pub blocked_domains: Vec<String>,
+    // When non-empty, only these domains (same matching rules) are emailed.
+    pub allowed_domains: Vec<String>,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        FilterConfig {
+            skip_role_accounts: true,
+            role_accounts: DEFAULT_ROLE_ACCOUNTS.iter().map(|role| role.to_string()).collect(),
+            allowed_role_accounts: DEFAULT_ALLOWED_ROLE_ACCOUNTS.iter().map(|role| role.to_string()).collect(),
+            blocked_domains: DEFAULT_BLOCKED_DOMAINS.iter().map(|domain| domain.to_string()).collect(),
+            allowed_domains: Vec::new(),
+        }
+    }
+}
+
+// "No-Reply+billing" and "noreply" are the same role.
+fn local_part_key(email: &str) -> Option<String> {
+    let (local, _) = email.rsplit_once('@')?;
+    let local = local.split('+').next().unwrap_or(local);
+    Some(local.trim().to_lowercase())
+}
+
+fn normalize_suffix(entry: &str) -> String {
+    entry.trim().trim_start_matches("*.").trim_start_matches('@').trim_matches('.').to_lowercase()
+}
+
+// True when `domain` equals one of `suffixes` or ends with "." followed by one.
+fn matches_suffix(suffixes: &[String], domain: &str) -> bool {
+    suffixes.iter().any(|suffix| {
+        domain == suffix || domain.strip_suffix(suffix.as_str()).is_some_and(|rest| rest.ends_with('.'))
+    })
+}
+
+// True when `domain` is one of `domains` or a subdomain of one.
+fn matches_domain(domains: &HashSet<String>, domain: &str) -> bool {
+    let mut candidate = domain;
+    loop {
+        if domains.contains(candidate) {
+            return true;
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => candidate = parent,
+            _ => return false,
+        }
+    }
+}
+
+// Decides which addresses are worth keeping, at scrape time and again in `validate`.
+pub struct EmailFilter {
+    disposable: HashSet<String>,
+    free: HashSet<String>,
+    pub business_domains_only: bool,
+    // Role local parts to skip, with the allowlist already taken out.
+    role_accounts: HashSet<String>,
+    blocked_domains: Vec<String>,
+    allowed_domains: Vec<String>,
+}
+
+impl Default for EmailFilter {
+    fn default() -> Self {
+        EmailFilter::new(&FilterConfig::default(), Vec::new(), false)
+    }
+}
+
+impl EmailFilter {
+    pub fn new(config: &FilterConfig, extra_disposable: Vec<String>, business_domains_only: bool) -> Self {
+        let disposable = BUNDLED_DISPOSABLE_DOMAINS
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .chain(extra_disposable.into_iter().map(|domain| domain.to_lowercase()))
+            .collect();
+        let role_accounts = if config.skip_role_accounts {
+            let allowed: HashSet<String> = config.allowed_role_accounts.iter().map(|role| role.to_lowercase()).collect();
+            config
+                .role_accounts
+                .iter()
+                .map(|role| role.to_lowercase())
+                .filter(|role| !allowed.contains(role))
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        EmailFilter {
+            disposable,
+            free: FREE_MAIL_DOMAINS.iter().map(|domain| domain.to_string()).collect(),
+            business_domains_only,
+            role_accounts,
+            blocked_domains: config.blocked_domains.iter().map(|entry| normalize_suffix(entry)).collect(),
+            allowed_domains: config.allowed_domains.iter().map(|entry| normalize_suffix(entry)).collect(),
+        }
+    }
+
+    // Adds the domains listed in `disposable_domains_file` (one per line) to the bundled disposable list.
+    pub fn load(
+        config: &FilterConfig,
+        disposable_domains_file: Option<&str>,
+        business_domains_only: bool,
+    ) -> Result<Self, BotError> {
+        let extra = match disposable_domains_file {
+            Some(path) => storage::load_line_list(path)?,
+            None => Vec::new(),
+        };
+        Ok(EmailFilter::new(config, extra, business_domains_only))
+    }
+
+    pub fn is_role_account(&self, email: &str) -> bool {
+        local_part_key(email).is_some_and(|local| self.role_accounts.contains(&local))
+    }
+
+    pub fn is_disposable(&self, domain: &str) -> bool {
+        matches_domain(&self.disposable, domain)
+    }
+
+    pub fn is_free_mail(&self, domain: &str) -> bool {
+        matches_domain(&self.free, domain)
+    }
+
+    // The config-driven block/allow lists only; also enforced right before sending.
+    pub fn domain_rejection(&self, email: &str) -> Option<&'static str> {
+        let domain = email_domain(email)?;
+        if matches_suffix(&self.blocked_domains, &domain) {
+            return Some("blocked domain");
+        }
+        if !self.allowed_domains.is_empty() && !matches_suffix(&self.allowed_domains, &domain) {
+            return Some("domain not in allowed_domains");
+        }
+        None
+    }
+
+    // Why the address should be dropped, or None to keep it.
+    pub fn rejection(&self, email: &str) -> Option<&'static str> {
+        if self.is_role_account(email) {
+            return Some("role account");
+        }
+        if let Some(reason) = self.domain_rejection(email) {
+            return Some(reason);
+        }
+        let domain = email_domain(email)?;
+        if self.is_disposable(&domain) {
+            return Some("disposable domain");
+        }
+        if self.business_domains_only && self.is_free_mail(&domain) {
+            return Some("free mail provider");
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_role_accounts_but_the_allowed_ones() {
+        let filter = EmailFilter::default();
+        assert_eq!(filter.rejection("No-Reply+billing@plumbing.com"), Some("role account"));
+        assert_eq!(filter.rejection("webmaster@plumbing.com"), Some("role account"));
+        assert_eq!(filter.rejection("info@plumbing.com"), None);
+        assert_eq!(filter.rejection("jane@plumbing.com"), None);
+
+        let config = FilterConfig { skip_role_accounts: false, ..FilterConfig::default() };
+        assert_eq!(EmailFilter::new(&config, Vec::new(), false).rejection("noreply@plumbing.com"), None);
+    }
+
+    #[test]
+    fn matches_disposable_and_free_mail_domains_with_their_subdomains() {
+        let filter = EmailFilter::new(&FilterConfig::default(), vec!["Throwaway.example".to_string()], false);
+        assert!(filter.is_disposable("guerrillamail.com"));
+        assert!(filter.is_disposable("mx.guerrillamail.com"));
+        assert!(filter.is_disposable("throwaway.example"));
+        assert!(!filter.is_disposable("notguerrillamail.com"));
+        assert_eq!(filter.rejection("jane@guerrillamail.com"), Some("disposable domain"));
+        assert_eq!(filter.rejection("jane@gmail.com"), None);
+
+        let business_only = EmailFilter::new(&FilterConfig::default(), Vec::new(), true);
+        assert_eq!(business_only.rejection("jane@gmail.com"), Some("free mail provider"));
+        assert_eq!(business_only.rejection("jane@plumbing.com"), None);
+    }
+
+    #[test]
+    fn blocks_and_allows_domains_by_suffix() {
+        let filter = EmailFilter::default();
+        assert_eq!(filter.domain_rejection("clerk@city.gov"), Some("blocked domain"));
+        assert_eq!(filter.domain_rejection("dean@college.edu."), Some("blocked domain"));
+        assert_eq!(filter.domain_rejection("jane@govtech.com"), None);
+
+        let config = FilterConfig {
+            blocked_domains: vec!["*.Example.com".to_string()],
+            allowed_domains: vec!["@plumbing.com".to_string(), "example.com".to_string()],
+            ..FilterConfig::default()
+        };
+        let filter = EmailFilter::new(&config, Vec::new(), false);
+        assert_eq!(filter.domain_rejection("jane@mail.example.com"), Some("blocked domain"));
+        assert_eq!(filter.domain_rejection("jane@shop.plumbing.com"), None);
+        assert_eq!(filter.domain_rejection("jane@roofing.com"), Some("domain not in allowed_domains"));
+    }
+}
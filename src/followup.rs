@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use crate::campaign::FollowUp;
+use crate::email::dedup_key;
+use crate::retry::FailedSend;
+use crate::storage::{SendEvent, SEND_BOUNCED, SEND_REPLIED, SEND_SENT, SEND_SOFT_BOUNCED, SEND_UNSUBSCRIBED};
+
+// How long after a soft bounce the email is tried again, giving a full
+// mailbox or an overloaded server time to recover.
+pub const SOFT_BOUNCE_RETRY_HOURS: i64 = 24;
+
+// Any of these ends a recipient's sequence.
+const STOP_STATUSES: &[&str] = &[SEND_REPLIED, SEND_UNSUBSCRIBED, SEND_BOUNCED];
+
+// One recipient's conversation with a campaign so far.
+struct Thread<'a> {
+    email: &'a str,
+    // The emails sent, with when.
+    sent: Vec<(&'a SendEvent, DateTime<Utc>)>,
+    // When the last email soft-bounced, if it did.
+    soft_bounced: Option<DateTime<Utc>>,
+    stopped: bool,
+}
+
+// Why an email is sent again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryCause {
+    SoftBounce,
+    // A send from the retry queue, with the attempts that failed so far.
+    Failed { attempts: u32 },
+}
+
+// An email a recipient is due for: the next step of the sequence, or one
+// again after a soft bounce or a failed send.
+#[derive(Debug)]
+pub struct DueEmail<'a> {
+    pub email: String,
+    pub step: u32,
+    // None for the campaign's own email.
+    pub follow_up: Option<&'a FollowUp>,
+    pub retry: Option<RetryCause>,
+    // The A/B variant of a retried first email.
+    pub variant: Option<String>,
+    pub subject: String,
+    // Message-IDs of the emails it follows up on.
+    pub references: Vec<String>,
+}
+
+// SQLite stores RFC 3339 timestamps, Postgres returns "2024-05-01 09:30:00.123+00".
+pub(crate) fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .or_else(|_| DateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f%#z"))
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+impl<'a> Thread<'a> {
+    fn due<'f>(&self, follow_ups: &'f [FollowUp], now: DateTime<Utc>) -> Option<DueEmail<'f>> {
+        let (first, _) = self.sent.first()?;
+        let &(last, sent_at) = self.sent.last()?;
+        if self.stopped || first.step != 0 {
+            return None;
+        }
+        let ids = |events: &[(&SendEvent, DateTime<Utc>)]| events.iter().filter_map(|(event, _)| event.message_id.clone()).collect();
+        // Each email is retried once; a second soft bounce ends the sequence.
+        if let Some(bounced_at) = self.soft_bounced {
+            let attempts = self.sent.iter().filter(|(event, _)| event.step == last.step).count();
+            if attempts > 1 || now - bounced_at < Duration::hours(SOFT_BOUNCE_RETRY_HOURS) {
+                return None;
+            }
+            let follow_up = follow_ups.iter().find(|follow_up| follow_up.step == last.step);
+            if last.step > 0 && follow_up.is_none() {
+                return None;
+            }
+            return Some(DueEmail {
+                email: self.email.to_string(),
+                step: last.step,
+                follow_up,
+                retry: Some(RetryCause::SoftBounce),
+                variant: last.variant.clone(),
+                subject: last.subject.clone()?,
+                references: ids(&self.sent[..self.sent.len() - 1]),
+            });
+        }
+        let follow_up = follow_ups.iter().find(|follow_up| follow_up.step == last.step + 1)?;
+        if now - sent_at < Duration::days(follow_up.delay_days.into()) {
+            return None;
+        }
+        Some(DueEmail {
+            email: self.email.to_string(),
+            step: follow_up.step,
+            follow_up: Some(follow_up),
+            retry: None,
+            variant: None,
+            subject: follow_up.reply_subject(first.subject.as_deref()?),
+            references: ids(&self.sent),
+        })
+    }
+}
+
+// The recipients of a campaign who are due for an email at `now`, from the
+// campaign's events: the step after the last one they were sent, once its
+// delay has passed since that email, or the last one again a day after it
+// soft-bounced. Recipients who replied, unsubscribed or bounced get nothing
+// more, and neither do those whose first email was sent before Message-IDs
+// were recorded, since a follow-up couldn't thread onto it.
+pub fn due_emails<'f>(events: &[SendEvent], follow_ups: &'f [FollowUp], now: DateTime<Utc>) -> Vec<DueEmail<'f>> {
+    let mut order = Vec::new();
+    let mut threads: HashMap<String, Thread> = HashMap::new();
+    for event in events {
+        let key = dedup_key(&event.email);
+        let thread = threads.entry(key.clone()).or_insert_with(|| {
+            order.push(key);
+            Thread { email: &event.email, sent: Vec::new(), soft_bounced: None, stopped: false }
+        });
+        let Some(time) = parse_timestamp(&event.created_at) else {
+            continue;
+        };
+        match event.status.as_str() {
+            status if STOP_STATUSES.contains(&status) => thread.stopped = true,
+            SEND_SENT if event.message_id.is_some() => {
+                thread.sent.push((event, time));
+                thread.soft_bounced = None;
+            }
+            SEND_SOFT_BOUNCED if !thread.sent.is_empty() => thread.soft_bounced = Some(time),
+            _ => {}
+        }
+    }
+    order.iter().filter_map(|key| threads[key].due(follow_ups, now)).collect()
+}
+
+// `due` with the retry queue's sends of the campaign that are due at `now`,
+// and without the steps waiting in the queue or the dead letters, which the
+// events alone would make due again. Recipients who replied, unsubscribed or
+// bounced since aren't retried.
+pub fn with_failed_sends<'f>(
+    mut due: Vec<DueEmail<'f>>,
+    events: &[SendEvent],
+    pending: &[FailedSend],
+    dead_letters: &[FailedSend],
+    follow_ups: &'f [FollowUp],
+    now: DateTime<Utc>,
+) -> Vec<DueEmail<'f>> {
+    let held = |email: &str, step: u32| {
+        pending.iter().chain(dead_letters).any(|failed| failed.step == step && dedup_key(&failed.email) == dedup_key(email))
+    };
+    due.retain(|email| !held(&email.email, email.step));
+    let stopped: Vec<String> =
+        events.iter().filter(|event| STOP_STATUSES.contains(&event.status.as_str())).map(|event| dedup_key(&event.email)).collect();
+    for failed in pending.iter().filter(|failed| failed.retry_at() <= now && !stopped.contains(&dedup_key(&failed.email))) {
+        let follow_up = follow_ups.iter().find(|follow_up| follow_up.step == failed.step);
+        if failed.step > 0 && follow_up.is_none() {
+            continue;
+        }
+        due.push(DueEmail {
+            email: failed.email.clone(),
+            step: failed.step,
+            follow_up,
+            retry: Some(RetryCause::Failed { attempts: failed.attempts }),
+            variant: failed.variant.clone(),
+            subject: failed.subject.clone(),
+            references: failed.references.clone(),
+        });
+    }
+    due
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use crate::storage::SEND_QUEUED;
+
+    fn follow_up(step: u32, delay_days: u32) -> FollowUp {
+        FollowUp { step, delay_days, subject: None, template: format!("follow_up_{}.html", step) }
+    }
+
+    fn event(email: &str, status: &str, created_at: &str, step: u32, message_id: Option<&str>) -> SendEvent {
+        SendEvent {
+            campaign_id: 1,
+            campaign_name: "spring".to_string(),
+            email: email.to_string(),
+            status: status.to_string(),
+            error: None,
+            created_at: created_at.to_string(),
+            variant: None,
+            step,
+            message_id: message_id.map(str::to_string),
+            subject: message_id.map(|_| "Quick question".to_string()),
+        }
+    }
+
+    #[test]
+    fn due_emails_sends_the_next_step_once_its_delay_has_passed() {
+        let follow_ups = [follow_up(1, 3), follow_up(2, 7)];
+        let events = [
+            event("jane@plumbing.com", SEND_QUEUED, "2024-05-01T09:00:00Z", 0, None),
+            event("jane@plumbing.com", SEND_SENT, "2024-05-01T09:30:00Z", 0, Some("<a@studio.dev>")),
+            event("bob@roofing.com", SEND_SENT, "2024-05-03T09:30:00Z", 0, Some("<b@studio.dev>")),
+        ];
+        let now = Utc.with_ymd_and_hms(2024, 5, 4, 12, 0, 0).unwrap();
+        let due = due_emails(&events, &follow_ups, now);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].email, "jane@plumbing.com");
+        assert_eq!(due[0].step, 1);
+        assert_eq!(due[0].subject, "Re: Quick question");
+        assert_eq!(due[0].references, vec!["<a@studio.dev>"]);
+        assert!(due[0].retry.is_none());
+    }
+
+    #[test]
+    fn due_emails_threads_later_steps_onto_every_email_before_them() {
+        let follow_ups = [follow_up(1, 3), follow_up(2, 7)];
+        let events = [
+            event("jane@plumbing.com", SEND_SENT, "2024-05-01T09:30:00Z", 0, Some("<a@studio.dev>")),
+            event("Jane@Plumbing.com", SEND_SENT, "2024-05-04 09:30:00.000+00", 1, Some("<b@studio.dev>")),
+        ];
+        assert!(due_emails(&events, &follow_ups, Utc.with_ymd_and_hms(2024, 5, 10, 9, 0, 0).unwrap()).is_empty());
+        let due = due_emails(&events, &follow_ups, Utc.with_ymd_and_hms(2024, 5, 11, 10, 0, 0).unwrap());
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].step, 2);
+        assert_eq!(due[0].references, vec!["<a@studio.dev>", "<b@studio.dev>"]);
+    }
+
+    #[test]
+    fn due_emails_stops_at_a_reply_the_last_step_or_an_email_without_message_id() {
+        let follow_ups = [follow_up(1, 3)];
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let replied = [
+            event("jane@plumbing.com", SEND_SENT, "2024-05-01T09:30:00Z", 0, Some("<a@studio.dev>")),
+            event("jane+x@plumbing.com", SEND_REPLIED, "2024-05-02T10:00:00Z", 0, None),
+        ];
+        assert!(due_emails(&replied, &follow_ups, now).is_empty());
+        let finished = [
+            event("jane@plumbing.com", SEND_SENT, "2024-05-01T09:30:00Z", 0, Some("<a@studio.dev>")),
+            event("jane@plumbing.com", SEND_SENT, "2024-05-04T09:30:00Z", 1, Some("<b@studio.dev>")),
+        ];
+        assert!(due_emails(&finished, &follow_ups, now).is_empty());
+        let untracked = [event("jane@plumbing.com", SEND_SENT, "2024-05-01T09:30:00Z", 0, None)];
+        assert!(due_emails(&untracked, &follow_ups, now).is_empty());
+    }
+
+    #[test]
+    fn due_emails_retries_a_soft_bounce_once_a_day_later() {
+        let follow_ups = [follow_up(1, 3)];
+        let events = [
+            event("jane@plumbing.com", SEND_SENT, "2024-05-01T09:30:00Z", 0, Some("<a@studio.dev>")),
+            event("jane@plumbing.com", SEND_SOFT_BOUNCED, "2024-05-01T10:00:00Z", 0, None),
+        ];
+        assert!(due_emails(&events, &follow_ups, Utc.with_ymd_and_hms(2024, 5, 2, 9, 0, 0).unwrap()).is_empty());
+        let due = due_emails(&events, &follow_ups, Utc.with_ymd_and_hms(2024, 5, 2, 10, 0, 0).unwrap());
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].step, 0);
+        assert_eq!(due[0].retry, Some(RetryCause::SoftBounce));
+        assert_eq!(due[0].subject, "Quick question");
+        assert!(due[0].references.is_empty());
+
+        let bounced_twice = [
+            event("jane@plumbing.com", SEND_SENT, "2024-05-01T09:30:00Z", 0, Some("<a@studio.dev>")),
+            event("jane@plumbing.com", SEND_SOFT_BOUNCED, "2024-05-01T10:00:00Z", 0, None),
+            event("jane@plumbing.com", SEND_SENT, "2024-05-02T10:00:00Z", 0, Some("<b@studio.dev>")),
+            event("jane@plumbing.com", SEND_SOFT_BOUNCED, "2024-05-02T10:30:00Z", 0, None),
+        ];
+        assert!(due_emails(&bounced_twice, &follow_ups, Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap()).is_empty());
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use reqwest::{StatusCode, Url};
+use rand::Rng;
+use reqwest::header::{ACCEPT, ACCEPT_LANGUAGE, RETRY_AFTER, USER_AGENT};
+use crate::error::BotError;
+
+pub const DEFAULT_ROTATE_EVERY: usize = 1;
+pub const DEFAULT_HOST_DELAY_MS: u64 = 1000;
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+pub const DEFAULT_RETRY_BASE_DELAY_MS: u64 = 500;
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+const MAX_HOST_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(900);
+pub const DEFAULT_ACCEPT_LANGUAGE: &str = "en-US,en;q=0.9";
+const ACCEPT_HTML: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8";
+const DEFAULT_USER_AGENTS: &[&str] = &[
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Safari/605.1.15",
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:125.0) Gecko/20100101 Firefox/125.0",
+    "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36 Edg/124.0.0.0",
+];
+const QUARANTINE_DURATION: Duration = Duration::from_secs(600);
+const BLOCK_STATUSES: &[StatusCode] = &[
+    StatusCode::FORBIDDEN,
+    StatusCode::PROXY_AUTHENTICATION_REQUIRED,
+    StatusCode::TOO_MANY_REQUESTS,
+];
+
+struct ProxyEntry {
+    url: String,
+    client: reqwest::Client,
+}
+
+struct PoolState {
+    current: usize,
+    uses: usize,
+    quarantined_until: Vec<Option<Instant>>,
+}
+
+pub struct ProxyPool {
+    entries: Vec<ProxyEntry>,
+    rotate_every: usize,
+    state: Mutex<PoolState>,
+}
+
+impl ProxyPool {
+    pub fn new(proxy_urls: &[String], rotate_every: usize, timeouts: &Timeouts) -> Result<Self, BotError> {
+        let entries = proxy_urls
+            .iter()
+            .map(|url| {
+                let proxy = reqwest::Proxy::all(url).map_err(BotError::NetworkError)?;
+                let client = timeouts
+                    .client_builder()
+                    .proxy(proxy)
+                    .build()
+                    .map_err(BotError::NetworkError)?;
+                Ok(ProxyEntry { url: url.clone(), client })
+            })
+            .collect::<Result<Vec<_>, BotError>>()?;
+
+        if entries.is_empty() {
+            return Err(BotError::InvalidData("proxy list is empty".to_string()));
+        }
+
+        Ok(ProxyPool {
+            state: Mutex::new(PoolState {
+                current: 0,
+                uses: 0,
+                quarantined_until: vec![None; entries.len()],
+            }),
+            entries,
+            rotate_every: rotate_every.max(1),
+        })
+    }
+
+    fn next(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        if state.uses >= self.rotate_every {
+            state.uses = 0;
+            state.current = (state.current + 1) % self.entries.len();
+        }
+
+        let available = (0..self.entries.len())
+            .map(|offset| (state.current + offset) % self.entries.len())
+            .find(|&i| state.quarantined_until[i].is_none_or(|until| until <= now));
+
+        let index = match available {
+            Some(index) => index,
+            // Everything is quarantined: fall back to the proxy that has been resting longest.
+            None => (0..self.entries.len())
+                .min_by_key(|&i| state.quarantined_until[i])
+                .unwrap_or(0),
+        };
+
+        if index != state.current {
+            state.current = index;
+            state.uses = 0;
+        }
+        state.quarantined_until[index] = None;
+        state.uses += 1;
+        index
+    }
+
+    fn quarantine(&self, index: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.quarantined_until[index] = Some(Instant::now() + QUARANTINE_DURATION);
+        state.uses = self.rotate_every;
+        eprintln!("Quarantining proxy {}", self.entries[index].url);
+    }
+
+    // Quarantines whichever proxy is currently in use so the next request goes elsewhere.
+    pub fn rotate(&self) {
+        let current = self.state.lock().unwrap().current;
+        self.quarantine(current);
+    }
+}
+
+pub struct UserAgentPool {
+    user_agents: Vec<String>,
+    accept_language: String,
+    next: AtomicUsize,
+}
+
+impl Default for UserAgentPool {
+    fn default() -> Self {
+        UserAgentPool::new(Vec::new(), DEFAULT_ACCEPT_LANGUAGE)
+    }
+}
+
+impl UserAgentPool {
+    // An empty list falls back to the built-in browser User-Agents.
+    pub fn new(user_agents: Vec<String>, accept_language: &str) -> Self {
+        let user_agents = if user_agents.is_empty() {
+            DEFAULT_USER_AGENTS.iter().map(|ua| ua.to_string()).collect()
+        } else {
+            user_agents
+        };
+        UserAgentPool {
+            user_agents,
+            accept_language: accept_language.to_string(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    fn next_user_agent(&self) -> &str {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.user_agents.len();
+        &self.user_agents[index]
+    }
+
+    fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        request
+            .header(USER_AGENT, self.next_user_agent())
+            .header(ACCEPT, ACCEPT_HTML)
+            .header(ACCEPT_LANGUAGE, &self.accept_language)
+    }
+}
+
+// reqwest waits forever by default, so without these one stalled host would
+// hold up its worker for good instead of timing out and being retried.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    pub connect: Duration,
+    // For the whole request, from connecting to reading the last of the body.
+    pub request: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            connect: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS),
+            request: Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+        }
+    }
+}
+
+impl Timeouts {
+    fn client_builder(&self) -> reqwest::ClientBuilder {
+        reqwest::Client::builder().connect_timeout(self.connect).timeout(self.request)
+    }
+}
+
+// A client with the default timeouts, for the API clients that call one
+// service rather than scrape many hosts.
+pub fn client() -> Result<reqwest::Client, BotError> {
+    Timeouts::default().client_builder().build().map_err(BotError::NetworkError)
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_delay: Duration::from_millis(DEFAULT_RETRY_BASE_DELAY_MS),
+            max_delay: MAX_RETRY_DELAY,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Exponential backoff with "full jitter": a random delay between 0 and base * 2^(attempt - 1).
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let capped = exponential.min(self.max_delay);
+        let jitter_ms = rand::thread_rng().gen_range(0..=capped.as_millis() as u64);
+        Duration::from_millis(jitter_ms)
+    }
+
+    pub fn is_retryable(error: &BotError) -> bool {
+        match error {
+            BotError::NetworkError(e) => e.is_connect() || e.is_timeout() || e.is_request() || e.is_body(),
+            BotError::HttpStatus { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+struct HostState {
+    next_slot: Instant,
+    delay: Duration,
+}
+
+// Spaces out requests to the same host and backs off when the host pushes back
+// with 429/503 (honoring Retry-After), then eases back to the base delay as
+// requests succeed again.
+pub struct HostThrottle {
+    base_delay: Duration,
+    hosts: Mutex<HashMap<String, HostState>>,
+}
+
+fn host_of(url: &str) -> Option<String> {
+    Url::parse(url).ok().and_then(|url| url.host_str().map(String::from))
+}
+
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
+
+impl HostThrottle {
+    pub fn new(base_delay: Duration) -> Self {
+        HostThrottle {
+            base_delay,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn wait(&self, url: &str) {
+        let Some(host) = host_of(url) else {
+            return;
+        };
+
+        let slot = {
+            let mut hosts = self.hosts.lock().unwrap();
+            let now = Instant::now();
+            let state = hosts.entry(host).or_insert(HostState {
+                next_slot: now,
+                delay: self.base_delay,
+            });
+            let slot = state.next_slot.max(now);
+            state.next_slot = slot + state.delay;
+            slot
+        };
+
+        tokio::time::sleep_until(slot.into()).await;
+    }
+
+    pub fn observe(&self, url: &str, response: &reqwest::Response) {
+        let Some(host) = host_of(url) else {
+            return;
+        };
+
+        let mut hosts = self.hosts.lock().unwrap();
+        let Some(state) = hosts.get_mut(&host) else {
+            return;
+        };
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            state.delay = (state.delay * 2).max(self.base_delay).min(MAX_HOST_DELAY);
+            let pause = retry_after(response).unwrap_or(state.delay).min(MAX_RETRY_AFTER);
+            state.next_slot = state.next_slot.max(Instant::now() + pause);
+            eprintln!(
+                "{} answered {}, pausing {}s and slowing to one request every {}ms",
+                host, status.as_u16(), pause.as_secs(), state.delay.as_millis()
+            );
+        } else if status.is_success() && state.delay > self.base_delay {
+            state.delay = state.delay.mul_f64(0.9).max(self.base_delay);
+        }
+    }
+}
+
+pub struct HttpClient {
+    direct: reqwest::Client,
+    proxies: Option<ProxyPool>,
+    user_agents: UserAgentPool,
+    retry: RetryPolicy,
+    throttle: HostThrottle,
+}
+
+impl HttpClient {
+    pub fn new(proxies: Option<ProxyPool>, user_agents: UserAgentPool, retry: RetryPolicy, throttle: HostThrottle, timeouts: &Timeouts) -> Result<Self, BotError> {
+        Ok(HttpClient {
+            direct: timeouts.client_builder().build().map_err(BotError::NetworkError)?,
+            proxies,
+            user_agents,
+            retry,
+            throttle,
+        })
+    }
+
+    // Returns false when there is no proxy pool to rotate.
+    pub fn rotate_proxy(&self) -> bool {
+        match &self.proxies {
+            Some(pool) => {
+                pool.rotate();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub async fn send(&self, url: &str) -> Result<reqwest::Response, BotError> {
+        self.throttle.wait(url).await;
+        let result = self.send_request(url).await;
+        if let Ok(response) = &result {
+            self.throttle.observe(url, response);
+        }
+        result
+    }
+
+    async fn send_request(&self, url: &str) -> Result<reqwest::Response, BotError> {
+        let Some(pool) = &self.proxies else {
+            let request = self.user_agents.apply(self.direct.get(url));
+            return request.send().await.map_err(BotError::NetworkError);
+        };
+
+        let index = pool.next();
+        let request = self.user_agents.apply(pool.entries[index].client.get(url));
+        match request.send().await {
+            Ok(response) => {
+                if BLOCK_STATUSES.contains(&response.status()) {
+                    pool.quarantine(index);
+                }
+                Ok(response)
+            }
+            Err(e) => {
+                pool.quarantine(index);
+                Err(BotError::NetworkError(e))
+            }
+        }
+    }
+
+    pub async fn get(&self, url: &str) -> Result<String, BotError> {
+        let mut attempt = 1;
+        loop {
+            match self.get_once(url).await {
+                Err(e) if attempt < self.retry.max_attempts && RetryPolicy::is_retryable(&e) => {
+                    let delay = self.retry.delay_for(attempt);
+                    eprintln!(
+                        "Request failed ({}), retrying in {}ms (Attempt: {}/{})",
+                        e, delay.as_millis(), attempt + 1, self.retry.max_attempts
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn get_once(&self, url: &str) -> Result<String, BotError> {
+        let response = self.send(url).await?;
+        let status = response.status();
+        if BLOCK_STATUSES.contains(&status) || status.is_server_error() {
+            return Err(BotError::HttpStatus { url: url.to_string(), status: status.as_u16() });
+        }
+        response.text().await.map_err(BotError::NetworkError)
+    }
+}
@@ -0,0 +1,381 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use chrono::{DateTime, NaiveDate, Utc};
+use imap::Session;
+use mailparse::{MailHeader, MailHeaderMap};
+use regex::Regex;
+use crate::config::ImapConfig;
+use crate::email::normalize_email;
+use crate::error::BotError;
+use crate::followup::parse_timestamp;
+use crate::storage::{has_event, last_campaign_sent, LeadStore, SEND_BOUNCED, SEND_REPLIED, SEND_SENT, SEND_SOFT_BOUNCED};
+
+pub const DEFAULT_INBOX_DAYS: u32 = 14;
+pub const DEFAULT_INBOX_INTERVAL_SECS: u64 = 300;
+
+// What the headers of a message in the inbox tell about it.
+#[derive(Debug, Clone)]
+pub struct InboxMessage {
+    // The sender's address.
+    pub from: Option<String>,
+    pub subject: String,
+    // Message-IDs from In-Reply-To and References, "<...>" like they're stored.
+    pub replying_to: Vec<String>,
+    // Out-of-office notices and other automatic responses.
+    pub automatic: bool,
+    // Bounces and other delivery status notifications.
+    pub delivery_report: bool,
+    pub date: Option<DateTime<Utc>>,
+}
+
+fn message_ids(headers: &[MailHeader], name: &str) -> Vec<String> {
+    headers
+        .get_all_values(name)
+        .iter()
+        .filter_map(|value| mailparse::msgidparse(value).ok())
+        .flat_map(|ids| ids.iter().map(|id| format!("<{}>", id)).collect::<Vec<_>>())
+        .collect()
+}
+
+pub fn parse_headers(raw: &[u8]) -> Result<InboxMessage, BotError> {
+    let (headers, _) = mailparse::parse_headers(raw).map_err(|e| BotError::InvalidData(format!("unreadable message headers: {}", e)))?;
+    let from = headers
+        .get_first_value("From")
+        .and_then(|from| mailparse::addrparse(&from).ok())
+        .and_then(|addresses| addresses.extract_single_info())
+        .map(|sender| normalize_email(&sender.addr));
+    let header = |name: &str| headers.get_first_value(name).map(|value| value.trim().to_lowercase());
+    // RFC 3834, plus the headers Exchange and older autoresponders send instead.
+    let automatic = header("Auto-Submitted").is_some_and(|value| value != "no")
+        || header("X-Autoreply").is_some()
+        || header("X-Autorespond").is_some()
+        || header("Precedence").is_some_and(|value| ["auto_reply", "bulk", "junk"].contains(&value.as_str()));
+    let delivery_report = header("Content-Type").is_some_and(|value| value.starts_with("multipart/report"))
+        || from.as_deref().is_some_and(|from| from.starts_with("mailer-daemon@") || from.starts_with("postmaster@"));
+    let mut replying_to = message_ids(&headers, "References");
+    for id in message_ids(&headers, "In-Reply-To") {
+        if !replying_to.contains(&id) {
+            replying_to.push(id);
+        }
+    }
+    let date = headers
+        .get_first_value("Date")
+        .and_then(|date| mailparse::dateparse(&date).ok())
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0));
+    Ok(InboxMessage {
+        from,
+        subject: headers.get_first_value("Subject").unwrap_or_default(),
+        replying_to,
+        automatic,
+        delivery_report,
+        date,
+    })
+}
+
+// A message in the inbox: its headers, and for delivery reports the whole
+// message too, whose body says which recipient bounced and why.
+pub struct FetchedMessage {
+    pub headers: Vec<u8>,
+    pub full: Option<Vec<u8>>,
+}
+
+fn fetch_from<T: Read + Write>(mut session: Session<T>, mailbox: &str, since: NaiveDate) -> Result<Vec<FetchedMessage>, BotError> {
+    // Read-only, and BODY.PEEK leaves the messages unread.
+    session.examine(mailbox)?;
+    let mut uids: Vec<u32> = session.uid_search(format!("SINCE {}", since.format("%d-%b-%Y")))?.into_iter().collect();
+    uids.sort_unstable();
+    let mut messages = Vec::new();
+    if uids.is_empty() {
+        session.logout()?;
+        return Ok(messages);
+    }
+    let set = uids.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+    let mut reports = Vec::new();
+    for fetch in session.uid_fetch(set, "BODY.PEEK[HEADER]")?.iter() {
+        let Some(headers) = fetch.header() else {
+            continue;
+        };
+        if let (Some(uid), Ok(message)) = (fetch.uid, parse_headers(headers)) {
+            if message.delivery_report {
+                reports.push((uid, messages.len()));
+            }
+        }
+        messages.push(FetchedMessage { headers: headers.to_vec(), full: None });
+    }
+    if !reports.is_empty() {
+        let set = reports.iter().map(|(uid, _)| uid.to_string()).collect::<Vec<_>>().join(",");
+        for fetch in session.uid_fetch(set, "BODY.PEEK[]")?.iter() {
+            let index = reports.iter().find(|(uid, _)| Some(*uid) == fetch.uid).map(|(_, index)| *index);
+            if let (Some(index), Some(body)) = (index, fetch.body()) {
+                messages[index].full = Some(body.to_vec());
+            }
+        }
+    }
+    session.logout()?;
+    Ok(messages)
+}
+
+// Every message in the mailbox received since `since`.
+pub fn fetch_messages(config: &ImapConfig, username: &str, password: &str, since: NaiveDate) -> Result<Vec<FetchedMessage>, BotError> {
+    let host = config
+        .host
+        .as_deref()
+        .ok_or_else(|| BotError::ConfigError("imap.host is not set; set IMAP_HOST or [imap] host in config.toml".to_string()))?;
+    if config.tls {
+        let tls = native_tls::TlsConnector::new().map_err(|e| BotError::ImapError(imap::Error::Tls(e)))?;
+        let client = imap::connect((host, config.port()), host, &tls)?;
+        fetch_from(client.login(username, password).map_err(|(e, _)| e)?, &config.mailbox, since)
+    } else {
+        let mut client = imap::Client::new(TcpStream::connect((host, config.port())).map_err(BotError::IOError)?);
+        client.read_greeting()?;
+        fetch_from(client.login(username, password).map_err(|(e, _)| e)?, &config.mailbox, since)
+    }
+}
+
+// The campaign email a message answers and the address it went to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub campaign_id: i64,
+    pub email: String,
+    // False when matched by the sender's address alone.
+    pub by_message_id: bool,
+}
+
+// Matched by the Message-IDs the message refers to, which also catches a
+// colleague answering from another address, or else by its sender, for mail
+// clients that drop the headers.
+pub async fn match_reply(store: &dyn LeadStore, message: &InboxMessage) -> Result<Option<Reply>, BotError> {
+    for id in message.replying_to.iter().rev() {
+        if let Some(sent) = store.find_sent_message(id).await? {
+            return Ok(Some(Reply { campaign_id: sent.campaign_id, email: sent.email, by_message_id: true }));
+        }
+    }
+    let Some(from) = &message.from else {
+        return Ok(None);
+    };
+    Ok(last_campaign_sent(store, from)
+        .await?
+        .map(|campaign_id| Reply { campaign_id, email: from.clone(), by_message_id: false }))
+}
+
+// Replies are recorded once per recipient and campaign, however often the
+// inbox is checked. Returns false when it was already recorded.
+pub async fn record_reply(store: &dyn LeadStore, reply: &Reply) -> Result<bool, BotError> {
+    if has_event(store, reply.campaign_id, &reply.email, SEND_REPLIED).await? {
+        return Ok(false);
+    }
+    store.record_send(reply.campaign_id, &reply.email, SEND_REPLIED, None, None).await?;
+    Ok(true)
+}
+
+// One recipient a delivery status notification reports as unreachable.
+#[derive(Debug, Clone)]
+pub struct Bounce {
+    pub recipient: String,
+    // The enhanced status code, e.g. "5.1.1", and the receiving server's reply.
+    pub status: String,
+    pub diagnostic: Option<String>,
+    // Of the email that bounced, when the report quotes its headers.
+    pub message_id: Option<String>,
+}
+
+impl Bounce {
+    // Permanent failures (5.x.x) other than a full mailbox (x.2.2), which
+    // many servers report as permanent but clears up on its own.
+    pub fn is_hard(&self) -> bool {
+        let parts: Vec<&str> = self.status.split('.').collect();
+        parts.first() == Some(&"5") && !(parts.get(1) == Some(&"2") && parts.get(2) == Some(&"2"))
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.diagnostic {
+            Some(diagnostic) => format!("{} {}", self.status, diagnostic),
+            None => self.status.clone(),
+        }
+    }
+}
+
+fn status_code(text: &str) -> Option<String> {
+    let pattern = Regex::new(r"\b([245]\.\d{1,3}\.\d{1,3})\b").expect("valid regex");
+    pattern.captures(text).map(|captures| captures[1].to_string())
+}
+
+// The part after "rfc822;" in Final-Recipient and Diagnostic-Code fields.
+fn field_value(value: &str) -> String {
+    value.split_once(';').map_or(value, |(_, value)| value).trim().to_string()
+}
+
+// The failed recipients of a delivery report: the per-recipient fields of its
+// message/delivery-status part (RFC 3464), or else an X-Failed-Recipients
+// header and the first status code in the text, as some servers send.
+// Delayed-delivery warnings are not bounces and are left out.
+pub fn parse_bounces(raw: &[u8]) -> Result<Vec<Bounce>, BotError> {
+    let mail = mailparse::parse_mail(raw).map_err(|e| BotError::InvalidData(format!("unreadable delivery report: {}", e)))?;
+    let mut message_id = None;
+    let mut reports = Vec::new();
+    let mut text = String::new();
+    for part in mail.parts() {
+        let body = part.get_body_raw().unwrap_or_default();
+        match part.ctype.mimetype.as_str() {
+            "message/delivery-status" => reports.push(String::from_utf8_lossy(&body).replace("\r\n", "\n")),
+            "message/rfc822" | "text/rfc822-headers" => {
+                if let Ok((headers, _)) = mailparse::parse_headers(&body) {
+                    message_id = headers.get_first_value("Message-ID").map(|id| id.trim().to_string());
+                }
+            }
+            mimetype if mimetype.starts_with("text/") => text.push_str(&String::from_utf8_lossy(&body)),
+            _ => {}
+        }
+    }
+
+    let mut bounces = Vec::new();
+    for report in &reports {
+        // The first block describes the report; each one after it a recipient.
+        for block in report.split("\n\n").skip(1) {
+            let Ok((fields, _)) = mailparse::parse_headers(block.trim().as_bytes()) else {
+                continue;
+            };
+            let action = fields.get_first_value("Action").unwrap_or_default().trim().to_lowercase();
+            let recipient = fields.get_first_value("Final-Recipient").or_else(|| fields.get_first_value("Original-Recipient"));
+            let (Some(recipient), Some(status)) = (recipient, fields.get_first_value("Status").and_then(|status| status_code(&status))) else {
+                continue;
+            };
+            if action != "failed" || status.starts_with('2') {
+                continue;
+            }
+            bounces.push(Bounce {
+                recipient: normalize_email(&field_value(&recipient)),
+                status,
+                diagnostic: fields.get_first_value("Diagnostic-Code").map(|code| field_value(&code)),
+                message_id: message_id.clone(),
+            });
+        }
+    }
+    if reports.is_empty() {
+        if let (Some(recipients), Some(status)) = (mail.headers.get_first_value("X-Failed-Recipients"), status_code(&text)) {
+            for recipient in recipients.split(',').map(str::trim).filter(|recipient| !recipient.is_empty()) {
+                bounces.push(Bounce { recipient: normalize_email(recipient), status: status.clone(), diagnostic: None, message_id: message_id.clone() });
+            }
+        }
+    }
+    Ok(bounces)
+}
+
+// The campaign whose email bounced and the address it went to: the email
+// the report quotes, or else the last campaign email sent to the recipient.
+pub async fn match_bounce(store: &dyn LeadStore, bounce: &Bounce) -> Result<Option<(i64, String)>, BotError> {
+    if let Some(id) = &bounce.message_id {
+        if let Some(sent) = store.find_sent_message(id).await? {
+            return Ok(Some((sent.campaign_id, sent.email)));
+        }
+    }
+    Ok(last_campaign_sent(store, &bounce.recipient).await?.map(|campaign_id| (campaign_id, bounce.recipient.clone())))
+}
+
+// Hard bounces are recorded once per recipient and campaign. A soft bounce
+// counts against the campaign's last email to the recipient, once, and only
+// when the report arrived after that email, so an old report seen again
+// doesn't cancel the retry it caused. Returns false when nothing new was
+// recorded.
+pub async fn record_bounce(
+    store: &dyn LeadStore,
+    campaign_id: i64,
+    email: &str,
+    bounce: &Bounce,
+    received: Option<DateTime<Utc>>,
+) -> Result<bool, BotError> {
+    let error = bounce.describe();
+    if bounce.is_hard() {
+        if has_event(store, campaign_id, email, SEND_BOUNCED).await? {
+            return Ok(false);
+        }
+        store.record_send(campaign_id, email, SEND_BOUNCED, Some(&error), None).await?;
+        return Ok(true);
+    }
+    let history: Vec<_> = store.send_history(email).await?.into_iter().filter(|event| event.campaign_id == campaign_id).collect();
+    let Some(last_sent) = history.iter().rposition(|event| event.status == SEND_SENT) else {
+        return Ok(false);
+    };
+    let sent_at = parse_timestamp(&history[last_sent].created_at);
+    if received.zip(sent_at).is_some_and(|(received, sent_at)| received < sent_at) {
+        return Ok(false);
+    }
+    if history[last_sent..].iter().any(|event| event.status == SEND_SOFT_BOUNCED || event.status == SEND_BOUNCED) {
+        return Ok(false);
+    }
+    store.record_send(campaign_id, email, SEND_SOFT_BOUNCED, Some(&error), None).await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounce(status: &str) -> Bounce {
+        Bounce { recipient: "jane@plumbing.com".to_string(), status: status.to_string(), diagnostic: None, message_id: None }
+    }
+
+    #[test]
+    fn only_permanent_failures_but_a_full_mailbox_are_hard() {
+        assert!(bounce("5.1.1").is_hard());
+        assert!(bounce("5.7.1").is_hard());
+        assert!(!bounce("5.2.2").is_hard());
+        assert!(!bounce("4.4.7").is_hard());
+    }
+
+    #[test]
+    fn parse_bounces_reads_the_failed_recipients_of_a_delivery_report() {
+        let raw = "From: Mail Delivery System <mailer-daemon@mx.studio.dev>\r\n\
+            To: me@studio.dev\r\n\
+            Subject: Undelivered Mail Returned to Sender\r\n\
+            MIME-Version: 1.0\r\n\
+            Content-Type: multipart/report; report-type=delivery-status; boundary=\"b\"\r\n\
+            \r\n\
+            --b\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            Your message could not be delivered.\r\n\
+            --b\r\n\
+            Content-Type: message/delivery-status\r\n\
+            \r\n\
+            Reporting-MTA: dns; mx.studio.dev\r\n\
+            \r\n\
+            Final-Recipient: rfc822; Jane@Plumbing.com\r\n\
+            Action: failed\r\n\
+            Status: 5.1.1\r\n\
+            Diagnostic-Code: smtp; 550 5.1.1 No such user\r\n\
+            \r\n\
+            Final-Recipient: rfc822; bob@roofing.com\r\n\
+            Action: delayed\r\n\
+            Status: 4.4.1\r\n\
+            \r\n\
+            --b\r\n\
+            Content-Type: text/rfc822-headers\r\n\
+            \r\n\
+            Message-ID: <a@studio.dev>\r\n\
+            Subject: Quick question\r\n\
+            --b--\r\n";
+        let bounces = parse_bounces(raw.as_bytes()).unwrap();
+        assert_eq!(bounces.len(), 1);
+        assert_eq!(bounces[0].recipient, "jane@plumbing.com");
+        assert_eq!(bounces[0].status, "5.1.1");
+        assert_eq!(bounces[0].diagnostic.as_deref(), Some("550 5.1.1 No such user"));
+        assert_eq!(bounces[0].message_id.as_deref(), Some("<a@studio.dev>"));
+        assert!(bounces[0].is_hard());
+    }
+
+    #[test]
+    fn parse_bounces_falls_back_to_the_failed_recipients_header() {
+        let raw = "From: postmaster@roofing.com\r\n\
+            X-Failed-Recipients: bob@roofing.com, amy@roofing.com\r\n\
+            Subject: Delivery failure\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            The mailbox is full: 552 5.2.2 over quota\r\n";
+        let bounces = parse_bounces(raw.as_bytes()).unwrap();
+        let recipients: Vec<&str> = bounces.iter().map(|bounce| bounce.recipient.as_str()).collect();
+        assert_eq!(recipients, vec!["bob@roofing.com", "amy@roofing.com"]);
+        assert!(bounces.iter().all(|bounce| bounce.status == "5.2.2" && !bounce.is_hard()));
+        assert!(parse_bounces(b"From: jane@plumbing.com\r\nSubject: Re: Quick question\r\n\r\nSounds good\r\n").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use std::time::Duration;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use crate::error::BotError;
+use crate::http_client;
+use crate::scrape::{Business, ReviewStatus};
+
+const API_URL: &str = "https://api.airtable.com/v0";
+// Airtable takes at most 10 records per write and 5 requests a second per base.
+const BATCH_SIZE: usize = 10;
+const REQUEST_INTERVAL: Duration = Duration::from_millis(250);
+
+pub const DEFAULT_TABLE: &str = "Leads";
+
+// Field names in the table. Status is a single select (or text) field the
+// reviewers fill in with approved or rejected; pushes never overwrite it.
+const EMAIL_FIELD: &str = "Email";
+const STATUS_FIELD: &str = "Status";
+
+#[derive(Deserialize)]
+struct ListResponse {
+    #[serde(default)]
+    records: Vec<Record>,
+    offset: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Record {
+    #[serde(default)]
+    fields: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct UpsertResponse {
+    #[serde(default, rename = "createdRecords")]
+    created_records: Vec<String>,
+}
+
+// Authenticates with a personal access token that has the
+// data.records:read and data.records:write scopes on the base.
+pub struct AirtableClient {
+    client: reqwest::Client,
+    token: String,
+    table_url: Url,
+}
+
+fn lead_fields(business: &Business) -> Map<String, Value> {
+    let mut fields = Map::new();
+    fields.insert(EMAIL_FIELD.to_string(), json!(business.email));
+    let optional = [
+        ("Name", &business.name),
+        ("Phone", &business.phone),
+        ("Website", &business.website),
+        ("Location", &business.location),
+    ];
+    for (field, value) in optional {
+        if let Some(value) = value {
+            fields.insert(field.to_string(), json!(value));
+        }
+    }
+    if !business.categories.is_empty() {
+        fields.insert("Categories".to_string(), json!(business.categories.join("; ")));
+    }
+    fields.insert("URL".to_string(), json!(business.url));
+    fields
+}
+
+// Status cells come back as a string for text and single select fields.
+fn cell_text(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(text) => Some(text),
+        Value::Bool(true) => Some("approved"),
+        _ => None,
+    }
+}
+
+impl AirtableClient {
+    pub fn new(token: &str, base_id: &str, table: &str) -> Result<Self, BotError> {
+        let mut table_url = Url::parse(API_URL).unwrap();
+        table_url.path_segments_mut().unwrap().push(base_id).push(table);
+        Ok(AirtableClient {
+            client: http_client::client()?,
+            token: token.to_string(),
+            table_url,
+        })
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, BotError> {
+        tokio::time::sleep(REQUEST_INTERVAL).await;
+        let response = request.bearer_auth(&self.token).send().await.map_err(BotError::NetworkError)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(BotError::HttpStatus { url: self.table_url.to_string(), status: status.as_u16() });
+        }
+        Ok(response)
+    }
+
+    // Creates or updates a record per lead, merged on the Email field.
+    // Returns (created, updated).
+    pub async fn push(&self, businesses: &[Business]) -> Result<(usize, usize), BotError> {
+        let mut created = 0;
+        let mut updated = 0;
+        for batch in businesses.chunks(BATCH_SIZE) {
+            let records: Vec<Value> = batch.iter().map(|business| json!({ "fields": lead_fields(business) })).collect();
+            let body = json!({
+                "performUpsert": { "fieldsToMergeOn": [EMAIL_FIELD] },
+                "records": records,
+                "typecast": true,
+            });
+            let response = self.send(self.client.patch(self.table_url.clone()).json(&body)).await?;
+            let result: UpsertResponse = response.json().await.map_err(BotError::NetworkError)?;
+            created += result.created_records.len();
+            updated += batch.len() - result.created_records.len();
+        }
+        Ok((created, updated))
+    }
+
+    // Every email in the table with its review status, following the
+    // pagination offset until the last page.
+    pub async fn pull(&self) -> Result<Vec<(String, Option<ReviewStatus>)>, BotError> {
+        let mut reviews = Vec::new();
+        let mut offset: Option<String> = None;
+        loop {
+            let mut url = self.table_url.clone();
+            url.query_pairs_mut()
+                .append_pair("fields[]", EMAIL_FIELD)
+                .append_pair("fields[]", STATUS_FIELD)
+                .append_pair("pageSize", "100");
+            if let Some(offset) = &offset {
+                url.query_pairs_mut().append_pair("offset", offset);
+            }
+            let response = self.send(self.client.get(url)).await?;
+            let page: ListResponse = response.json().await.map_err(BotError::NetworkError)?;
+            for record in page.records {
+                let Some(email) = record.fields.get(EMAIL_FIELD).and_then(Value::as_str) else {
+                    continue;
+                };
+                let review = record.fields.get(STATUS_FIELD).and_then(cell_text).and_then(ReviewStatus::from_cell);
+                reviews.push((email.trim().to_string(), review));
+            }
+            match page.offset {
+                Some(next) => offset = Some(next),
+                None => break,
+            }
+        }
+        Ok(reviews)
+    }
+}
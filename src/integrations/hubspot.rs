@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use crate::error::BotError;
+use crate::http_client;
+use crate::scrape::hunter::website_domain;
+use crate::scrape::Business;
+use crate::storage::{SEND_BOUNCED, SEND_REPLIED, SEND_SENT, SEND_UNSUBSCRIBED};
+
+const API_URL: &str = "https://api.hubapi.com";
+// The batch endpoints take at most 100 inputs per call.
+const BATCH_SIZE: usize = 100;
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+const RATE_LIMIT_PAUSE: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    #[serde(default)]
+    results: Vec<ObjectResult>,
+}
+
+#[derive(Deserialize)]
+struct ObjectResult {
+    id: String,
+    #[serde(default)]
+    properties: HashMap<String, Option<String>>,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    results: Vec<ObjectResult>,
+}
+
+#[derive(Debug, Default)]
+pub struct PushSummary {
+    pub contacts: usize,
+    pub companies_created: usize,
+    pub companies_updated: usize,
+}
+
+// Maps the latest outreach event onto HubSpot's built-in lead status.
+pub fn lead_status(send_status: Option<&str>) -> &'static str {
+    match send_status {
+        Some(SEND_REPLIED) => "CONNECTED",
+        Some(SEND_BOUNCED) | Some(SEND_UNSUBSCRIBED) => "UNQUALIFIED",
+        Some(SEND_SENT) => "ATTEMPTED_TO_CONTACT",
+        _ => "NEW",
+    }
+}
+
+// Authenticates with a private app access token that has the
+// crm.objects.contacts.write and crm.objects.companies.write scopes.
+pub struct HubspotClient {
+    client: reqwest::Client,
+    access_token: String,
+}
+
+impl HubspotClient {
+    pub fn new(access_token: &str) -> Result<Self, BotError> {
+        Ok(HubspotClient {
+            client: http_client::client()?,
+            access_token: access_token.to_string(),
+        })
+    }
+
+    // HubSpot answers 429 when the app's burst limit is hit; wait and retry.
+    async fn request(&self, method: reqwest::Method, path: &str, body: Option<&Value>) -> Result<Value, BotError> {
+        let url = format!("{}{}", API_URL, path);
+        let mut attempt = 0;
+        loop {
+            let mut request = self.client.request(method.clone(), &url).bearer_auth(&self.access_token);
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            let response = request.send().await.map_err(BotError::NetworkError)?;
+            let status = response.status();
+            if status.as_u16() == 429 && attempt < MAX_RATE_LIMIT_RETRIES {
+                attempt += 1;
+                eprintln!("HubSpot rate limit hit, retrying in {}s", RATE_LIMIT_PAUSE.as_secs());
+                tokio::time::sleep(RATE_LIMIT_PAUSE).await;
+                continue;
+            }
+            if !status.is_success() {
+                return Err(BotError::HttpStatus { url, status: status.as_u16() });
+            }
+            if status.as_u16() == 204 {
+                return Ok(Value::Null);
+            }
+            return response.json().await.map_err(BotError::NetworkError);
+        }
+    }
+
+    // Creates or updates one contact per lead, matched on email. Returns the
+    // contact ids by lowercased email.
+    async fn upsert_contacts(&self, leads: &[(&Business, Option<&str>)]) -> Result<HashMap<String, String>, BotError> {
+        let mut ids = HashMap::new();
+        for batch in leads.chunks(BATCH_SIZE) {
+            let inputs: Vec<Value> = batch
+                .iter()
+                .map(|(business, send_status)| {
+                    let mut properties = Map::new();
+                    properties.insert("email".to_string(), json!(business.email));
+                    properties.insert("hs_lead_status".to_string(), json!(lead_status(*send_status)));
+                    if let Some(name) = &business.name {
+                        properties.insert("company".to_string(), json!(name));
+                    }
+                    if let Some(phone) = &business.phone {
+                        properties.insert("phone".to_string(), json!(phone));
+                    }
+                    if let Some(website) = &business.website {
+                        properties.insert("website".to_string(), json!(website));
+                    }
+                    json!({ "idProperty": "email", "id": business.email, "properties": properties })
+                })
+                .collect();
+            let response = self
+                .request(reqwest::Method::POST, "/crm/v3/objects/contacts/batch/upsert", Some(&json!({ "inputs": inputs })))
+                .await?;
+            let response: BatchResponse = serde_json::from_value(response).map_err(BotError::DataParseError)?;
+            for result in response.results {
+                if let Some(Some(email)) = result.properties.get("email") {
+                    ids.insert(email.to_lowercase(), result.id);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    // Companies have no unique key HubSpot can upsert on, so look the
+    // domain up first. Returns the id and whether the company is new.
+    async fn upsert_company(&self, domain: &str, business: &Business) -> Result<(String, bool), BotError> {
+        let mut properties = Map::new();
+        properties.insert("domain".to_string(), json!(domain));
+        if let Some(name) = &business.name {
+            properties.insert("name".to_string(), json!(name));
+        }
+        if let Some(phone) = &business.phone {
+            properties.insert("phone".to_string(), json!(phone));
+        }
+        if let Some(category) = business.categories.first() {
+            properties.insert("industry_description".to_string(), json!(category));
+        }
+
+        let search = json!({
+            "filterGroups": [{ "filters": [{ "propertyName": "domain", "operator": "EQ", "value": domain }] }],
+            "properties": ["domain"],
+            "limit": 1,
+        });
+        let response = self.request(reqwest::Method::POST, "/crm/v3/objects/companies/search", Some(&search)).await?;
+        let found: SearchResponse = serde_json::from_value(response).map_err(BotError::DataParseError)?;
+        if let Some(existing) = found.results.into_iter().next() {
+            let path = format!("/crm/v3/objects/companies/{}", existing.id);
+            self.request(reqwest::Method::PATCH, &path, Some(&json!({ "properties": properties }))).await?;
+            return Ok((existing.id, false));
+        }
+        let response = self
+            .request(reqwest::Method::POST, "/crm/v3/objects/companies", Some(&json!({ "properties": properties })))
+            .await?;
+        let created: ObjectResult = serde_json::from_value(response).map_err(BotError::DataParseError)?;
+        Ok((created.id, true))
+    }
+
+    async fn associate(&self, contact_id: &str, company_id: &str) -> Result<(), BotError> {
+        let path = format!("/crm/v4/objects/contacts/{}/associations/default/companies/{}", contact_id, company_id);
+        self.request(reqwest::Method::PUT, &path, None).await?;
+        Ok(())
+    }
+
+    // Pushes each lead as a contact, with the status of its latest outreach
+    // event, and when it has a website, a company linked to it.
+    pub async fn push(&self, leads: &[(&Business, Option<&str>)]) -> Result<PushSummary, BotError> {
+        let mut summary = PushSummary::default();
+        let contact_ids = self.upsert_contacts(leads).await?;
+        summary.contacts = contact_ids.len();
+
+        let mut company_ids: HashMap<String, String> = HashMap::new();
+        for (business, _) in leads {
+            let Some(domain) = business.website.as_deref().and_then(website_domain) else {
+                continue;
+            };
+            let Some(contact_id) = contact_ids.get(&business.email.to_lowercase()) else {
+                continue;
+            };
+            let company_id = match company_ids.get(&domain) {
+                Some(id) => id.clone(),
+                None => {
+                    let (id, created) = self.upsert_company(&domain, business).await?;
+                    if created {
+                        summary.companies_created += 1;
+                    } else {
+                        summary.companies_updated += 1;
+                    }
+                    company_ids.insert(domain, id.clone());
+                    id
+                }
+            };
+            self.associate(contact_id, &company_id).await?;
+        }
+        Ok(summary)
+    }
+}
@@ -0,0 +1,3 @@
+pub mod airtable;
+pub mod hubspot;
+pub mod sheets;
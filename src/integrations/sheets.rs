@@ -0,0 +1,272 @@
+use std::collections::{HashMap, HashSet};
+use chrono::Utc;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::email::dedup_key;
+use crate::error::BotError;
+use crate::http_client;
+use crate::scrape::{Business, ReviewStatus};
+
+const SHEETS_API_URL: &str = "https://sheets.googleapis.com/v4/spreadsheets";
+const SHEETS_SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+const TOKEN_LIFETIME_SECS: i64 = 3600;
+
+pub const DEFAULT_SHEET_NAME: &str = "Leads";
+
+// The review column comes first so it's the one teammates see and fill in.
+const REVIEW_COLUMN: &str = "approved";
+const HEADER: &[&str] = &[REVIEW_COLUMN, "email", "name", "phone", "website", "location", "categories", "url"];
+
+// The JSON key file downloaded for a Google Cloud service account. The sheet
+// has to be shared with the account's client_email.
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct ValueRange {
+    #[serde(default)]
+    values: Vec<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct Spreadsheet {
+    #[serde(default)]
+    sheets: Vec<SheetEntry>,
+}
+
+#[derive(Deserialize)]
+struct SheetEntry {
+    properties: SheetProperties,
+}
+
+#[derive(Deserialize)]
+struct SheetProperties {
+    title: String,
+}
+
+pub struct SheetsClient {
+    client: reqwest::Client,
+    access_token: String,
+    spreadsheet_id: String,
+}
+
+async fn checked(response: reqwest::Response) -> Result<reqwest::Response, BotError> {
+    let status = response.status();
+    if !status.is_success() {
+        return Err(BotError::HttpStatus { url: response.url().to_string(), status: status.as_u16() });
+    }
+    Ok(response)
+}
+
+impl SheetsClient {
+    // Exchanges a signed service-account JWT for an access token (valid for an hour).
+    pub async fn connect(credentials_path: &str, spreadsheet_id: &str) -> Result<Self, BotError> {
+        let contents = std::fs::read_to_string(credentials_path).map_err(BotError::IOError)?;
+        let key: ServiceAccountKey = serde_json::from_str(&contents).map_err(BotError::DataParseError)?;
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            iss: &key.client_email,
+            scope: SHEETS_SCOPE,
+            aud: &key.token_uri,
+            iat: now,
+            exp: now + TOKEN_LIFETIME_SECS,
+        };
+        let signing_key = EncodingKey::from_rsa_pem(key.private_key.as_bytes())
+            .map_err(|e| BotError::ConfigError(format!("invalid private key in {}: {}", credentials_path, e)))?;
+        let assertion = jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &signing_key)
+            .map_err(|e| BotError::ConfigError(format!("could not sign service account token: {}", e)))?;
+
+        let client = http_client::client()?;
+        let response = client
+            .post(&key.token_uri)
+            .form(&[("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"), ("assertion", assertion.as_str())])
+            .send()
+            .await
+            .map_err(BotError::NetworkError)?;
+        let token: TokenResponse = checked(response).await?.json().await.map_err(BotError::NetworkError)?;
+        Ok(SheetsClient {
+            client,
+            access_token: token.access_token,
+            spreadsheet_id: spreadsheet_id.to_string(),
+        })
+    }
+
+    // Range names are pushed as path segments so sheet titles with spaces
+    // or slashes are escaped.
+    fn url(&self, segments: &[&str]) -> Url {
+        let mut url = Url::parse(SHEETS_API_URL).unwrap();
+        url.path_segments_mut().unwrap().push(&self.spreadsheet_id).extend(segments);
+        url
+    }
+
+    // Spreadsheet-level methods are suffixed onto the id, e.g. "{id}:batchUpdate".
+    fn method_url(&self, method: &str) -> Url {
+        let mut url = Url::parse(SHEETS_API_URL).unwrap();
+        url.path_segments_mut().unwrap().push(&format!("{}:{}", self.spreadsheet_id, method));
+        url
+    }
+
+    async fn ensure_sheet(&self, sheet: &str) -> Result<(), BotError> {
+        let mut url = self.url(&[]);
+        url.query_pairs_mut().append_pair("fields", "sheets.properties.title");
+        let response = self.client.get(url).bearer_auth(&self.access_token).send().await.map_err(BotError::NetworkError)?;
+        let spreadsheet: Spreadsheet = checked(response).await?.json().await.map_err(BotError::NetworkError)?;
+        if spreadsheet.sheets.iter().any(|entry| entry.properties.title == sheet) {
+            return Ok(());
+        }
+        let body = json!({ "requests": [{ "addSheet": { "properties": { "title": sheet } } }] });
+        let response = self
+            .client
+            .post(self.method_url("batchUpdate"))
+            .bearer_auth(&self.access_token)
+            .json(&body)
+            .send()
+            .await
+            .map_err(BotError::NetworkError)?;
+        checked(response).await?;
+        println!("Added sheet \"{}\"", sheet);
+        Ok(())
+    }
+
+    async fn read_rows(&self, sheet: &str) -> Result<Vec<Vec<String>>, BotError> {
+        let response = self
+            .client
+            .get(self.url(&["values", sheet]))
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .map_err(BotError::NetworkError)?;
+        let range: ValueRange = checked(response).await?.json().await.map_err(BotError::NetworkError)?;
+        Ok(range.values)
+    }
+
+    // Replaces the sheet's contents with the given leads, keeping whatever
+    // reviewers already entered in the approved column and any rows they
+    // added by hand. Returns how many lead rows were written.
+    pub async fn push(&self, sheet: &str, businesses: &[Business]) -> Result<usize, BotError> {
+        self.ensure_sheet(sheet).await?;
+        let existing = self.read_rows(sheet).await?;
+        let reviews = review_cells(&existing);
+
+        let mut rows: Vec<Vec<String>> = vec![HEADER.iter().map(|column| column.to_string()).collect()];
+        let mut written: HashSet<String> = HashSet::new();
+        for business in businesses {
+            let key = dedup_key(&business.email);
+            let review = reviews
+                .get(&key)
+                .cloned()
+                .or_else(|| business.review_status.map(|status| status.as_str().to_string()))
+                .unwrap_or_default();
+            rows.push(vec![
+                review,
+                business.email.clone(),
+                business.name.clone().unwrap_or_default(),
+                business.phone.clone().unwrap_or_default(),
+                business.website.clone().unwrap_or_default(),
+                business.location.clone().unwrap_or_default(),
+                business.categories.join("; "),
+                business.url.clone(),
+            ]);
+            written.insert(key);
+        }
+        let lead_rows = rows.len() - 1;
+        if let Some(email_column) = existing.first().and_then(|header| column_index(header, "email")) {
+            for row in existing.iter().skip(1) {
+                let email = row.get(email_column).map(String::as_str).unwrap_or_default();
+                if !email.trim().is_empty() && !written.contains(&dedup_key(email)) {
+                    rows.push(row.clone());
+                }
+            }
+        }
+
+        let response = self
+            .client
+            .post(self.url(&["values", &format!("{}:clear", sheet)]))
+            .bearer_auth(&self.access_token)
+            .json(&json!({}))
+            .send()
+            .await
+            .map_err(BotError::NetworkError)?;
+        checked(response).await?;
+
+        let mut url = self.url(&["values", sheet]);
+        url.query_pairs_mut().append_pair("valueInputOption", "RAW");
+        let response = self
+            .client
+            .put(url)
+            .bearer_auth(&self.access_token)
+            .json(&json!({ "range": sheet, "majorDimension": "ROWS", "values": rows }))
+            .send()
+            .await
+            .map_err(BotError::NetworkError)?;
+        checked(response).await?;
+        Ok(lead_rows)
+    }
+
+    // Every email in the sheet with what the reviewer put in the approved column.
+    pub async fn pull(&self, sheet: &str) -> Result<Vec<(String, Option<ReviewStatus>)>, BotError> {
+        let rows = self.read_rows(sheet).await?;
+        let Some(header) = rows.first() else {
+            return Ok(Vec::new());
+        };
+        let (Some(email_column), Some(review_column)) = (column_index(header, "email"), column_index(header, REVIEW_COLUMN)) else {
+            return Err(BotError::InvalidData(format!(
+                "sheet \"{}\" needs \"email\" and \"{}\" header cells in its first row",
+                sheet, REVIEW_COLUMN
+            )));
+        };
+        Ok(rows
+            .iter()
+            .skip(1)
+            .filter_map(|row| {
+                let email = row.get(email_column)?.trim();
+                if email.is_empty() {
+                    return None;
+                }
+                let review = row.get(review_column).and_then(|cell| ReviewStatus::from_cell(cell));
+                Some((email.to_string(), review))
+            })
+            .collect())
+    }
+}
+
+fn column_index(header: &[String], name: &str) -> Option<usize> {
+    header.iter().position(|cell| cell.trim().eq_ignore_ascii_case(name))
+}
+
+fn review_cells(rows: &[Vec<String>]) -> HashMap<String, String> {
+    let Some(header) = rows.first() else {
+        return HashMap::new();
+    };
+    let (Some(email_column), Some(review_column)) = (column_index(header, "email"), column_index(header, REVIEW_COLUMN)) else {
+        return HashMap::new();
+    };
+    rows.iter()
+        .skip(1)
+        .filter_map(|row| {
+            let email = row.get(email_column)?;
+            let review = row.get(review_column)?;
+            (!review.trim().is_empty()).then(|| (dedup_key(email), review.clone()))
+        })
+        .collect()
+}
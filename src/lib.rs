@@ -0,0 +1,34 @@
+pub mod attachment;
+pub mod campaign;
+pub mod config;
+pub mod css;
+pub mod doctor;
+pub mod error;
+pub mod filter;
+pub mod followup;
+pub mod http_client;
+pub mod inbox;
+pub mod integrations;
+pub mod lock;
+pub mod oauth;
+pub mod pipeline;
+pub mod queue;
+pub mod schedule;
+pub mod scrape;
+pub mod server;
+pub mod shutdown;
+pub mod spam;
+pub mod email;
+pub mod storage;
+pub mod suppression;
+pub mod template;
+pub mod tracking;
+pub mod transport;
+pub mod unsubscribe;
+pub mod ratelimit;
+pub mod retry;
+pub mod validation;
+
+pub use config::Config;
+pub use error::BotError;
+pub use scrape::Business;
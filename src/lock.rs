@@ -0,0 +1,100 @@
+use std::time::Duration;
+use chrono::Utc;
+use tokio::task::JoinHandle;
+use crate::error::BotError;
+use crate::queue::host_name;
+use crate::ratelimit::{connection, RedisPool};
+use crate::shutdown::Shutdown;
+
+const LOCK_KEY_PREFIX: &str = "outreach:lock:";
+// Held by the runs that email leads, except queue workers, which share the
+// queue instead.
+pub const SEND_LOCK: &str = "send";
+// How long the lock outlives a run that died without letting go of it.
+pub const LOCK_TTL_SECS: u64 = 60;
+const HEARTBEAT_SECS: u64 = 20;
+
+// Only the run holding the lock may renew or release it.
+const RENEW_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return 0";
+const RELEASE_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0";
+
+// Keeps a second run of the same kind from starting, on any machine sharing
+// the Redis server, while one is going. The key names its holder and expires
+// unless a heartbeat renews it, so a run that crashed frees it within
+// LOCK_TTL_SECS. A run that finds its lock taken over, after Redis lost it
+// or the run stalled past the TTL, is shut down rather than going on beside
+// the new holder.
+pub struct RunLock {
+    pool: RedisPool,
+    key: String,
+    holder: String,
+    heartbeat: JoinHandle<()>,
+}
+
+impl RunLock {
+    pub async fn acquire(pool: RedisPool, name: &str, shutdown: Shutdown) -> Result<Self, BotError> {
+        let key = format!("{}{}", LOCK_KEY_PREFIX, name);
+        let holder = format!("{} (pid {}) since {} UTC", host_name(), std::process::id(), Utc::now().format("%Y-%m-%d %H:%M:%S"));
+        let mut con = connection(&pool).await?;
+        let taken: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(&holder)
+            .arg("NX")
+            .arg("PX")
+            .arg(LOCK_TTL_SECS * 1000)
+            .query_async(&mut *con)
+            .await?;
+        if taken.is_none() {
+            let current: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut *con).await?;
+            return Err(BotError::AlreadyRunning(format!(
+                "another {} run holds its lock ({}); it expires {}s after that run stops",
+                name,
+                current.as_deref().unwrap_or("released just now, try again"),
+                LOCK_TTL_SECS
+            )));
+        }
+        let heartbeat = tokio::spawn(heartbeat(pool.clone(), key.clone(), holder.clone(), shutdown));
+        Ok(RunLock { pool, key, holder, heartbeat })
+    }
+
+    pub async fn release(self) -> Result<(), BotError> {
+        self.heartbeat.abort();
+        let mut con = connection(&self.pool).await?;
+        redis::Script::new(RELEASE_SCRIPT).key(&self.key).arg(&self.holder).invoke_async::<_, i64>(&mut *con).await?;
+        Ok(())
+    }
+}
+
+async fn heartbeat(pool: RedisPool, key: String, holder: String, shutdown: Shutdown) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(HEARTBEAT_SECS)).await;
+        let renewed = match connection(&pool).await {
+            Ok(mut con) => redis::Script::new(RENEW_SCRIPT)
+                .key(&key)
+                .arg(&holder)
+                .arg(LOCK_TTL_SECS * 1000)
+                .invoke_async::<_, i64>(&mut *con)
+                .await
+                .map_err(BotError::from),
+            Err(e) => Err(e),
+        };
+        match renewed {
+            Ok(1) => {}
+            Ok(_) => {
+                eprintln!("Lost the run lock {}; stopping after the work in hand so this run doesn't go on beside another", key);
+                shutdown.request();
+                return;
+            }
+            // Tried again at the next beat, well before the lock expires.
+            Err(e) => eprintln!("Could not renew the run lock {}: {}", key, e),
+        }
+    }
+}
@@ -1,253 +1,1555 @@
-use std::fs::File;
-use std::io::Write;
-use std::io;
-use std::collections::HashSet;
-use std::env;
+mod cli;
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use clap::Parser;
 use dotenvy::dotenv;
-use reqwest;
 use regex::Regex;
-use scraper::{Html, Selector};
-use serde::{Serialize, Deserialize};
-use serde_json;
-use lettre::{Message, SmtpTransport, Transport, message::header::ContentType};
-use lettre::transport::smtp::authentication::Credentials;
-use askama::Template;
-use redis::Commands;
-use chrono::Utc;
-use std::thread;
-use std::time::Duration;
-use thiserror::Error;
-use lettre::error::Error as LettreError;
-use redis::RedisError;
-use askama::Error as AskamaError;
+use email_bot::{BotError, Config};
+use email_bot::attachment::{check_total_size, load_campaign_attachments, Attachment};
+use email_bot::campaign::{Campaign, FollowUp, LeadFilter, Variant, VariantSplit};
+use email_bot::config::{LimitStrategy, TransportKind};
+use email_bot::email::{Resumed, SendMode, Throttle};
+use email_bot::doctor::{DomainChecks, Doctor, Severity, Signing};
+use email_bot::filter::EmailFilter;
+use email_bot::followup::{due_emails, with_failed_sends, RetryCause};
+use email_bot::integrations::hubspot::HubspotClient;
+use email_bot::integrations::airtable::AirtableClient;
+use email_bot::integrations::sheets::SheetsClient;
+use email_bot::lock::{RunLock, SEND_LOCK};
+use email_bot::pipeline::{self, ValidateOptions};
+use email_bot::queue::{default_worker_name, LeadQueue, QueueWorker};
+use email_bot::suppression::{SuppressionList, REASON_BOUNCE};
+use email_bot::ratelimit::Window;
+use email_bot::retry::{FailedSend, RetryQueue};
+use email_bot::template::{missing_footer, CampaignTemplate, FooterDetails, RenderedEmail};
+use email_bot::schedule::SendWindow;
+use email_bot::server::LinkServer;
+use email_bot::shutdown::{self, Shutdown};
+use email_bot::tracking::{add_utm_parameters, TrackingLinks};
+use email_bot::transport::smtp::build_message;
+use email_bot::transport::{new_message_id, OutgoingEmail};
+use email_bot::unsubscribe::UnsubscribeLinks;
+use email_bot::http_client::{HostThrottle, HttpClient, ProxyPool, RetryPolicy, Timeouts, UserAgentPool};
+use email_bot::transport::rotation::SenderPool;
+use email_bot::{email, inbox, ratelimit, scrape, spam, storage, validation};
+use cli::{AirtableCommand, CampaignCommand, Cli, Command, CrawlArgs, CrmCommand, DeadLetterCommand, HubspotCommand, FetchArgs, FilterArgs, FollowUpArgs, LeadFormat, PipelineArgs, RecipientStatus, ScrapeArgs, SendArgs, SheetsCommand, SuppressCommand, ValidateArgs};
+
+const STREAM_UPSERT_BATCH_SIZE: usize = 500;
+// Leads a sender worker takes off the queue at a time, and how often it looks
+// again once the queue is empty.
+const QUEUE_BATCH_SIZE: usize = 20;
+const QUEUE_POLL_SECS: u64 = 10;
+
+#[tokio::main]
+async fn main() -> Result<(), BotError> {
+    dotenv().ok();
+    let cli = Cli::parse();
+    let config = Config::load(cli.config.as_deref(), cli.provider.as_deref())?;
+
+    match cli.command {
+        Command::Scrape(args) => run_scrape(&config, &cli.db, &args).await,
+        Command::Crawl(args) => run_crawl(&config, &cli.db, &args).await,
+        Command::Send(args) if args.from_queue || args.dry_run => run_send(&config, &cli.db, &args, None, &shutdown::listen()).await,
+        Command::Send(args) => {
+            let shutdown = shutdown::listen();
+            with_send_lock(&config, &shutdown, run_send(&config, &cli.db, &args, None, &shutdown)).await
+        }
+        Command::FollowUp(args) => {
+            let shutdown = shutdown::listen();
+            with_send_lock(&config, &shutdown, run_follow_up(&config, &cli.db, &args, &shutdown)).await
+        }
+        Command::Validate(args) => run_validate(&config, &cli.db, &args).await,
+        Command::Pipeline(args) if args.scrape.enqueue || args.dry_run => run_pipeline(&config, &cli.db, &args, &shutdown::listen()).await,
+        Command::Pipeline(args) => {
+            let shutdown = shutdown::listen();
+            with_send_lock(&config, &shutdown, run_pipeline(&config, &cli.db, &args, &shutdown)).await
+        }
+        Command::Doctor { selectors, strict } => run_doctor(&config, &selectors, strict).await,
+        Command::Stats { max_per_day } => run_stats(&config, &cli.db, max_per_day.unwrap_or(config.send.max_per_day)).await,
+        Command::Export { output, format } => run_export(&cli.db, output.as_deref(), format).await,
+        Command::Import { input, format } => run_import(&cli.db, input.as_deref(), format).await,
+        Command::Campaign { command } => run_campaign(&cli.db, &command).await,
+        Command::Inbox { since_days, watch, interval_secs } => run_inbox(&config, &cli.db, since_days, watch.then_some(interval_secs)).await,
+        Command::Mark { status, emails, campaign } => run_mark(&config, &cli.db, status, &emails, campaign.as_deref()).await,
+        Command::History { emails } => run_history(&cli.db, &emails).await,
+        Command::Crm { command } => run_crm(&cli.db, &command).await,
+        Command::Sheets { command } => run_sheets(&cli.db, &command).await,
+        Command::Airtable { command } => run_airtable(&cli.db, &command).await,
+        Command::Suppress { command } => run_suppress(&config, &command).await,
+        Command::DeadLetters { command } => run_dead_letters(&config, &cli.db, &command).await,
+        Command::Serve { listen } => run_serve(&config, &cli.db, listen).await,
+    }
+}
 
-#[derive(Error, Debug)]
-pub enum BotError {
-    #[error("Network error: {0}")]
-    NetworkError(#[from] reqwest::Error),
+// Runs `work` holding the send lock, so a second sending run started by
+// mistake refuses to start instead of doubling the outreach. Losing the lock
+// asks `shutdown`, which `work` stops on, to stop the run.
+async fn with_send_lock(config: &Config, shutdown: &Shutdown, work: impl Future<Output = Result<(), BotError>>) -> Result<(), BotError> {
+    let lock = RunLock::acquire(ratelimit::connect(&config.redis).await?, SEND_LOCK, shutdown.clone()).await?;
+    let result = work.await;
+    if let Err(e) = lock.release().await {
+        eprintln!("Could not release the run lock: {}", e);
+    }
+    result
+}
 
-    #[error("Data parsing error: {0}")]
-    DataParseError(#[from] serde_json::Error),
+async fn build_fetcher(args: &FetchArgs) -> Result<scrape::Fetcher, BotError> {
+    let mut proxy_urls = args.proxies.clone();
+    if let Some(path) = &args.proxy_file {
+        proxy_urls.extend(storage::load_line_list(path)?);
+    }
 
-    #[error("I/O error: {0}")]
-    IOError(#[from] io::Error),
+    let timeouts = Timeouts {
+        connect: Duration::from_secs(args.connect_timeout_secs.max(1)),
+        request: Duration::from_secs(args.request_timeout_secs.max(1)),
+    };
+    let proxies = if proxy_urls.is_empty() {
+        None
+    } else {
+        println!("Using {} proxies, rotating every {} requests", proxy_urls.len(), args.rotate_proxy_every);
+        Some(ProxyPool::new(&proxy_urls, args.rotate_proxy_every, &timeouts)?)
+    };
+
+    let user_agents = match &args.user_agents_file {
+        Some(path) => storage::load_line_list(path)?,
+        None => Vec::new(),
+    };
+    let user_agents = UserAgentPool::new(user_agents, &args.accept_language);
+
+    let retry = RetryPolicy {
+        max_attempts: args.max_attempts.max(1),
+        base_delay: Duration::from_millis(args.retry_base_delay_ms),
+        ..RetryPolicy::default()
+    };
 
-    #[error("Email sending error: {0}")]
-    EmailError(#[from] LettreError),
+    let throttle = HostThrottle::new(Duration::from_millis(args.host_delay_ms));
 
-    #[error("SMTP transport error: {0}")]
-    SmtpTransportError(#[from] lettre::transport::smtp::Error),
+    let mut fetcher = scrape::Fetcher::new(HttpClient::new(proxies, user_agents, retry, throttle, &timeouts)?, !args.ignore_robots);
 
-    #[error("Redis operation error: {0}")]
-    RedisError(#[from] RedisError),
-    
-    #[error("Template rendering error: {0}")]
-    TemplateError(#[from] AskamaError),
-    
-    #[error("Invalid data: {0}")]
-    InvalidData(String),
+    if let Some(cache_dir) = &args.cache_dir {
+        let cache = scrape::PageCache::new(cache_dir, Duration::from_secs(args.cache_ttl_secs))?;
+        if args.clear_cache {
+            cache.clear()?;
+        }
+        fetcher = fetcher.with_cache(cache);
+    }
+
+    if !args.no_block_detection {
+        let cooldown = Duration::from_secs(args.block_cooldown_secs);
+        fetcher = fetcher.with_block_guard(scrape::BlockGuard::new(cooldown, args.max_block_pauses, args.rotate_proxy_on_block));
+    }
+
+    #[cfg(feature = "headless")]
+    let fetcher = match &args.webdriver_url {
+        Some(webdriver_url) => {
+            println!("Using headless browser fallback via {}", webdriver_url);
+            fetcher.with_headless(scrape::HeadlessBrowser::connect(webdriver_url).await?)
+        }
+        None => fetcher,
+    };
+
+    Ok(fetcher)
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Business {
-    url: String,
-    email: String,
+fn build_filter(config: &Config, args: &FilterArgs) -> Result<EmailFilter, BotError> {
+    let mut filter_config = config.filter.clone();
+    if args.keep_role_accounts {
+        filter_config.skip_role_accounts = false;
+    }
+    filter_config.allowed_role_accounts.extend(args.allow_role_account.iter().cloned());
+    EmailFilter::load(&filter_config, args.disposable_domains_file.as_deref(), args.business_domains_only)
 }
 
-#[derive(Template)]
-#[template(path = "email_template.html")]
-struct EmailTemplate {
-    subject: String,
+fn build_queries(search_terms: &[String], geo_locations: &[String]) -> Vec<scrape::SearchQuery> {
+    let mut queries: Vec<scrape::SearchQuery> = Vec::new();
+    for location in geo_locations.iter().map(|location| location.trim()).filter(|location| !location.is_empty()) {
+        for term in search_terms.iter().map(|term| term.trim()).filter(|term| !term.is_empty()) {
+            let query = scrape::SearchQuery {
+                search_terms: term.to_string(),
+                geo_location: location.to_string(),
+            };
+            if !queries.contains(&query) {
+                queries.push(query);
+            }
+        }
+    }
+    queries
 }
 
-fn current_day() -> String {
-    Utc::now().format("%Y-%m-%d").to_string()
+async fn run_scrape(config: &Config, db: &str, args: &ScrapeArgs) -> Result<(), BotError> {
+    let options = scrape_options(config, args, &shutdown::listen()).await?;
+    let store = storage::open_store(db).await?;
+    let businesses = scrape_leads(config, args, &options).await?;
+    save_leads(store.as_ref(), &businesses, args.output.as_deref()).await
 }
 
-fn check_update_email_count(con: &mut redis::Connection, max_emails_per_day: usize) -> Result<bool, BotError> {
-    let today = current_day();
-    let key = format!("emails_sent:{}", today);
-    let mut retry_count = 0;
-    let max_retries = 5;
+async fn scrape_options(config: &Config, args: &ScrapeArgs, shutdown: &Shutdown) -> Result<scrape::ScrapeOptions, BotError> {
+    let seen = if args.no_redis_dedup {
+        None
+    } else {
+        match ratelimit::connect(&config.redis).await {
+            Ok(pool) => {
+                let ttl = args.seen_ttl_days.map(|days| Duration::from_secs(days * 86400));
+                Some(Arc::new(scrape::SeenStore::new(pool, ttl)))
+            }
+            Err(e) => {
+                eprintln!("Redis unavailable, cross-run dedup disabled: {}", e);
+                None
+            }
+        }
+    };
+
+    let hunter = if args.no_hunter {
+        None
+    } else {
+        scrape::HunterClient::from_env()?.map(Arc::new)
+    };
+    if hunter.is_some() {
+        println!("Hunter.io lookups enabled for businesses without an on-page email");
+    }
+
+    Ok(scrape::ScrapeOptions {
+        concurrency: args.fetch.concurrency,
+        seen,
+        hunter,
+        follow_contact_links: !args.no_follow_contact_links,
+        checkpoint_path: Some(args.checkpoint.clone()),
+        resume: args.resume,
+        max_pages: args.max_pages,
+        max_leads: args.max_leads,
+        filter: Arc::new(build_filter(config, &args.filter)?),
+        stream: open_stream(args.output.as_deref())?,
+        queue: open_queue(config, args.enqueue).await?,
+        sink: None,
+        shutdown: shutdown.clone(),
+    })
+}
 
+async fn scrape_leads(config: &Config, args: &ScrapeArgs, options: &scrape::ScrapeOptions) -> Result<Vec<scrape::Business>, BotError> {
+    let mut search_terms = args.search_terms.clone();
+    if let Some(path) = &args.search_terms_file {
+        search_terms.extend(storage::load_line_list(path)?);
+    }
+    let mut geo_locations = args.geo_location.clone();
+    if let Some(path) = &args.geo_locations_file {
+        geo_locations.extend(storage::load_line_list(path)?);
+    }
+    let queries = build_queries(&search_terms, &geo_locations);
+    let url_pattern = match &args.url_pattern {
+        Some(pattern) => Some(Regex::new(pattern).map_err(|e| BotError::InvalidData(format!("invalid --url-pattern: {}", e)))?),
+        None => None,
+    };
+
+    let fetcher = build_fetcher(&args.fetch).await?;
+    let source = scrape::source_by_name(&args.source, config)?;
+    let result = if args.sitemaps.is_empty() {
+        for query in &queries {
+            println!("Searching {} for \"{}\" in \"{}\"", source.name(), query.search_terms, query.geo_location);
+        }
+        scrape::scrape_businesses(&fetcher, source.as_ref(), &queries, options).await
+    } else {
+        println!("Scraping {} pages listed in {}", source.name(), args.sitemaps.join(", "));
+        scrape::scrape_sitemaps(&fetcher, source.as_ref(), &args.sitemaps, url_pattern.as_ref(), options).await
+    };
+    fetcher.close().await;
+    result
+}
+
+// Runs scrape, validate, enqueue and send at once, each stage handing its
+// leads to the next over a bounded channel. A slow mail server holds up
+// scraping only once the channels are full, and a stage that fails stops
+// the whole run after the leads in hand.
+async fn run_pipeline(config: &Config, db: &str, args: &PipelineArgs, shutdown: &Shutdown) -> Result<(), BotError> {
+    let sending = !args.scrape.enqueue;
+    if sending && !args.dry_run && !args.yes && !confirm("Do you want to proceed with sending emails as leads are found? (yes/no):")? {
+        println!("Aborted by user.");
+        return Ok(());
+    }
+    let send_args = SendArgs {
+        input: None,
+        from_queue: false,
+        until_empty: false,
+        worker_name: None,
+        claim_after_minutes: 0,
+        campaign: args.campaign.clone(),
+        template: args.template.clone(),
+        max_per_day: args.max_per_day,
+        contact_cooldown_days: args.contact_cooldown_days,
+        approved_only: false,
+        dry_run: args.dry_run,
+        test_to: args.test_to.clone(),
+        yes: true,
+    };
+    let store: Arc<dyn storage::LeadStore> = Arc::from(storage::open_store(db).await?);
+    let mut options = scrape_options(config, &args.scrape, shutdown).await?;
+    // The enqueue stage queues the leads that passed validation, not every one scraped.
+    let queue = options.queue.take();
+    let capacity = args.channel_capacity.max(1);
+    let (scraped, scraped_leads) = mpsc::channel(capacity);
+    let (valid, valid_leads) = mpsc::channel(capacity);
+    let (stored, stored_leads) = mpsc::channel(capacity);
+    options.sink = Some(scraped);
+    let validate_options = ValidateOptions {
+        check_mx: !args.no_mx,
+        keep_undeliverable: args.keep_undeliverable,
+        concurrency: args.dns_concurrency,
+    };
+
+    // Each stage runs on a task of its own and so owns what it works with.
+    let config = Arc::new(config.clone());
+    let scrape_config = config.clone();
+    let scrape_args = args.scrape.clone();
+    let scrape = pipeline::stage("scrape", shutdown, async move {
+        let businesses = scrape_leads(&scrape_config, &scrape_args, &options).await?;
+        if let Some(output) = scrape_args.output.as_deref().filter(|path| !storage::is_jsonl_path(path)) {
+            storage::save_leads_file(output, &businesses)?;
+            println!("Saved {} leads to {}", businesses.len(), output);
+        }
+        Ok(())
+    });
+    let validate = pipeline::stage("validate", shutdown, async move { pipeline::validate(scraped_leads, valid, &validate_options).await });
+    let enqueue = pipeline::stage("enqueue", shutdown, async move { pipeline::enqueue(valid_leads, store.as_ref(), queue.as_deref(), sending.then_some(stored)).await });
+    let send = async {
+        if !sending {
+            return Some(());
+        }
+        let db = db.to_string();
+        let send_shutdown = shutdown.clone();
+        pipeline::stage("send", shutdown, async move { run_send(&config, &db, &send_args, Some(stored_leads), &send_shutdown).await }).await
+    };
+    let (scrape, validate, enqueue, send) = tokio::join!(scrape, validate, enqueue, send);
+    let failed: Vec<&str> = [("scrape", scrape), ("validate", validate), ("enqueue", enqueue), ("send", send)]
+        .into_iter()
+        .filter_map(|(name, outcome)| outcome.is_none().then_some(name))
+        .collect();
+    if !failed.is_empty() {
+        return Err(BotError::StageFailed(failed.join(", ")));
+    }
+    Ok(())
+}
+
+// A .jsonl output is written lead by lead while scraping rather than at the end.
+fn open_stream(output: Option<&str>) -> Result<Option<Arc<storage::LeadStream>>, BotError> {
+    match output {
+        Some(path) if storage::is_jsonl_path(path) => {
+            println!("Streaming leads to {}", path);
+            Ok(Some(Arc::new(storage::LeadStream::open(path)?)))
+        }
+        _ => Ok(None),
+    }
+}
+
+async fn open_queue(config: &Config, enqueue: bool) -> Result<Option<Arc<LeadQueue>>, BotError> {
+    if !enqueue {
+        return Ok(None);
+    }
+    println!("Pushing leads to the Redis queue for `send --from-queue` workers");
+    Ok(Some(Arc::new(LeadQueue::new(ratelimit::connect(&config.redis).await?))))
+}
+
+// Stores the leads of a run and optionally exports just those to a file. A
+// streamed .jsonl output already holds them and is synced into the database in
+// batches; leads left there by earlier interrupted runs are picked up too.
+async fn save_leads(store: &dyn storage::LeadStore, businesses: &[scrape::Business], output: Option<&str>) -> Result<(), BotError> {
+    if let Some(path) = output.filter(|path| storage::is_jsonl_path(path)) {
+        let mut leads = storage::jsonl_leads(path)?.peekable();
+        let mut total = 0;
+        let mut inserted = 0;
+        while leads.peek().is_some() {
+            let batch: Vec<scrape::Business> = leads.by_ref().take(STREAM_UPSERT_BATCH_SIZE).collect();
+            total += batch.len();
+            inserted += store.upsert_businesses(&batch).await?;
+        }
+        println!("Stored {} leads from {} ({} new)", total, path, inserted);
+        return Ok(());
+    }
+    let inserted = store.upsert_businesses(businesses).await?;
+    println!("Stored {} leads ({} new)", businesses.len(), inserted);
+    if let Some(output) = output {
+        storage::save_leads_file(output, businesses)?;
+        println!("Saved {} leads to {}", businesses.len(), output);
+    }
+    Ok(())
+}
+
+async fn run_crawl(config: &Config, db: &str, args: &CrawlArgs) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    let fetcher = build_fetcher(&args.fetch).await?;
+    let site_urls = storage::load_line_list(&args.input)?;
+    let crawler = scrape::WebsiteCrawler {
+        max_pages_per_site: args.max_pages_per_site,
+        concurrency: args.fetch.concurrency,
+        filter: Arc::new(build_filter(config, &args.filter)?),
+        stream: open_stream(args.output.as_deref())?,
+        queue: open_queue(config, args.enqueue).await?,
+        shutdown: shutdown::listen(),
+    };
+    println!("Crawling {} websites, up to {} pages each", site_urls.len(), args.max_pages_per_site);
+    let result = crawler.crawl_sites(&fetcher, &site_urls).await;
+    fetcher.close().await;
+    let businesses = result?;
+    save_leads(store.as_ref(), &businesses, args.output.as_deref()).await
+}
+
+async fn load_leads(store: &dyn storage::LeadStore, input: Option<&str>) -> Result<Vec<scrape::Business>, BotError> {
+    match input {
+        Some(path) => storage::load_leads_file(path),
+        None => store.load_businesses().await,
+    }
+}
+
+// Sends to the leads file or the database, or with `leads` to what the
+// pipeline's enqueue stage hands on.
+async fn run_send(
+    config: &Config,
+    db: &str,
+    args: &SendArgs,
+    leads: Option<mpsc::Receiver<scrape::Business>>,
+    shutdown: &Shutdown,
+) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    let campaign_name = args.campaign.clone().unwrap_or_else(|| format!("campaign-{}", ratelimit::current_day()));
+    // Unknown names become a new campaign, saved once the send is confirmed.
+    let mut campaign = store
+        .find_campaign(&campaign_name)
+        .await?
+        .unwrap_or_else(|| Campaign::new(&campaign_name, email::DEFAULT_SUBJECT));
+    if let Some(template) = &args.template {
+        campaign.template = template.clone();
+    }
+    campaign.validate()?;
+    let split = load_variants(store.as_ref(), &campaign).await?;
+    let attachments = load_campaign_attachments(&config.attachments, &campaign.name)?;
+
+    // A worker gets its leads from the queue as it goes, and a pipeline from its channel.
+    let streamed = args.from_queue || leads.is_some();
+    let mut businesses = if streamed { Vec::new() } else { load_leads(store.as_ref(), args.input.as_deref()).await? };
+    if args.approved_only && !streamed {
+        let total = businesses.len();
+        businesses.retain(|business| business.review_status == Some(scrape::ReviewStatus::Approved));
+        println!("{} of {} leads are approved", businesses.len(), total);
+    }
+    if !campaign.filter.is_empty() && !streamed {
+        let total = businesses.len();
+        businesses.retain(|business| campaign.filter.matches(business));
+        println!("{} of {} leads match the campaign's filter", businesses.len(), total);
+    }
+
+    // Establish Redis connections
+    let redis = ratelimit::connect(&config.redis).await?;
+    let mut limiter = ratelimit::open(config, &redis)?;
+    let suppression = SuppressionList::new(redis.clone());
+    let retries = RetryQueue::new(redis.clone(), &config.retry);
+    let filter = EmailFilter::new(&config.filter, Vec::new(), false);
+
+    let mut settings = config.send.clone();
+    if let Some(max_per_day) = args.max_per_day {
+        settings.max_per_day = max_per_day;
+    }
+    let sender = settings.sender_mailbox()?;
+    let mut senders = SenderPool::connect(config, &sender).await?;
+    let sample = businesses.first().cloned().unwrap_or_default();
+    for (variant, subject, template) in split.iter() {
+        let rendered = render_sample(config, template, subject, &sample, &sender)?;
+        match variant {
+            Some(variant) => println!("Email content preview (variant \"{}\", {}):", variant, template.describe()),
+            None => println!("Email content preview ({}):", template.describe()),
+        }
+        println!("Subject: {}", rendered.subject);
+        println!("Content: {}", rendered.html);
+        for image in template.images() {
+            println!("Embedding {} ({}, {} KiB)", image.filename, image.content_type, image.data.len().div_ceil(1024));
+        }
+        println!("-------------------------");
+        check_total_size(&config.attachments, &template.describe(), attachments.iter().chain(template.images()))?;
+        // Without leads the sample goes to the sender.
+        let recipient = if sample.email.parse::<lettre::Address>().is_ok() { sample.email.clone() } else { sender.email.to_string() };
+        check_spam_score(config, &campaign, template, &rendered, &recipient, &attachments, &sender).await?;
+    }
+    for attachment in &attachments {
+        println!("Attaching {} ({}, {} KiB)", attachment.filename, attachment.content_type, attachment.data.len().div_ceil(1024));
+    }
+
+    let mode = match &args.test_to {
+        Some(address) => {
+            if !email::is_valid_email(address) {
+                return Err(BotError::InvalidData(format!("--test-to: \"{}\" is not an email address", address)));
+            }
+            SendMode::TestTo(address.clone())
+        }
+        None if args.dry_run => SendMode::DryRun,
+        None => SendMode::Live,
+    };
+    match &mode {
+        SendMode::DryRun => println!("Dry run: nothing is sent, recorded or counted against the limits"),
+        SendMode::TestTo(address) => println!("Test send: every email goes to {} and nothing is recorded against the leads", address),
+        SendMode::Live => {}
+    }
+    if mode != SendMode::DryRun && !args.yes && !confirm("Do you want to proceed with sending emails? (yes/no):")? {
+        println!("Aborted by user.");
+        return Ok(());
+    }
+
+    if campaign.id == 0 && mode.records() {
+        campaign.absolutize_template()?;
+        campaign.id = store.create_campaign(&campaign).await?;
+    }
+    if let Some(provider) = config.send.provider {
+        println!("Using the {} preset for what config.toml leaves out", provider.name());
+    }
+    println!("Sending from {}, {} apart", senders.describe(), email::describe_send_pause(&settings));
+    let schedule = SendWindow::from_config(&config.schedule)?;
+    if let Some(schedule) = &schedule {
+        println!("Sending only {}", schedule.describe());
+    }
+    if mode.records() {
+        println!("Recording sends under campaign \"{}\" (#{}, {})", campaign.name, campaign.id, campaign.describe());
+    } else if campaign.id == 0 {
+        println!("Campaign \"{}\" is new and isn't saved by this run ({})", campaign.name, campaign.describe());
+    } else {
+        println!("Campaign \"{}\" (#{}, {})", campaign.name, campaign.id, campaign.describe());
+    }
+    let send_limit = campaign.remaining_sends(store.as_ref()).await?;
+    if let Some(limit) = send_limit {
+        println!("The campaign's limits allow {} more emails", limit);
+    }
+    let resumed = if campaign.id == 0 { Resumed::default() } else { email::resumed_recipients(store.as_ref(), campaign.id).await? };
+
+    let mut context = email::SendContext {
+        senders: &mut senders,
+        settings: &settings,
+        limiter: limiter.as_mut(),
+        company: &config.company,
+        unsubscribe: &config.unsubscribe,
+        tracking: &config.tracking,
+        utm: &config.utm,
+        redis: &redis,
+        suppression: &suppression,
+        retries: &retries,
+        filter: &filter,
+        store: store.as_ref(),
+        campaign: &campaign,
+        attachments: attachments.into(),
+        contact_cooldown: args.contact_cooldown_days.map(|days| chrono::Duration::days(days.into())),
+        mode,
+        emailed: HashSet::new(),
+        resumed,
+        schedule: schedule.as_ref(),
+        throttle: Throttle::default(),
+        attempted: false,
+        shutdown: shutdown.clone(),
+    };
+    if args.from_queue {
+        let name = args.worker_name.clone().unwrap_or_else(default_worker_name);
+        let claim_after = Duration::from_secs(args.claim_after_minutes * 60);
+        let worker = QueueWorker::new(redis.clone(), &name, claim_after).await?;
+        println!("Taking leads from the Redis queue as worker \"{}\" ({} waiting)", worker.name(), LeadQueue::new(redis.clone()).len().await?);
+        return work_queue(&mut context, &split, &worker, args).await;
+    }
+    if let Some(leads) = leads {
+        return work_channel(&mut context, &split, leads).await;
+    }
+    email::send_campaign(&mut context, &split, &businesses, send_limit).await?;
+    match &context.mode {
+        SendMode::DryRun => println!("Dry run finished: {} of {} leads would have been emailed", context.emailed.len(), businesses.len()),
+        SendMode::TestTo(address) => println!("Test send finished: {} of {} leads' emails went to {}", context.emailed.len(), businesses.len(), address),
+        SendMode::Live => {}
+    }
+    Ok(())
+}
+
+// Emails the pipeline's leads as they come, whatever has arrived at a time,
+// until the stages before it are done or a limit stops the run. The leads it
+// doesn't reach are stored already, for a later `send`.
+async fn work_channel(context: &mut email::SendContext<'_>, split: &VariantSplit, mut leads: mpsc::Receiver<scrape::Business>) -> Result<(), BotError> {
+    while let Some(business) = leads.recv().await {
+        let mut batch = vec![business];
+        while batch.len() < QUEUE_BATCH_SIZE {
+            match leads.try_recv() {
+                Ok(business) => batch.push(business),
+                Err(_) => break,
+            }
+        }
+        batch.retain(|business| {
+            let matches = context.campaign.filter.matches(business);
+            if !matches {
+                println!("Not for this campaign: {}", business.email);
+            }
+            matches
+        });
+        let send_limit = context.campaign.remaining_sends(context.store).await?;
+        if !email::send_campaign(context, split, &batch, send_limit).await?.unreached.is_empty() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+// Emails the queue's leads a batch at a time as scrapers add them, until a
+// limit stops the run or, with --until-empty, the queue runs dry. Leads a
+// limit kept this worker from reaching, and those their domain's daily cap
+// held back, are left unacknowledged, for a worker to claim later.
+async fn work_queue(context: &mut email::SendContext<'_>, split: &VariantSplit, worker: &QueueWorker, args: &SendArgs) -> Result<(), BotError> {
+    let mut idle = false;
     loop {
-        match con.get::<_, Option<isize>>(&key) {
-            Ok(Some(count)) => {
-                if count < max_emails_per_day as isize {
-                    let _: () = con.incr(&key, 1).map_err(BotError::RedisError)?;
-                    if count == 0 {
-                        let _: () = con.expire(&key, 86400).map_err(BotError::RedisError)?;
-                    }
-                    return Ok(true);
-                } else {
-                    return Ok(false);
-                }
-            },
-            Ok(None) => {
-                let _: () = con.set(&key, 1).map_err(BotError::RedisError)?;
-                let _: () = con.expire(&key, 86400).map_err(BotError::RedisError)?;
-                return Ok(true);
-            },
-            Err(err) => {
-                if retry_count >= max_retries {
-                    return Err(BotError::RedisError(err));
-                } else {
-                    eprintln!("Redis failed, retrying... (Attempt: {})", retry_count + 1);
-                    retry_count += 1;
-                    thread::sleep(Duration::from_millis(500));
+        if context.shutdown.requested() {
+            println!("Shutting down; the queue keeps the leads not taken yet");
+            return Ok(());
+        }
+        let mut batch = worker.take(QUEUE_BATCH_SIZE).await?;
+        if batch.is_empty() {
+            if args.until_empty {
+                println!("The queue is empty.");
+                return Ok(());
+            }
+            if !idle {
+                println!("The queue is empty; waiting for leads");
+                idle = true;
+            }
+            context.shutdown.sleep(Duration::from_secs(QUEUE_POLL_SECS)).await;
+            continue;
+        }
+        idle = false;
+        let (keep, other): (Vec<_>, Vec<_>) = batch.drain(..).partition(|lead| {
+            (!args.approved_only || lead.business.review_status == Some(scrape::ReviewStatus::Approved)) && context.campaign.filter.matches(&lead.business)
+        });
+        for lead in &other {
+            println!("Not for this campaign: {}", lead.business.email);
+        }
+        worker.ack(&other.into_iter().map(|lead| lead.id).collect::<Vec<_>>()).await?;
+        let businesses: Vec<scrape::Business> = keep.iter().map(|lead| lead.business.clone()).collect();
+        let send_limit = context.campaign.remaining_sends(context.store).await?;
+        let unsent = email::send_campaign(context, split, &businesses, send_limit).await?;
+        let unreached: HashSet<&str> = unsent.unreached.iter().map(|business| business.email.as_str()).collect();
+        let capped: HashSet<&str> = unsent.capped.iter().map(|business| business.email.as_str()).collect();
+        let (left, handled): (Vec<_>, Vec<_>) = keep.iter().partition(|lead| unreached.contains(lead.business.email.as_str()));
+        // Left unacknowledged, they're claimed again once the claim timeout has
+        // passed, by when their domain may take more.
+        let (held, handled): (Vec<_>, Vec<_>) = handled.into_iter().partition(|lead| capped.contains(lead.business.email.as_str()));
+        if !held.is_empty() {
+            println!("Leaving {} leads their domain's daily cap held back in the queue", held.len());
+        }
+        worker.ack(&handled.into_iter().map(|lead| lead.id.clone()).collect::<Vec<_>>()).await?;
+        if !left.is_empty() {
+            println!("Leaving {} leads in the queue for another worker or a later run", left.len());
+            return Ok(());
+        }
+    }
+}
+
+// The campaign's A/B variants, or its own subject and template when it has none.
+async fn load_variants(store: &dyn storage::LeadStore, campaign: &Campaign) -> Result<VariantSplit, BotError> {
+    let variants = if campaign.id == 0 { Vec::new() } else { store.campaign_variants(campaign.id).await? };
+    if variants.is_empty() {
+        Ok(VariantSplit::single(campaign, campaign.load_template()?))
+    } else {
+        VariantSplit::load(&variants)
+    }
+}
+
+// Renders the template for a sample lead, refusing it when its footer is missing.
+fn render_sample(
+    config: &Config,
+    template: &CampaignTemplate,
+    subject: &str,
+    sample: &scrape::Business,
+    sender: &lettre::message::Mailbox,
+) -> Result<RenderedEmail, BotError> {
+    let (company_name, postal_address) = config.company.footer_identity()?;
+    let unsubscribe = UnsubscribeLinks::new(&config.unsubscribe, &sample.email, &sender.email);
+    let footer = FooterDetails { company_name, postal_address, unsubscribe_url: unsubscribe.footer_url() };
+    let rendered = template.render(subject, sample, &footer)?;
+    if let Some(problem) = missing_footer(&rendered, &footer) {
+        return Err(BotError::InvalidData(format!("{}: {}; refusing to send", template.describe(), problem)));
+    }
+    Ok(rendered)
+}
+
+// Has spamd score a sample email as the leads will get it, links and all.
+// It only warns: a high score is worth a look before going ahead, and an
+// unreachable spamd says nothing about the email.
+async fn check_spam_score(
+    config: &Config,
+    campaign: &Campaign,
+    template: &CampaignTemplate,
+    rendered: &RenderedEmail,
+    recipient: &str,
+    attachments: &[Attachment],
+    sender: &lettre::message::Mailbox,
+) -> Result<(), BotError> {
+    if config.spamassassin.host.is_none() {
+        return Ok(());
+    }
+    let unsubscribe = UnsubscribeLinks::new(&config.unsubscribe, recipient, &sender.email);
+    let keep = [unsubscribe.footer_url()];
+    let (mut html, text) = add_utm_parameters(&rendered.html, &rendered.text, &config.utm, &campaign.name, &keep);
+    if let Some(tracking) = TrackingLinks::new(&config.tracking, campaign.id, recipient) {
+        html = tracking.track_clicks(&html, &keep);
+        tracking.add_open_pixel(&mut html);
+    }
+    let email = OutgoingEmail {
+        from: sender.clone(),
+        to: recipient.to_string(),
+        subject: rendered.subject.clone(),
+        html,
+        text,
+        campaign: campaign.name.clone(),
+        tags: Vec::new(),
+        list_unsubscribe: Some(unsubscribe.header()),
+        attachments: Arc::from(attachments),
+        inline_images: Arc::from(template.images()),
+        message_id: new_message_id(sender.email.domain()),
+        references: Vec::new(),
+    };
+    let report = match spam::score(&config.spamassassin, &build_message(&email)?.formatted()).await {
+        Ok(Some(report)) => report,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            eprintln!("Couldn't check the spam score: {}", e);
+            return Ok(());
+        }
+    };
+    let threshold = config.spamassassin.threshold;
+    if report.score <= threshold {
+        println!("Spam score: {:.1} (threshold {:.1})", report.score, threshold);
+        return Ok(());
+    }
+    println!("Warning: spam score {:.1} is above the threshold of {:.1}; expect this email to be filtered as spam", report.score, threshold);
+    for rule in report.top_rules(5) {
+        println!("    {:>5.1} {} {}", rule.points, rule.name, rule.description);
+    }
+    Ok(())
+}
+
+// Campaigns are checked one after the other, each with its own limits; the
+// daily limit and sender accounts are shared across all of them.
+async fn run_follow_up(config: &Config, db: &str, args: &FollowUpArgs, shutdown: &Shutdown) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    let campaigns = match &args.campaign {
+        Some(name) => match store.find_campaign(name).await? {
+            Some(campaign) => vec![campaign],
+            None => return Err(BotError::InvalidData(format!("no campaign named \"{}\"", name))),
+        },
+        None => store.campaign_summaries().await?.into_iter().map(|summary| summary.campaign).collect(),
+    };
+    let mut settings = config.send.clone();
+    if let Some(max_per_day) = args.max_per_day {
+        settings.max_per_day = max_per_day;
+    }
+    let sender = settings.sender_mailbox()?;
+    let now = chrono::Utc::now();
+    let redis = ratelimit::connect(&config.redis).await?;
+    let retries = RetryQueue::new(redis.clone(), &config.retry);
+    let pending = retries.pending().await?;
+    let dead_letters = retries.dead_letters().await?;
+
+    // Templates are only loaded for campaigns with something due.
+    let mut sequences = Vec::new();
+    let mut total = 0;
+    for campaign in campaigns {
+        let follow_ups = store.campaign_follow_ups(campaign.id).await?;
+        let events = store.campaign_events(campaign.id).await?;
+        let of_campaign = |failed: &[FailedSend]| failed.iter().filter(|failed| failed.campaign_id == campaign.id).cloned().collect::<Vec<_>>();
+        let (pending, dead_letters) = (of_campaign(&pending), of_campaign(&dead_letters));
+        let due = with_failed_sends(due_emails(&events, &follow_ups, now), &events, &pending, &dead_letters, &follow_ups, now);
+        if due.is_empty() {
+            if args.campaign.is_some() && follow_ups.is_empty() {
+                println!("Campaign \"{}\" has no follow-ups; add one with `campaign add-follow-up`", campaign.name);
+            }
+            continue;
+        }
+        let soft_bounced = due.iter().filter(|email| email.retry == Some(RetryCause::SoftBounce)).count();
+        let failed = due.iter().filter(|email| matches!(email.retry, Some(RetryCause::Failed { .. }))).count();
+        let mut templates = HashMap::new();
+        for follow_up in &follow_ups {
+            let template = CampaignTemplate::load(&follow_up.template)?;
+            let subject = follow_up.reply_subject(&campaign.subject);
+            render_sample(config, &template, &subject, &scrape::Business::default(), &sender)?;
+            let count = due.iter().filter(|email| email.step == follow_up.step && email.retry.is_none()).count();
+            println!(
+                "Campaign \"{}\" follow-up {} ({} days, template {}): {} due",
+                campaign.name, follow_up.step, follow_up.delay_days, follow_up.template, count
+            );
+            templates.insert(follow_up.step, template);
+        }
+        if soft_bounced > 0 {
+            println!("Campaign \"{}\": {} soft-bounced emails to retry", campaign.name, soft_bounced);
+        }
+        if failed > 0 {
+            println!("Campaign \"{}\": {} failed sends to retry", campaign.name, failed);
+        }
+        total += due.len();
+        let first = load_variants(store.as_ref(), &campaign).await?;
+        sequences.push((campaign, follow_ups, templates, first, events, pending, dead_letters));
+    }
+    if total == 0 {
+        match pending.first() {
+            Some(next) => println!(
+                "No follow-ups are due; the next of {} failed sends is retried from {} UTC",
+                pending.len(),
+                next.retry_at().format("%Y-%m-%d %H:%M")
+            ),
+            None => println!("No follow-ups are due."),
+        }
+        return Ok(());
+    }
+    if !args.yes && !confirm(&format!("Do you want to send {} emails? (yes/no):", total))? {
+        println!("Aborted by user.");
+        return Ok(());
+    }
+
+    let mut limiter = ratelimit::open(config, &redis)?;
+    let suppression = SuppressionList::new(redis.clone());
+    let filter = EmailFilter::new(&config.filter, Vec::new(), false);
+    let mut senders = SenderPool::connect(config, &sender).await?;
+    let leads = store.load_businesses().await?;
+    if let Some(provider) = config.send.provider {
+        println!("Using the {} preset for what config.toml leaves out", provider.name());
+    }
+    println!("Sending from {}, {} apart", senders.describe(), email::describe_send_pause(&settings));
+    let schedule = SendWindow::from_config(&config.schedule)?;
+    if let Some(schedule) = &schedule {
+        println!("Sending only {}", schedule.describe());
+    }
+    // Carried from campaign to campaign, so a throttled run stays slowed down
+    // and the first email of a campaign still waits for the pause.
+    let mut throttle = Throttle::default();
+    let mut attempted = false;
+    for (campaign, follow_ups, templates, first, events, pending, dead_letters) in &sequences {
+        if shutdown.requested() {
+            break;
+        }
+        let due = with_failed_sends(due_emails(events, follow_ups, now), events, pending, dead_letters, follow_ups, now);
+        println!("Sending {} emails of campaign \"{}\" (#{})", due.len(), campaign.name, campaign.id);
+        let send_limit = campaign.remaining_sends(store.as_ref()).await?;
+        let mut context = email::SendContext {
+            senders: &mut senders,
+            settings: &settings,
+            limiter: limiter.as_mut(),
+            company: &config.company,
+            unsubscribe: &config.unsubscribe,
+            tracking: &config.tracking,
+            utm: &config.utm,
+            redis: &redis,
+            suppression: &suppression,
+            retries: &retries,
+            filter: &filter,
+            store: store.as_ref(),
+            campaign,
+            // Replies go without the campaign's attachments.
+            attachments: Arc::from([]),
+            contact_cooldown: None,
+            mode: SendMode::Live,
+            emailed: HashSet::new(),
+            resumed: Resumed::default(),
+            schedule: schedule.as_ref(),
+            throttle: std::mem::take(&mut throttle),
+            attempted,
+            shutdown: shutdown.clone(),
+        };
+        email::send_follow_ups(&mut context, &due, templates, first, &leads, send_limit).await?;
+        throttle = context.throttle;
+        attempted = context.attempted;
+    }
+    Ok(())
+}
+
+async fn run_campaign(db: &str, command: &CampaignCommand) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    match command {
+        CampaignCommand::Create { name, subject, template, location, category, source, max_per_day, max_sends } => {
+            if store.find_campaign(name).await?.is_some() {
+                return Err(BotError::InvalidData(format!("campaign \"{}\" already exists", name)));
+            }
+            let mut campaign = Campaign::new(name, subject);
+            campaign.template = template.clone();
+            campaign.filter = LeadFilter {
+                location: location.clone(),
+                category: category.clone(),
+                source: source.clone(),
+            };
+            campaign.max_per_day = max_per_day.map(i64::from);
+            campaign.max_sends = max_sends.map(i64::from);
+            campaign.validate()?;
+            campaign.load_template()?;
+            campaign.absolutize_template()?;
+            campaign.id = store.create_campaign(&campaign).await?;
+            println!("Created campaign \"{}\" (#{}, {})", campaign.name, campaign.id, campaign.describe());
+        }
+        CampaignCommand::AddVariant { campaign, name, subject, template, weight } => {
+            let Some(found) = store.find_campaign(campaign).await? else {
+                return Err(BotError::InvalidData(format!("no campaign named \"{}\"", campaign)));
+            };
+            if store.campaign_variants(found.id).await?.iter().any(|variant| variant.name == *name) {
+                return Err(BotError::InvalidData(format!("campaign \"{}\" already has a variant \"{}\"", campaign, name)));
+            }
+            let mut variant = Variant {
+                name: name.clone(),
+                subject: subject.clone().unwrap_or_else(|| found.subject.clone()),
+                template: template.clone().unwrap_or_else(|| found.template.clone()),
+                weight: *weight,
+            };
+            variant.validate()?;
+            CampaignTemplate::load(&variant.template)?;
+            variant.absolutize_template()?;
+            store.add_variant(found.id, &variant).await?;
+            let count = store.campaign_variants(found.id).await?.len();
+            println!("Added variant \"{}\" to campaign \"{}\" (weight {}, template {})", variant.name, found.name, variant.weight, variant.template);
+            if count < 2 {
+                println!("Add at least one more variant before sending; an A/B test needs two");
+            }
+        }
+        CampaignCommand::AddFollowUp { campaign, template, after_days, subject } => {
+            let Some(found) = store.find_campaign(campaign).await? else {
+                return Err(BotError::InvalidData(format!("no campaign named \"{}\"", campaign)));
+            };
+            let step = store.campaign_follow_ups(found.id).await?.len() as u32 + 1;
+            let mut follow_up = FollowUp { step, delay_days: *after_days, subject: subject.clone(), template: template.clone() };
+            follow_up.validate()?;
+            CampaignTemplate::load(&follow_up.template)?;
+            follow_up.absolutize_template()?;
+            store.add_follow_up(found.id, &follow_up).await?;
+            println!(
+                "Added follow-up {} to campaign \"{}\": {} days after the previous email, subject \"{}\", template {}",
+                follow_up.step,
+                found.name,
+                follow_up.delay_days,
+                follow_up.reply_subject(&found.subject),
+                follow_up.template
+            );
+        }
+        CampaignCommand::List => {
+            for summary in store.campaign_summaries().await? {
+                let campaign = &summary.campaign;
+                println!("#{} \"{}\" created {}: {}", campaign.id, campaign.name, campaign.created_at, campaign.describe());
+                println!("    Subject: {}", campaign.subject);
+                println!(
+                    "    {} sent, {} failed, {} skipped, {} bounced, {} opened ({:.1}%), {} clicked ({:.1}%), {} replied, {} unsubscribed",
+                    summary.sent,
+                    summary.failed,
+                    summary.skipped,
+                    summary.bounced,
+                    summary.opened,
+                    percent(summary.opened, summary.sent),
+                    summary.clicked,
+                    percent(summary.clicked, summary.sent),
+                    summary.replied,
+                    summary.unsubscribed
+                );
+                print_variants(store.as_ref(), campaign.id).await?;
+                for follow_up in store.campaign_follow_ups(campaign.id).await? {
+                    println!(
+                        "    follow-up {} after {} days (subject \"{}\", template {})",
+                        follow_up.step,
+                        follow_up.delay_days,
+                        follow_up.reply_subject(&campaign.subject),
+                        follow_up.template
+                    );
                 }
             }
         }
     }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), BotError> {
-    let client = reqwest::Client::new();
-    let mut processed_emails: HashSet<String> = HashSet::new();
-    let mut page_number = 1;
-    let mut has_more_pages = true;
-    let mut businesses: Vec<Business> = Vec::new();
-
-    // Establish Redis connection
-    let redis_client = redis::Client::open("redis://127.0.0.1/").map_err(BotError::RedisError)?;
-    let mut redis_con = redis_client.get_connection().map_err(BotError::RedisError)?;
-
-    let max_emails_per_day = 400;
-
-    while has_more_pages {
-        let list_page_url = format!(
-            "https://www.yellowpages.com/search?search_terms=Electricians&geo_location_terms=Columbus%2C+OH&page={}",
-            page_number
+fn percent(count: i64, total: i64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+// One line per A/B variant, with its share of the split.
+async fn print_variants(store: &dyn storage::LeadStore, campaign_id: i64) -> Result<(), BotError> {
+    let summaries = store.variant_summaries(campaign_id).await?;
+    let total_weight: i64 = summaries.iter().map(|summary| i64::from(summary.variant.weight)).sum();
+    for summary in &summaries {
+        println!(
+            "    variant \"{}\" ({:.0}% of sends, subject \"{}\"): {} sent, {} opened ({:.1}%), {} clicked ({:.1}%), {} replied ({:.1}%), {} bounced ({:.1}%)",
+            summary.variant.name,
+            percent(summary.variant.weight.into(), total_weight),
+            summary.variant.subject,
+            summary.sent,
+            summary.opened,
+            percent(summary.opened, summary.sent),
+            summary.clicked,
+            percent(summary.clicked, summary.sent),
+            summary.replied,
+            percent(summary.replied, summary.sent),
+            summary.bounced,
+            percent(summary.bounced, summary.sent)
         );
+    }
+    Ok(())
+}
 
-        let list_page_response = client.get(&list_page_url)
-            .send()
-            .await
-            .map_err(BotError::NetworkError)?
-            .text()
-            .await
-            .map_err(BotError::NetworkError)?;
-        
-        let list_page_document = Html::parse_document(&list_page_response);
-        let business_link_selector = Selector::parse("a.business-name").unwrap();
-        let business_links: Vec<_> = list_page_document.select(&business_link_selector).collect();
-
-        if business_links.is_empty() {
-            break;
+async fn run_validate(config: &Config, db: &str, args: &ValidateArgs) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    let businesses = load_leads(store.as_ref(), args.input.as_deref()).await?;
+    let (mut valid, invalid): (Vec<_>, Vec<_>) = businesses
+        .into_iter()
+        .map(|mut business| {
+            business.email = email::normalize_email(&business.email);
+            business
+        })
+        .partition(|business| email::is_valid_email(&business.email));
+
+    for business in &invalid {
+        println!("Invalid email: {} ({})", business.email, business.url);
+    }
+    println!("{} valid, {} invalid", valid.len(), invalid.len());
+
+    let filter = build_filter(config, &args.filter)?;
+    let before = valid.len();
+    valid.retain(|business| match filter.rejection(&business.email) {
+        Some(reason) => {
+            println!("Filtered out: {} ({})", business.email, reason);
+            false
         }
+        None => true,
+    });
+    if valid.len() < before {
+        println!("{} filtered out", before - valid.len());
+    }
 
-        for link_element in business_links {
-            if let Some(href) = link_element.value().attr("href") {
-                let detail_url = format!("https://www.yellowpages.com{}", href);
-                let detail_page_response = client.get(&detail_url)
-                    .send()
-                    .await
-                    .map_err(BotError::NetworkError)?
-                    .text()
-                    .await
-                    .map_err(BotError::NetworkError)?;
-                
-                let detail_page_document = Html::parse_document(&detail_page_response);
-                let email_selector = Selector::parse("a.email-business").unwrap();
-        
-                if let Some(email_element) = detail_page_document.select(&email_selector).next() {
-                    let email = if let Some(email_href) = email_element.value().attr("href") {
-                        if email_href.starts_with("mailto:") {
-                            let re = Regex::new(r"mailto:([^?]+)").unwrap();
-                            if let Some(caps) = re.captures(email_href) {
-                                caps.get(1).map_or("", |m| m.as_str()).to_string()
-                            } else {
-                                "".to_string()
-                            }
-                        } else {
-                            email_href.to_string()
-                        }
-                    } else {
-                        email_element.inner_html()
-                    };
-                
-                    if !processed_emails.contains(&email) {
-                        processed_emails.insert(email.clone());
-        
-                        println!("Business URL: {}", detail_url);
-                        println!("Business Email: {}", email);
-        
-                        businesses.push(Business {
-                            url: detail_url,
-                            email: email.clone(),
-                        });
-                    } else {
-                        println!("Duplicate email found, skipping: {}", email);
+    let mut keys = HashSet::new();
+    let before = valid.len();
+    valid.retain(|business| keys.insert(email::dedup_key(&business.email)));
+    if valid.len() < before {
+        println!("{} duplicate addresses removed", before - valid.len());
+    }
+
+    if !args.no_mx {
+        let validator = validation::MxValidator::new();
+        let mut checked = validator.check_businesses(valid, args.dns_concurrency).await;
+        if args.smtp_verify {
+            println!("Verifying mailboxes over SMTP");
+            let mail_from = config.send.sender_mailbox()?.email.to_string();
+            let verifier = validation::SmtpVerifier::new(&args.helo_name, &mail_from);
+            checked = verifier.verify_businesses(checked, args.smtp_concurrency).await;
+        }
+
+        // Leads read from the database get their mx_valid / smtp_status marks saved back.
+        if args.input.is_none() {
+            let marked: Vec<scrape::Business> = checked.iter().map(|(business, _)| business.clone()).collect();
+            store.upsert_businesses(&marked).await?;
+        }
+
+        let mut undeliverable = 0;
+        let mut unknown = 0;
+        valid = Vec::new();
+        for (business, status) in checked {
+            match status {
+                validation::MxStatus::NoMail => {
+                    println!("No mail server for: {}", business.email);
+                    undeliverable += 1;
+                    if !args.keep_undeliverable {
+                        continue;
                     }
                 }
-        
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                validation::MxStatus::Unknown(e) => {
+                    println!("MX lookup failed for {}: {}", business.email, e);
+                    unknown += 1;
+                }
+                _ => {}
+            }
+            match business.smtp_status {
+                Some(validation::SmtpStatus::Undeliverable) => {
+                    undeliverable += 1;
+                    if !args.keep_undeliverable {
+                        continue;
+                    }
+                }
+                Some(validation::SmtpStatus::Unknown) => unknown += 1,
+                _ => {}
             }
+            valid.push(business);
         }
+        println!("{} undeliverable, {} could not be checked", undeliverable, unknown);
+    }
 
-        page_number += 1;
+    if let Some(output) = &args.output {
+        storage::save_leads_file(output, &valid)?;
+        println!("Saved {} leads to {}", valid.len(), output);
     }
+    Ok(())
+}
 
-    let json_data = serde_json::to_string_pretty(&businesses).map_err(BotError::DataParseError)?;
-    let mut file = File::create("business_emails.json").map_err(BotError::IOError)?;
-    file.write_all(json_data.as_bytes()).map_err(BotError::IOError)?;
+async fn run_stats(config: &Config, db: &str, max_per_day: usize) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    println!("Leads in {}: {}", db, store.count_businesses().await?);
+    for summary in store.campaign_summaries().await? {
+        println!(
+            "Campaign #{} \"{}\" ({}): {} sent, {} failed, {} skipped, {} bounced, {} opened ({:.1}%), {} clicked ({:.1}%), {} replied, {} unsubscribed",
+            summary.campaign.id,
+            summary.campaign.name,
+            summary.campaign.created_at,
+            summary.sent,
+            summary.failed,
+            summary.skipped,
+            summary.bounced,
+            summary.opened,
+            percent(summary.opened, summary.sent),
+            summary.clicked,
+            percent(summary.clicked, summary.sent),
+            summary.replied,
+            summary.unsubscribed
+        );
+        print_variants(store.as_ref(), summary.campaign.id).await?;
+    }
 
-    dotenv().expect(".env file not found");
+    let redis = ratelimit::connect(&config.redis).await?;
+    let mut limiter = ratelimit::open(config, &redis)?;
+    let limits = limiter.limits();
+    let sent_today = ratelimit::emails_sent_today(limiter.as_mut()).await?;
+    match limits.strategy {
+        LimitStrategy::Fixed => println!("Emails sent today ({} {}): {}/{}", limits.today(), limits.timezone.name(), sent_today, max_per_day),
+        LimitStrategy::Sliding => println!("Emails sent in the last 24 hours: {}/{}", sent_today, max_per_day),
+    }
+    for (window, max) in [(Window::Hour, config.send.max_per_hour), (Window::Minute, config.send.max_per_minute)] {
+        if let Some(max) = max {
+            let sent = ratelimit::window_sent(limiter.as_mut(), window).await?;
+            match limits.strategy {
+                LimitStrategy::Fixed => println!("Emails sent this {}: {}/{}", window.name(), sent, max),
+                LimitStrategy::Sliding => println!("Emails sent in the last {}: {}/{}", window.name(), sent, max),
+            }
+        }
+    }
+    for account in &config.accounts {
+        let sent = ratelimit::account_sent_today(limiter.as_mut(), &account.name).await?;
+        match account.max_per_day {
+            Some(max_per_day) => println!("  from {}: {}/{}", account.name, sent, max_per_day),
+            None => println!("  from {}: {}", account.name, sent),
+        }
+    }
+    // The sender's address names its counters when there are no accounts.
+    let sender = config.send.sender_mailbox()?.email.to_string();
+    let warming_up: Vec<&str> = if config.accounts.is_empty() {
+        config.warmup.enabled.then_some(sender.as_str()).into_iter().collect()
+    } else {
+        config.accounts.iter().filter(|account| account.warmup.unwrap_or(config.warmup.enabled)).map(|account| account.name.as_str()).collect()
+    };
+    for name in warming_up {
+        let day = ratelimit::warmup_day(limiter.as_mut(), name, false).await?;
+        println!("Warm-up of {}: day {}, up to {} emails today", name, day + 1, config.warmup.limit(day));
+    }
+    let queue = LeadQueue::new(redis.clone());
+    println!("Leads waiting in the queue: {}", queue.len().await?);
+    let retries = RetryQueue::new(redis.clone(), &config.retry);
+    println!("Failed sends waiting to be retried: {}", retries.pending().await?.len());
+    println!("Dead letters: {}", retries.dead_letters().await?.len());
+    let suppressed = SuppressionList::new(redis).len().await?;
+    println!("Suppressed addresses: {}", suppressed);
+    Ok(())
+}
 
-    let email_sender = "coffeecodestudio.dev@gmail.com";
-    let email_password = env::var("EMAIL_PASSWORD").expect("EMAIL_PASSWORD not set");
-    let creds = Credentials::new(email_sender.to_string(), email_password);
-    let mailer = SmtpTransport::relay("smtp.gmail.com")?.credentials(creds).build();
-    
-    let subject = "Grow Your Business with Coffee Code Studio - Special Offer Inside!".to_string();
+async fn run_export(db: &str, output: Option<&str>, format: Option<LeadFormat>) -> Result<(), BotError> {
+    let (format, output) = LeadFormat::resolve(format, output);
+    let store = storage::open_store(db).await?;
+    let businesses = store.load_businesses().await?;
+    match format {
+        LeadFormat::Json => storage::save_businesses(&output, &businesses)?,
+        LeadFormat::Csv => storage::save_businesses_csv(&output, &businesses)?,
+    }
+    println!("Exported {} leads to {}", businesses.len(), output);
+    Ok(())
+}
 
-    let email_template = EmailTemplate {
-        subject: subject.clone(),
+async fn run_import(db: &str, input: Option<&str>, format: Option<LeadFormat>) -> Result<(), BotError> {
+    let (format, input) = LeadFormat::resolve(format, input);
+    let store = storage::open_store(db).await?;
+    let businesses = match format {
+        LeadFormat::Json => storage::load_businesses(&input)?,
+        LeadFormat::Csv => storage::load_businesses_csv(&input)?,
     };
+    let inserted = store.upsert_businesses(&businesses).await?;
+    println!("Imported {} leads from {} ({} new)", businesses.len(), input, inserted);
+    Ok(())
+}
 
-    let email_content = email_template.render().map_err(BotError::TemplateError)?;
+async fn run_mark(config: &Config, db: &str, status: RecipientStatus, emails: &[String], campaign: Option<&str>) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    let campaign_id = match campaign {
+        Some(name) => Some(
+            store
+                .find_campaign(name)
+                .await?
+                .ok_or_else(|| BotError::InvalidData(format!("no campaign named \"{}\"", name)))?
+                .id,
+        ),
+        None => None,
+    };
+    let suppression = match status.suppression_reason() {
+        Some(_) => Some(SuppressionList::new(ratelimit::connect(&config.redis).await?)),
+        None => None,
+    };
 
-    println!("Email content preview:");
-    println!("Subject: {}", subject);
-    println!("Content: {}", email_content);
-    println!("-------------------------");
+    for email in emails {
+        let email = email::normalize_email(email);
+        let last_sent = storage::last_campaign_sent(store.as_ref(), &email).await?;
+        let Some(campaign_id) = campaign_id.or(last_sent) else {
+            eprintln!("No campaign has emailed {}, pass --campaign to record it anyway", email);
+            continue;
+        };
+        store.record_send(campaign_id, &email, status.as_str(), None, None).await?;
+        println!("Marked {} as {} (campaign #{})", email, status.as_str(), campaign_id);
+        if let (Some(suppression), Some(reason)) = (&suppression, status.suppression_reason()) {
+            if suppression.add(&email, reason).await? {
+                println!("Suppressed: {}", email);
+            }
+        }
+    }
+    Ok(())
+}
 
-    println!("Do you want to proceed with sending emails? (yes/no):");
-    let mut confirmation = String::new();
-    std::io::stdin().read_line(&mut confirmation).map_err(BotError::IOError)?;
-    if confirmation.trim().to_lowercase() != "yes" {
-        println!("Aborted by user.");
+// With `interval_secs`, checks again after every pause until stopped.
+async fn run_inbox(config: &Config, db: &str, since_days: u32, interval_secs: Option<u64>) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    let username = match &config.imap.username {
+        Some(username) => username.clone(),
+        None => config.send.sender_mailbox()?.email.to_string(),
+    };
+    let password = match &config.imap.password {
+        Some(password) => password.as_str(),
+        None => config.smtp.password().map_err(|_| {
+            BotError::ConfigError("imap.password is not set; set IMAP_PASSWORD or [imap] password in config.toml".to_string())
+        })?,
+    };
+    let suppression = SuppressionList::new(ratelimit::connect(&config.redis).await?);
+    let shutdown = shutdown::listen();
+    loop {
+        let since = (chrono::Utc::now() - chrono::Duration::days(since_days.into())).date_naive();
+        // The imap client blocks, so it runs off the runtime's own threads.
+        let (imap, username, password) = (config.imap.clone(), username.clone(), password.to_string());
+        let messages = tokio::task::spawn_blocking(move || inbox::fetch_messages(&imap, &username, &password, since)).await.map_err(std::io::Error::from)??;
+        let (mut replies, mut hard_bounces, mut soft_bounces) = (0, 0, 0);
+        for fetched in &messages {
+            let message = match inbox::parse_headers(&fetched.headers) {
+                Ok(message) => message,
+                Err(e) => {
+                    eprintln!("Skipping a message: {}", e);
+                    continue;
+                }
+            };
+            if message.delivery_report {
+                let bounces = match fetched.full.as_deref().map(inbox::parse_bounces).transpose() {
+                    Ok(bounces) => bounces.unwrap_or_default(),
+                    Err(e) => {
+                        eprintln!("Skipping a delivery report: {}", e);
+                        continue;
+                    }
+                };
+                for bounce in &bounces {
+                    let matched = inbox::match_bounce(store.as_ref(), bounce).await?;
+                    let email = matched.as_ref().map_or(bounce.recipient.as_str(), |(_, email)| email.as_str());
+                    // Nothing will reach a hard-bounced address, whichever email found that out.
+                    if bounce.is_hard() && suppression.add(email, REASON_BOUNCE).await? {
+                        println!("Suppressed: {}", email);
+                    }
+                    let Some((campaign_id, _)) = matched else {
+                        continue;
+                    };
+                    if inbox::record_bounce(store.as_ref(), campaign_id, email, bounce, message.date).await? {
+                        let kind = if bounce.is_hard() { "Hard" } else { "Soft" };
+                        println!("{} bounce for {} in campaign #{}: {}", kind, email, campaign_id, bounce.describe());
+                        if bounce.is_hard() {
+                            hard_bounces += 1;
+                        } else {
+                            soft_bounces += 1;
+                        }
+                    }
+                }
+                continue;
+            }
+            // Not someone answering.
+            if message.automatic {
+                continue;
+            }
+            let Some(reply) = inbox::match_reply(store.as_ref(), &message).await? else {
+                continue;
+            };
+            if inbox::record_reply(store.as_ref(), &reply).await? {
+                let matched_by = if reply.by_message_id { "Message-ID" } else { "sender" };
+                println!("Reply from {} to campaign #{} (matched by {}): {}", reply.email, reply.campaign_id, matched_by, message.subject);
+                replies += 1;
+            }
+        }
+        println!(
+            "Checked {} messages in {}, recorded {} new replies, {} hard and {} soft bounces",
+            messages.len(),
+            config.imap.mailbox,
+            replies,
+            hard_bounces,
+            soft_bounces
+        );
+        let Some(interval_secs) = interval_secs else {
+            return Ok(());
+        };
+        shutdown.sleep(Duration::from_secs(interval_secs)).await;
+        if shutdown.requested() {
+            return Ok(());
+        }
+    }
+}
+
+async fn run_doctor(config: &Config, selectors: &[String], strict: bool) -> Result<(), BotError> {
+    let mut domains = vec![config.send.sender_mailbox()?.email.domain().to_lowercase()];
+    for account in &config.accounts {
+        let domain = account.sender_mailbox()?.email.domain().to_lowercase();
+        if !domains.contains(&domain) {
+            domains.push(domain);
+        }
+    }
+    let signs = matches!(config.send.transport, TransportKind::Smtp | TransportKind::Sandbox);
+    if !signs && config.dkim.selector.is_some() {
+        println!("[dkim] only signs mail sent over SMTP; the API providers sign with the keys set up in their dashboards");
+    }
+    let doctor = Doctor::new();
+    let mut problems = 0;
+    for domain in &domains {
+        let signing = config.dkim.selector.as_deref().filter(|_| signs).map(|selector| Signing {
+            selector,
+            domain: config.dkim.domain.as_deref().unwrap_or(domain),
+            algorithm: config.dkim.algorithm,
+        });
+        println!("{}:", domain);
+        for finding in doctor.check(&DomainChecks { domain, signing, selectors }).await {
+            println!("    {:<8} {:<12} {}", finding.severity.as_str(), finding.check, finding.message);
+            if finding.severity == Severity::Problem {
+                problems += 1;
+            }
+        }
+    }
+    if problems == 0 {
+        println!("No problems found");
         return Ok(());
     }
+    println!("{} problems found; expect mail from these domains to be rejected or marked as spam", problems);
+    if strict {
+        return Err(BotError::ConfigError(format!("{} DNS problems with the sending domains", problems)));
+    }
+    Ok(())
+}
 
-    for business in &businesses {
-        if !business.email.contains('@') {
-            println!("Invalid email skipped: {}", business.email);
+async fn run_history(db: &str, emails: &[String]) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    for email in emails {
+        let events = store.send_history(email).await?;
+        if events.is_empty() {
+            println!("{}: never queued", email);
             continue;
         }
+        println!("{}:", email);
+        for event in events {
+            match &event.error {
+                Some(error) => println!(
+                    "    {} {} in #{} \"{}\" ({})",
+                    event.created_at, event.status, event.campaign_id, event.campaign_name, error
+                ),
+                None => println!("    {} {} in #{} \"{}\"", event.created_at, event.status, event.campaign_id, event.campaign_name),
+            }
+        }
+    }
+    Ok(())
+}
 
-        if check_update_email_count(&mut redis_con, max_emails_per_day)? {
-            let email = Message::builder()
-                .from(email_sender.parse().unwrap())
-                .to(business.email.parse().unwrap())
-                .subject(&subject)
-                .header(ContentType::TEXT_HTML) 
-                .body(email_content.clone())
-                .map_err(BotError::EmailError)?;
+async fn run_crm(db: &str, command: &CrmCommand) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    match command {
+        CrmCommand::Hubspot { command: HubspotCommand::Push { token, approved_only } } => {
+            let mut businesses = store.load_businesses().await?;
+            if *approved_only {
+                businesses.retain(|business| business.review_status == Some(scrape::ReviewStatus::Approved));
+            }
+            // The latest outreach result per lead; queued, skipped and failed sends don't count.
+            let mut statuses = Vec::with_capacity(businesses.len());
+            for business in &businesses {
+                let status = store
+                    .send_history(&business.email)
+                    .await?
+                    .into_iter()
+                    .rev()
+                    .map(|event| event.status)
+                    .find(|status| storage::CONTACTED_STATUSES.contains(&status.as_str()));
+                statuses.push(status);
+            }
+            let leads: Vec<(&scrape::Business, Option<&str>)> =
+                businesses.iter().zip(&statuses).map(|(business, status)| (business, status.as_deref())).collect();
 
+            println!("Pushing {} leads to HubSpot", leads.len());
+            let summary = HubspotClient::new(token)?.push(&leads).await?;
+            println!(
+                "Upserted {} contacts; {} companies created, {} updated",
+                summary.contacts, summary.companies_created, summary.companies_updated
+            );
+        }
+    }
+    Ok(())
+}
 
-            match mailer.send(&email) {
-                Ok(_) => println!("Email sent successfully to: {}", business.email),
-                Err(e) => eprintln!("Could not send email to: {}: {:?}", business.email, e),
-            }
+async fn run_sheets(db: &str, command: &SheetsCommand) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    match command {
+        SheetsCommand::Push(args) => {
+            let sheets = SheetsClient::connect(&args.credentials, &args.spreadsheet).await?;
+            let businesses = store.load_businesses().await?;
+            let written = sheets.push(&args.sheet, &businesses).await?;
+            println!("Pushed {} leads to sheet \"{}\"", written, args.sheet);
+        }
+        SheetsCommand::Pull(args) => {
+            let sheets = SheetsClient::connect(&args.credentials, &args.spreadsheet).await?;
+            let reviews = sheets.pull(&args.sheet).await?;
+            apply_reviews(store.as_ref(), reviews).await?;
+        }
+    }
+    Ok(())
+}
 
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-        } else {
-            println!("Reached the daily limit of max emails sent.");
-            break;
+async fn run_airtable(db: &str, command: &AirtableCommand) -> Result<(), BotError> {
+    let store = storage::open_store(db).await?;
+    match command {
+        AirtableCommand::Push(args) => {
+            let airtable = AirtableClient::new(&args.token, &args.base, &args.table)?;
+            let businesses = store.load_businesses().await?;
+            let (created, updated) = airtable.push(&businesses).await?;
+            println!("Pushed {} leads to table \"{}\" ({} new, {} updated)", created + updated, args.table, created, updated);
+        }
+        AirtableCommand::Pull(args) => {
+            let airtable = AirtableClient::new(&args.token, &args.base, &args.table)?;
+            let reviews = airtable.pull().await?;
+            apply_reviews(store.as_ref(), reviews).await?;
+        }
+    }
+    Ok(())
+}
+
+// Stores what reviewers decided in a shared sheet or table.
+async fn apply_reviews(store: &dyn storage::LeadStore, reviews: Vec<(String, Option<scrape::ReviewStatus>)>) -> Result<(), BotError> {
+    let (mut approved, mut rejected, mut pending, mut unknown) = (0, 0, 0, 0);
+    for (email, review) in reviews {
+        if !store.set_review_status(&email, review).await? {
+            unknown += 1;
+            continue;
+        }
+        match review {
+            Some(scrape::ReviewStatus::Approved) => approved += 1,
+            Some(scrape::ReviewStatus::Rejected) => rejected += 1,
+            None => pending += 1,
+        }
+    }
+    println!("{} approved, {} rejected, {} not reviewed yet", approved, rejected, pending);
+    if unknown > 0 {
+        println!("{} rows don't match a stored lead and were ignored", unknown);
+    }
+    Ok(())
+}
+
+async fn run_serve(config: &Config, db: &str, listen: std::net::SocketAddr) -> Result<(), BotError> {
+    let unsubscribe_secret = config.unsubscribe.secret.as_deref().filter(|secret| !secret.trim().is_empty());
+    let tracking_secret = config.tracking.secret.as_deref().filter(|secret| !secret.trim().is_empty());
+    if unsubscribe_secret.is_none() && tracking_secret.is_none() {
+        return Err(BotError::ConfigError(
+            "nothing to serve; set UNSUBSCRIBE_SECRET or TRACKING_SECRET to the secret the links were signed with".to_string(),
+        ));
+    }
+    match unsubscribe_secret {
+        Some(_) if config.unsubscribe.url.is_none() => {
+            println!("unsubscribe.url is not set, so emails only carry mailto unsubscribe links for now")
+        }
+        Some(_) => {}
+        None => println!("unsubscribe.secret is not set, so unsubscribe links aren't served"),
+    }
+    if tracking_secret.is_some() {
+        println!("Recording opens and clicks from tracking links");
+    }
+    let store = storage::open_store(db).await?;
+    let suppression = SuppressionList::new(ratelimit::connect(&config.redis).await?);
+    LinkServer::new(unsubscribe_secret, tracking_secret, suppression, store).run(listen, shutdown::listen()).await
+}
+
+async fn run_suppress(config: &Config, command: &SuppressCommand) -> Result<(), BotError> {
+    let suppression = SuppressionList::new(ratelimit::connect(&config.redis).await?);
+    match command {
+        SuppressCommand::Import { file, reason } => {
+            let (added, existing) = suppression.import_file(file, reason).await?;
+            println!("Suppressed {} new addresses from {} ({} already suppressed)", added, file, existing);
+        }
+        SuppressCommand::Add { emails, reason } => {
+            for email in emails {
+                if suppression.add(email, reason).await? {
+                    println!("Suppressed: {}", email);
+                } else {
+                    println!("Already suppressed: {}", email);
+                }
+            }
+        }
+        SuppressCommand::Remove { emails } => {
+            for email in emails {
+                if suppression.remove(email).await? {
+                    println!("Removed: {}", email);
+                } else {
+                    println!("Not suppressed: {}", email);
+                }
+            }
+        }
+        SuppressCommand::Check { emails } => {
+            for email in emails {
+                match suppression.reason(email).await? {
+                    Some(reason) => println!("{}: suppressed ({})", email, reason),
+                    None => println!("{}: not suppressed", email),
+                }
+            }
         }
     }
+    Ok(())
+}
 
+async fn run_dead_letters(config: &Config, db: &str, command: &DeadLetterCommand) -> Result<(), BotError> {
+    let retries = RetryQueue::new(ratelimit::connect(&config.redis).await?, &config.retry);
+    match command {
+        DeadLetterCommand::List => {
+            let dead_letters = retries.dead_letters().await?;
+            if dead_letters.is_empty() {
+                println!("No dead letters.");
+                return Ok(());
+            }
+            let store = storage::open_store(db).await?;
+            let names: HashMap<i64, String> =
+                store.campaign_summaries().await?.into_iter().map(|summary| (summary.campaign.id, summary.campaign.name)).collect();
+            for failed in &dead_letters {
+                let campaign = names.get(&failed.campaign_id).map_or_else(|| format!("#{}", failed.campaign_id), |name| format!("\"{}\"", name));
+                println!(
+                    "{}: campaign {} step {}, attempt {} failed at {} UTC: {}",
+                    failed.email,
+                    campaign,
+                    failed.step,
+                    failed.attempts,
+                    failed.failed_at().format("%Y-%m-%d %H:%M"),
+                    failed.error
+                );
+            }
+            println!("{} dead letters", dead_letters.len());
+        }
+        DeadLetterCommand::Retry { emails, .. } => {
+            let requeued = retries.requeue(emails).await?;
+            println!("Requeued {} dead letters; the next `follow-up` sends them", requeued);
+        }
+        DeadLetterCommand::Clear { emails, .. } => {
+            let cleared = retries.clear(emails).await?;
+            println!("Deleted {} dead letters", cleared);
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+fn confirm(prompt: &str) -> Result<bool, BotError> {
+    println!("{}", prompt);
+    let mut confirmation = String::new();
+    std::io::stdin().read_line(&mut confirmation).map_err(BotError::IOError)?;
+    Ok(confirmation.trim().to_lowercase() == "yes")
+}
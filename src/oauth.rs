@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use crate::config::OAuth2Config;
+use crate::error::BotError;
+
+// Tokens are renewed this long before they expire so none runs out mid-send.
+const REFRESH_MARGIN: Duration = Duration::from_secs(300);
+// Used when the token endpoint doesn't say how long the token lasts.
+const DEFAULT_TOKEN_LIFETIME_SECS: u64 = 3600;
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+pub struct AccessToken {
+    pub token: String,
+    expires_at: Instant,
+}
+
+impl AccessToken {
+    pub fn needs_refresh(&self) -> bool {
+        Instant::now() + REFRESH_MARGIN >= self.expires_at
+    }
+}
+
+// Exchanges the configured refresh token for a new access token.
+pub async fn refresh_access_token(client: &reqwest::Client, config: &OAuth2Config) -> Result<AccessToken, BotError> {
+    let response = client
+        .post(&config.token_url)
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", config.client_id.as_str()),
+            ("client_secret", config.client_secret.as_str()),
+            ("refresh_token", config.refresh_token.as_str()),
+        ])
+        .send()
+        .await
+        .map_err(BotError::NetworkError)?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(BotError::HttpStatus { url: config.token_url.clone(), status: status.as_u16() });
+    }
+    let token: TokenResponse = response.json().await.map_err(BotError::NetworkError)?;
+    let lifetime = Duration::from_secs(token.expires_in.unwrap_or(DEFAULT_TOKEN_LIFETIME_SECS));
+    Ok(AccessToken {
+        token: token.access_token,
+        expires_at: Instant::now() + lifetime,
+    })
+}
@@ -0,0 +1,123 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::pin;
+use futures::stream::{self, StreamExt};
+use tokio::sync::mpsc;
+use crate::email;
+use crate::error::BotError;
+use crate::queue::LeadQueue;
+use crate::scrape::Business;
+use crate::shutdown::Shutdown;
+use crate::storage::LeadStore;
+use crate::validation::{MxStatus, MxValidator};
+
+// Leads a channel between two stages holds before the stage feeding it waits.
+// A slow send stage only holds up scraping once this many are waiting.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1000;
+
+// Runs one of the stages run side by side on a task of its own, so a stage
+// that waits on a slow server holds up none of the others, and logs how it
+// ended. A stage that fails or panics asks the shutdown, so the other stages
+// stop after the lead in hand, and drops its channels, so the stages feeding
+// it stop at their next lead.
+pub async fn stage<T: Send + 'static>(name: &str, shutdown: &Shutdown, work: impl Future<Output = Result<T, BotError>> + Send + 'static) -> Option<T> {
+    let failure = match tokio::spawn(work).await {
+        Ok(Ok(value)) => {
+            println!("The {} stage is done", name);
+            return Some(value);
+        }
+        Ok(Err(e)) => e.to_string(),
+        Err(e) => e.to_string(),
+    };
+    eprintln!("The {} stage failed: {}", name, failure);
+    shutdown.request();
+    None
+}
+
+pub struct ValidateOptions {
+    pub check_mx: bool,
+    pub keep_undeliverable: bool,
+    // DNS lookups in flight at once.
+    pub concurrency: usize,
+}
+
+fn closed(stage: &str) -> BotError {
+    BotError::Cancelled(format!("the {} stage stopped taking leads", stage))
+}
+
+// Passes on each address once, normalized, when it's well-formed and, unless
+// check_mx is off, its domain has a mail server; the lookups for several
+// leads run at once and a lead goes on as soon as its own is done.
+pub async fn validate(leads: mpsc::Receiver<Business>, valid: mpsc::Sender<Business>, options: &ValidateOptions) -> Result<(), BotError> {
+    let validator = MxValidator::new();
+    let mut keys = HashSet::new();
+    let checked = stream::unfold(leads, |mut leads| async move { leads.recv().await.map(|business| (business, leads)) })
+        .filter_map(|mut business| {
+            business.email = email::normalize_email(&business.email);
+            let keep = if !email::is_valid_email(&business.email) {
+                println!("Invalid email: {} ({})", business.email, business.url);
+                false
+            } else if !keys.insert(email::dedup_key(&business.email)) {
+                println!("Duplicate email found, skipping: {}", business.email);
+                false
+            } else {
+                true
+            };
+            async move { keep.then_some(business) }
+        })
+        .map(|mut business| {
+            let validator = &validator;
+            async move {
+                if !options.check_mx {
+                    return (business, None);
+                }
+                let status = validator.check_email(&business.email).await;
+                business.mx_valid = status.accepts_mail();
+                (business, Some(status))
+            }
+        })
+        .buffer_unordered(options.concurrency.max(1));
+    let mut checked = pin!(checked);
+    while let Some((business, status)) = checked.next().await {
+        match status {
+            Some(MxStatus::NoMail) => {
+                println!("No mail server for: {}", business.email);
+                if !options.keep_undeliverable {
+                    continue;
+                }
+            }
+            Some(MxStatus::Unknown(e)) => println!("MX lookup failed for {}: {}", business.email, e),
+            _ => {}
+        }
+        valid.send(business).await.map_err(|_| closed("enqueue"))?;
+    }
+    Ok(())
+}
+
+// Stores each lead as it comes and hands it on to the send stage, and to the
+// Redis queue's workers when given one. Once the send stage has stopped, for
+// a limit or an error, the rest are only stored, for a later `send`.
+pub async fn enqueue(
+    mut leads: mpsc::Receiver<Business>,
+    store: &dyn LeadStore,
+    queue: Option<&LeadQueue>,
+    mut send: Option<mpsc::Sender<Business>>,
+) -> Result<(), BotError> {
+    let mut total = 0;
+    let mut inserted = 0;
+    while let Some(business) = leads.recv().await {
+        inserted += store.upsert_businesses(std::slice::from_ref(&business)).await?;
+        total += 1;
+        if let Some(queue) = queue {
+            queue.push(&business).await?;
+        }
+        if let Some(next) = &send {
+            if next.send(business).await.is_err() {
+                println!("The send stage has stopped; storing the remaining leads for a later `send`");
+                send = None;
+            }
+        }
+    }
+    println!("Stored {} leads ({} new)", total, inserted);
+    Ok(())
+}
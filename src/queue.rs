@@ -0,0 +1,186 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use redis::streams::{StreamClaimReply, StreamId, StreamPendingCountReply, StreamReadOptions, StreamReadReply};
+use redis::AsyncCommands;
+use crate::error::BotError;
+use crate::ratelimit::{connection, RedisPool};
+use crate::scrape::Business;
+
+const LEAD_STREAM_KEY: &str = "queue:lead_stream";
+const SENDER_GROUP: &str = "senders";
+const LEAD_FIELD: &str = "lead";
+// Well past what a worker takes over one batch of leads, pauses included.
+pub const DEFAULT_CLAIM_AFTER_MINUTES: u64 = 60;
+
+// Leads waiting for a sender, so scraping and sending can run as separate
+// processes on as many machines as needed: `scrape --enqueue` and
+// `crawl --enqueue` add each lead as it's found, and `send --from-queue`
+// workers read them off as one Redis Streams consumer group, so each lead is
+// delivered to one worker only.
+pub struct LeadQueue {
+    pool: RedisPool,
+}
+
+impl LeadQueue {
+    pub fn new(pool: RedisPool) -> Self {
+        LeadQueue { pool }
+    }
+
+    pub async fn push(&self, business: &Business) -> Result<(), BotError> {
+        let entry = serde_json::to_string(business).map_err(BotError::DataParseError)?;
+        let mut con = connection(&self.pool).await?;
+        con.xadd::<_, _, _, _, ()>(LEAD_STREAM_KEY, "*", &[(LEAD_FIELD, entry)]).await.map_err(BotError::RedisError)
+    }
+
+    // Leads added and not yet handled by a worker, including those a worker
+    // has taken and is still working on.
+    pub async fn len(&self) -> Result<usize, BotError> {
+        let mut con = connection(&self.pool).await?;
+        con.xlen(LEAD_STREAM_KEY).await.map_err(BotError::RedisError)
+    }
+
+    pub async fn is_empty(&self) -> Result<bool, BotError> {
+        Ok(self.len().await? == 0)
+    }
+}
+
+// The host name and process id, so workers on the same machine each have
+// their own. A worker restarted under it gets a new one, and the leads it
+// left pending are claimed once they've gone untouched for long enough.
+pub fn default_worker_name() -> String {
+    format!("{}-{}", host_name(), std::process::id())
+}
+
+pub fn host_name() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "sender".to_string())
+}
+
+// A lead a worker took off the queue; it stays pending under the worker's
+// name until acknowledged.
+#[derive(Debug, Clone)]
+pub struct QueuedLead {
+    pub id: String,
+    pub business: Business,
+}
+
+fn parse_leads(entries: Vec<StreamId>) -> (Vec<QueuedLead>, Vec<String>) {
+    let mut leads = Vec::new();
+    let mut broken = Vec::new();
+    for entry in entries {
+        match entry.get::<String>(LEAD_FIELD).map(|lead| serde_json::from_str(&lead)) {
+            Some(Ok(business)) => leads.push(QueuedLead { id: entry.id, business }),
+            _ => {
+                eprintln!("Dropping queue entry {}, which doesn't hold a lead", entry.id);
+                broken.push(entry.id);
+            }
+        }
+    }
+    (leads, broken)
+}
+
+// One `send --from-queue` process in the consumer group. A lead it takes
+// is only acknowledged once handled, so the leads of a worker that crashed
+// or stopped at a limit are taken again: by the worker itself when it comes
+// back under the same name, or by any worker once they've gone untouched for
+// `claim_after`, as are those it held back for their domain's daily cap. A
+// lead claimed after it was sent but before it was acknowledged isn't
+// emailed twice, since the contacted check skips it.
+pub struct QueueWorker {
+    pool: RedisPool,
+    name: String,
+    claim_after: Duration,
+    // Whether the leads an earlier run under this name left pending were read;
+    // those past the first batch are claimed with the idle ones.
+    resumed: AtomicBool,
+}
+
+impl QueueWorker {
+    pub async fn new(pool: RedisPool, name: &str, claim_after: Duration) -> Result<Self, BotError> {
+        let mut con = connection(&pool).await?;
+        let created: Result<(), _> = con.xgroup_create_mkstream(LEAD_STREAM_KEY, SENDER_GROUP, "0").await;
+        if let Err(e) = created {
+            if e.code() != Some("BUSYGROUP") {
+                return Err(BotError::RedisError(e));
+            }
+        }
+        Ok(QueueWorker { pool, name: name.to_string(), claim_after, resumed: AtomicBool::new(false) })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Up to `max` leads: first those an earlier run of this worker took and
+    // didn't acknowledge, then those left untouched for too long, then new
+    // ones. Empty when there are none of any.
+    pub async fn take(&self, max: usize) -> Result<Vec<QueuedLead>, BotError> {
+        for step in 0..3 {
+            let entries = match step {
+                0 if self.resumed.swap(true, Ordering::Relaxed) => Vec::new(),
+                0 => self.read_own(max).await?,
+                1 => self.claim_idle(max).await?,
+                _ => self.read_new(max).await?,
+            };
+            let (leads, broken) = parse_leads(entries);
+            self.ack(&broken).await?;
+            if !leads.is_empty() {
+                return Ok(leads);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    async fn read(&self, id: &str, max: usize) -> Result<Vec<StreamId>, BotError> {
+        let mut con = connection(&self.pool).await?;
+        let options = StreamReadOptions::default().group(SENDER_GROUP, &self.name).count(max);
+        let reply: Option<StreamReadReply> = con.xread_options(&[LEAD_STREAM_KEY], &[id], &options).await.map_err(BotError::RedisError)?;
+        Ok(reply.into_iter().flat_map(|reply| reply.keys).flat_map(|key| key.ids).collect())
+    }
+
+    async fn read_own(&self, max: usize) -> Result<Vec<StreamId>, BotError> {
+        self.read("0", max).await
+    }
+
+    async fn read_new(&self, max: usize) -> Result<Vec<StreamId>, BotError> {
+        self.read(">", max).await
+    }
+
+    async fn claim_idle(&self, max: usize) -> Result<Vec<StreamId>, BotError> {
+        let mut con = connection(&self.pool).await?;
+        let pending: StreamPendingCountReply = con.xpending_count(LEAD_STREAM_KEY, SENDER_GROUP, "-", "+", max).await.map_err(BotError::RedisError)?;
+        let min_idle = self.claim_after.as_millis() as usize;
+        let idle: Vec<String> = pending
+            .ids
+            .into_iter()
+            .filter(|entry| entry.last_delivered_ms >= min_idle)
+            .map(|entry| entry.id)
+            .collect();
+        if idle.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Another worker claiming the same entries first gets them instead.
+        let claimed: StreamClaimReply = con.xclaim(LEAD_STREAM_KEY, SENDER_GROUP, &self.name, min_idle, &idle).await.map_err(BotError::RedisError)?;
+        Ok(claimed.ids)
+    }
+
+    // Marks leads handled, whether they were sent, skipped or failed, and
+    // drops them from the stream.
+    pub async fn ack(&self, ids: &[String]) -> Result<(), BotError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let mut con = connection(&self.pool).await?;
+        redis::pipe()
+            .atomic()
+            .xack(LEAD_STREAM_KEY, SENDER_GROUP, ids)
+            .xdel(LEAD_STREAM_KEY, ids)
+            .query_async::<_, ()>(&mut *con)
+            .await
+            .map_err(BotError::RedisError)
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use crate::error::BotError;
+use crate::ratelimit::memory::Tally;
+use crate::ratelimit::{Limits, RateLimiter};
+
+// Counts in a JSON file, so the sends of earlier runs on this machine are
+// still known. It's read before and written after every change, but not
+// locked: two processes sharing the file can each take the same last send.
+pub struct FileLimiter {
+    path: String,
+    limits: Limits,
+}
+
+impl FileLimiter {
+    // Fails when the file exists but doesn't hold counters.
+    pub fn open(path: &str, limits: Limits) -> Result<Self, BotError> {
+        let limiter = FileLimiter { path: path.to_string(), limits };
+        limiter.load()?;
+        Ok(limiter)
+    }
+
+    fn load(&self) -> Result<Tally, BotError> {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) => serde_json::from_str(&contents).map_err(BotError::DataParseError),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Tally::default()),
+            Err(e) => Err(BotError::IOError(e)),
+        }
+    }
+
+    // Written to a temp file and renamed so a crash mid-write never corrupts the counters.
+    fn save(&self, tally: &Tally) -> Result<(), BotError> {
+        let tmp_path = format!("{}.tmp", self.path);
+        let json_data = serde_json::to_string(tally).map_err(BotError::DataParseError)?;
+        fs::write(&tmp_path, json_data).map_err(BotError::IOError)?;
+        fs::rename(&tmp_path, &self.path).map_err(BotError::IOError)
+    }
+
+    fn update<T>(&self, change: impl FnOnce(&mut Tally) -> T) -> Result<T, BotError> {
+        let mut tally = self.load()?;
+        let result = change(&mut tally);
+        self.save(&tally)?;
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl RateLimiter for FileLimiter {
+    fn name(&self) -> String {
+        self.path.clone()
+    }
+
+    fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    async fn sent(&mut self, key: &str, span: Duration) -> Result<usize, BotError> {
+        Ok(self.load()?.sent(key, span))
+    }
+
+    async fn take(&mut self, key: &str, span: Duration, max: usize) -> Result<bool, BotError> {
+        self.update(|tally| tally.take(key, span, max))
+    }
+
+    async fn count(&mut self, key: &str, span: Duration) -> Result<(), BotError> {
+        self.update(|tally| tally.count(key, span))
+    }
+
+    async fn release(&mut self, key: &str) -> Result<(), BotError> {
+        self.update(|tally| tally.release(key))
+    }
+
+    async fn oldest(&mut self, key: &str, span: Duration) -> Result<Option<DateTime<Utc>>, BotError> {
+        Ok(self.load()?.oldest(key, span))
+    }
+
+    async fn value(&mut self, key: &str, initial: Option<&str>) -> Result<Option<String>, BotError> {
+        self.update(|tally| tally.value(key, initial))
+    }
+}
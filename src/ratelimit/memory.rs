@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::error::BotError;
+use crate::ratelimit::{Limits, RateLimiter};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Sends {
+    // Unix milliseconds of each send, oldest first.
+    at: Vec<i64>,
+    expires_at: i64,
+}
+
+// The sends under each counter's key and the values kept next to them, the
+// same whichever strategy names the keys: a fixed counter counts all the
+// sends under its key, which expires with it, a sliding one those of its span.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct Tally {
+    sends: HashMap<String, Sends>,
+    values: HashMap<String, String>,
+}
+
+impl Tally {
+    // Drops expired counters, and the sends of this one older than `span`.
+    fn recent(&mut self, key: &str, span: Duration) -> &mut Sends {
+        let now = Utc::now().timestamp_millis();
+        self.sends.retain(|_, sends| sends.expires_at > now);
+        let sends = self.sends.entry(key.to_string()).or_default();
+        let since = now - span.as_millis() as i64;
+        sends.at.retain(|&at| at > since);
+        sends
+    }
+
+    pub(crate) fn sent(&mut self, key: &str, span: Duration) -> usize {
+        self.recent(key, span).at.len()
+    }
+
+    pub(crate) fn take(&mut self, key: &str, span: Duration, max: usize) -> bool {
+        if self.sent(key, span) >= max {
+            return false;
+        }
+        self.count(key, span);
+        true
+    }
+
+    pub(crate) fn count(&mut self, key: &str, span: Duration) {
+        let now = Utc::now().timestamp_millis();
+        let sends = self.recent(key, span);
+        sends.at.push(now);
+        sends.expires_at = now + span.as_millis() as i64;
+    }
+
+    pub(crate) fn release(&mut self, key: &str) {
+        if let Some(sends) = self.sends.get_mut(key) {
+            sends.at.pop();
+        }
+    }
+
+    pub(crate) fn oldest(&mut self, key: &str, span: Duration) -> Option<DateTime<Utc>> {
+        self.recent(key, span).at.first().and_then(|&at| DateTime::from_timestamp_millis(at))
+    }
+
+    pub(crate) fn value(&mut self, key: &str, initial: Option<&str>) -> Option<String> {
+        if let Some(initial) = initial {
+            self.values.entry(key.to_string()).or_insert_with(|| initial.to_string());
+        }
+        self.values.get(key).cloned()
+    }
+}
+
+// Counts in the process itself, so only this run's sends are known.
+pub struct MemoryLimiter {
+    tally: Tally,
+    limits: Limits,
+}
+
+impl MemoryLimiter {
+    pub fn new(limits: Limits) -> Self {
+        MemoryLimiter { tally: Tally::default(), limits }
+    }
+}
+
+#[async_trait]
+impl RateLimiter for MemoryLimiter {
+    fn name(&self) -> String {
+        "memory".to_string()
+    }
+
+    fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    async fn sent(&mut self, key: &str, span: Duration) -> Result<usize, BotError> {
+        Ok(self.tally.sent(key, span))
+    }
+
+    async fn take(&mut self, key: &str, span: Duration, max: usize) -> Result<bool, BotError> {
+        Ok(self.tally.take(key, span, max))
+    }
+
+    async fn count(&mut self, key: &str, span: Duration) -> Result<(), BotError> {
+        self.tally.count(key, span);
+        Ok(())
+    }
+
+    async fn release(&mut self, key: &str) -> Result<(), BotError> {
+        self.tally.release(key);
+        Ok(())
+    }
+
+    async fn oldest(&mut self, key: &str, span: Duration) -> Result<Option<DateTime<Utc>>, BotError> {
+        Ok(self.tally.oldest(key, span))
+    }
+
+    async fn value(&mut self, key: &str, initial: Option<&str>) -> Result<Option<String>, BotError> {
+        Ok(self.tally.value(key, initial))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_stops_at_the_max() {
+        let mut tally = Tally::default();
+        let span = Duration::from_secs(60);
+        assert!(tally.take("minute", span, 2));
+        assert!(tally.take("minute", span, 2));
+        assert!(!tally.take("minute", span, 2));
+        assert_eq!(tally.sent("minute", span), 2);
+    }
+
+    #[test]
+    fn release_gives_a_send_back() {
+        let mut tally = Tally::default();
+        let span = Duration::from_secs(60);
+        assert!(tally.take("minute", span, 1));
+        tally.release("minute");
+        assert_eq!(tally.sent("minute", span), 0);
+        assert!(tally.take("minute", span, 1));
+    }
+
+    #[test]
+    fn keys_are_counted_apart() {
+        let mut tally = Tally::default();
+        let span = Duration::from_secs(60);
+        tally.count("a", span);
+        tally.count("a", span);
+        tally.count("b", span);
+        assert_eq!(tally.sent("a", span), 2);
+        assert_eq!(tally.sent("b", span), 1);
+    }
+
+    #[test]
+    fn sends_older_than_the_span_drop_out() {
+        let mut tally = Tally::default();
+        tally.count("minute", Duration::from_secs(60));
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(tally.sent("minute", Duration::from_millis(10)), 0);
+        assert_eq!(tally.oldest("minute", Duration::from_millis(10)), None);
+    }
+
+    #[test]
+    fn value_keeps_the_first_initial() {
+        let mut tally = Tally::default();
+        assert_eq!(tally.value("warmup", None), None);
+        assert_eq!(tally.value("warmup", Some("2026-01-01")).as_deref(), Some("2026-01-01"));
+        assert_eq!(tally.value("warmup", Some("2026-02-01")).as_deref(), Some("2026-01-01"));
+    }
+}
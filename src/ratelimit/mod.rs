@@ -0,0 +1,415 @@
+pub mod file;
+pub mod memory;
+pub mod pool;
+pub mod redis;
+
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Timelike, Utc};
+use chrono_tz::Tz;
+use deadpool::managed::{self, PoolError};
+use crate::config::{Config, LimitFallback, LimitStrategy, RedisConfig, SendConfig};
+use crate::error::BotError;
+
+pub use file::FileLimiter;
+pub use memory::MemoryLimiter;
+pub use self::pool::{RedisConnection, RedisManager};
+pub use self::redis::RedisLimiter;
+
+pub const DEFAULT_MAX_EMAILS_PER_DAY: usize = 400;
+pub const DEFAULT_REDIS_URL: &str = "redis://127.0.0.1/";
+pub const DEFAULT_REDIS_POOL_SIZE: usize = 8;
+pub const DEFAULT_SENTINEL_MASTER: &str = "mymaster";
+pub const DEFAULT_LIMIT_FILE: &str = "limits.json";
+
+pub fn current_day() -> String {
+    Utc::now().format("%Y-%m-%d").to_string()
+}
+
+// How the limits count their sends, and the zone whose midnight starts a
+// fixed counter's day, which is when some providers reset their own caps.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub strategy: LimitStrategy,
+    pub timezone: Tz,
+}
+
+impl Limits {
+    pub fn from_config(config: &SendConfig) -> Result<Self, BotError> {
+        let timezone: Tz = config.limit_timezone.trim().parse().map_err(|_| {
+            BotError::ConfigError(format!("send.limit_timezone: \"{}\" is not a time zone like \"America/Los_Angeles\"", config.limit_timezone))
+        })?;
+        Ok(Limits { strategy: config.limit_strategy, timezone })
+    }
+
+    // The date in send.limit_timezone, which names today's daily counters.
+    pub fn today(self) -> String {
+        Utc::now().with_timezone(&self.timezone).format("%Y-%m-%d").to_string()
+    }
+}
+
+pub type RedisPool = managed::Pool<RedisManager>;
+
+// The pool a command shares between everything it keeps in Redis. One
+// connection is made right away, so an unreachable Redis fails here.
+pub async fn connect(config: &RedisConfig) -> Result<RedisPool, BotError> {
+    let pool = RedisPool::builder(RedisManager::new(config)?)
+        .max_size(config.pool_size)
+        .build()
+        .map_err(|e| BotError::ConfigError(format!("redis: {}", e)))?;
+    let _ = connection(&pool).await?;
+    Ok(pool)
+}
+
+// A connection from the pool, back in it once dropped.
+pub async fn connection(pool: &RedisPool) -> Result<managed::Object<RedisManager>, BotError> {
+    pool.get().await.map_err(|e| match e {
+        PoolError::Backend(e) => BotError::RedisError(e),
+        e => BotError::RedisPoolError(e),
+    })
+}
+
+// Where the sends against each limit are counted. Counters are named by key
+// and count the sends of `span` back from now: a fixed counter's key names
+// its day, hour or minute and `span` only has to outlast it, while a sliding
+// one keeps the same key and drops the sends older than `span`.
+#[async_trait]
+pub trait RateLimiter: Send + Sync {
+    // For messages, e.g. "Redis".
+    fn name(&self) -> String;
+
+    fn limits(&self) -> Limits;
+
+    async fn sent(&mut self, key: &str, span: Duration) -> Result<usize, BotError>;
+
+    // Counts a send unless `max` were already counted, as one step, so two
+    // workers sharing a limit can't both take its last send.
+    async fn take(&mut self, key: &str, span: Duration, max: usize) -> Result<bool, BotError>;
+
+    // Counts a send without a limit to check it against.
+    async fn count(&mut self, key: &str, span: Duration) -> Result<(), BotError>;
+
+    // Gives back the latest send, for one that didn't happen. That's this
+    // send's or, when another worker took one since, one just as recent.
+    async fn release(&mut self, key: &str) -> Result<(), BotError>;
+
+    // When the oldest send of the last `span` was counted, for a sliding counter.
+    async fn oldest(&mut self, key: &str, span: Duration) -> Result<Option<DateTime<Utc>>, BotError>;
+
+    // The value kept at `key` without an expiry, set to `initial` first
+    // when there is none yet and `initial` is given.
+    async fn value(&mut self, key: &str, initial: Option<&str>) -> Result<Option<String>, BotError>;
+}
+
+// Redis, with send.limit_fallback taking over for the rest of the run if it
+// fails after its own retries.
+pub fn open(config: &Config, pool: &RedisPool) -> Result<Box<dyn RateLimiter>, BotError> {
+    let limits = Limits::from_config(&config.send)?;
+    let redis = RedisLimiter::new(pool.clone(), limits);
+    let fallback: Box<dyn RateLimiter> = match config.send.limit_fallback {
+        LimitFallback::None => return Ok(Box::new(redis)),
+        LimitFallback::Memory => Box::new(MemoryLimiter::new(limits)),
+        LimitFallback::File => Box::new(FileLimiter::open(&config.send.limit_file, limits)?),
+    };
+    Ok(Box::new(FallbackLimiter { primary: Some(Box::new(redis)), fallback }))
+}
+
+// Counts every send in the fallback as well while the primary works, so on
+// taking over it knows what this run (or, from a file, this machine) sent,
+// though not what other machines did.
+struct FallbackLimiter {
+    // None once it failed.
+    primary: Option<Box<dyn RateLimiter>>,
+    fallback: Box<dyn RateLimiter>,
+}
+
+impl FallbackLimiter {
+    // Some with what the primary gave, or None once it failed.
+    fn checked<T>(&mut self, result: Result<T, BotError>) -> Result<Option<T>, BotError> {
+        match result {
+            Ok(result) => Ok(Some(result)),
+            Err(e @ (BotError::RedisError(_) | BotError::RedisPoolError(_))) => {
+                eprintln!("Redis failed while counting the limits: {}; counting them in {} for the rest of the run", e, self.fallback.name());
+                self.primary = None;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimiter for FallbackLimiter {
+    fn name(&self) -> String {
+        match &self.primary {
+            Some(primary) => format!("{} (falling back to {})", primary.name(), self.fallback.name()),
+            None => self.fallback.name(),
+        }
+    }
+
+    fn limits(&self) -> Limits {
+        self.fallback.limits()
+    }
+
+    async fn sent(&mut self, key: &str, span: Duration) -> Result<usize, BotError> {
+        if let Some(primary) = self.primary.as_mut() {
+            let result = primary.sent(key, span).await;
+            if let Some(sent) = self.checked(result)? {
+                return Ok(sent);
+            }
+        }
+        self.fallback.sent(key, span).await
+    }
+
+    async fn take(&mut self, key: &str, span: Duration, max: usize) -> Result<bool, BotError> {
+        if let Some(primary) = self.primary.as_mut() {
+            let result = primary.take(key, span, max).await;
+            if let Some(taken) = self.checked(result)? {
+                if taken {
+                    self.fallback.count(key, span).await?;
+                }
+                return Ok(taken);
+            }
+        }
+        self.fallback.take(key, span, max).await
+    }
+
+    async fn count(&mut self, key: &str, span: Duration) -> Result<(), BotError> {
+        if let Some(primary) = self.primary.as_mut() {
+            let result = primary.count(key, span).await;
+            self.checked(result)?;
+        }
+        self.fallback.count(key, span).await
+    }
+
+    async fn release(&mut self, key: &str) -> Result<(), BotError> {
+        if let Some(primary) = self.primary.as_mut() {
+            let result = primary.release(key).await;
+            self.checked(result)?;
+        }
+        self.fallback.release(key).await
+    }
+
+    async fn oldest(&mut self, key: &str, span: Duration) -> Result<Option<DateTime<Utc>>, BotError> {
+        if let Some(primary) = self.primary.as_mut() {
+            let result = primary.oldest(key, span).await;
+            if let Some(oldest) = self.checked(result)? {
+                return Ok(oldest);
+            }
+        }
+        self.fallback.oldest(key, span).await
+    }
+
+    async fn value(&mut self, key: &str, initial: Option<&str>) -> Result<Option<String>, BotError> {
+        if let Some(primary) = self.primary.as_mut() {
+            let result = primary.value(key, initial).await;
+            if let Some(value) = self.checked(result)? {
+                if let Some(value) = &value {
+                    self.fallback.value(key, Some(value)).await?;
+                }
+                return Ok(value);
+            }
+        }
+        self.fallback.value(key, initial).await
+    }
+}
+
+// The shorter windows counted next to the day, so a run spreads its sends out
+// instead of using up the daily limit in one go. A fixed one starts on the
+// clock's minute and hour (UTC).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    Minute,
+    Hour,
+}
+
+impl Window {
+    pub fn name(self) -> &'static str {
+        match self {
+            Window::Minute => "minute",
+            Window::Hour => "hour",
+        }
+    }
+
+    fn seconds(self) -> u64 {
+        match self {
+            Window::Minute => 60,
+            Window::Hour => 3600,
+        }
+    }
+
+    fn key(self, now: DateTime<Utc>) -> String {
+        match self {
+            Window::Minute => format!("emails_sent:{}", now.format("%Y-%m-%dT%H:%M")),
+            Window::Hour => format!("emails_sent:{}", now.format("%Y-%m-%dT%H")),
+        }
+    }
+
+    // How long until the current window ends.
+    fn remaining(self) -> Duration {
+        let now = Utc::now();
+        let elapsed = match self {
+            Window::Minute => now.second() as u64,
+            Window::Hour => now.minute() as u64 * 60 + now.second() as u64,
+        };
+        Duration::from_secs(self.seconds() - elapsed)
+    }
+}
+
+// What a limit counts the sends of.
+#[derive(Debug, Clone, Copy)]
+enum Counter<'a> {
+    Day,
+    // Per sender account, next to the global counter.
+    Account(&'a str),
+    // Per recipient domain, so one company's spam filter doesn't see a burst.
+    Domain(&'a str),
+    Window(Window),
+}
+
+impl Counter<'_> {
+    fn seconds(self) -> u64 {
+        match self {
+            Counter::Window(window) => window.seconds(),
+            Counter::Day | Counter::Account(_) | Counter::Domain(_) => 86400,
+        }
+    }
+
+    // How long a fixed counter is kept. A day's lasts 25 hours, so one
+    // started at a local midnight outlives a day made longer by daylight
+    // saving; its key names the date either way.
+    fn expiry(self) -> u64 {
+        match self {
+            Counter::Window(window) => window.seconds(),
+            Counter::Day | Counter::Account(_) | Counter::Domain(_) => 90000,
+        }
+    }
+
+    fn span(self, limits: Limits) -> Duration {
+        match limits.strategy {
+            LimitStrategy::Fixed => Duration::from_secs(self.expiry()),
+            LimitStrategy::Sliding => Duration::from_secs(self.seconds()),
+        }
+    }
+
+    fn key(self, limits: Limits) -> String {
+        match (limits.strategy, self) {
+            (LimitStrategy::Fixed, Counter::Day) => format!("emails_sent:{}", limits.today()),
+            (LimitStrategy::Fixed, Counter::Account(account)) => format!("emails_sent:{}:{}", limits.today(), account),
+            (LimitStrategy::Fixed, Counter::Domain(domain)) => format!("emails_sent_domain:{}:{}", limits.today(), domain),
+            (LimitStrategy::Fixed, Counter::Window(window)) => window.key(Utc::now()),
+            (LimitStrategy::Sliding, Counter::Day) => "emails_sent:last_day".to_string(),
+            (LimitStrategy::Sliding, Counter::Account(account)) => format!("emails_sent:last_day:{}", account),
+            (LimitStrategy::Sliding, Counter::Domain(domain)) => format!("emails_sent_domain:last_day:{}", domain),
+            (LimitStrategy::Sliding, Counter::Window(window)) => format!("emails_sent:last_{}", window.name()),
+        }
+    }
+}
+
+async fn sent(limiter: &mut dyn RateLimiter, counter: Counter<'_>) -> Result<usize, BotError> {
+    let limits = limiter.limits();
+    limiter.sent(&counter.key(limits), counter.span(limits)).await
+}
+
+async fn take(limiter: &mut dyn RateLimiter, counter: Counter<'_>, max_emails: usize) -> Result<bool, BotError> {
+    let limits = limiter.limits();
+    limiter.take(&counter.key(limits), counter.span(limits), max_emails).await
+}
+
+async fn release(limiter: &mut dyn RateLimiter, counter: Counter<'_>) -> Result<(), BotError> {
+    let limits = limiter.limits();
+    limiter.release(&counter.key(limits)).await
+}
+
+pub async fn window_sent(limiter: &mut dyn RateLimiter, window: Window) -> Result<usize, BotError> {
+    sent(limiter, Counter::Window(window)).await
+}
+
+pub async fn check_update_window_count(limiter: &mut dyn RateLimiter, window: Window, max_emails: usize) -> Result<bool, BotError> {
+    take(limiter, Counter::Window(window), max_emails).await
+}
+
+// Gives back a send taken with check_update_window_count that didn't happen.
+pub async fn release_window_count(limiter: &mut dyn RateLimiter, window: Window) -> Result<(), BotError> {
+    release(limiter, Counter::Window(window)).await
+}
+
+// How long until a used-up window has a send again: the next minute or hour
+// for a fixed one, the moment its oldest send falls out for a sliding one.
+pub async fn window_reopens(limiter: &mut dyn RateLimiter, window: Window) -> Result<Duration, BotError> {
+    let limits = limiter.limits();
+    match limits.strategy {
+        LimitStrategy::Fixed => Ok(window.remaining()),
+        LimitStrategy::Sliding => {
+            let counter = Counter::Window(window);
+            let oldest = limiter.oldest(&counter.key(limits), counter.span(limits)).await?;
+            let reopens = oldest.map_or(0, |taken| (taken + chrono::Duration::seconds(window.seconds() as i64) - Utc::now()).num_milliseconds());
+            Ok(Duration::from_millis(reopens.max(0) as u64))
+        }
+    }
+}
+
+pub async fn domain_sent_today(limiter: &mut dyn RateLimiter, domain: &str) -> Result<usize, BotError> {
+    sent(limiter, Counter::Domain(domain)).await
+}
+
+pub async fn check_update_domain_count(limiter: &mut dyn RateLimiter, domain: &str, max_emails_per_day: usize) -> Result<bool, BotError> {
+    take(limiter, Counter::Domain(domain), max_emails_per_day).await
+}
+
+// Gives back a send taken with check_update_domain_count that didn't happen.
+pub async fn release_domain_count(limiter: &mut dyn RateLimiter, domain: &str) -> Result<(), BotError> {
+    release(limiter, Counter::Domain(domain)).await
+}
+
+pub async fn emails_sent_today(limiter: &mut dyn RateLimiter) -> Result<usize, BotError> {
+    sent(limiter, Counter::Day).await
+}
+
+pub async fn account_sent_today(limiter: &mut dyn RateLimiter, account: &str) -> Result<usize, BotError> {
+    sent(limiter, Counter::Account(account)).await
+}
+
+pub async fn check_update_email_count(limiter: &mut dyn RateLimiter, max_emails_per_day: usize) -> Result<bool, BotError> {
+    take(limiter, Counter::Day, max_emails_per_day).await
+}
+
+pub async fn check_update_account_count(limiter: &mut dyn RateLimiter, account: &str, max_emails_per_day: usize) -> Result<bool, BotError> {
+    take(limiter, Counter::Account(account), max_emails_per_day).await
+}
+
+// For accounts without a cap of their own, so `stats` can still show their share.
+pub async fn count_account_send(limiter: &mut dyn RateLimiter, account: &str) -> Result<(), BotError> {
+    let limits = limiter.limits();
+    let counter = Counter::Account(account);
+    limiter.count(&counter.key(limits), counter.span(limits)).await
+}
+
+fn warmup_key(account: &str) -> String {
+    format!("warmup_started:{}", account)
+}
+
+// Which day of its warm-up the account is on, the first being day 0. The day
+// it started is kept without an expiry, so every run and machine ramps up
+// from the same day; `start` starts it today when it hasn't yet. Its days
+// turn over with the daily counters'.
+pub async fn warmup_day(limiter: &mut dyn RateLimiter, account: &str, start: bool) -> Result<u32, BotError> {
+    let limits = limiter.limits();
+    let today = limits.today();
+    let started = limiter.value(&warmup_key(account), start.then_some(today.as_str())).await?;
+    let Some(started) = started.and_then(|day| NaiveDate::parse_from_str(&day, "%Y-%m-%d").ok()) else {
+        return Ok(0);
+    };
+    Ok((Utc::now().with_timezone(&limits.timezone).date_naive() - started).num_days().max(0) as u32)
+}
+
+// Gives back a send taken with check_update_account_count or
+// count_account_send that didn't happen.
+pub async fn release_account_count(limiter: &mut dyn RateLimiter, account: &str) -> Result<(), BotError> {
+    release(limiter, Counter::Account(account)).await
+}
+
+// Gives back a send taken with check_update_email_count that didn't happen.
+pub async fn release_email_count(limiter: &mut dyn RateLimiter) -> Result<(), BotError> {
+    release(limiter, Counter::Day).await
+}
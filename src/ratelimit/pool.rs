@@ -0,0 +1,114 @@
+use async_trait::async_trait;
+use deadpool::managed::{self, Metrics, RecycleError, RecycleResult};
+use redis::aio::{ConnectionLike, MultiplexedConnection};
+use redis::cluster::{ClusterClient, ClusterClientBuilder};
+use redis::cluster_async::ClusterConnection;
+use redis::sentinel::{SentinelClient, SentinelNodeConnectionInfo, SentinelServerType};
+use redis::{Client, Cmd, ConnectionAddr, Pipeline, RedisError, RedisFuture, TlsMode, Value};
+use tokio::sync::Mutex;
+use crate::config::{RedisConfig, RedisMode};
+use crate::error::BotError;
+
+// What the pool opens its connections to.
+enum Target {
+    Standalone(Client),
+    // Locked while asking, as the client keeps its connections to the sentinels.
+    Sentinel(Mutex<SentinelClient>),
+    Cluster(ClusterClient),
+}
+
+// Opens the pool's connections the way redis.mode says. The cluster client
+// follows the slots moving between nodes itself; a sentinel's master is
+// found again by each new connection, and an old one is dropped once the
+// master it's on has been demoted.
+pub struct RedisManager {
+    target: Target,
+}
+
+impl RedisManager {
+    pub fn new(config: &RedisConfig) -> Result<Self, BotError> {
+        let info = config.connection_info()?;
+        let target = match config.mode {
+            RedisMode::Standalone => Client::open(info).map(Target::Standalone),
+            RedisMode::Sentinel => {
+                let tls_mode = match info.addr {
+                    ConnectionAddr::TcpTls { insecure: true, .. } => Some(TlsMode::Insecure),
+                    ConnectionAddr::TcpTls { insecure: false, .. } => Some(TlsMode::Secure),
+                    _ => None,
+                };
+                let master = SentinelNodeConnectionInfo { tls_mode, redis_connection_info: Some(info.redis) };
+                SentinelClient::build(config.node_infos()?, config.sentinel_master.clone(), Some(master), SentinelServerType::Master)
+                    .map(|client| Target::Sentinel(Mutex::new(client)))
+            }
+            RedisMode::Cluster => {
+                let nodes = if config.nodes.is_empty() { vec![info.clone()] } else { config.node_infos()? };
+                let mut builder = ClusterClientBuilder::new(nodes);
+                if let Some(username) = info.redis.username {
+                    builder = builder.username(username);
+                }
+                if let Some(password) = info.redis.password {
+                    builder = builder.password(password);
+                }
+                builder.build().map(Target::Cluster)
+            }
+        };
+        let target = target.map_err(|e| BotError::ConfigError(format!("redis: {}", e)))?;
+        Ok(RedisManager { target })
+    }
+}
+
+pub enum RedisConnection {
+    Single(MultiplexedConnection),
+    Cluster(ClusterConnection),
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            RedisConnection::Single(con) => con.req_packed_command(cmd),
+            RedisConnection::Cluster(con) => con.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(&'a mut self, cmd: &'a Pipeline, offset: usize, count: usize) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            RedisConnection::Single(con) => con.req_packed_commands(cmd, offset, count),
+            RedisConnection::Cluster(con) => con.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            RedisConnection::Single(con) => con.get_db(),
+            RedisConnection::Cluster(con) => con.get_db(),
+        }
+    }
+}
+
+#[async_trait]
+impl managed::Manager for RedisManager {
+    type Type = RedisConnection;
+    type Error = RedisError;
+
+    async fn create(&self) -> Result<RedisConnection, RedisError> {
+        match &self.target {
+            Target::Standalone(client) => client.get_multiplexed_async_connection().await.map(RedisConnection::Single),
+            Target::Sentinel(client) => client.lock().await.get_async_connection().await.map(RedisConnection::Single),
+            Target::Cluster(client) => client.get_async_connection().await.map(RedisConnection::Cluster),
+        }
+    }
+
+    async fn recycle(&self, con: &mut RedisConnection, _: &Metrics) -> RecycleResult<RedisError> {
+        if let Target::Sentinel(_) = self.target {
+            // A demoted master still answers, as a replica refusing writes.
+            let role: Vec<Value> = redis::cmd("ROLE").query_async(con).await?;
+            let role: String = role.first().map(redis::from_redis_value).transpose()?.unwrap_or_default();
+            if role != "master" {
+                return Err(RecycleError::StaticMessage("the connection's server is no longer the master"));
+            }
+            return Ok(());
+        }
+        redis::cmd("PING").query_async::<_, ()>(con).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use redis::AsyncCommands;
+use crate::config::LimitStrategy;
+use crate::error::BotError;
+use crate::ratelimit::{connection, Limits, RateLimiter, RedisConnection, RedisPool};
+
+// Takes a send from the counter at KEYS[1] unless that would take it over
+// ARGV[1], in one step on the Redis server, so two workers sharing a limit
+// can't both take its last send. The counter expires ARGV[2] seconds after
+// the send that created it; one left without an expiry gets one.
+const CHECK_UPDATE_SCRIPT: &str = r"
+local count = redis.call('INCR', KEYS[1])
+if redis.call('TTL', KEYS[1]) < 0 then
+    redis.call('EXPIRE', KEYS[1], ARGV[2])
+end
+if count > tonumber(ARGV[1]) then
+    redis.call('DECR', KEYS[1])
+    return 0
+end
+return 1
+";
+
+// The sliding counterpart: KEYS[1] is a sorted set of the sends taken, each
+// scored with the millisecond it was taken at, ARGV[3]. Those older than
+// ARGV[2] milliseconds are dropped first, and send ARGV[4] is added unless
+// ARGV[1] are left.
+const SLIDING_CHECK_UPDATE_SCRIPT: &str = r"
+local now = tonumber(ARGV[3])
+local window = tonumber(ARGV[2])
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+if redis.call('ZCARD', KEYS[1]) >= tonumber(ARGV[1]) then
+    return 0
+end
+redis.call('ZADD', KEYS[1], now, ARGV[4])
+redis.call('PEXPIRE', KEYS[1], window)
+return 1
+";
+
+fn now_ms() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+// Sends are scored with the millisecond they were taken at; the random
+// suffix keeps two taken in the same millisecond apart.
+fn new_send() -> String {
+    format!("{}-{:08x}", now_ms(), rand::random::<u32>())
+}
+
+// The counters shared by every run and machine: fixed ones as plain counters,
+// sliding ones as sorted sets of their sends.
+pub struct RedisLimiter {
+    pool: RedisPool,
+    limits: Limits,
+}
+
+impl RedisLimiter {
+    pub fn new(pool: RedisPool, limits: Limits) -> Self {
+        RedisLimiter { pool, limits }
+    }
+}
+
+#[async_trait]
+impl RateLimiter for RedisLimiter {
+    fn name(&self) -> String {
+        "Redis".to_string()
+    }
+
+    fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    async fn sent(&mut self, key: &str, span: Duration) -> Result<usize, BotError> {
+        let mut con = connection(&self.pool).await?;
+        match self.limits.strategy {
+            LimitStrategy::Fixed => {
+                let count: Option<usize> = con.get(key).await.map_err(BotError::RedisError)?;
+                Ok(count.unwrap_or(0))
+            }
+            LimitStrategy::Sliding => {
+                let since = now_ms() - span.as_millis() as i64;
+                con.zcount(key, format!("({}", since), "+inf").await.map_err(BotError::RedisError)
+            }
+        }
+    }
+
+    async fn take(&mut self, key: &str, span: Duration, max: usize) -> Result<bool, BotError> {
+        let mut con = connection(&self.pool).await?;
+        match self.limits.strategy {
+            LimitStrategy::Fixed => {
+                let script = redis::Script::new(CHECK_UPDATE_SCRIPT);
+                invoke_with_retries(&mut con, script.key(key).arg(max).arg(span.as_secs())).await
+            }
+            LimitStrategy::Sliding => {
+                let script = redis::Script::new(SLIDING_CHECK_UPDATE_SCRIPT);
+                invoke_with_retries(&mut con, script.key(key).arg(max).arg(span.as_millis() as u64).arg(now_ms()).arg(new_send())).await
+            }
+        }
+    }
+
+    async fn count(&mut self, key: &str, span: Duration) -> Result<(), BotError> {
+        let mut con = connection(&self.pool).await?;
+        match self.limits.strategy {
+            LimitStrategy::Fixed => redis::pipe().atomic().incr(key, 1).expire(key, span.as_secs() as i64).query_async::<_, ()>(&mut *con).await,
+            LimitStrategy::Sliding => redis::pipe()
+                .atomic()
+                .zadd(key, new_send(), now_ms())
+                .pexpire(key, span.as_millis() as i64)
+                .query_async::<_, ()>(&mut *con)
+                .await,
+        }
+        .map_err(BotError::RedisError)
+    }
+
+    async fn release(&mut self, key: &str) -> Result<(), BotError> {
+        let mut con = connection(&self.pool).await?;
+        match self.limits.strategy {
+            LimitStrategy::Fixed => con.decr::<_, _, ()>(key, 1).await,
+            LimitStrategy::Sliding => con.zpopmax::<_, ()>(key, 1).await,
+        }
+        .map_err(BotError::RedisError)
+    }
+
+    async fn oldest(&mut self, key: &str, span: Duration) -> Result<Option<DateTime<Utc>>, BotError> {
+        let mut con = connection(&self.pool).await?;
+        let since = now_ms() - span.as_millis() as i64;
+        let oldest: Vec<(String, f64)> =
+            con.zrangebyscore_limit_withscores(key, format!("({}", since), "+inf", 0, 1).await.map_err(BotError::RedisError)?;
+        Ok(oldest.first().and_then(|&(_, taken)| DateTime::from_timestamp_millis(taken as i64)))
+    }
+
+    async fn value(&mut self, key: &str, initial: Option<&str>) -> Result<Option<String>, BotError> {
+        let mut con = connection(&self.pool).await?;
+        if let Some(initial) = initial {
+            let _: bool = con.set_nx(key, initial).await.map_err(BotError::RedisError)?;
+        }
+        con.get(key).await.map_err(BotError::RedisError)
+    }
+}
+
+async fn invoke_with_retries(con: &mut RedisConnection, invocation: &redis::ScriptInvocation<'_>) -> Result<bool, BotError> {
+    let mut retry_count = 0;
+    let max_retries = 5;
+
+    loop {
+        match invocation.invoke_async::<_, bool>(con).await {
+            Ok(taken) => return Ok(taken),
+            Err(err) => {
+                if retry_count >= max_retries {
+                    return Err(BotError::RedisError(err));
+                } else {
+                    eprintln!("Redis failed, retrying... (Attempt: {})", retry_count + 1);
+                    retry_count += 1;
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+            }
+        }
+    }
+}
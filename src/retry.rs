@@ -0,0 +1,154 @@
+use chrono::{DateTime, Utc};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use crate::config::RetryConfig;
+use crate::email::dedup_key;
+use crate::error::BotError;
+use crate::ratelimit::{connection, RedisPool};
+
+// Redis hashes of "<campaign id>:<step>:<address>" -> FailedSend as JSON.
+// They can be on different nodes of a cluster, so a send moving between
+// them is written to the other first and then deleted, never atomically:
+// cut off in between, it's left in both rather than lost.
+const RETRY_QUEUE_KEY: &str = "retry:queue";
+const DEAD_LETTER_KEY: &str = "retry:dead_letters";
+
+// A send that failed, with what it takes to send it again the same way.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FailedSend {
+    pub campaign_id: i64,
+    pub email: String,
+    pub step: u32,
+    pub variant: Option<String>,
+    pub subject: String,
+    // Message-IDs of the emails it follows up on.
+    pub references: Vec<String>,
+    pub attempts: u32,
+    pub error: String,
+    // Unix timestamps of the last failure and of when it may be tried again.
+    pub failed_at: i64,
+    pub retry_at: i64,
+}
+
+impl FailedSend {
+    fn key(&self) -> String {
+        format!("{}:{}:{}", self.campaign_id, self.step, dedup_key(&self.email))
+    }
+
+    pub fn failed_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.failed_at, 0).unwrap_or_default()
+    }
+
+    pub fn retry_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.retry_at, 0).unwrap_or_default()
+    }
+}
+
+fn parse_entries(entries: Vec<String>) -> Result<Vec<FailedSend>, BotError> {
+    entries.iter().map(|entry| serde_json::from_str(entry).map_err(BotError::DataParseError)).collect()
+}
+
+// Failed sends waiting for `follow-up` to try them again, and the dead
+// letters: those that ran out of attempts or were refused for good, kept
+// until someone looks at them.
+pub struct RetryQueue {
+    pool: RedisPool,
+    config: RetryConfig,
+}
+
+impl RetryQueue {
+    pub fn new(pool: RedisPool, config: &RetryConfig) -> Self {
+        RetryQueue { pool, config: config.clone() }
+    }
+
+    // Queues the send to be tried again after the backoff for its attempts so
+    // far, and returns when. None means it moved to the dead letters instead,
+    // having failed max_attempts times or, when `permanent`, for good.
+    pub async fn record_failure(&self, mut failed: FailedSend, permanent: bool) -> Result<Option<DateTime<Utc>>, BotError> {
+        let mut con = connection(&self.pool).await?;
+        let key = failed.key();
+        let previous: Option<String> = con.hget(RETRY_QUEUE_KEY, &key).await.map_err(BotError::RedisError)?;
+        failed.attempts = match previous {
+            Some(previous) => serde_json::from_str::<FailedSend>(&previous).map_err(BotError::DataParseError)?.attempts + 1,
+            None => 1,
+        };
+        let now = Utc::now();
+        failed.failed_at = now.timestamp();
+        if permanent || failed.attempts >= self.config.max_attempts {
+            failed.retry_at = failed.failed_at;
+            let entry = serde_json::to_string(&failed).map_err(BotError::DataParseError)?;
+            con.hset::<_, _, _, ()>(DEAD_LETTER_KEY, &key, entry).await.map_err(BotError::RedisError)?;
+            con.hdel::<_, _, ()>(RETRY_QUEUE_KEY, &key).await.map_err(BotError::RedisError)?;
+            return Ok(None);
+        }
+        let retry_at = now + self.config.backoff(failed.attempts);
+        failed.retry_at = retry_at.timestamp();
+        let entry = serde_json::to_string(&failed).map_err(BotError::DataParseError)?;
+        con.hset::<_, _, _, ()>(RETRY_QUEUE_KEY, &key, entry).await.map_err(BotError::RedisError)?;
+        Ok(Some(retry_at))
+    }
+
+    // Drops the send from the queue once it has gone out.
+    pub async fn resolve(&self, campaign_id: i64, step: u32, email: &str) -> Result<(), BotError> {
+        let mut con = connection(&self.pool).await?;
+        let key = format!("{}:{}:{}", campaign_id, step, dedup_key(email));
+        con.hdel::<_, _, ()>(RETRY_QUEUE_KEY, key).await.map_err(BotError::RedisError)
+    }
+
+    // The queue, due ones first.
+    pub async fn pending(&self) -> Result<Vec<FailedSend>, BotError> {
+        let mut con = connection(&self.pool).await?;
+        let mut pending = parse_entries(con.hvals(RETRY_QUEUE_KEY).await.map_err(BotError::RedisError)?)?;
+        pending.sort_by_key(|failed| failed.retry_at);
+        Ok(pending)
+    }
+
+    // The dead letters, oldest first.
+    pub async fn dead_letters(&self) -> Result<Vec<FailedSend>, BotError> {
+        let mut con = connection(&self.pool).await?;
+        let mut dead = parse_entries(con.hvals(DEAD_LETTER_KEY).await.map_err(BotError::RedisError)?)?;
+        dead.sort_by_key(|failed| failed.failed_at);
+        Ok(dead)
+    }
+
+    // Moves the dead letters to these addresses, or all of them when `emails`
+    // is empty, back to the queue with their attempts reset, due right away.
+    pub async fn requeue(&self, emails: &[String]) -> Result<usize, BotError> {
+        let selected = self.select_dead_letters(emails).await?;
+        if selected.is_empty() {
+            return Ok(0);
+        }
+        let mut con = connection(&self.pool).await?;
+        let mut entries = Vec::new();
+        for mut failed in selected.iter().cloned() {
+            failed.attempts = 0;
+            failed.retry_at = Utc::now().timestamp();
+            entries.push((failed.key(), serde_json::to_string(&failed).map_err(BotError::DataParseError)?));
+        }
+        let keys: Vec<String> = selected.iter().map(FailedSend::key).collect();
+        con.hset_multiple::<_, _, _, ()>(RETRY_QUEUE_KEY, &entries).await.map_err(BotError::RedisError)?;
+        con.hdel::<_, _, ()>(DEAD_LETTER_KEY, keys).await.map_err(BotError::RedisError)?;
+        Ok(selected.len())
+    }
+
+    // Deletes the dead letters to these addresses, or all of them when
+    // `emails` is empty.
+    pub async fn clear(&self, emails: &[String]) -> Result<usize, BotError> {
+        let selected = self.select_dead_letters(emails).await?;
+        if selected.is_empty() {
+            return Ok(0);
+        }
+        let mut con = connection(&self.pool).await?;
+        let keys: Vec<String> = selected.iter().map(FailedSend::key).collect();
+        con.hdel(DEAD_LETTER_KEY, keys).await.map_err(BotError::RedisError)
+    }
+
+    async fn select_dead_letters(&self, emails: &[String]) -> Result<Vec<FailedSend>, BotError> {
+        let emails: Vec<String> = emails.iter().map(|email| dedup_key(email)).collect();
+        let mut dead = self.dead_letters().await?;
+        if !emails.is_empty() {
+            dead.retain(|failed| emails.contains(&dedup_key(&failed.email)));
+        }
+        Ok(dead)
+    }
+}
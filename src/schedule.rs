@@ -0,0 +1,224 @@
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::{America, Pacific, Tz};
+use crate::config::ScheduleConfig;
+use crate::error::BotError;
+use crate::scrape::Business;
+
+const WEEK: [Weekday; 7] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun];
+
+// One zone per state, the one most of its people live in.
+const STATE_TIMEZONES: &[(&str, Tz)] = &[
+    ("AL", America::Chicago), ("AK", America::Anchorage), ("AZ", America::Phoenix), ("AR", America::Chicago),
+    ("CA", America::Los_Angeles), ("CO", America::Denver), ("CT", America::New_York), ("DC", America::New_York),
+    ("DE", America::New_York), ("FL", America::New_York), ("GA", America::New_York), ("HI", Pacific::Honolulu),
+    ("IA", America::Chicago), ("ID", America::Boise), ("IL", America::Chicago), ("IN", America::Indiana::Indianapolis),
+    ("KS", America::Chicago), ("KY", America::New_York), ("LA", America::Chicago), ("MA", America::New_York),
+    ("MD", America::New_York), ("ME", America::New_York), ("MI", America::Detroit), ("MN", America::Chicago),
+    ("MO", America::Chicago), ("MS", America::Chicago), ("MT", America::Denver), ("NC", America::New_York),
+    ("ND", America::Chicago), ("NE", America::Chicago), ("NH", America::New_York), ("NJ", America::New_York),
+    ("NM", America::Denver), ("NV", America::Los_Angeles), ("NY", America::New_York), ("OH", America::New_York),
+    ("OK", America::Chicago), ("OR", America::Los_Angeles), ("PA", America::New_York), ("RI", America::New_York),
+    ("SC", America::New_York), ("SD", America::Chicago), ("TN", America::Chicago), ("TX", America::Chicago),
+    ("UT", America::Denver), ("VA", America::New_York), ("VT", America::New_York), ("WA", America::Los_Angeles),
+    ("WI", America::Chicago), ("WV", America::New_York), ("WY", America::Denver),
+];
+
+// The zone of the US state in a "123 Main St, Columbus, OH 43215" address,
+// or else in a "Columbus, OH" search location.
+fn state_timezone(business: &Business) -> Option<Tz> {
+    [business.address.as_deref(), business.location.as_deref()].into_iter().flatten().find_map(|place| {
+        place.rsplit(',').find_map(|part| {
+            let state = part.split_whitespace().next()?.to_uppercase();
+            STATE_TIMEZONES.iter().find(|(code, _)| *code == state).map(|&(_, timezone)| timezone)
+        })
+    })
+}
+
+fn parse_time(key: &str, value: &str) -> Result<NaiveTime, BotError> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map_err(|_| BotError::ConfigError(format!("{}: expected a time like \"09:00\", got \"{}\"", key, value)))
+}
+
+// The days and hours [schedule] allows sending in.
+#[derive(Debug, Clone)]
+pub struct SendWindow {
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    // None runs to midnight.
+    end: Option<NaiveTime>,
+    timezone: Tz,
+    recipient_timezone: bool,
+}
+
+impl SendWindow {
+    // None when every hour of every day is allowed.
+    pub fn from_config(config: &ScheduleConfig) -> Result<Option<Self>, BotError> {
+        let timezone: Tz = config.timezone.trim().parse().map_err(|_| {
+            BotError::ConfigError(format!("schedule.timezone: \"{}\" is not a time zone like \"America/New_York\"", config.timezone))
+        })?;
+        let mut days = Vec::new();
+        for day in &config.days {
+            let day: Weekday = day
+                .trim()
+                .parse()
+                .map_err(|_| BotError::ConfigError(format!("schedule.days: \"{}\" is not a day like \"mon\"", day)))?;
+            if !days.contains(&day) {
+                days.push(day);
+            }
+        }
+        if days.is_empty() {
+            days = WEEK.to_vec();
+        }
+        days.sort_by_key(Weekday::num_days_from_monday);
+        let start = config.start.as_deref().map(|start| parse_time("schedule.start", start)).transpose()?;
+        let end = config.end.as_deref().map(|end| parse_time("schedule.end", end)).transpose()?;
+        let start = start.unwrap_or(NaiveTime::MIN);
+        if end.is_some_and(|end| end <= start) {
+            return Err(BotError::ConfigError("schedule.end must be later than schedule.start".to_string()));
+        }
+        if days.len() == WEEK.len() && start == NaiveTime::MIN && end.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(SendWindow { days, start, end, timezone, recipient_timezone: config.recipient_timezone }))
+    }
+
+    // Whether leads are emailed in their own hours, so some may have to wait
+    // while others go out.
+    pub fn per_recipient(&self) -> bool {
+        self.recipient_timezone
+    }
+
+    // "Mon-Fri 09:00-17:00 America/New_York".
+    pub fn describe(&self) -> String {
+        let indexes: Vec<u32> = self.days.iter().map(Weekday::num_days_from_monday).collect();
+        let days = if indexes.windows(2).all(|pair| pair[1] == pair[0] + 1) && self.days.len() > 2 {
+            format!("{}-{}", self.days[0], self.days[self.days.len() - 1])
+        } else {
+            self.days.iter().map(Weekday::to_string).collect::<Vec<_>>().join(",")
+        };
+        let end = self.end.map_or("24:00".to_string(), |end| end.format("%H:%M").to_string());
+        let zone = if self.recipient_timezone {
+            format!("in the lead's time zone ({} when its state isn't known)", self.timezone.name())
+        } else {
+            self.timezone.name().to_string()
+        };
+        format!("{} {}-{} {}", days, self.start.format("%H:%M"), end, zone)
+    }
+
+    // The zone the window is in for this lead.
+    pub fn timezone_for(&self, business: &Business) -> Tz {
+        self.recipient_timezone.then(|| state_timezone(business)).flatten().unwrap_or(self.timezone)
+    }
+
+    // None while the window is open at `now` in `timezone`, or else when it
+    // opens next.
+    pub fn next_opening(&self, now: DateTime<Utc>, timezone: Tz) -> Option<DateTime<Utc>> {
+        let local = now.with_timezone(&timezone);
+        let time = local.time();
+        if self.days.contains(&local.weekday()) && time >= self.start && self.end.is_none_or(|end| time < end) {
+            return None;
+        }
+        (0..=7).find_map(|days| {
+            let date = local.date_naive() + Duration::days(days);
+            if !self.days.contains(&date.weekday()) || (days == 0 && time >= self.start) {
+                return None;
+            }
+            // A start skipped by a daylight saving jump opens an hour later.
+            let opening = date.and_time(self.start);
+            let opening = timezone.from_local_datetime(&opening).earliest().or_else(|| timezone.from_local_datetime(&(opening + Duration::hours(1))).earliest())?;
+            Some(opening.with_timezone(&Utc))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn every_hour_of_every_day_is_no_window() {
+        assert!(SendWindow::from_config(&ScheduleConfig::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn end_before_start_is_rejected() {
+        let config = ScheduleConfig { start: Some("17:00".to_string()), end: Some("09:00".to_string()), ..ScheduleConfig::default() };
+        assert!(SendWindow::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn open_inside_the_hours() {
+        let config = ScheduleConfig {
+            days: vec!["mon".to_string(), "tue".to_string(), "wed".to_string(), "thu".to_string(), "fri".to_string()],
+            start: Some("09:00".to_string()),
+            end: Some("17:00".to_string()),
+            timezone: "America/New_York".to_string(),
+            recipient_timezone: false,
+        };
+        let window = SendWindow::from_config(&config).unwrap().unwrap();
+        // Wednesday 12:00 in New York.
+        assert_eq!(window.next_opening(utc("2026-10-14T16:00:00Z"), America::New_York), None);
+        assert_eq!(window.describe(), "Mon-Fri 09:00-17:00 America/New_York");
+    }
+
+    #[test]
+    fn opens_the_next_morning_after_hours() {
+        let config = ScheduleConfig {
+            start: Some("09:00".to_string()),
+            end: Some("17:00".to_string()),
+            timezone: "America/New_York".to_string(),
+            ..ScheduleConfig::default()
+        };
+        let window = SendWindow::from_config(&config).unwrap().unwrap();
+        // Wednesday 18:00 in New York (EDT).
+        assert_eq!(window.next_opening(utc("2026-10-14T22:00:00Z"), America::New_York), Some(utc("2026-10-15T13:00:00Z")));
+        // Wednesday 07:00, before the start the same day.
+        assert_eq!(window.next_opening(utc("2026-10-14T11:00:00Z"), America::New_York), Some(utc("2026-10-14T13:00:00Z")));
+    }
+
+    #[test]
+    fn skips_to_the_next_allowed_day() {
+        let config = ScheduleConfig {
+            days: vec!["mon".to_string(), "tue".to_string(), "wed".to_string(), "thu".to_string(), "fri".to_string()],
+            start: Some("09:00".to_string()),
+            end: Some("17:00".to_string()),
+            timezone: "UTC".to_string(),
+            recipient_timezone: false,
+        };
+        let window = SendWindow::from_config(&config).unwrap().unwrap();
+        // Saturday noon opens on Monday morning.
+        assert_eq!(window.next_opening(utc("2026-10-17T12:00:00Z"), Tz::UTC), Some(utc("2026-10-19T09:00:00Z")));
+    }
+
+    #[test]
+    fn follows_the_clock_change() {
+        let config = ScheduleConfig { start: Some("09:00".to_string()), timezone: "America/New_York".to_string(), ..ScheduleConfig::default() };
+        let window = SendWindow::from_config(&config).unwrap().unwrap();
+        // Clocks go back on Sunday 2026-11-01, so 09:00 is 13:00 UTC before and 14:00 after.
+        assert_eq!(window.next_opening(utc("2026-10-31T12:00:00Z"), America::New_York), Some(utc("2026-10-31T13:00:00Z")));
+        assert_eq!(window.next_opening(utc("2026-11-01T12:00:00Z"), America::New_York), Some(utc("2026-11-01T14:00:00Z")));
+    }
+
+    #[test]
+    fn a_start_skipped_by_the_clock_change_opens_an_hour_later() {
+        let config = ScheduleConfig { start: Some("02:30".to_string()), timezone: "America/New_York".to_string(), ..ScheduleConfig::default() };
+        let window = SendWindow::from_config(&config).unwrap().unwrap();
+        // 02:30 doesn't exist on Sunday 2026-03-08; 03:30 EDT is 07:30 UTC.
+        assert_eq!(window.next_opening(utc("2026-03-08T05:00:00Z"), America::New_York), Some(utc("2026-03-08T07:30:00Z")));
+    }
+
+    #[test]
+    fn recipient_timezone_goes_by_the_state() {
+        let config = ScheduleConfig { start: Some("09:00".to_string()), recipient_timezone: true, ..ScheduleConfig::default() };
+        let window = SendWindow::from_config(&config).unwrap().unwrap();
+        let business = Business { address: Some("1 Main St, Austin, TX 78701".to_string()), ..Business::default() };
+        assert_eq!(window.timezone_for(&business), America::Chicago);
+        let business = Business { location: Some("Columbus, OH".to_string()), ..Business::default() };
+        assert_eq!(window.timezone_for(&business), America::New_York);
+        assert_eq!(window.timezone_for(&Business::default()), Tz::UTC);
+    }
+}
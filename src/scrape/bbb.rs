@@ -0,0 +1,84 @@
+use std::collections::HashSet;
+use reqwest::Url;
+use scraper::Html;
+use crate::config::SourceConfig;
+use crate::error::BotError;
+use crate::scrape::selectors::{SelectorDefaults, Selectors};
+use crate::scrape::{Business, ScrapeSource, SearchQuery};
+
+const BASE_URL: &str = "https://www.bbb.org";
+
+const DEFAULT_SELECTORS: SelectorDefaults = SelectorDefaults {
+    business_link: r#"a[href*="/profile/"]"#,
+    email: r#"a[href^="mailto:"]"#,
+    name: "h1",
+    phone: r#"a[href^="tel:"]"#,
+    address: "address",
+    website: "a.dtm-url",
+    category: r#"a[href*="/category/"]"#,
+};
+
+pub struct BbbSource {
+    selectors: Selectors,
+}
+
+impl BbbSource {
+    pub fn new(config: &SourceConfig) -> Result<Self, BotError> {
+        Ok(BbbSource {
+            selectors: Selectors::compile("bbb", &config.selectors, &DEFAULT_SELECTORS)?,
+        })
+    }
+
+    // Restricted to BBB accredited businesses.
+    pub fn list_page_url(query: &SearchQuery, page_number: usize) -> String {
+        let url = Url::parse_with_params(
+            &format!("{}/search", BASE_URL),
+            &[
+                ("find_country", "USA"),
+                ("find_text", query.search_terms.as_str()),
+                ("find_loc", query.geo_location.as_str()),
+                ("filter_accredited", "1"),
+                ("page", &page_number.to_string()),
+            ],
+        ).expect("base URL is valid");
+        url.to_string()
+    }
+}
+
+impl ScrapeSource for BbbSource {
+    fn name(&self) -> &'static str {
+        "bbb"
+    }
+
+    fn list_pages(&self, query: &SearchQuery) -> Box<dyn Iterator<Item = String> + Send> {
+        let query = query.clone();
+        Box::new((1..).map(move |page_number| Self::list_page_url(&query, page_number)))
+    }
+
+    fn detail_links(&self, list_page_html: &str) -> Vec<String> {
+        let list_page_document = Html::parse_document(list_page_html);
+        let base = Url::parse(BASE_URL).expect("base URL is valid");
+        let mut seen = HashSet::new();
+
+        // Profile links are sometimes absolute and sometimes relative, and the
+        // "/addressId/..." variants point back at the same business.
+        list_page_document
+            .select(&self.selectors.business_link)
+            .filter_map(|link_element| link_element.value().attr("href"))
+            .filter_map(|href| base.join(href).ok())
+            .map(|mut url| {
+                url.set_query(None);
+                url.set_fragment(None);
+                let path = url.path().split("/addressId/").next().unwrap_or("").to_string();
+                url.set_path(&path);
+                url.to_string()
+            })
+            .filter(|url| seen.insert(url.clone()))
+            .collect()
+    }
+
+    fn extract_business(&self, detail_url: &str, detail_page_html: &str) -> Business {
+        let detail_page_document = Html::parse_document(detail_page_html);
+        self.selectors.extract_business(detail_url, &detail_page_document)
+    }
+}
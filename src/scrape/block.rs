@@ -0,0 +1,102 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const DEFAULT_BLOCK_COOLDOWN_SECS: u64 = 60;
+pub const DEFAULT_MAX_BLOCK_PAUSES: u32 = 5;
+pub const DEFAULT_EMPTY_PAGE_STREAK: usize = 5;
+const MAX_COOLDOWN: Duration = Duration::from_secs(1800);
+
+// Challenge pages from bot-protection vendors; these never show up on a normal page.
+const CHALLENGE_MARKERS: &[(&str, &str)] = &[
+    ("cf-chl-", "Cloudflare challenge"),
+    ("/cdn-cgi/challenge-platform/", "Cloudflare challenge"),
+    ("attention required! | cloudflare", "Cloudflare block page"),
+    ("captcha-delivery.com", "DataDome CAPTCHA"),
+    ("px-captcha", "PerimeterX CAPTCHA"),
+    ("_incapsula_resource", "Incapsula interstitial"),
+    ("distil_r_captcha", "Distil CAPTCHA"),
+];
+
+// Contact forms embed CAPTCHAs too, so these only count on small pages.
+const CAPTCHA_MARKERS: &[(&str, &str)] = &[
+    ("g-recaptcha", "reCAPTCHA"),
+    ("hcaptcha.com", "hCaptcha"),
+    ("are you a robot", "robot check"),
+    ("unusual traffic", "unusual traffic warning"),
+    ("<title>access denied", "access denied page"),
+    ("<title>just a moment", "interstitial"),
+];
+const SMALL_PAGE_BYTES: usize = 20_000;
+
+// Returns why the page looks like a block or CAPTCHA rather than real content.
+pub fn block_reason(html: &str) -> Option<&'static str> {
+    let lower = html.to_lowercase();
+    if let Some((_, reason)) = CHALLENGE_MARKERS.iter().find(|(marker, _)| lower.contains(marker)) {
+        return Some(reason);
+    }
+    if html.len() < SMALL_PAGE_BYTES {
+        if let Some((_, reason)) = CAPTCHA_MARKERS.iter().find(|(marker, _)| lower.contains(marker)) {
+            return Some(reason);
+        }
+    }
+    None
+}
+
+struct GuardState {
+    pauses: u32,
+    empty_streak: usize,
+}
+
+// Pauses scraping with an exponentially growing cool-down when pages look
+// blocked, and gives up after `max_pauses` pauses in a row.
+pub struct BlockGuard {
+    pub base_cooldown: Duration,
+    pub max_pauses: u32,
+    pub empty_page_streak: usize,
+    pub rotate_proxy: bool,
+    state: Mutex<GuardState>,
+}
+
+impl BlockGuard {
+    pub fn new(base_cooldown: Duration, max_pauses: u32, rotate_proxy: bool) -> Self {
+        BlockGuard {
+            base_cooldown,
+            max_pauses,
+            empty_page_streak: DEFAULT_EMPTY_PAGE_STREAK,
+            rotate_proxy,
+            state: Mutex::new(GuardState { pauses: 0, empty_streak: 0 }),
+        }
+    }
+
+    // The cool-down to wait before trying again, or None once the pause budget is spent.
+    pub fn next_cooldown(&self) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap();
+        if state.pauses >= self.max_pauses {
+            return None;
+        }
+        state.pauses += 1;
+        let cooldown = self.base_cooldown.saturating_mul(2u32.saturating_pow(state.pauses - 1));
+        Some(cooldown.min(MAX_COOLDOWN))
+    }
+
+    pub fn reset(&self) {
+        self.state.lock().unwrap().pauses = 0;
+    }
+
+    // Tracks detail pages where the selectors found nothing at all. Returns true
+    // when enough of them came in a row that the site is probably serving a block.
+    pub fn record_extraction(&self, empty: bool) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !empty {
+            state.empty_streak = 0;
+            state.pauses = 0;
+            return false;
+        }
+        state.empty_streak += 1;
+        if state.empty_streak >= self.empty_page_streak {
+            state.empty_streak = 0;
+            return true;
+        }
+        false
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use sha2::{Digest, Sha256};
+use crate::error::BotError;
+
+pub const DEFAULT_CACHE_TTL_SECS: u64 = 86400;
+
+pub struct PageCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl PageCache {
+    pub fn new(dir: &str, ttl: Duration) -> Result<Self, BotError> {
+        std::fs::create_dir_all(dir).map_err(BotError::IOError)?;
+        Ok(PageCache {
+            dir: PathBuf::from(dir),
+            ttl,
+        })
+    }
+
+    fn path_for(&self, url: &str) -> PathBuf {
+        let digest = Sha256::digest(url.as_bytes());
+        self.dir.join(format!("{:x}.html", digest))
+    }
+
+    pub async fn get(&self, url: &str) -> Option<String> {
+        let path = self.path_for(url);
+        let metadata = tokio::fs::metadata(&path).await.ok()?;
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or(Duration::MAX);
+        if age > self.ttl {
+            return None;
+        }
+        tokio::fs::read_to_string(&path).await.ok()
+    }
+
+    pub async fn put(&self, url: &str, html: &str) -> Result<(), BotError> {
+        let path = self.path_for(url);
+        tokio::fs::write(&path, html).await.map_err(BotError::IOError)
+    }
+
+    pub fn clear(&self) -> Result<(), BotError> {
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(BotError::IOError(e)),
+            _ => {}
+        }
+        std::fs::create_dir_all(&self.dir).map_err(BotError::IOError)
+    }
+}
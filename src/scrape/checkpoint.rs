@@ -0,0 +1,68 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use serde::{Serialize, Deserialize};
+use crate::error::BotError;
+use crate::scrape::{Business, SearchQuery};
+
+pub const DEFAULT_CHECKPOINT_PATH: &str = "scrape_checkpoint.json";
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ScrapeCheckpoint {
+    pub source: String,
+    pub queries: Vec<SearchQuery>,
+    pub query_index: usize,
+    pub next_page: usize,
+    pub processed_urls: HashSet<String>,
+    pub businesses: Vec<Business>,
+    // Emails of leads already written to the output stream instead of being
+    // kept in `businesses`.
+    #[serde(default)]
+    pub streamed_emails: Vec<String>,
+}
+
+impl ScrapeCheckpoint {
+    pub fn new(source: &str, queries: &[SearchQuery]) -> Self {
+        ScrapeCheckpoint {
+            source: source.to_string(),
+            queries: queries.to_vec(),
+            next_page: 1,
+            ..ScrapeCheckpoint::default()
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Option<Self>, BotError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Some(serde_json::from_str(&contents).map_err(BotError::DataParseError)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(BotError::IOError(e)),
+        }
+    }
+
+    // Written to a temp file and renamed so a crash mid-write never corrupts the checkpoint.
+    pub fn save(&self, path: &str) -> Result<(), BotError> {
+        let tmp_path = format!("{}.tmp", path);
+        let json_data = serde_json::to_string(self).map_err(BotError::DataParseError)?;
+        fs::write(&tmp_path, json_data).map_err(BotError::IOError)?;
+        fs::rename(&tmp_path, path).map_err(BotError::IOError)
+    }
+
+    pub fn remove(path: &str) -> Result<(), BotError> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(BotError::IOError(e)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn lead_count(&self) -> usize {
+        self.businesses.len() + self.streamed_emails.len()
+    }
+
+    pub fn lead_emails(&self) -> impl Iterator<Item = &String> {
+        self.businesses.iter().map(|business| &business.email).chain(&self.streamed_emails)
+    }
+
+    pub fn matches(&self, source: &str, queries: &[SearchQuery]) -> bool {
+        self.source == source && self.queries == queries
+    }
+}
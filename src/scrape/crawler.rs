@@ -0,0 +1,167 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use futures::stream::{self, StreamExt};
+use reqwest::Url;
+use scraper::{Html, Selector};
+use crate::email;
+use crate::error::BotError;
+use crate::filter::EmailFilter;
+use crate::queue::LeadQueue;
+use crate::scrape::extract::{is_contact_link, page_emails};
+use crate::scrape::{Business, Fetcher, DEFAULT_CONCURRENCY};
+use crate::shutdown::Shutdown;
+use crate::storage::LeadStream;
+
+pub const DEFAULT_MAX_PAGES_PER_SITE: usize = 5;
+
+const SKIPPED_EXTENSIONS: &[&str] = &[".pdf", ".jpg", ".jpeg", ".png", ".gif", ".svg", ".zip", ".mp4", ".css", ".js"];
+
+pub struct WebsiteCrawler {
+    pub max_pages_per_site: usize,
+    pub concurrency: usize,
+    pub filter: Arc<EmailFilter>,
+    pub stream: Option<Arc<LeadStream>>,
+    pub queue: Option<Arc<LeadQueue>>,
+    // Asked for, each site stops at its next page.
+    pub shutdown: Shutdown,
+}
+
+impl Default for WebsiteCrawler {
+    fn default() -> Self {
+        WebsiteCrawler {
+            max_pages_per_site: DEFAULT_MAX_PAGES_PER_SITE,
+            concurrency: DEFAULT_CONCURRENCY,
+            filter: Arc::new(EmailFilter::default()),
+            stream: None,
+            queue: None,
+            shutdown: Shutdown::never(),
+        }
+    }
+}
+
+struct SitePage {
+    emails: Vec<String>,
+    links: Vec<(Url, bool)>,
+}
+
+fn parse_site_page(base: &Url, html: &str) -> SitePage {
+    let document = Html::parse_document(html);
+    let link_selector = Selector::parse("a[href]").unwrap();
+    let links = document
+        .select(&link_selector)
+        .filter_map(|link_element| {
+            let href = link_element.value().attr("href")?;
+            let mut url = base.join(href).ok()?;
+            url.set_fragment(None);
+            let priority = is_contact_link(&link_element, &url);
+            Some((url, priority))
+        })
+        .collect();
+
+    SitePage {
+        emails: page_emails(&document),
+        links,
+    }
+}
+
+fn is_crawlable(site: &Url, url: &Url) -> bool {
+    let path = url.path().to_lowercase();
+    matches!(url.scheme(), "http" | "https")
+        && url.host_str() == site.host_str()
+        && !SKIPPED_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+}
+
+impl WebsiteCrawler {
+    pub async fn crawl_site(&self, fetcher: &Fetcher, site_url: &str) -> Result<Vec<String>, BotError> {
+        let site = Url::parse(site_url)
+            .map_err(|e| BotError::InvalidData(format!("invalid site URL '{}': {}", site_url, e)))?;
+
+        let mut emails = Vec::new();
+        let mut visited: HashSet<Url> = HashSet::new();
+        let mut priority_queue: VecDeque<Url> = VecDeque::new();
+        let mut queue: VecDeque<Url> = VecDeque::from([site.clone()]);
+
+        while visited.len() < self.max_pages_per_site && !self.shutdown.requested() {
+            let Some(page_url) = priority_queue.pop_front().or_else(|| queue.pop_front()) else {
+                break;
+            };
+            if !visited.insert(page_url.clone()) {
+                continue;
+            }
+
+            let html = match fetcher.fetch(page_url.as_str()).await {
+                Ok(html) => html,
+                Err(e) => {
+                    eprintln!("Could not fetch {}: {}", page_url, e);
+                    continue;
+                }
+            };
+
+            let page = parse_site_page(&page_url, &html);
+            for email in page.emails {
+                if !emails.contains(&email) {
+                    emails.push(email);
+                }
+            }
+            for (link, priority) in page.links {
+                if !is_crawlable(&site, &link) || visited.contains(&link) {
+                    continue;
+                }
+                if priority {
+                    priority_queue.push_back(link);
+                } else {
+                    queue.push_back(link);
+                }
+            }
+        }
+
+        Ok(emails)
+    }
+
+    // With a stream configured, leads are appended to it and only the
+    // returned Vec stays empty.
+    pub async fn crawl_sites(&self, fetcher: &Fetcher, site_urls: &[String]) -> Result<Vec<Business>, BotError> {
+        let mut processed_emails: HashSet<String> = HashSet::new();
+        let mut businesses = Vec::new();
+
+        let results: Vec<(&String, Result<Vec<String>, BotError>)> = stream::iter(site_urls)
+            .map(|site_url| async move { (site_url, self.crawl_site(fetcher, site_url).await) })
+            .buffer_unordered(self.concurrency.max(1))
+            .collect()
+            .await;
+
+        for (site_url, result) in results {
+            match result {
+                Ok(emails) => {
+                    for email in emails {
+                        if let Some(reason) = self.filter.rejection(&email) {
+                            println!("Skipping {} ({})", email, reason);
+                            continue;
+                        }
+                        let email = email::normalize_email(&email);
+                        if processed_emails.insert(email::dedup_key(&email)) {
+                            println!("Business URL: {}", site_url);
+                            println!("Business Email: {}", email);
+                            let business = Business {
+                                url: site_url.clone(),
+                                email,
+                                website: Some(site_url.clone()),
+                                ..Business::default()
+                            };
+                            if let Some(queue) = &self.queue {
+                                queue.push(&business).await?;
+                            }
+                            match &self.stream {
+                                Some(stream) => stream.append(&business)?,
+                                None => businesses.push(business),
+                            }
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Skipping {}: {}", site_url, e),
+            }
+        }
+
+        Ok(businesses)
+    }
+}
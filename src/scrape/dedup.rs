@@ -0,0 +1,69 @@
+use std::time::Duration;
+use chrono::Utc;
+use redis::AsyncCommands;
+use crate::email::dedup_key;
+use crate::error::BotError;
+use crate::ratelimit::{connection, RedisPool};
+
+const SEEN_URLS_KEY: &str = "scrape:seen_urls";
+const SEEN_EMAILS_KEY: &str = "scrape:seen_emails";
+
+// Detail URLs and emails seen by earlier runs. Stored as sorted sets scored by
+// the time they were seen, so entries can age out individually after `ttl`.
+pub struct SeenStore {
+    pool: RedisPool,
+    ttl: Option<Duration>,
+}
+
+impl SeenStore {
+    pub fn new(pool: RedisPool, ttl: Option<Duration>) -> Self {
+        SeenStore { pool, ttl }
+    }
+
+    fn cutoff(&self) -> i64 {
+        match self.ttl {
+            Some(ttl) => Utc::now().timestamp() - ttl.as_secs() as i64,
+            None => i64::MIN,
+        }
+    }
+
+    async fn contains(&self, key: &str, member: &str) -> Result<bool, BotError> {
+        let mut con = connection(&self.pool).await?;
+        let score: Option<i64> = con.zscore(key, member).await.map_err(BotError::RedisError)?;
+        Ok(score.is_some_and(|seen_at| seen_at >= self.cutoff()))
+    }
+
+    async fn insert(&self, key: &str, member: &str) -> Result<(), BotError> {
+        let mut con = connection(&self.pool).await?;
+        let _: () = con.zadd(key, member, Utc::now().timestamp()).await.map_err(BotError::RedisError)?;
+        Ok(())
+    }
+
+    pub async fn prune(&self) -> Result<(), BotError> {
+        if self.ttl.is_none() {
+            return Ok(());
+        }
+        let cutoff = self.cutoff();
+        let mut con = connection(&self.pool).await?;
+        for key in [SEEN_URLS_KEY, SEEN_EMAILS_KEY] {
+            let _: () = con.zrembyscore(key, "-inf", cutoff - 1).await.map_err(BotError::RedisError)?;
+        }
+        Ok(())
+    }
+
+    pub async fn has_url(&self, url: &str) -> Result<bool, BotError> {
+        self.contains(SEEN_URLS_KEY, url).await
+    }
+
+    pub async fn add_url(&self, url: &str) -> Result<(), BotError> {
+        self.insert(SEEN_URLS_KEY, url).await
+    }
+
+    pub async fn has_email(&self, email: &str) -> Result<bool, BotError> {
+        self.contains(SEEN_EMAILS_KEY, &dedup_key(email)).await
+    }
+
+    pub async fn add_email(&self, email: &str) -> Result<(), BotError> {
+        self.insert(SEEN_EMAILS_KEY, &dedup_key(email)).await
+    }
+}
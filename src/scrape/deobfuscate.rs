@@ -0,0 +1,94 @@
+use std::sync::LazyLock;
+use regex::Regex;
+
+static BRACKETED_AT_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\s*[\[\(\{<]\s*(?:at|@)\s*[\]\)\}>]\s*").unwrap()
+});
+static BRACKETED_DOT_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\s*[\[\(\{<]\s*(?:dot|\.)\s*[\]\)\}>]\s*").unwrap()
+});
+// Bare words only count when they form a whole address: "jane at acme dot com".
+static SPELLED_OUT_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b([a-z0-9._%+-]+)\s+at\s+([a-z0-9-]+(?:\s+dot\s+[a-z0-9-]+)+)\b").unwrap()
+});
+static SPELLED_DOT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\s+dot\s+").unwrap());
+static REVERSED_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b[A-Za-z]{2,}\.[A-Za-z0-9.-]+@[A-Za-z0-9._%+-]+").unwrap()
+});
+
+const ENTITIES: &[(&str, &str)] = &[
+    ("&#64;", "@"),
+    ("&#x40;", "@"),
+    ("&#X40;", "@"),
+    ("&commat;", "@"),
+    ("%40", "@"),
+    ("&#46;", "."),
+    ("&#x2e;", "."),
+    ("&#x2E;", "."),
+    ("&period;", "."),
+    ("&amp;", "&"),
+];
+
+pub fn deobfuscate(text: &str) -> String {
+    let mut decoded = text.to_string();
+    // &amp; goes last in ENTITIES, so run twice to catch double-escaped "&amp;#64;".
+    for _ in 0..2 {
+        for (entity, replacement) in ENTITIES {
+            decoded = decoded.replace(entity, replacement);
+        }
+    }
+
+    let decoded = BRACKETED_AT_RE.replace_all(&decoded, "@");
+    let decoded = BRACKETED_DOT_RE.replace_all(&decoded, ".");
+    SPELLED_OUT_RE
+        .replace_all(&decoded, |caps: &regex::Captures| {
+            format!("{}@{}", &caps[1], SPELLED_DOT_RE.replace_all(&caps[2], "."))
+        })
+        .into_owned()
+}
+
+// Catches addresses rendered backwards and flipped with CSS (direction: rtl),
+// e.g. "moc.emca@enaj". A dot after the @ means it already reads as a normal
+// address, so those are left alone.
+pub fn reversed_candidates(text: &str) -> Vec<String> {
+    REVERSED_RE
+        .find_iter(text)
+        .map(|m| m.as_str())
+        .filter(|candidate| candidate.rsplit_once('@').is_some_and(|(_, tail)| !tail.contains('.')))
+        .map(|candidate| candidate.chars().rev().collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(deobfuscate("jane&#64;acme&#46;com"), "jane@acme.com");
+        assert_eq!(deobfuscate("jane&amp;#64;acme.com"), "jane@acme.com");
+        assert_eq!(deobfuscate("jane%40acme.com"), "jane@acme.com");
+    }
+
+    #[test]
+    fn replaces_bracketed_words() {
+        assert_eq!(deobfuscate("jane [at] acme (dot) com"), "jane@acme.com");
+        assert_eq!(deobfuscate("jane{@}acme<.>com"), "jane@acme.com");
+    }
+
+    #[test]
+    fn replaces_a_spelled_out_address() {
+        assert_eq!(deobfuscate("Write to jane at acme dot co dot uk today"), "Write to jane@acme.co.uk today");
+    }
+
+    #[test]
+    fn leaves_bare_words_in_sentences() {
+        assert_eq!(deobfuscate("Open at 9, closed at noon"), "Open at 9, closed at noon");
+    }
+
+    #[test]
+    fn reverses_flipped_addresses() {
+        assert_eq!(reversed_candidates("mail moc.emca@enaj now"), vec!["jane@acme.com".to_string()]);
+        assert!(reversed_candidates("jane@acme.com").is_empty());
+    }
+}
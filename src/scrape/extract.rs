@@ -0,0 +1,120 @@
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use regex::Regex;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use crate::email::is_valid_email;
+use crate::scrape::deobfuscate::{deobfuscate, reversed_candidates};
+use crate::scrape::email_from_element;
+
+static EMAIL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap()
+});
+
+pub const CONTACT_HINTS: &[&str] = &["contact", "kontakt", "about", "impressum", "team"];
+const MAX_CONTACT_LINKS: usize = 3;
+
+const ASSET_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "svg", "webp", "ico", "css", "js", "bmp", "tiff"];
+const PLACEHOLDER_DOMAINS: &[&str] = &[
+    "example.com", "example.org", "domain.com", "email.com", "yourdomain.com",
+    "sentry.io", "sentry.wixpress.com", "wixpress.com",
+];
+
+// Things the regex picks up that aren't real contact addresses: retina image names
+// like logo@2x.png, placeholder addresses and error-tracker DSNs.
+pub fn is_false_positive(email: &str) -> bool {
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return true;
+    };
+    let domain = domain.to_lowercase();
+    let tld = domain.rsplit('.').next().unwrap_or("");
+
+    ASSET_EXTENSIONS.contains(&tld)
+        || PLACEHOLDER_DOMAINS.iter().any(|placeholder| domain == *placeholder || domain.ends_with(&format!(".{}", placeholder)))
+        || (local.len() >= 24 && local.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+pub fn mailto_emails(document: &Html) -> Vec<String> {
+    let mailto_selector = Selector::parse(r#"a[href^="mailto:"]"#).unwrap();
+    document
+        .select(&mailto_selector)
+        .map(email_from_element)
+        .filter(|email| !email.is_empty())
+        .collect()
+}
+
+pub fn text_emails(text: &str) -> Vec<String> {
+    EMAIL_RE
+        .find_iter(text)
+        .map(|m| m.as_str().trim_end_matches('.').to_string())
+        .filter(|email| !is_false_positive(email))
+        .collect()
+}
+
+pub fn page_text(document: &Html) -> String {
+    document.root_element().text().collect::<Vec<_>>().join(" ")
+}
+
+// mailto: links first, then anything that looks like an address in the visible text,
+// then addresses that only appear once the text is deobfuscated or reversed.
+pub fn page_emails(document: &Html) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let text = page_text(document);
+    let reversed: Vec<String> = reversed_candidates(&text)
+        .iter()
+        .flat_map(|candidate| text_emails(candidate))
+        .collect();
+
+    mailto_emails(document)
+        .into_iter()
+        .chain(text_emails(&text))
+        .chain(text_emails(&deobfuscate(&text)))
+        .chain(reversed)
+        .filter(|email| is_valid_email(email))
+        .filter(|email| seen.insert(email.to_lowercase()))
+        .collect()
+}
+
+// Used when a source's email selector finds nothing on the detail page.
+pub fn fallback_email(document: &Html) -> Option<String> {
+    page_emails(document).into_iter().next()
+}
+
+pub fn is_contact_link(link_element: &ElementRef, url: &Url) -> bool {
+    let text = link_element.text().collect::<String>().to_lowercase();
+    let path = url.path().to_lowercase();
+    CONTACT_HINTS.iter().any(|hint| path.contains(hint) || text.contains(hint))
+}
+
+// Links that look like contact/about pages, resolved against `page_url`.
+// `keep` decides which hosts are worth following.
+pub fn contact_links(page_url: &str, html: &str, keep: impl Fn(&Url) -> bool) -> Vec<String> {
+    let Ok(base) = Url::parse(page_url) else {
+        return Vec::new();
+    };
+    let document = Html::parse_document(html);
+    let link_selector = Selector::parse("a[href]").unwrap();
+    let mut links: Vec<String> = Vec::new();
+
+    for link_element in document.select(&link_selector) {
+        let Some(mut url) = link_element.value().attr("href").and_then(|href| base.join(href).ok()) else {
+            continue;
+        };
+        url.set_fragment(None);
+        if !matches!(url.scheme(), "http" | "https") || !keep(&url) || !is_contact_link(&link_element, &url) {
+            continue;
+        }
+        let url = url.to_string();
+        if url != page_url && !links.contains(&url) {
+            links.push(url);
+        }
+        if links.len() >= MAX_CONTACT_LINKS {
+            break;
+        }
+    }
+    links
+}
+
+pub fn html_emails(html: &str) -> Vec<String> {
+    page_emails(&Html::parse_document(html))
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+use fantoccini::{Client, ClientBuilder};
+use tokio::sync::Mutex;
+use crate::error::BotError;
+
+const RENDER_WAIT: Duration = Duration::from_secs(2);
+
+pub struct HeadlessBrowser {
+    client: Mutex<Client>,
+}
+
+impl HeadlessBrowser {
+    pub async fn connect(webdriver_url: &str) -> Result<Self, BotError> {
+        let mut capabilities = serde_json::Map::new();
+        capabilities.insert(
+            "goog:chromeOptions".to_string(),
+            serde_json::json!({ "args": ["--headless", "--disable-gpu", "--no-sandbox"] }),
+        );
+        capabilities.insert(
+            "moz:firefoxOptions".to_string(),
+            serde_json::json!({ "args": ["-headless"] }),
+        );
+
+        let client = ClientBuilder::native()
+            .capabilities(capabilities)
+            .connect(webdriver_url)
+            .await
+            .map_err(|e| BotError::BrowserError(e.to_string()))?;
+
+        Ok(HeadlessBrowser {
+            client: Mutex::new(client),
+        })
+    }
+
+    pub async fn render(&self, url: &str) -> Result<String, BotError> {
+        let client = self.client.lock().await;
+        client.goto(url).await.map_err(|e| BotError::BrowserError(e.to_string()))?;
+        // Give client-side scripts a moment to inject the contact details.
+        tokio::time::sleep(RENDER_WAIT).await;
+        client.source().await.map_err(|e| BotError::BrowserError(e.to_string()))
+    }
+
+    pub async fn close(&self) {
+        let client = self.client.lock().await.clone();
+        if let Err(e) = client.close().await {
+            eprintln!("Could not close headless browser session: {}", e);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use reqwest::Url;
+use serde::Deserialize;
+use crate::error::BotError;
+use crate::http_client;
+
+const DOMAIN_SEARCH_URL: &str = "https://api.hunter.io/v2/domain-search";
+
+#[derive(Deserialize, Debug)]
+struct DomainSearchResponse {
+    data: DomainSearchData,
+}
+
+#[derive(Deserialize, Debug)]
+struct DomainSearchData {
+    #[serde(default)]
+    emails: Vec<HunterEmail>,
+}
+
+#[derive(Deserialize, Debug)]
+struct HunterEmail {
+    value: String,
+    #[serde(default)]
+    confidence: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct HunterMatch {
+    pub email: String,
+    pub confidence: u8,
+}
+
+pub struct HunterClient {
+    client: reqwest::Client,
+    api_key: String,
+}
+
+pub fn website_domain(website: &str) -> Option<String> {
+    let url = if website.contains("://") {
+        Url::parse(website)
+    } else {
+        Url::parse(&format!("http://{}", website))
+    };
+    let host = url.ok()?.host_str()?.to_lowercase();
+    Some(host.strip_prefix("www.").unwrap_or(&host).to_string())
+}
+
+impl HunterClient {
+    pub fn new(api_key: &str) -> Result<Self, BotError> {
+        Ok(HunterClient {
+            client: http_client::client()?,
+            api_key: api_key.to_string(),
+        })
+    }
+
+    pub fn from_env() -> Result<Option<Self>, BotError> {
+        std::env::var("HUNTER_API_KEY")
+            .ok()
+            .filter(|key| !key.is_empty())
+            .map(|key| HunterClient::new(&key))
+            .transpose()
+    }
+
+    // The highest-confidence address Hunter knows for the website's domain.
+    pub async fn domain_search(&self, website: &str) -> Result<Option<HunterMatch>, BotError> {
+        let Some(domain) = website_domain(website) else {
+            return Ok(None);
+        };
+
+        let response = self.client
+            .get(DOMAIN_SEARCH_URL)
+            .query(&[("domain", domain.as_str()), ("api_key", self.api_key.as_str()), ("limit", "10")])
+            .send()
+            .await
+            .map_err(BotError::NetworkError)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(BotError::HttpStatus { url: DOMAIN_SEARCH_URL.to_string(), status: status.as_u16() });
+        }
+
+        let body: DomainSearchResponse = response.json().await.map_err(BotError::NetworkError)?;
+        Ok(body
+            .data
+            .emails
+            .into_iter()
+            .max_by_key(|email| email.confidence)
+            .map(|email| HunterMatch {
+                email: email.value,
+                confidence: email.confidence,
+            }))
+    }
+}
@@ -0,0 +1,723 @@
+pub mod bbb;
+pub mod block;
+pub mod cache;
+pub mod checkpoint;
+pub mod crawler;
+pub mod dedup;
+pub mod deobfuscate;
+pub mod extract;
+#[cfg(feature = "headless")]
+pub mod headless;
+pub mod hunter;
+pub mod robots;
+pub mod selectors;
+pub mod sitemap;
+pub mod structured;
+pub mod yellowpages;
+pub mod yelp;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use futures::stream::{self, StreamExt};
+use reqwest::Url;
+use regex::Regex;
+use scraper::ElementRef;
+use serde::{Serialize, Deserialize};
+use tokio::sync::mpsc;
+use crate::config::Config;
+use crate::email;
+use crate::error::BotError;
+use crate::filter::EmailFilter;
+use crate::http_client::HttpClient;
+use crate::queue::LeadQueue;
+use crate::shutdown::Shutdown;
+use crate::storage::LeadStream;
+use crate::validation::SmtpStatus;
+
+pub use bbb::BbbSource;
+pub use block::BlockGuard;
+pub use cache::PageCache;
+pub use checkpoint::{ScrapeCheckpoint, DEFAULT_CHECKPOINT_PATH};
+pub use crawler::WebsiteCrawler;
+pub use dedup::SeenStore;
+pub use hunter::HunterClient;
+#[cfg(feature = "headless")]
+pub use headless::HeadlessBrowser;
+pub use robots::RobotsChecker;
+pub use yellowpages::YellowPagesSource;
+pub use yelp::YelpSource;
+
+pub const DEFAULT_SEARCH_TERMS: &str = "Electricians";
+pub const DEFAULT_GEO_LOCATION: &str = "Columbus, OH";
+pub const DEFAULT_CONCURRENCY: usize = 4;
+pub const DEFAULT_SOURCE: &str = "yellowpages";
+// Sitemap pages are fetched in batches so --max-leads can stop between them.
+const SITEMAP_BATCH_SIZE: usize = 20;
+pub const SOURCE_NAMES: &[&str] = &["yellowpages", "yelp", "bbb"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Business {
+    pub url: String,
+    pub email: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub website: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    // The geo location of the search that found this business.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    // Hunter.io confidence (0-100) when the email came from its domain search.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_confidence: Option<u8>,
+    // Whether the email domain accepts mail, once `validate` has looked up its MX records.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mx_valid: Option<bool>,
+    // Result of the optional SMTP RCPT TO check.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smtp_status: Option<SmtpStatus>,
+    // Set when a teammate reviews the lead, e.g. in a shared spreadsheet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review_status: Option<ReviewStatus>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewStatus {
+    Approved,
+    Rejected,
+}
+
+impl ReviewStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReviewStatus::Approved => "approved",
+            ReviewStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "approved" => Some(ReviewStatus::Approved),
+            "rejected" => Some(ReviewStatus::Rejected),
+            _ => None,
+        }
+    }
+
+    // How reviewers tend to fill in a spreadsheet cell: checkboxes, yes/no,
+    // x, or the status itself. Blank or anything else means not reviewed yet.
+    pub fn from_cell(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "approved" | "approve" | "yes" | "y" | "true" | "x" | "1" | "ok" | "\u{2713}" => Some(ReviewStatus::Approved),
+            "rejected" | "reject" | "no" | "n" | "false" | "0" => Some(ReviewStatus::Rejected),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub search_terms: String,
+    pub geo_location: String,
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        SearchQuery {
+            search_terms: DEFAULT_SEARCH_TERMS.to_string(),
+            geo_location: DEFAULT_GEO_LOCATION.to_string(),
+        }
+    }
+}
+
+pub trait ScrapeSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Paged search result URLs, in order. Scraping stops at the first page without detail links.
+    fn list_pages(&self, query: &SearchQuery) -> Box<dyn Iterator<Item = String> + Send>;
+
+    fn detail_links(&self, list_page_html: &str) -> Vec<String>;
+
+    // Returns whatever the page has; `email` is left empty when none was found so
+    // the pipeline can try other ways of finding one.
+    fn extract_business(&self, detail_url: &str, detail_page_html: &str) -> Business;
+}
+
+pub fn source_by_name(name: &str, config: &Config) -> Result<Box<dyn ScrapeSource>, BotError> {
+    let source_config = config.source(name);
+    match name {
+        "yellowpages" => Ok(Box::new(YellowPagesSource::new(&source_config)?)),
+        "yelp" => Ok(Box::new(YelpSource::new(&source_config)?)),
+        "bbb" => Ok(Box::new(BbbSource::new(&source_config)?)),
+        other => Err(BotError::InvalidData(format!(
+            "unknown scrape source '{}', expected one of: {}",
+            other,
+            SOURCE_NAMES.join(", ")
+        ))),
+    }
+}
+
+pub fn email_from_element(email_element: ElementRef) -> String {
+    let raw = if let Some(email_href) = email_element.value().attr("href") {
+        if email_href.starts_with("mailto:") {
+            let re = Regex::new(r"mailto:([^?]+)").unwrap();
+            if let Some(caps) = re.captures(email_href) {
+                caps.get(1).map_or("", |m| m.as_str()).to_string()
+            } else {
+                "".to_string()
+            }
+        } else {
+            email_href.to_string()
+        }
+    } else {
+        email_element.inner_html()
+    };
+    deobfuscate::deobfuscate(raw.trim()).trim().to_string()
+}
+
+pub struct Fetcher {
+    client: Arc<HttpClient>,
+    robots: Option<RobotsChecker>,
+    cache: Option<PageCache>,
+    block_guard: Option<BlockGuard>,
+    #[cfg(feature = "headless")]
+    headless: Option<HeadlessBrowser>,
+}
+
+impl Fetcher {
+    pub fn new(client: HttpClient, respect_robots: bool) -> Self {
+        let client = Arc::new(client);
+        let robots = respect_robots.then(|| RobotsChecker::new(client.clone(), robots::ROBOTS_USER_AGENT));
+        Fetcher {
+            client,
+            robots,
+            cache: None,
+            block_guard: None,
+            #[cfg(feature = "headless")]
+            headless: None,
+        }
+    }
+
+    pub fn with_cache(mut self, cache: PageCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn with_block_guard(mut self, guard: BlockGuard) -> Self {
+        self.block_guard = Some(guard);
+        self
+    }
+
+    #[cfg(feature = "headless")]
+    pub fn with_headless(mut self, browser: HeadlessBrowser) -> Self {
+        self.headless = Some(browser);
+        self
+    }
+
+    async fn check_robots(&self, url: &str) -> Result<(), BotError> {
+        if let Some(robots) = &self.robots {
+            if !robots.is_allowed(url).await {
+                return Err(BotError::RobotsDisallowed(url.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn fetch(&self, url: &str) -> Result<String, BotError> {
+        if let Some(cache) = &self.cache {
+            if let Some(html) = cache.get(url).await {
+                return Ok(html);
+            }
+        }
+
+        self.check_robots(url).await?;
+        let mut html = self.client.get(url).await?;
+        if let Some(guard) = &self.block_guard {
+            let mut paused = false;
+            while let Some(reason) = block::block_reason(&html) {
+                self.cool_down(guard, url, reason).await?;
+                paused = true;
+                html = self.client.get(url).await?;
+            }
+            if paused {
+                guard.reset();
+            }
+        }
+
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.put(url, &html).await {
+                eprintln!("Could not cache {}: {}", url, e);
+            }
+        }
+        Ok(html)
+    }
+
+    async fn cool_down(&self, guard: &BlockGuard, url: &str, reason: &str) -> Result<(), BotError> {
+        let Some(cooldown) = guard.next_cooldown() else {
+            return Err(BotError::Blocked { url: url.to_string(), reason: reason.to_string() });
+        };
+        eprintln!("Looks blocked ({}) at {}, pausing for {}s", reason, url, cooldown.as_secs());
+        if guard.rotate_proxy && self.client.rotate_proxy() {
+            eprintln!("Rotated to the next proxy");
+        }
+        tokio::time::sleep(cooldown).await;
+        Ok(())
+    }
+
+    // Called for every detail page with whether the selectors found anything;
+    // a long run of empty pages is treated like a block page.
+    pub async fn record_extraction(&self, url: &str, empty: bool) -> Result<(), BotError> {
+        match &self.block_guard {
+            Some(guard) if guard.record_extraction(empty) => {
+                let reason = format!("{} detail pages in a row with no data", guard.empty_page_streak);
+                self.cool_down(guard, url, &reason).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Renders the page in the headless browser, if one is configured.
+    pub async fn fetch_rendered(&self, url: &str) -> Option<Result<String, BotError>> {
+        #[cfg(feature = "headless")]
+        if let Some(browser) = &self.headless {
+            if let Err(e) = self.check_robots(url).await {
+                return Some(Err(e));
+            }
+            return Some(browser.render(url).await);
+        }
+
+        let _ = url;
+        None
+    }
+
+    pub async fn close(&self) {
+        #[cfg(feature = "headless")]
+        if let Some(browser) = &self.headless {
+            browser.close().await;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ScrapeOptions {
+    pub concurrency: usize,
+    pub seen: Option<Arc<SeenStore>>,
+    pub hunter: Option<Arc<HunterClient>>,
+    pub follow_contact_links: bool,
+    pub checkpoint_path: Option<String>,
+    pub resume: bool,
+    pub max_pages: Option<usize>,
+    pub max_leads: Option<usize>,
+    pub filter: Arc<EmailFilter>,
+    // When set, leads are appended here as they're found instead of being
+    // collected and returned.
+    pub stream: Option<Arc<LeadStream>>,
+    // When set, each lead is also pushed here as it's found, for
+    // `send --from-queue` workers.
+    pub queue: Option<Arc<LeadQueue>>,
+    // When set, each lead is also handed to the pipeline's next stage as it's
+    // found, waiting while that stage is behind.
+    pub sink: Option<mpsc::Sender<Business>>,
+    // Asked for, the scrape stops once the detail pages in hand are done,
+    // saving the checkpoint.
+    pub shutdown: Shutdown,
+}
+
+impl Default for ScrapeOptions {
+    fn default() -> Self {
+        ScrapeOptions {
+            concurrency: DEFAULT_CONCURRENCY,
+            seen: None,
+            hunter: None,
+            follow_contact_links: true,
+            checkpoint_path: Some(DEFAULT_CHECKPOINT_PATH.to_string()),
+            resume: false,
+            max_pages: None,
+            max_leads: None,
+            filter: Arc::new(EmailFilter::default()),
+            stream: None,
+            queue: None,
+            sink: None,
+            shutdown: Shutdown::never(),
+        }
+    }
+}
+
+async fn first_email_on(fetcher: &Fetcher, urls: &[String]) -> Option<String> {
+    for url in urls {
+        match fetcher.fetch(url).await {
+            Ok(html) => {
+                if let Some(email) = extract::html_emails(&html).into_iter().next() {
+                    return Some(email);
+                }
+            }
+            Err(e) => eprintln!("Could not fetch contact page {}: {}", url, e),
+        }
+    }
+    None
+}
+
+// One level deeper than the detail page: contact/about links that leave the
+// directory, then the business website itself and its own contact pages.
+async fn email_from_contact_pages(
+    fetcher: &Fetcher,
+    detail_url: &str,
+    detail_html: &str,
+    website: Option<&str>,
+) -> Option<String> {
+    let directory_host = Url::parse(detail_url).ok().and_then(|url| url.host_str().map(String::from));
+    let off_directory = extract::contact_links(detail_url, detail_html, |url| url.host_str() != directory_host.as_deref());
+    if let Some(email) = first_email_on(fetcher, &off_directory).await {
+        return Some(email);
+    }
+
+    let website = website?;
+    let homepage = match fetcher.fetch(website).await {
+        Ok(html) => html,
+        Err(e) => {
+            eprintln!("Could not fetch business website {}: {}", website, e);
+            return None;
+        }
+    };
+    if let Some(email) = extract::html_emails(&homepage).into_iter().next() {
+        return Some(email);
+    }
+
+    let website_host = Url::parse(website).ok().and_then(|url| url.host_str().map(String::from));
+    let on_website = extract::contact_links(website, &homepage, |url| url.host_str() == website_host.as_deref());
+    first_email_on(fetcher, &on_website).await
+}
+
+// Runs every query in turn. Businesses are deduplicated across queries by
+// detail URL and by email, so overlapping searches don't produce repeats.
+pub async fn scrape_businesses(
+    fetcher: &Fetcher,
+    source: &dyn ScrapeSource,
+    queries: &[SearchQuery],
+    options: &ScrapeOptions,
+) -> Result<Vec<Business>, BotError> {
+    let mut checkpoint = match (&options.checkpoint_path, options.resume) {
+        (Some(path), true) => match ScrapeCheckpoint::load(path)? {
+            Some(checkpoint) if checkpoint.matches(source.name(), queries) => {
+                println!(
+                    "Resuming query {}/{} from page {} with {} leads already collected",
+                    checkpoint.query_index + 1,
+                    queries.len(),
+                    checkpoint.next_page,
+                    checkpoint.lead_count()
+                );
+                checkpoint
+            }
+            Some(_) => {
+                return Err(BotError::InvalidData(format!(
+                    "checkpoint {} belongs to a different source or set of queries",
+                    path
+                )));
+            }
+            None => {
+                println!("No checkpoint found at {}, starting fresh", path);
+                ScrapeCheckpoint::new(source.name(), queries)
+            }
+        },
+        _ => ScrapeCheckpoint::new(source.name(), queries),
+    };
+
+    let mut result = Ok(());
+    while checkpoint.query_index < queries.len() {
+        let query = &queries[checkpoint.query_index];
+        if options.max_leads.is_some_and(|max_leads| checkpoint.lead_count() >= max_leads) {
+            break;
+        }
+        if queries.len() > 1 {
+            println!(
+                "Query {}/{}: \"{}\" in \"{}\"",
+                checkpoint.query_index + 1,
+                queries.len(),
+                query.search_terms,
+                query.geo_location
+            );
+        }
+
+        result = scrape_pages(fetcher, source, query, options, &mut checkpoint).await;
+        if result.is_err() {
+            break;
+        }
+        checkpoint.query_index += 1;
+        checkpoint.next_page = 1;
+    }
+
+    // Only a completed run marks its URLs and emails as seen; an interrupted one
+    // keeps them in the checkpoint so nothing is lost if it isn't resumed.
+    if let (Ok(()), Some(seen)) = (&result, &options.seen) {
+        mark_seen(seen, &checkpoint).await?;
+    }
+
+    if let Some(path) = &options.checkpoint_path {
+        match &result {
+            Ok(()) => ScrapeCheckpoint::remove(path)?,
+            Err(_) => {
+                checkpoint.save(path)?;
+                eprintln!("Scrape interrupted, progress saved to {} (rerun with --resume)", path);
+            }
+        }
+    }
+
+    // Stopped early, the leads so far are still the caller's to store.
+    if let Err(BotError::Cancelled(e)) = &result {
+        eprintln!("Scrape stopped after {} leads: {}", checkpoint.lead_count(), e);
+        return Ok(checkpoint.businesses);
+    }
+
+    result.map(|()| checkpoint.businesses)
+}
+
+async fn mark_seen(seen: &SeenStore, checkpoint: &ScrapeCheckpoint) -> Result<(), BotError> {
+    for url in &checkpoint.processed_urls {
+        seen.add_url(url).await?;
+    }
+    for email in checkpoint.lead_emails() {
+        seen.add_email(email).await?;
+    }
+    seen.prune().await
+}
+
+// Sitemap mode: the detail pages come from the sites' sitemap.xml files instead
+// of search result pages. Runs aren't checkpointed; an interrupted run returns
+// the leads collected so far and leaves Redis dedup untouched.
+pub async fn scrape_sitemaps(
+    fetcher: &Fetcher,
+    source: &dyn ScrapeSource,
+    sitemap_urls: &[String],
+    url_pattern: Option<&Regex>,
+    options: &ScrapeOptions,
+) -> Result<Vec<Business>, BotError> {
+    let mut detail_urls = sitemap::sitemap_urls(fetcher, sitemap_urls).await;
+    if let Some(pattern) = url_pattern {
+        detail_urls.retain(|url| pattern.is_match(url));
+    }
+    println!("{} sitemap URLs to scrape", detail_urls.len());
+
+    let mut checkpoint = ScrapeCheckpoint::new(source.name(), &[]);
+    let mut processed_emails: HashSet<String> = HashSet::new();
+    for batch in detail_urls.chunks(SITEMAP_BATCH_SIZE) {
+        if options.shutdown.requested() {
+            eprintln!("Sitemap scrape interrupted after {} leads: shutting down", checkpoint.lead_count());
+            return Ok(checkpoint.businesses);
+        }
+        if options.max_leads.is_some_and(|max_leads| checkpoint.lead_count() >= max_leads) {
+            println!("Reached the lead limit ({} leads)", checkpoint.lead_count());
+            break;
+        }
+        let result = scrape_detail_pages(
+            fetcher,
+            source,
+            batch.to_vec(),
+            None,
+            options,
+            &mut checkpoint,
+            &mut processed_emails,
+        )
+        .await;
+        if let Err(e) = result {
+            eprintln!("Sitemap scrape interrupted after {} leads: {}", checkpoint.lead_count(), e);
+            return Ok(checkpoint.businesses);
+        }
+    }
+
+    if let Some(seen) = &options.seen {
+        mark_seen(seen, &checkpoint).await?;
+    }
+    Ok(checkpoint.businesses)
+}
+
+async fn scrape_pages(
+    fetcher: &Fetcher,
+    source: &dyn ScrapeSource,
+    query: &SearchQuery,
+    options: &ScrapeOptions,
+    checkpoint: &mut ScrapeCheckpoint,
+) -> Result<(), BotError> {
+    let mut processed_emails: HashSet<String> = checkpoint.lead_emails().map(|email| email::dedup_key(email)).collect();
+
+    let list_pages = source.list_pages(query).enumerate().skip(checkpoint.next_page.saturating_sub(1));
+    for (page_index, list_page_url) in list_pages {
+        if options.shutdown.requested() {
+            return Err(BotError::Cancelled("shutting down".to_string()));
+        }
+        if options.max_pages.is_some_and(|max_pages| page_index >= max_pages) {
+            println!("Reached the page limit ({} pages)", page_index);
+            break;
+        }
+        if options.max_leads.is_some_and(|max_leads| checkpoint.lead_count() >= max_leads) {
+            println!("Reached the lead limit ({} leads)", checkpoint.lead_count());
+            break;
+        }
+
+        let list_page_response = match fetcher.fetch(&list_page_url).await {
+            Ok(html) => html,
+            Err(BotError::RobotsDisallowed(url)) => {
+                println!("Search page disallowed by robots.txt, stopping: {}", url);
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        let detail_urls: Vec<String> = source.detail_links(&list_page_response);
+
+        if detail_urls.is_empty() {
+            break;
+        }
+
+        scrape_detail_pages(
+            fetcher,
+            source,
+            detail_urls,
+            Some(&query.geo_location),
+            options,
+            checkpoint,
+            &mut processed_emails,
+        )
+        .await?;
+
+        checkpoint.next_page = page_index + 2;
+        if let Some(path) = &options.checkpoint_path {
+            checkpoint.save(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Fetches and extracts the given detail pages, then runs the fallbacks for
+// pages without an email. New leads are appended to the checkpoint.
+async fn scrape_detail_pages(
+    fetcher: &Fetcher,
+    source: &dyn ScrapeSource,
+    detail_urls: Vec<String>,
+    location: Option<&str>,
+    options: &ScrapeOptions,
+    checkpoint: &mut ScrapeCheckpoint,
+    processed_emails: &mut HashSet<String>,
+) -> Result<(), BotError> {
+    let mut pending: Vec<String> = Vec::new();
+    for detail_url in detail_urls {
+        if checkpoint.processed_urls.contains(&detail_url) {
+            continue;
+        }
+        if let Some(seen) = &options.seen {
+            if seen.has_url(&detail_url).await? {
+                println!("Already scraped in an earlier run, skipping: {}", detail_url);
+                continue;
+            }
+        }
+        pending.push(detail_url);
+    }
+
+    let detail_pages: Vec<(String, Result<String, BotError>)> = stream::iter(pending)
+        .map(|detail_url| async move {
+            let result = fetcher.fetch(&detail_url).await;
+            (detail_url, result)
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .collect()
+        .await;
+
+    for (detail_url, result) in detail_pages {
+        let detail_page_response = match result {
+            Ok(html) => html,
+            Err(BotError::RobotsDisallowed(url)) => {
+                println!("Disallowed by robots.txt, skipping: {}", url);
+                checkpoint.processed_urls.insert(detail_url);
+                continue;
+            }
+            Err(e @ BotError::Blocked { .. }) => return Err(e),
+            // Left out of processed_urls, so a resumed run tries the page again.
+            Err(e) => {
+                eprintln!("Could not fetch {}, skipping: {}", detail_url, e);
+                continue;
+            }
+        };
+
+        let mut business = source.extract_business(&detail_url, &detail_page_response);
+        let empty = business.email.is_empty() && business.name.is_none() && business.phone.is_none();
+        fetcher.record_extraction(&detail_url, empty).await?;
+        if business.email.is_empty() {
+            match fetcher.fetch_rendered(&detail_url).await {
+                Some(Ok(rendered)) => business = source.extract_business(&detail_url, &rendered),
+                Some(Err(e)) => eprintln!("Headless render failed for {}: {}", detail_url, e),
+                None => {}
+            }
+        }
+        if business.email.is_empty() && options.follow_contact_links {
+            if let Some(email) = email_from_contact_pages(fetcher, &detail_url, &detail_page_response, business.website.as_deref()).await {
+                println!("Found {} on a linked contact page", email);
+                business.email = email;
+            }
+        }
+        if business.email.is_empty() {
+            if let (Some(hunter), Some(website)) = (&options.hunter, &business.website) {
+                match hunter.domain_search(website).await {
+                    Ok(Some(found)) if email::is_valid_email(&found.email) => {
+                        println!("Hunter.io found {} ({}% confidence)", found.email, found.confidence);
+                        business.email = found.email;
+                        business.email_confidence = Some(found.confidence);
+                    }
+                    Ok(Some(found)) => println!("Hunter.io returned an invalid address, ignoring: {}", found.email),
+                    Ok(None) => {}
+                    Err(e) => eprintln!("Hunter.io lookup failed for {}: {}", website, e),
+                }
+            }
+        }
+        checkpoint.processed_urls.insert(detail_url);
+
+        if !business.email.is_empty() {
+            business.email = email::normalize_email(&business.email);
+            business.location = location.map(String::from);
+            if let Some(reason) = options.filter.rejection(&business.email) {
+                println!("Skipping {} ({})", business.email, reason);
+                continue;
+            }
+            if let Some(seen) = &options.seen {
+                if seen.has_email(&business.email).await? {
+                    println!("Email collected in an earlier run, skipping: {}", business.email);
+                    continue;
+                }
+            }
+            if processed_emails.insert(email::dedup_key(&business.email)) {
+
+                println!("Business URL: {}", business.url);
+                if let Some(name) = &business.name {
+                    println!("Business Name: {}", name);
+                }
+                println!("Business Email: {}", business.email);
+
+                if let Some(queue) = &options.queue {
+                    queue.push(&business).await?;
+                }
+                if let Some(sink) = &options.sink {
+                    sink.send(business.clone())
+                        .await
+                        .map_err(|_| BotError::Cancelled("the validate stage stopped taking leads".to_string()))?;
+                }
+                match &options.stream {
+                    Some(stream) => {
+                        stream.append(&business)?;
+                        checkpoint.streamed_emails.push(business.email);
+                    }
+                    None => checkpoint.businesses.push(business),
+                }
+                if options.max_leads.is_some_and(|max_leads| checkpoint.lead_count() >= max_leads) {
+                    break;
+                }
+            } else {
+                println!("Duplicate email found, skipping: {}", business.email);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use reqwest::Url;
+use crate::http_client::HttpClient;
+
+pub const ROBOTS_USER_AGENT: &str = "email-bot";
+
+#[derive(Debug, Default)]
+struct RobotsRules {
+    // (allow, pattern)
+    rules: Vec<(bool, String)>,
+}
+
+impl RobotsRules {
+    fn parse(body: &str, user_agent: &str) -> RobotsRules {
+        let user_agent = user_agent.to_lowercase();
+        let mut specific = Vec::new();
+        let mut wildcard = Vec::new();
+        let mut matched_specific = false;
+
+        let mut group_agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let field = field.trim().to_lowercase();
+            let value = value.trim();
+
+            match field.as_str() {
+                "user-agent" => {
+                    if in_rules {
+                        group_agents.clear();
+                        in_rules = false;
+                    }
+                    group_agents.push(value.to_lowercase());
+                }
+                "allow" | "disallow" => {
+                    in_rules = true;
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let rule = (field == "allow", value.to_string());
+                    for agent in &group_agents {
+                        if agent == "*" {
+                            wildcard.push(rule.clone());
+                        } else if user_agent.contains(agent.as_str()) {
+                            matched_specific = true;
+                            specific.push(rule.clone());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        RobotsRules {
+            rules: if matched_specific { specific } else { wildcard },
+        }
+    }
+
+    fn is_allowed(&self, path: &str) -> bool {
+        // Longest matching pattern wins; on a tie Allow beats Disallow.
+        let mut best: Option<(usize, bool)> = None;
+        for (allow, pattern) in &self.rules {
+            if pattern_matches(pattern, path) {
+                let len = pattern.len();
+                best = match best {
+                    Some((best_len, best_allow)) if best_len > len || (best_len == len && best_allow) => best,
+                    _ => Some((len, *allow)),
+                };
+            }
+        }
+        best.is_none_or(|(_, allow)| allow)
+    }
+}
+
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(stripped) => (stripped, true),
+        None => (pattern, false),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+
+    let Some(rest) = path.strip_prefix(parts[0]) else {
+        return false;
+    };
+    let mut rest = rest;
+    for (i, part) in parts.iter().enumerate().skip(1) {
+        let is_last = i == parts.len() - 1;
+        if is_last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+pub struct RobotsChecker {
+    client: Arc<HttpClient>,
+    user_agent: String,
+    cache: Mutex<HashMap<String, Arc<RobotsRules>>>,
+}
+
+impl RobotsChecker {
+    pub fn new(client: Arc<HttpClient>, user_agent: &str) -> Self {
+        RobotsChecker {
+            client,
+            user_agent: user_agent.to_string(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn is_allowed(&self, url: &str) -> bool {
+        let Ok(url) = Url::parse(url) else {
+            return true;
+        };
+        let origin = url.origin().ascii_serialization();
+
+        let cached = self.cache.lock().unwrap().get(&origin).cloned();
+        let rules = match cached {
+            Some(rules) => rules,
+            None => {
+                let rules = Arc::new(self.fetch_rules(&origin).await);
+                self.cache.lock().unwrap().insert(origin, rules.clone());
+                rules
+            }
+        };
+
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+        rules.is_allowed(&path)
+    }
+
+    async fn fetch_rules(&self, origin: &str) -> RobotsRules {
+        let robots_url = format!("{}/robots.txt", origin);
+        let response = match self.client.send(&robots_url).await {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Could not fetch {}, assuming everything is allowed: {}", robots_url, e);
+                return RobotsRules::default();
+            }
+        };
+
+        // Missing or broken robots.txt means no restrictions.
+        if !response.status().is_success() {
+            return RobotsRules::default();
+        }
+
+        match response.text().await {
+            Ok(body) => RobotsRules::parse(&body, &self.user_agent),
+            Err(_) => RobotsRules::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keeps_the_group_for_our_agent_over_the_wildcard_one() {
+        let body = "User-agent: *\nDisallow: /\n\n# ours\nUser-agent: Googlebot\nUser-Agent: Email-Bot\nDisallow: /private # staff only\nAllow: /private/contact\n";
+        let rules = RobotsRules::parse(body, "Mozilla/5.0 (compatible; email-bot/1.0)");
+        assert!(rules.is_allowed("/"));
+        assert!(rules.is_allowed("/about"));
+        assert!(!rules.is_allowed("/private/team"));
+        assert!(rules.is_allowed("/private/contact"));
+
+        let others = RobotsRules::parse(body, "SomeOtherBot");
+        assert!(!others.is_allowed("/about"));
+    }
+
+    #[test]
+    fn parse_starts_a_new_group_after_rules_and_skips_empty_disallows() {
+        let body = "User-agent: *\nDisallow:\n\nUser-agent: otherbot\nDisallow: /\nUser-agent: *\nDisallow: /cart\n";
+        let rules = RobotsRules::parse(body, ROBOTS_USER_AGENT);
+        assert!(rules.is_allowed("/"));
+        assert!(!rules.is_allowed("/cart/checkout"));
+        assert!(RobotsRules::parse("", ROBOTS_USER_AGENT).is_allowed("/anything"));
+    }
+
+    #[test]
+    fn longest_match_wins_and_allow_wins_a_tie() {
+        let rules = RobotsRules::parse("User-agent: *\nDisallow: /*.pdf$\nDisallow: /shop\nAllow: /shop\nDisallow: /a*/c\n", ROBOTS_USER_AGENT);
+        assert!(!rules.is_allowed("/files/menu.pdf"));
+        assert!(rules.is_allowed("/files/menu.pdf?download=1"));
+        assert!(rules.is_allowed("/shop/tools"));
+        assert!(!rules.is_allowed("/ab/c/d"));
+        assert!(rules.is_allowed("/b/c"));
+    }
+}
@@ -0,0 +1,125 @@
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::Deserialize;
+use crate::error::BotError;
+use crate::email::is_valid_email;
+use crate::scrape::extract::fallback_email;
+use crate::scrape::structured::StructuredData;
+use crate::scrape::{email_from_element, Business};
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SelectorConfig {
+    pub business_link: Option<String>,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    pub website: Option<String>,
+    pub category: Option<String>,
+}
+
+pub struct SelectorDefaults {
+    pub business_link: &'static str,
+    pub email: &'static str,
+    pub name: &'static str,
+    pub phone: &'static str,
+    pub address: &'static str,
+    pub website: &'static str,
+    pub category: &'static str,
+}
+
+pub struct Selectors {
+    pub business_link: Selector,
+    pub email: Selector,
+    pub name: Selector,
+    pub phone: Selector,
+    pub address: Selector,
+    pub website: Selector,
+    pub category: Selector,
+}
+
+fn compile(source: &str, key: &str, configured: &Option<String>, default: &str) -> Result<Selector, BotError> {
+    let css = configured.as_deref().unwrap_or(default);
+    Selector::parse(css).map_err(|e| {
+        BotError::ConfigError(format!("sources.{}.selectors.{} = {:?} is not a valid CSS selector: {}", source, key, css, e))
+    })
+}
+
+impl Selectors {
+    pub fn compile(source: &str, config: &SelectorConfig, defaults: &SelectorDefaults) -> Result<Self, BotError> {
+        Ok(Selectors {
+            business_link: compile(source, "business_link", &config.business_link, defaults.business_link)?,
+            email: compile(source, "email", &config.email, defaults.email)?,
+            name: compile(source, "name", &config.name, defaults.name)?,
+            phone: compile(source, "phone", &config.phone, defaults.phone)?,
+            address: compile(source, "address", &config.address, defaults.address)?,
+            website: compile(source, "website", &config.website, defaults.website)?,
+            category: compile(source, "category", &config.category, defaults.category)?,
+        })
+    }
+}
+
+fn host(url: &str) -> Option<String> {
+    Url::parse(url).ok().and_then(|url| url.host_str().map(String::from))
+}
+
+impl Selectors {
+    // CSS selectors first, then schema.org structured data, then (for the email
+    // only) a scan of the page text.
+    pub fn extract_business(&self, detail_url: &str, document: &Html) -> Business {
+        let structured = StructuredData::from_document(document);
+        let email = document
+            .select(&self.email)
+            .next()
+            .map(email_from_element)
+            .filter(|email| is_valid_email(email))
+            .or(structured.email.filter(|email| is_valid_email(email)))
+            .or_else(|| fallback_email(document))
+            .unwrap_or_default();
+
+        // Directory pages often publish their own URL as the business "url".
+        let structured_website = structured.website.filter(|website| host(website) != host(detail_url));
+
+        Business {
+            url: detail_url.to_string(),
+            email,
+            name: select_text(document, &self.name).or(structured.name),
+            phone: select_text(document, &self.phone).or(structured.phone),
+            address: select_text(document, &self.address).or(structured.address),
+            website: select_attr(document, &self.website, "href").or(structured_website),
+            categories: select_all_text(document, &self.category),
+            ..Business::default()
+        }
+    }
+}
+
+fn clean_text<'a>(parts: impl Iterator<Item = &'a str>) -> String {
+    parts.flat_map(str::split_whitespace).collect::<Vec<_>>().join(" ")
+}
+
+pub fn select_text(document: &Html, selector: &Selector) -> Option<String> {
+    document
+        .select(selector)
+        .map(|element| clean_text(element.text()))
+        .find(|text| !text.is_empty())
+}
+
+pub fn select_all_text(document: &Html, selector: &Selector) -> Vec<String> {
+    let mut values: Vec<String> = Vec::new();
+    for text in document.select(selector).map(|element| clean_text(element.text())) {
+        if !text.is_empty() && !values.contains(&text) {
+            values.push(text);
+        }
+    }
+    values
+}
+
+pub fn select_attr(document: &Html, selector: &Selector, attr: &str) -> Option<String> {
+    document
+        .select(selector)
+        .filter_map(|element| element.value().attr(attr))
+        .map(str::trim)
+        .find(|value| !value.is_empty())
+        .map(String::from)
+}
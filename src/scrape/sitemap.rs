@@ -0,0 +1,89 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::LazyLock;
+use regex::Regex;
+use crate::scrape::Fetcher;
+
+// Sitemap indexes can nest; this bounds how many sitemap files one run reads.
+pub const MAX_SITEMAPS: usize = 200;
+
+static LOC_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<loc>\s*(?:<!\[CDATA\[)?\s*(.*?)\s*(?:\]\]>)?\s*</loc>").unwrap()
+});
+static INDEX_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<(?:\w+:)?sitemapindex[\s>]").unwrap());
+
+pub struct Sitemap {
+    // Page URLs from a <urlset>.
+    pub urls: Vec<String>,
+    // Child sitemaps from a <sitemapindex>.
+    pub sitemaps: Vec<String>,
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+pub fn parse_sitemap(xml: &str) -> Sitemap {
+    let locs: Vec<String> = LOC_RE
+        .captures_iter(xml)
+        .filter_map(|caps| caps.get(1))
+        .map(|loc| unescape_xml(loc.as_str()))
+        .filter(|loc| !loc.is_empty())
+        .collect();
+
+    if INDEX_RE.is_match(xml) {
+        Sitemap { urls: Vec::new(), sitemaps: locs }
+    } else {
+        Sitemap { urls: locs, sitemaps: Vec::new() }
+    }
+}
+
+// Reads the given sitemaps and every sitemap they index, returning the page
+// URLs in the order they were listed. Sitemaps that can't be fetched are skipped.
+pub async fn sitemap_urls(fetcher: &Fetcher, sitemap_urls: &[String]) -> Vec<String> {
+    let mut urls = Vec::new();
+    let mut seen_urls: HashSet<String> = HashSet::new();
+    let mut visited: HashSet<String> = HashSet::new();
+    let mut queue: VecDeque<String> = sitemap_urls.iter().cloned().collect();
+
+    while let Some(sitemap_url) = queue.pop_front() {
+        if !visited.insert(sitemap_url.clone()) {
+            continue;
+        }
+        if visited.len() > MAX_SITEMAPS {
+            println!("Read {} sitemaps, ignoring the rest", MAX_SITEMAPS);
+            break;
+        }
+        if sitemap_url.ends_with(".gz") {
+            eprintln!("Compressed sitemaps are not supported, skipping: {}", sitemap_url);
+            continue;
+        }
+
+        let xml = match fetcher.fetch(&sitemap_url).await {
+            Ok(xml) => xml,
+            Err(e) => {
+                eprintln!("Could not fetch sitemap {}: {}", sitemap_url, e);
+                continue;
+            }
+        };
+
+        let sitemap = parse_sitemap(&xml);
+        println!(
+            "Sitemap {}: {} pages, {} child sitemaps",
+            sitemap_url,
+            sitemap.urls.len(),
+            sitemap.sitemaps.len()
+        );
+        queue.extend(sitemap.sitemaps);
+        for url in sitemap.urls {
+            if seen_urls.insert(url.clone()) {
+                urls.push(url);
+            }
+        }
+    }
+
+    urls
+}
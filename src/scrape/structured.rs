@@ -0,0 +1,110 @@
+use scraper::{Html, Selector};
+use serde_json::Value;
+
+// Contact details published as schema.org data, either JSON-LD
+// (<script type="application/ld+json">) or microdata (itemprop attributes).
+#[derive(Debug, Default, Clone)]
+pub struct StructuredData {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    pub website: Option<String>,
+}
+
+fn string_field(object: &Value, key: &str) -> Option<String> {
+    match object.get(key)? {
+        Value::String(value) => Some(value.trim().to_string()).filter(|value| !value.is_empty()),
+        Value::Array(values) => values.iter().find_map(|value| value.as_str()).map(|value| value.trim().to_string()),
+        _ => None,
+    }
+}
+
+fn postal_address(value: &Value) -> Option<String> {
+    match value {
+        Value::String(address) => Some(address.trim().to_string()),
+        Value::Object(_) => {
+            let parts: Vec<String> = ["streetAddress", "addressLocality", "addressRegion", "postalCode"]
+                .iter()
+                .filter_map(|key| string_field(value, key))
+                .collect();
+            (!parts.is_empty()).then(|| parts.join(", "))
+        }
+        Value::Array(values) => values.iter().find_map(postal_address),
+        _ => None,
+    }
+}
+
+fn strip_mailto(email: String) -> String {
+    email.strip_prefix("mailto:").map(String::from).unwrap_or(email)
+}
+
+impl StructuredData {
+    pub fn from_document(document: &Html) -> Self {
+        let mut data = StructuredData::default();
+        let script_selector = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+        for script in document.select(&script_selector) {
+            if let Ok(json) = serde_json::from_str::<Value>(&script.text().collect::<String>()) {
+                data.merge_json(&json);
+            }
+        }
+        data.merge_microdata(document);
+        data
+    }
+
+    fn is_empty(&self) -> bool {
+        self.email.is_none() && self.phone.is_none() && self.address.is_none()
+    }
+
+    // Walks objects, arrays and @graph blocks; the first business-like object wins
+    // for each field.
+    fn merge_json(&mut self, value: &Value) {
+        match value {
+            Value::Array(values) => values.iter().for_each(|value| self.merge_json(value)),
+            Value::Object(object) => {
+                if let Some(graph) = object.get("@graph") {
+                    self.merge_json(graph);
+                }
+
+                let has_contact = ["email", "telephone", "address"].iter().any(|key| object.contains_key(*key));
+                if has_contact {
+                    let first_contact = self.is_empty();
+                    self.email = self.email.take().or_else(|| string_field(value, "email").map(strip_mailto));
+                    self.phone = self.phone.take().or_else(|| string_field(value, "telephone"));
+                    self.address = self.address.take().or_else(|| object.get("address").and_then(postal_address));
+                    if first_contact {
+                        self.name = self.name.take().or_else(|| string_field(value, "name"));
+                        self.website = self.website.take().or_else(|| string_field(value, "url"));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn merge_microdata(&mut self, document: &Html) {
+        let itemprop = |prop: &str| -> Option<String> {
+            let selector = Selector::parse(&format!(r#"[itemscope] [itemprop="{}"]"#, prop)).unwrap();
+            document.select(&selector).find_map(|element| {
+                let value = element
+                    .value()
+                    .attr("content")
+                    .or_else(|| element.value().attr("href"))
+                    .map(String::from)
+                    .unwrap_or_else(|| element.text().collect::<Vec<_>>().join(" "));
+                let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                (!value.is_empty()).then_some(value)
+            })
+        };
+
+        self.email = self.email.take().or_else(|| itemprop("email").map(strip_mailto));
+        self.phone = self.phone.take().or_else(|| itemprop("telephone"));
+        self.address = self.address.take().or_else(|| {
+            let parts: Vec<String> = ["streetAddress", "addressLocality", "addressRegion", "postalCode"]
+                .iter()
+                .filter_map(|prop| itemprop(prop))
+                .collect();
+            (!parts.is_empty()).then(|| parts.join(", "))
+        });
+    }
+}
@@ -0,0 +1,67 @@
+use reqwest::Url;
+use scraper::Html;
+use crate::config::SourceConfig;
+use crate::error::BotError;
+use crate::scrape::selectors::{SelectorDefaults, Selectors};
+use crate::scrape::{Business, ScrapeSource, SearchQuery};
+
+const BASE_URL: &str = "https://www.yellowpages.com";
+
+const DEFAULT_SELECTORS: SelectorDefaults = SelectorDefaults {
+    business_link: "a.business-name",
+    email: "a.email-business",
+    name: "h1.business-name",
+    phone: "a.phone, .phone",
+    address: ".address",
+    website: "a.website-link",
+    category: ".categories a",
+};
+
+pub struct YellowPagesSource {
+    selectors: Selectors,
+}
+
+impl YellowPagesSource {
+    pub fn new(config: &SourceConfig) -> Result<Self, BotError> {
+        Ok(YellowPagesSource {
+            selectors: Selectors::compile("yellowpages", &config.selectors, &DEFAULT_SELECTORS)?,
+        })
+    }
+
+    pub fn list_page_url(query: &SearchQuery, page_number: usize) -> String {
+        let url = Url::parse_with_params(
+            &format!("{}/search", BASE_URL),
+            &[
+                ("search_terms", query.search_terms.as_str()),
+                ("geo_location_terms", query.geo_location.as_str()),
+                ("page", &page_number.to_string()),
+            ],
+        ).expect("base URL is valid");
+        url.to_string()
+    }
+}
+
+impl ScrapeSource for YellowPagesSource {
+    fn name(&self) -> &'static str {
+        "yellowpages"
+    }
+
+    fn list_pages(&self, query: &SearchQuery) -> Box<dyn Iterator<Item = String> + Send> {
+        let query = query.clone();
+        Box::new((1..).map(move |page_number| Self::list_page_url(&query, page_number)))
+    }
+
+    fn detail_links(&self, list_page_html: &str) -> Vec<String> {
+        let list_page_document = Html::parse_document(list_page_html);
+        list_page_document
+            .select(&self.selectors.business_link)
+            .filter_map(|link_element| link_element.value().attr("href"))
+            .map(|href| format!("{}{}", BASE_URL, href))
+            .collect()
+    }
+
+    fn extract_business(&self, detail_url: &str, detail_page_html: &str) -> Business {
+        let detail_page_document = Html::parse_document(detail_page_html);
+        self.selectors.extract_business(detail_url, &detail_page_document)
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+use reqwest::Url;
+use scraper::Html;
+use crate::config::SourceConfig;
+use crate::error::BotError;
+use crate::scrape::selectors::{SelectorDefaults, Selectors};
+use crate::scrape::{Business, ScrapeSource, SearchQuery};
+
+const BASE_URL: &str = "https://www.yelp.com";
+const RESULTS_PER_PAGE: usize = 10;
+
+const DEFAULT_SELECTORS: SelectorDefaults = SelectorDefaults {
+    business_link: r#"a[href^="/biz/"]"#,
+    email: r#"a[href^="mailto:"]"#,
+    name: "h1",
+    phone: r#"a[href^="tel:"], p[class*="phone"]"#,
+    address: "address",
+    website: r#"a[href*="/biz_redir"]"#,
+    category: r#"a[href*="cflt="]"#,
+};
+
+// Outbound links go through /biz_redir?url=<target>&...
+fn unwrap_redirect(href: &str) -> String {
+    Url::parse(BASE_URL)
+        .and_then(|base| base.join(href))
+        .ok()
+        .filter(|url| url.path().starts_with("/biz_redir"))
+        .and_then(|url| url.query_pairs().find(|(key, _)| key == "url").map(|(_, value)| value.into_owned()))
+        .unwrap_or_else(|| href.to_string())
+}
+
+pub struct YelpSource {
+    selectors: Selectors,
+}
+
+impl YelpSource {
+    pub fn new(config: &SourceConfig) -> Result<Self, BotError> {
+        Ok(YelpSource {
+            selectors: Selectors::compile("yelp", &config.selectors, &DEFAULT_SELECTORS)?,
+        })
+    }
+
+    pub fn list_page_url(query: &SearchQuery, page_number: usize) -> String {
+        let start = (page_number - 1) * RESULTS_PER_PAGE;
+        let url = Url::parse_with_params(
+            &format!("{}/search", BASE_URL),
+            &[
+                ("find_desc", query.search_terms.as_str()),
+                ("find_loc", query.geo_location.as_str()),
+                ("start", &start.to_string()),
+            ],
+        ).expect("base URL is valid");
+        url.to_string()
+    }
+}
+
+impl ScrapeSource for YelpSource {
+    fn name(&self) -> &'static str {
+        "yelp"
+    }
+
+    fn list_pages(&self, query: &SearchQuery) -> Box<dyn Iterator<Item = String> + Send> {
+        let query = query.clone();
+        Box::new((1..).map(move |page_number| Self::list_page_url(&query, page_number)))
+    }
+
+    fn detail_links(&self, list_page_html: &str) -> Vec<String> {
+        let list_page_document = Html::parse_document(list_page_html);
+        let mut seen = HashSet::new();
+
+        // Each result links to its /biz/ page several times (photo, name, review snippet),
+        // usually with tracking query strings attached.
+        list_page_document
+            .select(&self.selectors.business_link)
+            .filter_map(|link_element| link_element.value().attr("href"))
+            .map(|href| href.split(['?', '#']).next().unwrap_or(href))
+            .filter(|path| seen.insert(path.to_string()))
+            .map(|path| format!("{}{}", BASE_URL, path))
+            .collect()
+    }
+
+    fn extract_business(&self, detail_url: &str, detail_page_html: &str) -> Business {
+        let detail_page_document = Html::parse_document(detail_page_html);
+        let mut business = self.selectors.extract_business(detail_url, &detail_page_document);
+        business.website = business.website.map(|href| unwrap_redirect(&href));
+        business
+    }
+}
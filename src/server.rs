@@ -0,0 +1,215 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use askama::Template;
+use hyper::header::{ALLOW, CACHE_CONTROL, CONTENT_TYPE, LOCATION};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use crate::error::BotError;
+use crate::shutdown::Shutdown;
+use crate::storage::{has_event, last_campaign_sent, LeadStore, SEND_CLICKED, SEND_OPENED, SEND_UNSUBSCRIBED};
+use crate::suppression::{SuppressionList, REASON_OPT_OUT};
+use crate::tracking::{verify_tracking_token, TrackedRecipient, CLICK_PATH, OPEN_PATH};
+use crate::unsubscribe::verify_token;
+
+pub const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:8080";
+
+// A transparent 1x1 GIF.
+const PIXEL_GIF: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0x21,
+    0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44,
+    0x01, 0x00, 0x3b,
+];
+
+#[derive(Template)]
+#[template(path = "unsubscribe_page.html")]
+struct UnsubscribePage<'a> {
+    title: &'a str,
+    message: &'a str,
+    // Whether to show the button that posts the unsubscribe back to the link.
+    confirm: bool,
+}
+
+fn page(status: StatusCode, title: &str, message: &str) -> Response<Body> {
+    render(status, UnsubscribePage { title, message, confirm: false })
+}
+
+fn render(status: StatusCode, page: UnsubscribePage) -> Response<Body> {
+    let html = page.render().unwrap_or_else(|_| page.message.to_string());
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(html))
+        .expect("static response parts are valid")
+}
+
+fn pixel() -> Response<Body> {
+    Response::builder()
+        .header(CONTENT_TYPE, "image/gif")
+        .header(CACHE_CONTROL, "no-store, max-age=0")
+        .body(Body::from(PIXEL_GIF))
+        .expect("static response parts are valid")
+}
+
+// Hosts the targets of the links in sent emails, each enabled by its secret.
+//
+// Unsubscribe links: opening one (GET) shows a page asking to confirm, which
+// posts back to the link, since mail scanners and link previews open links
+// nobody clicked. Only that POST or a mail client's one-click request (POST,
+// RFC 8058) suppresses the address and records the opt-out against the
+// campaign that last emailed it. The token may sit in any query
+// parameter or be the last path segment, so whatever shape unsubscribe.url
+// has, and whatever prefix a reverse proxy adds, works.
+//
+// Open pixels, .../open/<token>: the first load records an open against the
+// token's campaign. The image is served whatever the token, so a bad one
+// doesn't show as a broken image.
+//
+// Tracked links, .../click/<token>: the first click records a click (and an
+// open, as images may have been blocked), then every click redirects to the
+// link's target.
+pub struct LinkServer {
+    unsubscribe_secret: Option<String>,
+    tracking_secret: Option<String>,
+    suppression: SuppressionList,
+    store: Box<dyn LeadStore>,
+}
+
+impl LinkServer {
+    pub fn new(
+        unsubscribe_secret: Option<&str>,
+        tracking_secret: Option<&str>,
+        suppression: SuppressionList,
+        store: Box<dyn LeadStore>,
+    ) -> Self {
+        LinkServer {
+            unsubscribe_secret: unsubscribe_secret.map(str::to_string),
+            tracking_secret: tracking_secret.map(str::to_string),
+            suppression,
+            store,
+        }
+    }
+
+    fn email_from_request(&self, request: &Request<Body>) -> Option<String> {
+        let secret = self.unsubscribe_secret.as_deref()?;
+        let uri = request.uri();
+        let query_values = uri.query().unwrap_or("").split('&').filter_map(|pair| pair.split_once('=').map(|(_, value)| value));
+        let last_segment = uri.path().rsplit('/').next();
+        query_values.chain(last_segment).find_map(|candidate| verify_token(secret, candidate))
+    }
+
+    // The token of a .../<kind>/<token> path.
+    fn tracked_token<'a>(request: &'a Request<Body>, kind: &str) -> Option<&'a str> {
+        let mut segments = request.uri().path().rsplit('/');
+        let token = segments.next()?;
+        (segments.next()? == kind).then_some(token)
+    }
+
+    fn tracked_recipient(&self, token: &str) -> Option<TrackedRecipient> {
+        verify_tracking_token(self.tracking_secret.as_deref()?, token)
+    }
+
+    // Only the first event of each kind per recipient and campaign is kept.
+    async fn record_once(&self, recipient: &TrackedRecipient, status: &str) -> Result<(), BotError> {
+        if !has_event(self.store.as_ref(), recipient.campaign_id, &recipient.email, status).await? {
+            println!("{} {} (campaign #{})", recipient.email, status, recipient.campaign_id);
+            self.store.record_send(recipient.campaign_id, &recipient.email, status, None, None).await?;
+        }
+        Ok(())
+    }
+
+    async fn open(&self, token: &str) -> Response<Body> {
+        if let Some(recipient) = self.tracked_recipient(token).filter(|recipient| recipient.target.is_none()) {
+            if let Err(e) = self.record_once(&recipient, SEND_OPENED).await {
+                eprintln!("Could not record an open by {}: {:?}", recipient.email, e);
+            }
+        }
+        pixel()
+    }
+
+    async fn click(&self, token: &str) -> Response<Body> {
+        let Some(recipient) = self.tracked_recipient(token).filter(|recipient| recipient.target.is_some()) else {
+            return page(StatusCode::NOT_FOUND, "Link not recognized", "This link is incomplete or invalid.");
+        };
+        for status in [SEND_OPENED, SEND_CLICKED] {
+            if let Err(e) = self.record_once(&recipient, status).await {
+                eprintln!("Could not record a click by {}: {:?}", recipient.email, e);
+            }
+        }
+        match Response::builder().status(StatusCode::FOUND).header(LOCATION, recipient.target.as_deref().unwrap_or_default()).body(Body::empty()) {
+            Ok(response) => response,
+            Err(_) => page(StatusCode::NOT_FOUND, "Link not recognized", "This link is incomplete or invalid."),
+        }
+    }
+
+    async fn unsubscribe(&self, email: &str) -> Result<(), BotError> {
+        if !self.suppression.add(email, REASON_OPT_OUT).await? {
+            return Ok(());
+        }
+        println!("Unsubscribed: {}", email);
+        if let Some(campaign_id) = last_campaign_sent(self.store.as_ref(), email).await? {
+            self.store.record_send(campaign_id, email, SEND_UNSUBSCRIBED, None, None).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        if self.tracking_secret.is_some() {
+            if let Some(token) = Self::tracked_token(&request, OPEN_PATH) {
+                return self.open(token).await;
+            }
+            if let Some(token) = Self::tracked_token(&request, CLICK_PATH) {
+                return self.click(token).await;
+            }
+        }
+        if !matches!(*request.method(), Method::GET | Method::HEAD | Method::POST) {
+            let mut response = page(StatusCode::METHOD_NOT_ALLOWED, "Not allowed", "Open the unsubscribe link from the email.");
+            response.headers_mut().insert(ALLOW, "GET, HEAD, POST".parse().expect("valid header value"));
+            return response;
+        }
+        let Some(email) = self.email_from_request(&request) else {
+            return page(
+                StatusCode::NOT_FOUND,
+                "Link not recognized",
+                "This unsubscribe link is incomplete or invalid. Reply to the email with \"unsubscribe\" and we'll remove you.",
+            );
+        };
+        if *request.method() != Method::POST {
+            let message = format!("Stop emails to {}?", email);
+            return render(StatusCode::OK, UnsubscribePage { title: "Unsubscribe", message: &message, confirm: true });
+        }
+        match self.unsubscribe(&email).await {
+            Ok(()) => page(StatusCode::OK, "You're unsubscribed", &format!("{} won't receive any more emails from us.", email)),
+            Err(e) => {
+                eprintln!("Could not unsubscribe {}: {:?}", email, e);
+                page(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Something went wrong",
+                    "We couldn't process your request. Please try again later, or reply to the email with \"unsubscribe\".",
+                )
+            }
+        }
+    }
+
+    // Serves until the shutdown, then finishes the requests in flight.
+    pub async fn run(self, addr: SocketAddr, shutdown: Shutdown) -> Result<(), BotError> {
+        let server = Arc::new(self);
+        let make_service = make_service_fn(move |_| {
+            let server = server.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.handle(request).await) }
+                }))
+            }
+        });
+        let listener = Server::try_bind(&addr).map_err(BotError::ServerError)?;
+        println!("Serving email links on http://{}", addr);
+        listener
+            .serve(make_service)
+            .with_graceful_shutdown(async move { shutdown.wait().await })
+            .await
+            .map_err(BotError::ServerError)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+// Whether Ctrl-C or SIGTERM, or the run itself, asked the run to stop. Loops
+// check it between leads or pages and stop there, after the work in hand; the
+// waits between them end early once it's asked.
+#[derive(Clone)]
+pub struct Shutdown(Arc<watch::Sender<bool>>);
+
+// Starts listening for Ctrl-C and SIGTERM. A second signal ends the process
+// at once, for when the work in hand hangs.
+pub fn listen() -> Shutdown {
+    let shutdown = Shutdown::never();
+    let requested = shutdown.clone();
+    tokio::spawn(async move {
+        signal().await;
+        eprintln!("Shutting down once the work in hand is done; press Ctrl-C again to stop at once");
+        requested.request();
+        signal().await;
+        eprintln!("Stopping at once");
+        std::process::exit(130);
+    });
+    shutdown
+}
+
+async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+impl Shutdown {
+    // For runs nothing stops but the end of their work.
+    pub fn never() -> Self {
+        Shutdown(Arc::new(watch::channel(false).0))
+    }
+
+    pub fn request(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    pub async fn wait(&self) {
+        // The sender is held right here, so the channel can't close.
+        let _ = self.0.subscribe().wait_for(|requested| *requested).await;
+    }
+
+    // Sleeps for `duration`, or until the shutdown is asked for.
+    pub async fn sleep(&self, duration: Duration) {
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => {}
+            _ = self.wait() => {}
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown::never()
+    }
+}
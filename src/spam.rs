@@ -0,0 +1,87 @@
+use std::time::Duration;
+use regex::Regex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use crate::config::SpamAssassinConfig;
+use crate::error::BotError;
+
+// spamd can take a while on its network tests (DNS blocklists, Razor).
+const SPAMD_TIMEOUT_SECS: u64 = 30;
+
+// One rule the message matched, e.g. (1.1, "MIME_HTML_ONLY", "BODY: Message only has text/html MIME parts").
+#[derive(Debug, Clone)]
+pub struct SpamRule {
+    pub points: f64,
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpamReport {
+    pub score: f64,
+    pub rules: Vec<SpamRule>,
+}
+
+impl SpamReport {
+    // The rules that added the most points, highest first.
+    pub fn top_rules(&self, count: usize) -> Vec<&SpamRule> {
+        let mut rules: Vec<&SpamRule> = self.rules.iter().filter(|rule| rule.points > 0.0).collect();
+        rules.sort_by(|a, b| b.points.total_cmp(&a.points));
+        rules.truncate(count);
+        rules
+    }
+}
+
+// The reply to REPORT: "SPAMD/1.1 0 EX_OK", a "Spam: True ; 6.1 / 5.0" header,
+// then the report with one "pts rule description" line per matched rule.
+fn parse_reply(reply: &str) -> Result<SpamReport, BotError> {
+    let (head, report) = reply.split_once("\r\n\r\n").unwrap_or((reply, ""));
+    let mut lines = head.lines();
+    let status = lines.next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("0") {
+        return Err(BotError::InvalidData(format!("spamd: {}", status.trim())));
+    }
+    let score = lines
+        .find_map(|line| line.strip_prefix("Spam:"))
+        .and_then(|value| value.split(';').nth(1)?.split('/').next()?.trim().parse().ok())
+        .ok_or_else(|| BotError::InvalidData("spamd sent no score".to_string()))?;
+    let pattern = Regex::new(r"^\s*(-?\d+(?:\.\d+)?)\s+([A-Z0-9_]+)\s+(.*)$").expect("valid regex");
+    let mut rules: Vec<SpamRule> = Vec::new();
+    for line in report.lines() {
+        if let Some(captures) = pattern.captures(line) {
+            rules.push(SpamRule {
+                points: captures[1].parse().unwrap_or_default(),
+                name: captures[2].to_string(),
+                description: captures[3].trim().to_string(),
+            });
+        } else if let Some(rule) = rules.last_mut().filter(|_| line.starts_with("    ")) {
+            // Long descriptions continue on the next line.
+            rule.description.push(' ');
+            rule.description.push_str(line.trim());
+        }
+    }
+    Ok(SpamReport { score, rules })
+}
+
+// Has spamd score a whole message, with the spamc protocol's REPORT command.
+// None when spamassassin.host isn't set.
+pub async fn score(config: &SpamAssassinConfig, message: &[u8]) -> Result<Option<SpamReport>, BotError> {
+    let Some(host) = &config.host else {
+        return Ok(None);
+    };
+    let exchange = async {
+        let mut stream = TcpStream::connect((host.as_str(), config.port)).await?;
+        let request = format!("REPORT SPAMC/1.5\r\nContent-length: {}\r\n\r\n", message.len());
+        stream.write_all(request.as_bytes()).await?;
+        stream.write_all(message).await?;
+        stream.shutdown().await?;
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+        Ok::<_, std::io::Error>(reply)
+    };
+    let reply = tokio::time::timeout(Duration::from_secs(SPAMD_TIMEOUT_SECS), exchange)
+        .await
+        .map_err(|_| BotError::InvalidData(format!("spamd at {}:{} didn't answer in {}s", host, config.port, SPAMD_TIMEOUT_SECS)))?
+        .map_err(BotError::IOError)?;
+    parse_reply(&String::from_utf8_lossy(&reply)).map(Some)
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use crate::email::{is_valid_email, normalize_email};
+use crate::error::BotError;
+use crate::scrape::{Business, ReviewStatus};
+use crate::validation::SmtpStatus;
+
+const CATEGORY_SEPARATOR: &str = "; ";
+
+// One spreadsheet row per lead. Every column but email and url may be left
+// blank; categories share one cell, separated by semicolons.
+#[derive(Serialize, Deserialize)]
+struct LeadRow {
+    email: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    phone: String,
+    #[serde(default)]
+    address: String,
+    #[serde(default)]
+    website: String,
+    #[serde(default)]
+    categories: String,
+    #[serde(default)]
+    location: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    email_confidence: String,
+    #[serde(default)]
+    mx_valid: String,
+    #[serde(default)]
+    smtp_status: String,
+    #[serde(default)]
+    review_status: String,
+}
+
+fn cell(value: &Option<String>) -> String {
+    value.clone().unwrap_or_default()
+}
+
+fn optional(value: String) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+impl From<&Business> for LeadRow {
+    fn from(business: &Business) -> Self {
+        LeadRow {
+            email: business.email.clone(),
+            name: cell(&business.name),
+            phone: cell(&business.phone),
+            address: cell(&business.address),
+            website: cell(&business.website),
+            categories: business.categories.join(CATEGORY_SEPARATOR),
+            location: cell(&business.location),
+            url: business.url.clone(),
+            email_confidence: business.email_confidence.map(|confidence| confidence.to_string()).unwrap_or_default(),
+            mx_valid: business.mx_valid.map(|valid| valid.to_string()).unwrap_or_default(),
+            smtp_status: business.smtp_status.map(|status| status.as_str().to_string()).unwrap_or_default(),
+            review_status: business.review_status.map(|status| status.as_str().to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+impl LeadRow {
+    fn into_business(self, line: u64) -> Result<Business, BotError> {
+        let invalid = |column: &str, value: &str| BotError::InvalidData(format!("line {}: bad {} {:?}", line, column, value));
+        let email_confidence = match self.email_confidence.trim() {
+            "" => None,
+            value => Some(value.parse::<u8>().map_err(|_| invalid("email_confidence", value))?.min(100)),
+        };
+        let mx_valid = match self.mx_valid.trim().to_lowercase().as_str() {
+            "" => None,
+            "true" | "yes" | "1" => Some(true),
+            "false" | "no" | "0" => Some(false),
+            value => return Err(invalid("mx_valid", value)),
+        };
+        let smtp_status = match self.smtp_status.trim() {
+            "" => None,
+            value => Some(SmtpStatus::parse(&value.to_lowercase()).ok_or_else(|| invalid("smtp_status", value))?),
+        };
+        let review_status = match self.review_status.trim() {
+            "" => None,
+            value => Some(ReviewStatus::from_cell(value).ok_or_else(|| invalid("review_status", value))?),
+        };
+        Ok(Business {
+            url: self.url.trim().to_string(),
+            email: normalize_email(&self.email),
+            name: optional(self.name),
+            phone: optional(self.phone),
+            address: optional(self.address),
+            website: optional(self.website),
+            categories: self
+                .categories
+                .split(';')
+                .map(str::trim)
+                .filter(|category| !category.is_empty())
+                .map(String::from)
+                .collect(),
+            location: optional(self.location),
+            email_confidence,
+            mx_valid,
+            smtp_status,
+            review_status,
+        })
+    }
+}
+
+pub fn save_businesses_csv(path: &str, businesses: &[Business]) -> Result<(), BotError> {
+    let mut writer = csv::Writer::from_path(path).map_err(BotError::CsvError)?;
+    for business in businesses {
+        writer.serialize(LeadRow::from(business)).map_err(BotError::CsvError)?;
+    }
+    writer.flush().map_err(BotError::IOError)?;
+    Ok(())
+}
+
+// Rows whose email was cleared or mangled while editing are skipped with a
+// warning rather than failing the whole import.
+pub fn load_businesses_csv(path: &str) -> Result<Vec<Business>, BotError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_path(path)
+        .map_err(BotError::CsvError)?;
+    let headers = reader.headers().map_err(BotError::CsvError)?.clone();
+    let mut businesses = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(BotError::CsvError)?;
+        let line = record.position().map_or(0, |position| position.line());
+        let row: LeadRow = record.deserialize(Some(&headers)).map_err(BotError::CsvError)?;
+        let business = row.into_business(line)?;
+        if !is_valid_email(&business.email) {
+            eprintln!("Skipping line {} of {}: invalid email {:?}", line, path, business.email);
+            continue;
+        }
+        businesses.push(business);
+    }
+    Ok(businesses)
+}
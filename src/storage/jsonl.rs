@@ -0,0 +1,82 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+use crate::error::BotError;
+use crate::scrape::Business;
+
+// Append-only lead file, one JSON object per line. Each lead is written and
+// flushed as soon as it's scraped, so a crash loses at most the line in flight.
+pub struct LeadStream {
+    file: Mutex<File>,
+}
+
+impl LeadStream {
+    pub fn open(path: &str) -> Result<Self, BotError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .map_err(BotError::IOError)?;
+        // A crash mid-write leaves a partial last line; start on a fresh one.
+        if file.metadata().map_err(BotError::IOError)?.len() > 0 {
+            let mut last = [0u8; 1];
+            file.seek(SeekFrom::End(-1)).map_err(BotError::IOError)?;
+            file.read_exact(&mut last).map_err(BotError::IOError)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n").map_err(BotError::IOError)?;
+            }
+        }
+        Ok(LeadStream { file: Mutex::new(file) })
+    }
+
+    pub fn append(&self, business: &Business) -> Result<(), BotError> {
+        let mut line = serde_json::to_string(business).map_err(BotError::DataParseError)?;
+        line.push('\n');
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes()).map_err(BotError::IOError)?;
+        file.flush().map_err(BotError::IOError)
+    }
+}
+
+// Reads leads back one line at a time. Lines that don't parse (e.g. cut off
+// by a crash) are skipped with a warning.
+pub fn jsonl_leads(path: &str) -> Result<impl Iterator<Item = Business>, BotError> {
+    let file = File::open(path).map_err(BotError::IOError)?;
+    let path = path.to_string();
+    Ok(BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter_map(move |(index, line)| {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("Could not read line {} of {}: {}", index + 1, path, e);
+                    return None;
+                }
+            };
+            if line.trim().is_empty() {
+                return None;
+            }
+            match serde_json::from_str(&line) {
+                Ok(business) => Some(business),
+                Err(e) => {
+                    eprintln!("Skipping line {} of {}: {}", index + 1, path, e);
+                    None
+                }
+            }
+        }))
+}
+
+pub fn load_businesses_jsonl(path: &str) -> Result<Vec<Business>, BotError> {
+    Ok(jsonl_leads(path)?.collect())
+}
+
+pub fn save_businesses_jsonl(path: &str, businesses: &[Business]) -> Result<(), BotError> {
+    let mut file = File::create(path).map_err(BotError::IOError)?;
+    for business in businesses {
+        let line = serde_json::to_string(business).map_err(BotError::DataParseError)?;
+        writeln!(file, "{}", line).map_err(BotError::IOError)?;
+    }
+    Ok(())
+}
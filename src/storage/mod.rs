@@ -0,0 +1,248 @@
+pub mod csv_leads;
+pub mod jsonl;
+pub mod postgres;
+pub mod sqlite;
+
+use std::fs::File;
+use std::io::Write;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use crate::campaign::{Campaign, FollowUp, Variant};
+use crate::error::BotError;
+use crate::scrape::{Business, ReviewStatus};
+
+pub use csv_leads::{load_businesses_csv, save_businesses_csv};
+pub use jsonl::{jsonl_leads, load_businesses_jsonl, save_businesses_jsonl, LeadStream};
+pub use postgres::PostgresStore;
+pub use sqlite::{SqliteStore, DEFAULT_DB_PATH};
+
+// Per-recipient statuses recorded in send_events. A run queues every lead it
+// was given, then records how each send went; bounces, replies and
+// unsubscribes are recorded later against the campaign that sent the email.
+pub const SEND_QUEUED: &str = "queued";
+pub const SEND_SENT: &str = "sent";
+pub const SEND_FAILED: &str = "failed";
+pub const SEND_SKIPPED: &str = "skipped";
+pub const SEND_BOUNCED: &str = "bounced";
+pub const SEND_REPLIED: &str = "replied";
+pub const SEND_UNSUBSCRIBED: &str = "unsubscribed";
+// A temporary delivery failure, e.g. a full mailbox; the email is retried once.
+pub const SEND_SOFT_BOUNCED: &str = "soft_bounced";
+// Recorded once per recipient and campaign, when the open pixel loads or a
+// tracked link is first followed.
+pub const SEND_OPENED: &str = "opened";
+pub const SEND_CLICKED: &str = "clicked";
+
+// Any of these means the address has been emailed and must not be emailed again.
+pub const CONTACTED_STATUSES: &[&str] = &[SEND_SENT, SEND_BOUNCED, SEND_REPLIED, SEND_UNSUBSCRIBED];
+
+// For SQL `status IN (...)` clauses; the statuses are constants, not input.
+fn contacted_status_list() -> String {
+    CONTACTED_STATUSES.iter().map(|status| format!("'{}'", status)).collect::<Vec<_>>().join(", ")
+}
+
+pub struct CampaignSummary {
+    pub campaign: Campaign,
+    pub sent: i64,
+    pub failed: i64,
+    pub skipped: i64,
+    pub bounced: i64,
+    pub replied: i64,
+    pub unsubscribed: i64,
+    // Recipients who opened at least one of the campaign's emails, and who
+    // followed at least one of its links.
+    pub opened: i64,
+    pub clicked: i64,
+}
+
+// Sends of one A/B variant, with how many of those recipients later opened,
+// clicked, replied or bounced.
+pub struct VariantSummary {
+    pub variant: Variant,
+    pub sent: i64,
+    pub opened: i64,
+    pub clicked: i64,
+    pub replied: i64,
+    pub bounced: i64,
+}
+
+pub struct SendEvent {
+    pub campaign_id: i64,
+    pub campaign_name: String,
+    pub email: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub variant: Option<String>,
+    // For sent emails: the step of the campaign's sequence (0 for its first
+    // email), and the Message-ID and subject follow-ups thread onto. Emails
+    // sent before follow-ups existed have neither.
+    pub step: u32,
+    pub message_id: Option<String>,
+    pub subject: Option<String>,
+}
+
+// What a successful send records besides its status.
+pub struct SentEmail<'a> {
+    pub variant: Option<&'a str>,
+    pub step: u32,
+    pub message_id: &'a str,
+    pub subject: &'a str,
+}
+
+// Leads, campaigns and send history. Leads are keyed by their normalized
+// address, so re-scraping a business updates its row instead of adding one.
+#[async_trait]
+pub trait LeadStore: Send + Sync {
+    // Inserts new leads and refreshes known ones; fields the new copy doesn't
+    // have keep their stored value. Returns how many leads were new.
+    async fn upsert_businesses(&self, businesses: &[Business]) -> Result<usize, BotError>;
+
+    async fn load_businesses(&self) -> Result<Vec<Business>, BotError>;
+
+    // Returns false when no stored lead has this address.
+    async fn set_review_status(&self, email: &str, status: Option<ReviewStatus>) -> Result<bool, BotError>;
+
+    async fn count_businesses(&self) -> Result<i64, BotError>;
+
+    // Returns the new campaign's id. Names aren't unique in old databases;
+    // lookups by name pick the most recent campaign.
+    async fn create_campaign(&self, campaign: &Campaign) -> Result<i64, BotError>;
+
+    async fn find_campaign(&self, name: &str) -> Result<Option<Campaign>, BotError>;
+
+    // Successful sends for a campaign, optionally only those since a point in time.
+    async fn campaign_sent_count(&self, campaign_id: i64, since: Option<DateTime<Utc>>) -> Result<i64, BotError>;
+
+    // `variant` names the A/B variant a sent email used.
+    async fn record_send(
+        &self,
+        campaign_id: i64,
+        email: &str,
+        status: &str,
+        error: Option<&str>,
+        variant: Option<&str>,
+    ) -> Result<(), BotError>;
+
+    async fn record_sent(&self, campaign_id: i64, email: &str, sent: &SentEmail<'_>) -> Result<(), BotError>;
+
+    // Records a queued event for each address in one transaction.
+    async fn record_queued(&self, campaign_id: i64, emails: &[&str]) -> Result<(), BotError>;
+
+    // Whether any campaign has emailed this mailbox, matched by dedup key,
+    // optionally only counting emails since a point in time.
+    async fn was_contacted(&self, email: &str, since: Option<DateTime<Utc>>) -> Result<bool, BotError>;
+
+    // Every event recorded for this mailbox, oldest first.
+    async fn send_history(&self, email: &str) -> Result<Vec<SendEvent>, BotError>;
+
+    // The sent email with this Message-ID, e.g. the one a reply answers.
+    async fn find_sent_message(&self, message_id: &str) -> Result<Option<SendEvent>, BotError>;
+
+    // Every event recorded under the campaign, oldest first.
+    async fn campaign_events(&self, campaign_id: i64) -> Result<Vec<SendEvent>, BotError>;
+
+    async fn campaign_summaries(&self) -> Result<Vec<CampaignSummary>, BotError>;
+
+    // Fails when the campaign already has a variant of that name.
+    async fn add_variant(&self, campaign_id: i64, variant: &Variant) -> Result<(), BotError>;
+
+    // In the order they were added.
+    async fn campaign_variants(&self, campaign_id: i64) -> Result<Vec<Variant>, BotError>;
+
+    async fn variant_summaries(&self, campaign_id: i64) -> Result<Vec<VariantSummary>, BotError>;
+
+    // Fails when the campaign already has a follow-up with that step number.
+    async fn add_follow_up(&self, campaign_id: i64, follow_up: &FollowUp) -> Result<(), BotError>;
+
+    // By step.
+    async fn campaign_follow_ups(&self, campaign_id: i64) -> Result<Vec<FollowUp>, BotError>;
+}
+
+// The campaign that most recently emailed this mailbox, which replies,
+// bounces and unsubscribes are recorded against.
+pub async fn last_campaign_sent(store: &dyn LeadStore, email: &str) -> Result<Option<i64>, BotError> {
+    Ok(store
+        .send_history(email)
+        .await?
+        .into_iter()
+        .rev()
+        .find(|event| event.status == SEND_SENT)
+        .map(|event| event.campaign_id))
+}
+
+// Whether an event with this status is already recorded for the mailbox
+// under the campaign.
+pub async fn has_event(store: &dyn LeadStore, campaign_id: i64, email: &str, status: &str) -> Result<bool, BotError> {
+    Ok(store
+        .send_history(email)
+        .await?
+        .iter()
+        .any(|event| event.campaign_id == campaign_id && event.status == status))
+}
+
+// postgres:// and postgresql:// URLs go to Postgres; anything else is a SQLite
+// file path (or sqlite:// URL).
+pub async fn open_store(database: &str) -> Result<Box<dyn LeadStore>, BotError> {
+    if database.starts_with("postgres://") || database.starts_with("postgresql://") {
+        Ok(Box::new(PostgresStore::connect(database).await?))
+    } else {
+        Ok(Box::new(SqliteStore::open(database).await?))
+    }
+}
+
+pub const DEFAULT_LEADS_PATH: &str = "business_emails.json";
+pub const DEFAULT_LEADS_CSV_PATH: &str = "business_emails.csv";
+
+pub fn save_businesses(path: &str, businesses: &[Business]) -> Result<(), BotError> {
+    let json_data = serde_json::to_string_pretty(businesses).map_err(BotError::DataParseError)?;
+    let mut file = File::create(path).map_err(BotError::IOError)?;
+    file.write_all(json_data.as_bytes()).map_err(BotError::IOError)?;
+    Ok(())
+}
+
+pub fn load_businesses(path: &str) -> Result<Vec<Business>, BotError> {
+    let file = File::open(path).map_err(BotError::IOError)?;
+    let businesses = serde_json::from_reader(file).map_err(BotError::DataParseError)?;
+    Ok(businesses)
+}
+
+// Lead files ending in .csv are read and written as spreadsheets, .jsonl as
+// one lead per line, anything else as a JSON array.
+pub fn is_csv_path(path: &str) -> bool {
+    path.to_lowercase().ends_with(".csv")
+}
+
+pub fn is_jsonl_path(path: &str) -> bool {
+    path.to_lowercase().ends_with(".jsonl")
+}
+
+pub fn save_leads_file(path: &str, businesses: &[Business]) -> Result<(), BotError> {
+    if is_csv_path(path) {
+        save_businesses_csv(path, businesses)
+    } else if is_jsonl_path(path) {
+        save_businesses_jsonl(path, businesses)
+    } else {
+        save_businesses(path, businesses)
+    }
+}
+
+pub fn load_leads_file(path: &str) -> Result<Vec<Business>, BotError> {
+    if is_csv_path(path) {
+        load_businesses_csv(path)
+    } else if is_jsonl_path(path) {
+        load_businesses_jsonl(path)
+    } else {
+        load_businesses(path)
+    }
+}
+
+pub fn load_line_list(path: &str) -> Result<Vec<String>, BotError> {
+    let contents = std::fs::read_to_string(path).map_err(BotError::IOError)?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
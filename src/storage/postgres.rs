@@ -0,0 +1,536 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use sqlx::Row;
+use crate::email::dedup_key;
+use crate::error::BotError;
+use crate::scrape::{Business, ReviewStatus};
+use crate::campaign::{Campaign, FollowUp, LeadFilter, Variant};
+use crate::storage::{contacted_status_list, CampaignSummary, LeadStore, SendEvent, SentEmail, VariantSummary, SEND_QUEUED, SEND_SENT};
+use crate::validation::SmtpStatus;
+
+const MAX_CONNECTIONS: u32 = 5;
+
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS businesses (
+        id BIGSERIAL PRIMARY KEY,
+        email_key TEXT NOT NULL UNIQUE,
+        email TEXT NOT NULL,
+        url TEXT NOT NULL,
+        name TEXT,
+        phone TEXT,
+        address TEXT,
+        website TEXT,
+        categories TEXT NOT NULL DEFAULT '[]',
+        location TEXT,
+        email_confidence INTEGER,
+        mx_valid BOOLEAN,
+        smtp_status TEXT,
+        created_at TIMESTAMPTZ NOT NULL,
+        updated_at TIMESTAMPTZ NOT NULL
+    )",
+    "ALTER TABLE businesses ADD COLUMN IF NOT EXISTS review_status TEXT",
+    "CREATE TABLE IF NOT EXISTS campaigns (
+        id BIGSERIAL PRIMARY KEY,
+        name TEXT NOT NULL,
+        subject TEXT NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL
+    )",
+    "ALTER TABLE campaigns ADD COLUMN IF NOT EXISTS template TEXT NOT NULL DEFAULT 'default'",
+    "ALTER TABLE campaigns ADD COLUMN IF NOT EXISTS location_filter TEXT",
+    "ALTER TABLE campaigns ADD COLUMN IF NOT EXISTS category_filter TEXT",
+    "ALTER TABLE campaigns ADD COLUMN IF NOT EXISTS source_filter TEXT",
+    "ALTER TABLE campaigns ADD COLUMN IF NOT EXISTS max_per_day BIGINT",
+    "ALTER TABLE campaigns ADD COLUMN IF NOT EXISTS max_sends BIGINT",
+    "CREATE INDEX IF NOT EXISTS campaigns_name ON campaigns(name)",
+    "CREATE TABLE IF NOT EXISTS send_events (
+        id BIGSERIAL PRIMARY KEY,
+        campaign_id BIGINT NOT NULL REFERENCES campaigns(id),
+        email TEXT NOT NULL,
+        status TEXT NOT NULL,
+        error TEXT,
+        created_at TIMESTAMPTZ NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS send_events_campaign ON send_events(campaign_id)",
+    "CREATE INDEX IF NOT EXISTS send_events_email ON send_events(email)",
+    "ALTER TABLE send_events ADD COLUMN IF NOT EXISTS email_key TEXT",
+    "CREATE INDEX IF NOT EXISTS send_events_email_key ON send_events(email_key)",
+    "ALTER TABLE send_events ADD COLUMN IF NOT EXISTS variant TEXT",
+    "CREATE TABLE IF NOT EXISTS campaign_variants (
+        id BIGSERIAL PRIMARY KEY,
+        campaign_id BIGINT NOT NULL REFERENCES campaigns(id),
+        name TEXT NOT NULL,
+        subject TEXT NOT NULL,
+        template TEXT NOT NULL,
+        weight INTEGER NOT NULL,
+        UNIQUE (campaign_id, name)
+    )",
+    "ALTER TABLE send_events ADD COLUMN IF NOT EXISTS step INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE send_events ADD COLUMN IF NOT EXISTS message_id TEXT",
+    "ALTER TABLE send_events ADD COLUMN IF NOT EXISTS subject TEXT",
+    "CREATE INDEX IF NOT EXISTS send_events_message_id ON send_events(message_id)",
+    "CREATE TABLE IF NOT EXISTS campaign_follow_ups (
+        id BIGSERIAL PRIMARY KEY,
+        campaign_id BIGINT NOT NULL REFERENCES campaigns(id),
+        step INTEGER NOT NULL,
+        delay_days INTEGER NOT NULL,
+        subject TEXT,
+        template TEXT NOT NULL,
+        UNIQUE (campaign_id, step)
+    )",
+];
+
+// A shared database for teams running the bot on several machines.
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+fn business_from_row(row: &PgRow) -> Result<Business, BotError> {
+    let categories: String = row.try_get("categories").map_err(BotError::DatabaseError)?;
+    let smtp_status: Option<String> = row.try_get("smtp_status").map_err(BotError::DatabaseError)?;
+    let review_status: Option<String> = row.try_get("review_status").map_err(BotError::DatabaseError)?;
+    let email_confidence: Option<i32> = row.try_get("email_confidence").map_err(BotError::DatabaseError)?;
+    Ok(Business {
+        url: row.try_get("url").map_err(BotError::DatabaseError)?,
+        email: row.try_get("email").map_err(BotError::DatabaseError)?,
+        name: row.try_get("name").map_err(BotError::DatabaseError)?,
+        phone: row.try_get("phone").map_err(BotError::DatabaseError)?,
+        address: row.try_get("address").map_err(BotError::DatabaseError)?,
+        website: row.try_get("website").map_err(BotError::DatabaseError)?,
+        categories: serde_json::from_str(&categories).map_err(BotError::DataParseError)?,
+        location: row.try_get("location").map_err(BotError::DatabaseError)?,
+        email_confidence: email_confidence.map(|confidence| confidence.clamp(0, 100) as u8),
+        mx_valid: row.try_get("mx_valid").map_err(BotError::DatabaseError)?,
+        smtp_status: smtp_status.as_deref().and_then(SmtpStatus::parse),
+        review_status: review_status.as_deref().and_then(ReviewStatus::parse),
+    })
+}
+
+fn campaign_from_row(row: &PgRow) -> Result<Campaign, BotError> {
+    Ok(Campaign {
+        id: row.try_get("id").map_err(BotError::DatabaseError)?,
+        name: row.try_get("name").map_err(BotError::DatabaseError)?,
+        subject: row.try_get("subject").map_err(BotError::DatabaseError)?,
+        template: row.try_get("template").map_err(BotError::DatabaseError)?,
+        filter: LeadFilter {
+            location: row.try_get("location_filter").map_err(BotError::DatabaseError)?,
+            category: row.try_get("category_filter").map_err(BotError::DatabaseError)?,
+            source: row.try_get("source_filter").map_err(BotError::DatabaseError)?,
+        },
+        max_per_day: row.try_get("max_per_day").map_err(BotError::DatabaseError)?,
+        max_sends: row.try_get("max_sends").map_err(BotError::DatabaseError)?,
+        created_at: row.try_get("created_text").map_err(BotError::DatabaseError)?,
+    })
+}
+
+fn variant_from_row(row: &PgRow) -> Result<Variant, BotError> {
+    let weight: i32 = row.try_get("weight").map_err(BotError::DatabaseError)?;
+    Ok(Variant {
+        name: row.try_get("name").map_err(BotError::DatabaseError)?,
+        subject: row.try_get("subject").map_err(BotError::DatabaseError)?,
+        template: row.try_get("template").map_err(BotError::DatabaseError)?,
+        weight: weight.max(0) as u32,
+    })
+}
+
+fn follow_up_from_row(row: &PgRow) -> Result<FollowUp, BotError> {
+    let step: i32 = row.try_get("step").map_err(BotError::DatabaseError)?;
+    let delay_days: i32 = row.try_get("delay_days").map_err(BotError::DatabaseError)?;
+    Ok(FollowUp {
+        step: step.max(0) as u32,
+        delay_days: delay_days.max(0) as u32,
+        subject: row.try_get("subject").map_err(BotError::DatabaseError)?,
+        template: row.try_get("template").map_err(BotError::DatabaseError)?,
+    })
+}
+
+fn event_from_row(row: &PgRow) -> Result<SendEvent, BotError> {
+    let step: i32 = row.try_get("step").map_err(BotError::DatabaseError)?;
+    Ok(SendEvent {
+        campaign_id: row.try_get("campaign_id").map_err(BotError::DatabaseError)?,
+        campaign_name: row.try_get("campaign_name").map_err(BotError::DatabaseError)?,
+        email: row.try_get("email").map_err(BotError::DatabaseError)?,
+        status: row.try_get("status").map_err(BotError::DatabaseError)?,
+        error: row.try_get("error").map_err(BotError::DatabaseError)?,
+        variant: row.try_get("variant").map_err(BotError::DatabaseError)?,
+        created_at: row.try_get("created_text").map_err(BotError::DatabaseError)?,
+        step: step.max(0) as u32,
+        message_id: row.try_get("message_id").map_err(BotError::DatabaseError)?,
+        subject: row.try_get("subject").map_err(BotError::DatabaseError)?,
+    })
+}
+
+impl PostgresStore {
+    // Creates the tables on first use.
+    pub async fn connect(url: &str) -> Result<Self, BotError> {
+        let pool = PgPoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect(url)
+            .await
+            .map_err(BotError::DatabaseError)?;
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await.map_err(BotError::DatabaseError)?;
+        }
+        Ok(PostgresStore { pool })
+    }
+}
+
+#[async_trait]
+impl LeadStore for PostgresStore {
+    async fn upsert_businesses(&self, businesses: &[Business]) -> Result<usize, BotError> {
+        let mut tx = self.pool.begin().await.map_err(BotError::DatabaseError)?;
+        let mut inserted = 0;
+        for business in businesses {
+            let categories = serde_json::to_string(&business.categories).map_err(BotError::DataParseError)?;
+            // xmax is 0 only for a freshly inserted row.
+            let was_inserted: bool = sqlx::query_scalar(
+                "INSERT INTO businesses (email_key, email, url, name, phone, address, website, categories,
+                    location, email_confidence, mx_valid, smtp_status, review_status, created_at, updated_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $14)
+                 ON CONFLICT (email_key) DO UPDATE SET
+                    email = excluded.email,
+                    url = excluded.url,
+                    name = COALESCE(excluded.name, businesses.name),
+                    phone = COALESCE(excluded.phone, businesses.phone),
+                    address = COALESCE(excluded.address, businesses.address),
+                    website = COALESCE(excluded.website, businesses.website),
+                    categories = CASE WHEN excluded.categories = '[]' THEN businesses.categories ELSE excluded.categories END,
+                    location = COALESCE(excluded.location, businesses.location),
+                    email_confidence = COALESCE(excluded.email_confidence, businesses.email_confidence),
+                    mx_valid = COALESCE(excluded.mx_valid, businesses.mx_valid),
+                    smtp_status = COALESCE(excluded.smtp_status, businesses.smtp_status),
+                    review_status = COALESCE(excluded.review_status, businesses.review_status),
+                    updated_at = excluded.updated_at
+                 RETURNING (xmax = 0)",
+            )
+            .bind(dedup_key(&business.email))
+            .bind(&business.email)
+            .bind(&business.url)
+            .bind(&business.name)
+            .bind(&business.phone)
+            .bind(&business.address)
+            .bind(&business.website)
+            .bind(categories)
+            .bind(&business.location)
+            .bind(business.email_confidence.map(i32::from))
+            .bind(business.mx_valid)
+            .bind(business.smtp_status.map(SmtpStatus::as_str))
+            .bind(business.review_status.map(ReviewStatus::as_str))
+            .bind(Utc::now())
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(BotError::DatabaseError)?;
+            if was_inserted {
+                inserted += 1;
+            }
+        }
+        tx.commit().await.map_err(BotError::DatabaseError)?;
+        Ok(inserted)
+    }
+
+    async fn load_businesses(&self) -> Result<Vec<Business>, BotError> {
+        let rows = sqlx::query("SELECT * FROM businesses ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(BotError::DatabaseError)?;
+        rows.iter().map(business_from_row).collect()
+    }
+
+    async fn set_review_status(&self, email: &str, status: Option<ReviewStatus>) -> Result<bool, BotError> {
+        let result = sqlx::query("UPDATE businesses SET review_status = $1, updated_at = $2 WHERE email_key = $3")
+            .bind(status.map(ReviewStatus::as_str))
+            .bind(Utc::now())
+            .bind(dedup_key(email))
+            .execute(&self.pool)
+            .await
+            .map_err(BotError::DatabaseError)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn count_businesses(&self) -> Result<i64, BotError> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM businesses")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(BotError::DatabaseError)
+    }
+
+    async fn create_campaign(&self, campaign: &Campaign) -> Result<i64, BotError> {
+        sqlx::query_scalar(
+            "INSERT INTO campaigns (name, subject, template, location_filter, category_filter, source_filter,
+                max_per_day, max_sends, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id",
+        )
+        .bind(&campaign.name)
+        .bind(&campaign.subject)
+        .bind(&campaign.template)
+        .bind(&campaign.filter.location)
+        .bind(&campaign.filter.category)
+        .bind(&campaign.filter.source)
+        .bind(campaign.max_per_day)
+        .bind(campaign.max_sends)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await
+        .map_err(BotError::DatabaseError)
+    }
+
+    async fn find_campaign(&self, name: &str) -> Result<Option<Campaign>, BotError> {
+        let row = sqlx::query("SELECT *, created_at::text AS created_text FROM campaigns WHERE name = $1 ORDER BY id DESC LIMIT 1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(BotError::DatabaseError)?;
+        row.as_ref().map(campaign_from_row).transpose()
+    }
+
+    async fn campaign_sent_count(&self, campaign_id: i64, since: Option<DateTime<Utc>>) -> Result<i64, BotError> {
+        sqlx::query_scalar(
+            "SELECT COUNT(*) FROM send_events
+             WHERE campaign_id = $1 AND status = $2 AND ($3::timestamptz IS NULL OR created_at >= $3)",
+        )
+        .bind(campaign_id)
+        .bind(SEND_SENT)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(BotError::DatabaseError)
+    }
+
+    async fn record_send(
+        &self,
+        campaign_id: i64,
+        email: &str,
+        status: &str,
+        error: Option<&str>,
+        variant: Option<&str>,
+    ) -> Result<(), BotError> {
+        sqlx::query(
+            "INSERT INTO send_events (campaign_id, email, email_key, status, error, variant, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(campaign_id)
+        .bind(email)
+        .bind(dedup_key(email))
+        .bind(status)
+        .bind(error)
+        .bind(variant)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await
+        .map_err(BotError::DatabaseError)?;
+        Ok(())
+    }
+
+    async fn record_sent(&self, campaign_id: i64, email: &str, sent: &SentEmail<'_>) -> Result<(), BotError> {
+        sqlx::query(
+            "INSERT INTO send_events (campaign_id, email, email_key, status, variant, step, message_id, subject, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(campaign_id)
+        .bind(email)
+        .bind(dedup_key(email))
+        .bind(SEND_SENT)
+        .bind(sent.variant)
+        .bind(sent.step.min(i32::MAX as u32) as i32)
+        .bind(sent.message_id)
+        .bind(sent.subject)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await
+        .map_err(BotError::DatabaseError)?;
+        Ok(())
+    }
+
+    async fn record_queued(&self, campaign_id: i64, emails: &[&str]) -> Result<(), BotError> {
+        let mut tx = self.pool.begin().await.map_err(BotError::DatabaseError)?;
+        let now = Utc::now();
+        for email in emails {
+            sqlx::query("INSERT INTO send_events (campaign_id, email, email_key, status, created_at) VALUES ($1, $2, $3, $4, $5)")
+                .bind(campaign_id)
+                .bind(email)
+                .bind(dedup_key(email))
+                .bind(SEND_QUEUED)
+                .bind(now)
+                .execute(&mut *tx)
+                .await
+                .map_err(BotError::DatabaseError)?;
+        }
+        tx.commit().await.map_err(BotError::DatabaseError)
+    }
+
+    // Events from before email_key existed are matched on the address itself.
+    async fn was_contacted(&self, email: &str, since: Option<DateTime<Utc>>) -> Result<bool, BotError> {
+        let query = format!(
+            "SELECT EXISTS (SELECT 1 FROM send_events
+                WHERE (email_key = $1 OR (email_key IS NULL AND lower(email) = $2)) AND status IN ({})
+                    AND ($3::timestamptz IS NULL OR created_at >= $3))",
+            contacted_status_list()
+        );
+        sqlx::query_scalar(&query)
+            .bind(dedup_key(email))
+            .bind(email.trim().to_lowercase())
+            .bind(since)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(BotError::DatabaseError)
+    }
+
+    async fn send_history(&self, email: &str) -> Result<Vec<SendEvent>, BotError> {
+        let rows = sqlx::query(
+            "SELECT e.*, e.created_at::text AS created_text, c.name AS campaign_name
+             FROM send_events e JOIN campaigns c ON c.id = e.campaign_id
+             WHERE e.email_key = $1 OR (e.email_key IS NULL AND lower(e.email) = $2)
+             ORDER BY e.id",
+        )
+        .bind(dedup_key(email))
+        .bind(email.trim().to_lowercase())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(BotError::DatabaseError)?;
+        rows.iter().map(event_from_row).collect()
+    }
+
+    async fn find_sent_message(&self, message_id: &str) -> Result<Option<SendEvent>, BotError> {
+        let row = sqlx::query(
+            "SELECT e.*, e.created_at::text AS created_text, c.name AS campaign_name
+             FROM send_events e JOIN campaigns c ON c.id = e.campaign_id
+             WHERE e.message_id = $1 AND e.status = $2
+             LIMIT 1",
+        )
+        .bind(message_id)
+        .bind(SEND_SENT)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(BotError::DatabaseError)?;
+        row.as_ref().map(event_from_row).transpose()
+    }
+
+    async fn campaign_events(&self, campaign_id: i64) -> Result<Vec<SendEvent>, BotError> {
+        let rows = sqlx::query(
+            "SELECT e.*, e.created_at::text AS created_text, c.name AS campaign_name
+             FROM send_events e JOIN campaigns c ON c.id = e.campaign_id
+             WHERE e.campaign_id = $1
+             ORDER BY e.id",
+        )
+        .bind(campaign_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(BotError::DatabaseError)?;
+        rows.iter().map(event_from_row).collect()
+    }
+
+    async fn campaign_summaries(&self) -> Result<Vec<CampaignSummary>, BotError> {
+        let rows = sqlx::query(
+            "SELECT c.*, c.created_at::text AS created_text,
+                COUNT(CASE WHEN e.status = 'sent' THEN 1 END) AS sent,
+                COUNT(CASE WHEN e.status = 'failed' THEN 1 END) AS failed,
+                COUNT(CASE WHEN e.status = 'skipped' THEN 1 END) AS skipped,
+                COUNT(CASE WHEN e.status = 'bounced' THEN 1 END) AS bounced,
+                COUNT(CASE WHEN e.status = 'replied' THEN 1 END) AS replied,
+                COUNT(CASE WHEN e.status = 'unsubscribed' THEN 1 END) AS unsubscribed,
+                COUNT(DISTINCT CASE WHEN e.status = 'opened' THEN e.email_key END) AS opened,
+                COUNT(DISTINCT CASE WHEN e.status = 'clicked' THEN e.email_key END) AS clicked
+             FROM campaigns c LEFT JOIN send_events e ON e.campaign_id = c.id
+             GROUP BY c.id ORDER BY c.id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(BotError::DatabaseError)?;
+        rows.iter()
+            .map(|row| {
+                Ok(CampaignSummary {
+                    campaign: campaign_from_row(row)?,
+                    sent: row.try_get("sent").map_err(BotError::DatabaseError)?,
+                    failed: row.try_get("failed").map_err(BotError::DatabaseError)?,
+                    skipped: row.try_get("skipped").map_err(BotError::DatabaseError)?,
+                    bounced: row.try_get("bounced").map_err(BotError::DatabaseError)?,
+                    replied: row.try_get("replied").map_err(BotError::DatabaseError)?,
+                    unsubscribed: row.try_get("unsubscribed").map_err(BotError::DatabaseError)?,
+                    opened: row.try_get("opened").map_err(BotError::DatabaseError)?,
+                    clicked: row.try_get("clicked").map_err(BotError::DatabaseError)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn add_variant(&self, campaign_id: i64, variant: &Variant) -> Result<(), BotError> {
+        sqlx::query("INSERT INTO campaign_variants (campaign_id, name, subject, template, weight) VALUES ($1, $2, $3, $4, $5)")
+            .bind(campaign_id)
+            .bind(&variant.name)
+            .bind(&variant.subject)
+            .bind(&variant.template)
+            .bind(variant.weight.min(i32::MAX as u32) as i32)
+            .execute(&self.pool)
+            .await
+            .map_err(BotError::DatabaseError)?;
+        Ok(())
+    }
+
+    async fn campaign_variants(&self, campaign_id: i64) -> Result<Vec<Variant>, BotError> {
+        let rows = sqlx::query("SELECT * FROM campaign_variants WHERE campaign_id = $1 ORDER BY id")
+            .bind(campaign_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(BotError::DatabaseError)?;
+        rows.iter().map(variant_from_row).collect()
+    }
+
+    // Replies and bounces are recorded without a variant, so they are credited
+    // to the variant of the campaign's email to that address.
+    async fn variant_summaries(&self, campaign_id: i64) -> Result<Vec<VariantSummary>, BotError> {
+        let rows = sqlx::query(
+            "SELECT v.*,
+                COUNT(e.id) AS sent,
+                COUNT(CASE WHEN EXISTS (SELECT 1 FROM send_events r
+                    WHERE r.campaign_id = e.campaign_id AND r.email_key = e.email_key AND r.status = 'replied') THEN 1 END) AS replied,
+                COUNT(CASE WHEN EXISTS (SELECT 1 FROM send_events o
+                    WHERE o.campaign_id = e.campaign_id AND o.email_key = e.email_key AND o.status = 'opened') THEN 1 END) AS opened,
+                COUNT(CASE WHEN EXISTS (SELECT 1 FROM send_events k
+                    WHERE k.campaign_id = e.campaign_id AND k.email_key = e.email_key AND k.status = 'clicked') THEN 1 END) AS clicked,
+                COUNT(CASE WHEN EXISTS (SELECT 1 FROM send_events b
+                    WHERE b.campaign_id = e.campaign_id AND b.email_key = e.email_key AND b.status = 'bounced') THEN 1 END) AS bounced
+             FROM campaign_variants v
+             LEFT JOIN send_events e ON e.campaign_id = v.campaign_id AND e.variant = v.name AND e.status = 'sent'
+             WHERE v.campaign_id = $1
+             GROUP BY v.id ORDER BY v.id",
+        )
+        .bind(campaign_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(BotError::DatabaseError)?;
+        rows.iter()
+            .map(|row| {
+                Ok(VariantSummary {
+                    variant: variant_from_row(row)?,
+                    sent: row.try_get("sent").map_err(BotError::DatabaseError)?,
+                    replied: row.try_get("replied").map_err(BotError::DatabaseError)?,
+                    opened: row.try_get("opened").map_err(BotError::DatabaseError)?,
+                    clicked: row.try_get("clicked").map_err(BotError::DatabaseError)?,
+                    bounced: row.try_get("bounced").map_err(BotError::DatabaseError)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn add_follow_up(&self, campaign_id: i64, follow_up: &FollowUp) -> Result<(), BotError> {
+        sqlx::query("INSERT INTO campaign_follow_ups (campaign_id, step, delay_days, subject, template) VALUES ($1, $2, $3, $4, $5)")
+            .bind(campaign_id)
+            .bind(follow_up.step.min(i32::MAX as u32) as i32)
+            .bind(follow_up.delay_days.min(i32::MAX as u32) as i32)
+            .bind(&follow_up.subject)
+            .bind(&follow_up.template)
+            .execute(&self.pool)
+            .await
+            .map_err(BotError::DatabaseError)?;
+        Ok(())
+    }
+
+    async fn campaign_follow_ups(&self, campaign_id: i64) -> Result<Vec<FollowUp>, BotError> {
+        let rows = sqlx::query("SELECT * FROM campaign_follow_ups WHERE campaign_id = $1 ORDER BY step")
+            .bind(campaign_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(BotError::DatabaseError)?;
+        rows.iter().map(follow_up_from_row).collect()
+    }
+}
@@ -0,0 +1,116 @@
+use redis::AsyncCommands;
+use crate::email::{dedup_key, is_valid_email};
+use crate::error::BotError;
+use crate::ratelimit::{connection, RedisPool};
+
+const SUPPRESSION_KEY: &str = "suppression:emails";
+
+pub const REASON_OPT_OUT: &str = "opt-out";
+pub const REASON_BOUNCE: &str = "bounce";
+pub const REASON_MANUAL: &str = "manual";
+
+// Addresses that must never be emailed again, with why they were added.
+// Stored as a Redis hash of dedup key -> reason, so a mailbox stays suppressed
+// under its plus-tags and Gmail's dotted spellings; entries never expire.
+pub struct SuppressionList {
+    pool: RedisPool,
+}
+
+impl SuppressionList {
+    pub fn new(pool: RedisPool) -> Self {
+        SuppressionList { pool }
+    }
+
+    pub async fn contains(&self, email: &str) -> Result<bool, BotError> {
+        Ok(self.reason(email).await?.is_some())
+    }
+
+    pub async fn reason(&self, email: &str) -> Result<Option<String>, BotError> {
+        let mut con = connection(&self.pool).await?;
+        for key in keys(email) {
+            let reason: Option<String> = con.hget(SUPPRESSION_KEY, key).await.map_err(BotError::RedisError)?;
+            if reason.is_some() {
+                return Ok(reason);
+            }
+        }
+        Ok(None)
+    }
+
+    // Returns false when the address was already suppressed; the original reason is kept.
+    pub async fn add(&self, email: &str, reason: &str) -> Result<bool, BotError> {
+        if self.contains(email).await? {
+            return Ok(false);
+        }
+        let mut con = connection(&self.pool).await?;
+        con.hset_nx(SUPPRESSION_KEY, dedup_key(email), reason).await.map_err(BotError::RedisError)
+    }
+
+    pub async fn remove(&self, email: &str) -> Result<bool, BotError> {
+        let mut con = connection(&self.pool).await?;
+        let removed: usize = con.hdel(SUPPRESSION_KEY, keys(email)).await.map_err(BotError::RedisError)?;
+        Ok(removed > 0)
+    }
+
+    pub async fn len(&self) -> Result<usize, BotError> {
+        let mut con = connection(&self.pool).await?;
+        con.hlen(SUPPRESSION_KEY).await.map_err(BotError::RedisError)
+    }
+
+    pub async fn is_empty(&self) -> Result<bool, BotError> {
+        Ok(self.len().await? == 0)
+    }
+
+    // Adds every address in `path` and returns (added, already suppressed).
+    pub async fn import_file(&self, path: &str, reason: &str) -> Result<(usize, usize), BotError> {
+        let contents = std::fs::read_to_string(path).map_err(BotError::IOError)?;
+        let mut added = 0;
+        let mut existing = 0;
+        for email in emails_in_list(&contents) {
+            if self.add(&email, reason).await? {
+                added += 1;
+            } else {
+                existing += 1;
+            }
+        }
+        Ok((added, existing))
+    }
+}
+
+// The entry's key, then the lowercased address entries added before they were
+// keyed by dedup key were stored under.
+fn keys(email: &str) -> Vec<String> {
+    let key = dedup_key(email);
+    let address = email.trim().to_lowercase();
+    if address == key {
+        vec![key]
+    } else {
+        vec![key, address]
+    }
+}
+
+// Pulls addresses out of a plain list or a CSV export: the first field of each
+// line that is a valid email. Header rows and comments fall out on their own.
+pub fn emails_in_list(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            line.split([',', ';', '\t'])
+                .map(|field| field.trim().trim_matches('"').trim())
+                .map(|field| field.strip_prefix("mailto:").unwrap_or(field))
+                .find(|field| is_valid_email(field))
+                .map(str::to_lowercase)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_by_dedup_key_then_the_address() {
+        assert_eq!(keys(" John.Doe+promo@Gmail.com "), vec!["johndoe@gmail.com", "john.doe+promo@gmail.com"]);
+        assert_eq!(keys("Owner@Example.com"), vec!["owner@example.com"]);
+    }
+}
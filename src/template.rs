@@ -0,0 +1,514 @@
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::attachment::Attachment;
+use crate::campaign::DEFAULT_TEMPLATE;
+use crate::css::inline_css;
+use crate::email::{render_email, render_text_email, Personalization};
+use crate::error::BotError;
+use crate::scrape::Business;
+
+// Placeholders a template file can use, written as {{ name }}.
+pub const TEMPLATE_VARIABLES: &[&str] = &[
+    "subject",
+    "business_name",
+    "city",
+    "category",
+    "unsubscribe_url",
+    "company_name",
+    "postal_address",
+    "business.name",
+    "business.email",
+    "business.phone",
+    "business.address",
+    "business.website",
+    "business.location",
+    "business.url",
+];
+
+// What a campaign's emails are rendered from: the built-in askama template,
+// or an HTML file given by path. A file's plain-text alternative is the .txt
+// file next to it when there is one, else the HTML with its markup stripped.
+// `images` are the local images the HTML embeds.
+#[derive(Debug, Clone)]
+pub enum CampaignTemplate {
+    Builtin,
+    File { path: PathBuf, html: String, text: Option<String>, images: Arc<[Attachment]> },
+}
+
+// One recipient's email, with spintax expanded.
+#[derive(Debug, Clone)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+// What CAN-SPAM requires at the bottom of every commercial email: who sent
+// it, a postal address they can be reached at, and a way to opt out.
+#[derive(Debug, Clone, Copy)]
+pub struct FooterDetails<'a> {
+    pub company_name: &'a str,
+    pub postal_address: &'a str,
+    pub unsubscribe_url: &'a str,
+}
+
+// Stands in for the real details when a template is checked at startup.
+const SAMPLE_FOOTER: FooterDetails<'static> = FooterDetails {
+    company_name: "Sample Company",
+    postal_address: "123 Main St, Columbus, OH 43215",
+    unsubscribe_url: "https://example.com/unsubscribe",
+};
+
+fn variable_value(name: &str, subject: &str, business: &Business, vars: &Personalization, footer: &FooterDetails) -> Option<String> {
+    let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+    Some(match name {
+        "subject" => subject.to_string(),
+        "unsubscribe_url" => footer.unsubscribe_url.to_string(),
+        "company_name" => footer.company_name.to_string(),
+        "postal_address" => footer.postal_address.to_string(),
+        "business_name" => vars.business_name.clone(),
+        "city" => vars.city.clone(),
+        "category" => vars.category.clone(),
+        "business.name" => optional(&business.name),
+        "business.email" => business.email.clone(),
+        "business.phone" => optional(&business.phone),
+        "business.address" => optional(&business.address),
+        "business.website" => optional(&business.website),
+        "business.location" => optional(&business.location),
+        "business.url" => business.url.clone(),
+        _ => return None,
+    })
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+// Substitutes every {{ name }} placeholder, escaping values for HTML output.
+fn substitute(source: &str, path: &Path, escape: bool, value: impl Fn(&str) -> Option<String>) -> Result<String, BotError> {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let line = source[..source.len() - rest.len() + start].matches('\n').count() + 1;
+        let Some(end) = rest[start..].find("}}") else {
+            return Err(BotError::InvalidData(format!("{} line {}: unclosed {{{{", path.display(), line)));
+        };
+        let name = rest[start + 2..start + end].trim();
+        let Some(value) = value(name) else {
+            return Err(BotError::InvalidData(format!(
+                "{} line {}: unknown variable {{{{ {} }}}} (available: {})",
+                path.display(),
+                line,
+                name,
+                TEMPLATE_VARIABLES.join(", ")
+            )));
+        };
+        output.push_str(&if escape { escape_html(&value) } else { value });
+        rest = &rest[start + end + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+// The top-level options of the {...} group `text` starts with, and the
+// group's length; None when the group isn't closed or has no "|".
+fn spin_options(text: &str) -> Option<(Vec<&str>, usize)> {
+    let mut depth = 0;
+    let mut options = Vec::new();
+    let mut option_start = 1;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    options.push(&text[option_start..i]);
+                    return (options.len() > 1).then_some((options, i + 1));
+                }
+            }
+            '|' if depth == 1 => {
+                options.push(&text[option_start..i]);
+                option_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Expands spintax: each {Hello|Hi|Hey} group becomes one of its options at
+// random, and groups can nest. Braces without a "|", like CSS rules, are kept.
+fn spin(text: &str, rng: &mut StdRng) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let group = &rest[start..];
+        match spin_options(group) {
+            Some((options, len)) => {
+                let option = options[rng.gen_range(0..options.len())];
+                output.push_str(&spin(option, rng));
+                rest = &group[len..];
+            }
+            None => {
+                output.push('{');
+                rest = &group[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn uses_variable(source: &str, variable: &str) -> bool {
+    source.split("{{").skip(1).any(|rest| rest.split("}}").next().is_some_and(|name| name.trim() == variable))
+}
+
+// Templates that don't place the postal address or the unsubscribe link
+// themselves get a footer with whatever they lack.
+fn add_html_footer(html: &mut String, source: &str, footer: &FooterDetails) {
+    let mut lines = Vec::new();
+    if !uses_variable(source, "postal_address") {
+        lines.push(format!("{}, {}", escape_html(footer.company_name), escape_html(footer.postal_address)));
+    }
+    if !uses_variable(source, "unsubscribe_url") {
+        lines.push(format!("Don't want to hear from us? <a href=\"{}\">Unsubscribe</a>.", escape_html(footer.unsubscribe_url)));
+    }
+    if lines.is_empty() {
+        return;
+    }
+    let block = format!("<p style=\"font-size: 12px; color: #888888;\">{}</p>\n", lines.join("<br>\n"));
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(end) => html.insert_str(end, &block),
+        None => html.push_str(&block),
+    }
+}
+
+fn add_text_footer(text: &mut String, source: &str, footer: &FooterDetails) {
+    let mut lines = Vec::new();
+    if !uses_variable(source, "postal_address") {
+        lines.push(format!("{}, {}", footer.company_name, footer.postal_address));
+    }
+    if !uses_variable(source, "unsubscribe_url") {
+        lines.push(format!("Don't want to hear from us? Unsubscribe: {}", footer.unsubscribe_url));
+    }
+    if !lines.is_empty() {
+        text.push_str(&format!("\n\n{}\n", lines.join("\n")));
+    }
+}
+
+// What an email's body lacks of the required footer, in either part: the
+// postal address or the unsubscribe link, e.g. lost to a spintax group that
+// can leave it out.
+pub fn missing_footer(email: &RenderedEmail, footer: &FooterDetails) -> Option<String> {
+    // Re-serializing the HTML to inline its CSS leaves quotes in text unescaped.
+    let in_html = |value: &str| {
+        let minimal = value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        email.html.contains(&escape_html(value)) || email.html.contains(&minimal)
+    };
+    let parts = [
+        ("HTML", in_html(footer.postal_address), in_html(footer.unsubscribe_url)),
+        ("plain-text", email.text.contains(footer.postal_address), email.text.contains(footer.unsubscribe_url)),
+    ];
+    parts.into_iter().find_map(|(part, has_address, has_link)| {
+        if !has_address {
+            Some(format!("the {} email lacks the postal address required in the footer", part))
+        } else if !has_link {
+            Some(format!("the {} email lacks the unsubscribe link required in the footer", part))
+        } else {
+            None
+        }
+    })
+}
+
+// The href of an <a ...> tag's contents.
+fn link_target(tag: &str) -> Option<String> {
+    let start = tag.to_ascii_lowercase().find("href=")? + "href=".len();
+    let value = &tag[start..];
+    let target = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split_whitespace().next()?,
+    };
+    Some(target.replace("&amp;", "&")).filter(|target| !target.is_empty())
+}
+
+// Drops each <name> element and what it holds. Only that exact tag counts, so
+// <header> isn't taken for <head>; an element left open loses just its tag.
+fn strip_block(html: &str, name: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+    let mut kept = String::new();
+    let mut from = 0;
+    while let Some(found) = lower[from..].find(&open) {
+        let start = from + found;
+        let after = start + open.len();
+        if !lower[after..].starts_with(|c: char| c == '>' || c == '/' || c.is_ascii_whitespace()) {
+            kept.push_str(&html[from..after]);
+            from = after;
+            continue;
+        }
+        kept.push_str(&html[from..start]);
+        from = match lower[after..].find(&close) {
+            Some(end) => after + end + close.len(),
+            None => lower[after..].find('>').map_or(html.len(), |end| after + end + 1),
+        };
+    }
+    kept.push_str(&html[from..]);
+    kept
+}
+
+// A readable plain-text version of an HTML email for templates without a .txt file.
+fn html_to_text(html: &str) -> String {
+    let mut html = html.to_string();
+    for block in ["head", "style", "script"] {
+        html = strip_block(&html, block);
+    }
+    let mut text = String::new();
+    let mut in_tag = false;
+    let mut tag = String::new();
+    // Link targets are written after the link text, as text can't link.
+    let mut href: Option<String> = None;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let name = tag.trim_start_matches('/').split_whitespace().next().unwrap_or("").to_lowercase();
+                match name.as_str() {
+                    "br" | "p" | "div" | "h1" | "h2" | "h3" | "tr" | "ul" | "ol" => text.push('\n'),
+                    "li" if !tag.starts_with('/') => text.push_str("\n- "),
+                    "a" if !tag.starts_with('/') => href = link_target(&tag),
+                    "a" => {
+                        if let Some(target) = href.take().filter(|target| !text.ends_with(target.trim_start_matches("mailto:"))) {
+                            text.push_str(&format!(" ({})", target));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ if in_tag => tag.push(c),
+            _ => text.push(c),
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push(line);
+        }
+    }
+    lines.join("\n").trim().to_string()
+}
+
+// Where the value of a tag's `name` attribute sits in it, without quotes.
+pub(crate) fn attribute_range(tag: &str, name: &str) -> Option<Range<usize>> {
+    let lower = tag.to_ascii_lowercase();
+    let pattern = format!("{}=", name);
+    let mut from = 0;
+    while let Some(found) = lower[from..].find(&pattern) {
+        let at = from + found;
+        from = at + pattern.len();
+        // Skips longer names that end the same, like data-src.
+        if !lower[..at].ends_with(char::is_whitespace) {
+            continue;
+        }
+        let value = &tag[from..];
+        return match value.chars().next()? {
+            quote @ ('"' | '\'') => value[1..].find(quote).map(|len| from + 1..from + 1 + len),
+            _ => Some(from..from + value.find(char::is_whitespace).unwrap_or(value.len())),
+        };
+    }
+    None
+}
+
+// A path to a file next to the template, rather than a URL, a data: or cid:
+// URI, or a placeholder.
+fn is_local_image(src: &str) -> bool {
+    !src.is_empty() && !src.contains(':') && !src.starts_with("//") && !src.contains("{{")
+}
+
+// Mail clients block remote images until the reader allows them, but show
+// embedded ones: every <img> with a local src, resolved from `dir`, is
+// embedded and pointed at by content id instead.
+fn embed_images(html: &str, dir: &Path) -> Result<(String, Vec<Attachment>), BotError> {
+    let mut output = String::with_capacity(html.len());
+    let mut images: Vec<(PathBuf, Attachment)> = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.to_ascii_lowercase().find("<img") {
+        let end = rest[start..].find('>').map_or(rest.len(), |end| start + end);
+        let tag = &rest[start..end];
+        output.push_str(&rest[..start]);
+        match attribute_range(tag, "src").filter(|range| is_local_image(&tag[range.clone()])) {
+            Some(range) => {
+                let path = dir.join(&tag[range.clone()]);
+                let content_id = match images.iter().find(|(loaded, _)| *loaded == path) {
+                    Some((_, image)) => image.content_id.clone().unwrap_or_default(),
+                    None => {
+                        // The file name, which is what Mailgun matches cid: references against.
+                        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+                        let taken = images.iter().any(|(_, image)| image.content_id.as_deref() == Some(&name));
+                        let content_id = if taken { format!("{}-{}", images.len() + 1, name) } else { name };
+                        let mut image = Attachment::inline_image(&path.to_string_lossy(), &content_id)?;
+                        image.filename = content_id.clone();
+                        images.push((path, image));
+                        content_id
+                    }
+                };
+                output.push_str(&format!("{}cid:{}{}", &tag[..range.start], content_id, &tag[range.end..]));
+            }
+            None => output.push_str(tag),
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    Ok((output, images.into_iter().map(|(_, image)| image).collect()))
+}
+
+impl CampaignTemplate {
+    // "default" is the built-in template; anything else is read as a path.
+    pub fn load(template: &str) -> Result<Self, BotError> {
+        if template == DEFAULT_TEMPLATE {
+            return Ok(CampaignTemplate::Builtin);
+        }
+        let path = PathBuf::from(template);
+        let html = fs::read_to_string(&path)
+            .map_err(|e| BotError::InvalidData(format!("template {} can't be read: {}", path.display(), e)))?;
+        let text_path = path.with_extension("txt");
+        let text = if text_path != path && text_path.exists() {
+            Some(fs::read_to_string(&text_path).map_err(BotError::IOError)?)
+        } else {
+            None
+        };
+        let (html, images) = embed_images(&html, path.parent().unwrap_or(Path::new("")))?;
+        let template = CampaignTemplate::File { path, html, text, images: images.into() };
+        // Catches unknown variables now rather than on the first send.
+        template.render("Sample subject", &Business::default(), &SAMPLE_FOOTER)?;
+        Ok(template)
+    }
+
+    pub fn images(&self) -> &[Attachment] {
+        match self {
+            CampaignTemplate::Builtin => &[],
+            CampaignTemplate::File { images, .. } => images,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            CampaignTemplate::Builtin => format!("the built-in \"{}\" template", DEFAULT_TEMPLATE),
+            CampaignTemplate::File { path, text: Some(_), .. } => format!("{} (with {})", path.display(), path.with_extension("txt").display()),
+            CampaignTemplate::File { path, .. } => path.display().to_string(),
+        }
+    }
+
+    // Spintax in the subject and body is expanded afresh for every email. The
+    // HTML and plain-text parts are spun from the same seed, so a .txt file
+    // whose groups come in the same order as the HTML's picks the same options.
+    // The HTML's CSS is inlined last.
+    pub fn render(&self, subject: &str, business: &Business, footer: &FooterDetails) -> Result<RenderedEmail, BotError> {
+        let seed: u64 = rand::random();
+        let subject = spin(subject, &mut StdRng::seed_from_u64(seed));
+        let (html, text) = match self {
+            CampaignTemplate::Builtin => {
+                let html = spin(&render_email(&subject, business, footer)?, &mut StdRng::seed_from_u64(seed));
+                let text = spin(&render_text_email(&subject, business, footer)?, &mut StdRng::seed_from_u64(seed));
+                (html, text)
+            }
+            CampaignTemplate::File { path, html: html_source, text: text_source, .. } => {
+                let vars = Personalization::new(business);
+                let value = |name: &str| variable_value(name, &subject, business, &vars, footer);
+                let mut html = spin(&substitute(html_source, path, true, value)?, &mut StdRng::seed_from_u64(seed));
+                add_html_footer(&mut html, html_source, footer);
+                let text = match text_source {
+                    Some(text_source) => {
+                        let mut text = spin(&substitute(text_source, &path.with_extension("txt"), false, value)?, &mut StdRng::seed_from_u64(seed));
+                        add_text_footer(&mut text, text_source, footer);
+                        text
+                    }
+                    None => html_to_text(&html),
+                };
+                (html, text)
+            }
+        };
+        Ok(RenderedEmail { subject, html: inline_css(&html), text })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_to_text_drops_head_style_and_script() {
+        let html = "<html><head><title>T</title></head><body><style>p { color: red; }</style><p>Hello</p><script>x()</script></body></html>";
+        assert_eq!(html_to_text(html), "Hello");
+    }
+
+    #[test]
+    fn html_to_text_decodes_entities() {
+        assert_eq!(html_to_text("<p>Tom &amp; Jerry&#39;s &lt;shop&gt;</p>"), "Tom & Jerry's <shop>");
+    }
+
+    #[test]
+    fn spin_picks_one_option_of_each_group() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            let spun = spin("{Hello|Hi|Hey} there", &mut rng);
+            assert!(["Hello there", "Hi there", "Hey there"].contains(&spun.as_str()), "{}", spun);
+        }
+    }
+
+    #[test]
+    fn spin_expands_nested_groups() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            let spun = spin("{Good {morning|day}|Hi}!", &mut rng);
+            assert!(["Good morning!", "Good day!", "Hi!"].contains(&spun.as_str()), "{}", spun);
+        }
+    }
+
+    #[test]
+    fn spin_keeps_braces_without_options() {
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(spin("a { color: red; } {{ city }}", &mut rng), "a { color: red; } {{ city }}");
+        assert_eq!(spin("unclosed {a|b", &mut rng), "unclosed {a|b");
+    }
+
+    #[test]
+    fn html_to_text_writes_link_targets_and_list_items() {
+        let html = "<p>See <a href=\"https://example.com/?a=1&amp;b=2\">our site</a></p><ul><li>One</li><li>Two</li></ul>";
+        assert_eq!(html_to_text(html), "See our site (https://example.com/?a=1&b=2)\n\n- One\n- Two");
+    }
+
+    #[test]
+    fn html_to_text_keeps_header_elements() {
+        let html = "<html><head><title>T</title></head><body><header>Coffee Code Studio</header><p>Hello</p></body></html>";
+        assert_eq!(html_to_text(html), "Coffee Code Studio\nHello");
+    }
+
+    #[test]
+    fn html_to_text_keeps_the_rest_after_an_unclosed_block() {
+        assert_eq!(html_to_text("<head><p>Hello</p>"), "Hello");
+    }
+}
@@ -0,0 +1,206 @@
+use reqwest::Url;
+use crate::config::{TrackingConfig, UtmConfig};
+use crate::email::normalize_email;
+use crate::template::attribute_range;
+use crate::unsubscribe::{sign, verify};
+
+// The path segments `serve` recognizes open pixels and tracked links by.
+pub const OPEN_PATH: &str = "open";
+pub const CLICK_PATH: &str = "click";
+
+// "<campaign id>:<address>", so an open counts towards the campaign that sent
+// the email even when the recipient got several. Link tokens add
+// ":<target URL>", which being signed keeps the redirect from sending anyone
+// anywhere else.
+pub fn tracking_token(secret: &str, campaign_id: i64, email: &str, target: Option<&str>) -> String {
+    let mut payload = format!("{}:{}", campaign_id, normalize_email(email));
+    if let Some(target) = target {
+        payload.push(':');
+        payload.push_str(target);
+    }
+    sign(secret, &payload)
+}
+
+// What a token was issued for: the campaign, the address and, for a link,
+// its target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackedRecipient {
+    pub campaign_id: i64,
+    pub email: String,
+    pub target: Option<String>,
+}
+
+pub fn verify_tracking_token(secret: &str, token: &str) -> Option<TrackedRecipient> {
+    let payload = verify(secret, token)?;
+    let mut parts = payload.splitn(3, ':');
+    let campaign_id = parts.next()?.parse().ok()?;
+    let email = parts.next()?.to_string();
+    Some(TrackedRecipient { campaign_id, email, target: parts.next().map(str::to_string) })
+}
+
+// The tracking URLs of one recipient's email.
+pub struct TrackingLinks<'a> {
+    base: &'a str,
+    secret: &'a str,
+    clicks: bool,
+    campaign_id: i64,
+    email: &'a str,
+}
+
+impl<'a> TrackingLinks<'a> {
+    // None when tracking.url isn't set; Config::validate makes sure it comes
+    // with a secret.
+    pub fn new(config: &'a TrackingConfig, campaign_id: i64, email: &'a str) -> Option<Self> {
+        let (url, secret) = config.url.as_ref().zip(config.secret.as_ref())?;
+        Some(TrackingLinks { base: url.trim_end_matches('/'), secret, clicks: config.clicks, campaign_id, email })
+    }
+
+    pub fn open_pixel_url(&self) -> String {
+        format!("{}/{}/{}", self.base, OPEN_PATH, tracking_token(self.secret, self.campaign_id, self.email, None))
+    }
+
+    pub fn click_url(&self, target: &str) -> String {
+        format!("{}/{}/{}", self.base, CLICK_PATH, tracking_token(self.secret, self.campaign_id, self.email, Some(target)))
+    }
+
+    // Points every http(s) link of the HTML at its click URL, except those in
+    // `keep`, like the unsubscribe link, which must work without the
+    // tracking server. The plain-text part keeps its links as they are.
+    pub fn track_clicks(&self, html: &str, keep: &[&str]) -> String {
+        if !self.clicks {
+            return html.to_string();
+        }
+        rewrite_html_links(html, |target| (!keep.contains(&target)).then(|| self.click_url(target)))
+    }
+
+    // Appends the open pixel to the end of the body.
+    pub fn add_open_pixel(&self, html: &mut String) {
+        let pixel = format!(
+            "<img src=\"{}\" width=\"1\" height=\"1\" alt=\"\" style=\"display: block; width: 1px; height: 1px; border: 0\">\n",
+            self.open_pixel_url().replace('&', "&amp;")
+        );
+        match html.to_ascii_lowercase().rfind("</body>") {
+            Some(end) => html.insert_str(end, &pixel),
+            None => html.push_str(&pixel),
+        }
+    }
+}
+
+fn is_web_link(target: &str) -> bool {
+    target.starts_with("https://") || target.starts_with("http://")
+}
+
+// Replaces the href of every <a> tag linking to an http(s) URL with what
+// `rewrite` returns for it, if anything.
+fn rewrite_html_links(html: &str, rewrite: impl Fn(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.to_ascii_lowercase().find("<a ") {
+        let end = rest[start..].find('>').map_or(rest.len(), |end| start + end);
+        let tag = &rest[start..end];
+        output.push_str(&rest[..start]);
+        let rewritten = attribute_range(tag, "href").and_then(|range| {
+            let target = tag[range.clone()].replace("&amp;", "&");
+            let replacement = rewrite(&target).filter(|_| is_web_link(&target))?;
+            Some(format!("{}{}{}", &tag[..range.start], replacement.replace('&', "&amp;"), &tag[range.end..]))
+        });
+        output.push_str(rewritten.as_deref().unwrap_or(tag));
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+// The same for bare URLs in plain text, which end at whitespace or a closing
+// bracket or quote, without trailing punctuation.
+fn rewrite_text_links(text: &str, rewrite: impl Fn(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = [rest.find("https://"), rest.find("http://")].into_iter().flatten().min() {
+        output.push_str(&rest[..start]);
+        let link = &rest[start..];
+        let end = link.find(|c: char| c.is_whitespace() || ")]>\"'".contains(c)).unwrap_or(link.len());
+        let target = link[..end].trim_end_matches(|c: char| ".,;:!?".contains(c));
+        output.push_str(&rewrite(target).unwrap_or_else(|| target.to_string()));
+        rest = &link[target.len()..];
+    }
+    output.push_str(rest);
+    output
+}
+
+// `target` with the UTM parameters added, or None when it already has some.
+fn with_utm(target: &str, utm: &UtmConfig, campaign: &str) -> Option<String> {
+    let mut url = Url::parse(target).ok()?;
+    if url.query_pairs().any(|(name, _)| name.starts_with("utm_")) {
+        return None;
+    }
+    url.query_pairs_mut()
+        .append_pair("utm_source", &utm.source.replace("{campaign}", campaign))
+        .append_pair("utm_medium", &utm.medium.replace("{campaign}", campaign))
+        .append_pair("utm_campaign", &utm.campaign.replace("{campaign}", campaign));
+    Some(url.to_string())
+}
+
+// Adds UTM parameters to the links of both parts of an email, except those in
+// `keep`. Runs before click tracking, so the redirect lands on the tagged URL.
+pub fn add_utm_parameters(html: &str, text: &str, utm: &UtmConfig, campaign: &str, keep: &[&str]) -> (String, String) {
+    if !utm.enabled {
+        return (html.to_string(), text.to_string());
+    }
+    let rewrite = |target: &str| (!keep.contains(&target)).then(|| with_utm(target, utm, campaign)).flatten();
+    (rewrite_html_links(html, rewrite), rewrite_text_links(text, rewrite))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_tracking_token_reads_back_the_campaign_address_and_target() {
+        let open = tracking_token("secret", 7, "Jane@Plumbing.com", None);
+        assert_eq!(
+            verify_tracking_token("secret", &open),
+            Some(TrackedRecipient { campaign_id: 7, email: "jane@plumbing.com".to_string(), target: None })
+        );
+        let click = tracking_token("secret", 7, "jane@plumbing.com", Some("https://studio.dev/work?a=1:2"));
+        assert_eq!(verify_tracking_token("secret", &click).and_then(|recipient| recipient.target).as_deref(), Some("https://studio.dev/work?a=1:2"));
+        assert_eq!(verify_tracking_token("other secret", &click), None);
+        assert_eq!(verify_tracking_token("secret", &sign("secret", "jane@plumbing.com")), None);
+        assert_eq!(verify_tracking_token("secret", &sign("secret", "seven:jane@plumbing.com")), None);
+    }
+
+    #[test]
+    fn rewrite_html_links_replaces_only_the_web_links_of_a_tags() {
+        let html = r#"<p>See <A class="x" href="https://studio.dev/a?b=1&amp;c=2">work</A>, <a href="mailto:me@studio.dev">mail</a> and <img src="https://studio.dev/i.png"></p>"#;
+        let rewritten = rewrite_html_links(html, |target| (target == "https://studio.dev/a?b=1&c=2").then(|| "https://t.dev/?x=1&y=2".to_string()));
+        assert_eq!(
+            rewritten,
+            r#"<p>See <A class="x" href="https://t.dev/?x=1&amp;y=2">work</A>, <a href="mailto:me@studio.dev">mail</a> and <img src="https://studio.dev/i.png"></p>"#
+        );
+        assert_eq!(rewrite_html_links(html, |_| None), html);
+    }
+
+    #[test]
+    fn with_utm_tags_untagged_links_only() {
+        let utm = UtmConfig { enabled: true, source: "email".to_string(), medium: "outreach".to_string(), campaign: "{campaign}".to_string() };
+        assert_eq!(
+            with_utm("https://studio.dev/work?ref=1", &utm, "spring").as_deref(),
+            Some("https://studio.dev/work?ref=1&utm_source=email&utm_medium=outreach&utm_campaign=spring")
+        );
+        assert_eq!(with_utm("https://studio.dev/?utm_source=x", &utm, "spring"), None);
+        assert_eq!(with_utm("not a url", &utm, "spring"), None);
+    }
+
+    #[test]
+    fn add_utm_parameters_leaves_the_kept_links_alone() {
+        let utm = UtmConfig { enabled: true, source: "email".to_string(), medium: "outreach".to_string(), campaign: "{campaign}".to_string() };
+        let html = r#"<a href="https://studio.dev/">Us</a> <a href="https://studio.dev/unsubscribe?t=1">Unsubscribe</a>"#;
+        let text = "Us: https://studio.dev/. Unsubscribe: https://studio.dev/unsubscribe?t=1";
+        let (html, text) = add_utm_parameters(html, text, &utm, "spring", &["https://studio.dev/unsubscribe?t=1"]);
+        assert_eq!(
+            html,
+            r#"<a href="https://studio.dev/?utm_source=email&amp;utm_medium=outreach&amp;utm_campaign=spring">Us</a> <a href="https://studio.dev/unsubscribe?t=1">Unsubscribe</a>"#
+        );
+        assert_eq!(text, "Us: https://studio.dev/?utm_source=email&utm_medium=outreach&utm_campaign=spring. Unsubscribe: https://studio.dev/unsubscribe?t=1");
+    }
+}
@@ -0,0 +1,93 @@
+use async_trait::async_trait;
+use reqwest::multipart::{Form, Part};
+use crate::config::MailgunConfig;
+use crate::error::BotError;
+use crate::http_client;
+use crate::transport::{EmailTransport, OutgoingEmail};
+
+// Mailgun keeps at most three tags per message.
+const MAX_TAGS: usize = 3;
+
+// Sends through the messages API of one sending domain, authenticating as
+// "api" with the account's private API key. Tags show up in Mailgun's
+// analytics and can be filtered on in its event logs.
+pub struct MailgunTransport {
+    client: reqwest::Client,
+    api_key: String,
+    messages_url: String,
+    sandbox: bool,
+}
+
+impl MailgunTransport {
+    pub fn new(config: &MailgunConfig) -> Result<Self, BotError> {
+        let domain = config.sending_domain()?;
+        let sandbox = config.sandbox;
+        if sandbox {
+            println!("Using the Mailgun sandbox domain {}; only its authorized recipients get mail", domain);
+        }
+        Ok(MailgunTransport {
+            client: http_client::client()?,
+            api_key: config.api_key()?.to_string(),
+            messages_url: format!("{}/v3/{}/messages", config.region.api_url(), domain),
+            sandbox,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for MailgunTransport {
+    fn name(&self) -> &str {
+        if self.sandbox {
+            "Mailgun (sandbox)"
+        } else {
+            "Mailgun"
+        }
+    }
+
+    async fn send(&self, email: &OutgoingEmail) -> Result<(), BotError> {
+        let mut form: Vec<(&str, String)> = vec![
+            ("from", email.from.to_string()),
+            ("to", email.recipient()?.to_string()),
+            ("subject", email.subject.clone()),
+            ("text", email.text.clone()),
+            ("html", email.html.clone()),
+        ];
+        for tag in email.tags.iter().take(MAX_TAGS) {
+            form.push(("o:tag", tag.clone()));
+        }
+        let headers: Vec<(String, String)> =
+            email.extra_headers().into_iter().map(|(name, value)| (format!("h:{}", name), value)).collect();
+        form.extend(headers.iter().map(|(name, value)| (name.as_str(), value.clone())));
+        let request = self.client.post(&self.messages_url).basic_auth("api", Some(&self.api_key));
+        // Attachments are file parts, which only a multipart/form-data body can
+        // carry. Mailgun gives an inline part its file name as content id.
+        let request = if email.files().next().is_none() {
+            request.form(&form)
+        } else {
+            let mut multipart = Form::new();
+            for (name, value) in form {
+                multipart = multipart.text(name.to_string(), value);
+            }
+            for attachment in email.files() {
+                let part = Part::bytes(attachment.data.clone())
+                    .file_name(attachment.content_id.clone().unwrap_or_else(|| attachment.filename.clone()))
+                    .mime_str(&attachment.content_type)
+                    .map_err(BotError::NetworkError)?;
+                let field = if attachment.content_id.is_some() { "inline" } else { "attachment" };
+                multipart = multipart.part(field, part);
+            }
+            request.multipart(multipart)
+        };
+        let response = request
+            .send()
+            .await
+            .map_err(BotError::NetworkError)?;
+        let status = response.status();
+        if !status.is_success() {
+            // Errors come back as {"message": ...}.
+            let message = response.text().await.unwrap_or_default();
+            return Err(BotError::ProviderError { provider: "Mailgun".to_string(), status: status.as_u16(), message });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::message::Mailbox;
+use rand::Rng;
+use crate::attachment::Attachment;
+use crate::config::{Config, TransportKind};
+use crate::error::BotError;
+
+pub mod mailgun;
+pub mod postmark;
+pub mod rotation;
+pub mod sendgrid;
+pub mod ses;
+pub mod smtp;
+
+pub use mailgun::MailgunTransport;
+pub use postmark::PostmarkTransport;
+pub use sendgrid::SendgridTransport;
+pub use ses::SesTransport;
+pub use smtp::SmtpMailer;
+
+// A List-Unsubscribe header value, e.g. "<mailto:...>, <https://...>". With
+// `one_click`, List-Unsubscribe-Post is sent as well.
+#[derive(Debug, Clone)]
+pub struct ListUnsubscribe {
+    pub value: String,
+    pub one_click: bool,
+}
+
+// A fresh "<...@domain>" Message-ID for an email sent from `domain`.
+pub fn new_message_id(domain: &str) -> String {
+    format!("<{}.{:016x}@{}>", Utc::now().timestamp_millis(), rand::thread_rng().gen::<u64>(), domain)
+}
+
+// One rendered email, independent of how it is delivered.
+#[derive(Debug, Clone)]
+pub struct OutgoingEmail {
+    pub from: Mailbox,
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    // Plain-text alternative to `html`.
+    pub text: String,
+    // Name of the campaign the email belongs to.
+    pub campaign: String,
+    // Labels for the provider's analytics, e.g. the campaign name.
+    pub tags: Vec<String>,
+    pub list_unsubscribe: Option<ListUnsubscribe>,
+    // Shared by every email of the campaign.
+    pub attachments: Arc<[Attachment]>,
+    // Images the HTML shows by content id; shared by every email of the template.
+    pub inline_images: Arc<[Attachment]>,
+    // Set by us rather than the transport, so follow-ups can refer to it.
+    // Some providers replace it with their own.
+    pub message_id: String,
+    // Message-IDs of the earlier emails of the conversation, oldest first;
+    // a follow-up is a reply to the last of them.
+    pub references: Vec<String>,
+}
+
+impl OutgoingEmail {
+    pub fn recipient(&self) -> Result<Mailbox, BotError> {
+        self.to
+            .parse()
+            .map_err(|e| BotError::InvalidData(format!("recipient \"{}\" is not an email address: {}", self.to, e)))
+    }
+
+    // The attachments followed by the inline images.
+    pub fn files(&self) -> impl Iterator<Item = &Attachment> {
+        self.attachments.iter().chain(self.inline_images.iter())
+    }
+
+    // Headers beyond the ones every email has, for APIs that take them by name.
+    pub fn extra_headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![("Message-ID", self.message_id.clone())];
+        if let Some(parent) = self.references.last() {
+            headers.push(("In-Reply-To", parent.clone()));
+            headers.push(("References", self.references.join(" ")));
+        }
+        if let Some(unsubscribe) = &self.list_unsubscribe {
+            headers.push(("List-Unsubscribe", unsubscribe.value.clone()));
+            if unsubscribe.one_click {
+                headers.push(("List-Unsubscribe-Post", "List-Unsubscribe=One-Click".to_string()));
+            }
+        }
+        headers
+    }
+}
+
+// Something that delivers emails: an SMTP server or a provider's HTTP API.
+#[async_trait]
+pub trait EmailTransport: Send + Sync {
+    fn name(&self) -> &str;
+
+    // Called before every send, e.g. to renew credentials; an error here
+    // skips the lead, and a few leads in a row failing it stop the campaign.
+    async fn prepare(&mut self) -> Result<(), BotError> {
+        Ok(())
+    }
+
+    // How many more emails the provider allows right now, for providers with
+    // a sending quota; the campaign stops at zero.
+    fn quota_left(&self) -> Option<u64> {
+        None
+    }
+
+    // Errors here are recorded against the recipient and the campaign continues.
+    async fn send(&self, email: &OutgoingEmail) -> Result<(), BotError>;
+}
+
+// The transport picked by send.transport in config.toml.
+pub async fn connect(config: &Config, sender: &Mailbox) -> Result<Box<dyn EmailTransport>, BotError> {
+    Ok(match config.send.transport {
+        TransportKind::Smtp => Box::new(SmtpMailer::connect(&config.smtp, &config.dkim, sender).await?),
+        TransportKind::Sendgrid => Box::new(SendgridTransport::new(&config.sendgrid)?),
+        TransportKind::Mailgun => Box::new(MailgunTransport::new(&config.mailgun)?),
+        TransportKind::Ses => Box::new(SesTransport::connect(&config.ses).await?),
+        TransportKind::Postmark => Box::new(PostmarkTransport::new(&config.postmark)?),
+        TransportKind::Sandbox => Box::new(SmtpMailer::sandbox(&config.sandbox, &config.dkim, sender).await?),
+    })
+}
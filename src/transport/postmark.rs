@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use crate::config::PostmarkConfig;
+use crate::error::BotError;
+use crate::http_client;
+use crate::transport::{EmailTransport, OutgoingEmail};
+
+const EMAIL_URL: &str = "https://api.postmarkapp.com/email";
+
+#[derive(Deserialize)]
+struct SendResponse {
+    #[serde(rename = "ErrorCode")]
+    error_code: i64,
+    #[serde(default, rename = "Message")]
+    message: String,
+}
+
+// Sends through a Postmark server's email API. Each campaign goes out on a
+// message stream: the one mapped to it in [postmark.streams], or the default
+// stream. Postmark only allows bulk and cold mail on broadcast streams, which
+// also get its unsubscribe handling, so the default is the server's
+// "broadcast" stream rather than the transactional "outbound" one.
+pub struct PostmarkTransport {
+    client: reqwest::Client,
+    server_token: String,
+    message_stream: String,
+    streams: HashMap<String, String>,
+}
+
+impl PostmarkTransport {
+    pub fn new(config: &PostmarkConfig) -> Result<Self, BotError> {
+        Ok(PostmarkTransport {
+            client: http_client::client()?,
+            server_token: config.server_token()?.to_string(),
+            message_stream: config.message_stream.clone(),
+            streams: config.streams.clone(),
+        })
+    }
+
+    fn stream_for(&self, campaign: &str) -> &str {
+        self.streams.get(campaign).unwrap_or(&self.message_stream)
+    }
+}
+
+#[async_trait]
+impl EmailTransport for PostmarkTransport {
+    fn name(&self) -> &str {
+        "Postmark"
+    }
+
+    async fn send(&self, email: &OutgoingEmail) -> Result<(), BotError> {
+        let mut body = json!({
+            "From": email.from.to_string(),
+            "To": email.recipient()?.to_string(),
+            "Subject": email.subject,
+            "HtmlBody": email.html,
+            "TextBody": email.text,
+            "MessageStream": self.stream_for(&email.campaign),
+        });
+        // Postmark takes a single tag per message.
+        if let Some(tag) = email.tags.first() {
+            body["Tag"] = json!(tag);
+        }
+        let attachments: Vec<_> = email
+            .files()
+            .map(|attachment| {
+                let mut file = json!({ "Name": attachment.filename, "Content": attachment.base64(), "ContentType": attachment.content_type });
+                if let Some(content_id) = &attachment.content_id {
+                    file["ContentID"] = json!(format!("cid:{}", content_id));
+                }
+                file
+            })
+            .collect();
+        if !attachments.is_empty() {
+            body["Attachments"] = json!(attachments);
+        }
+        let headers = email.extra_headers();
+        if !headers.is_empty() {
+            let headers: Vec<_> = headers.iter().map(|(name, value)| json!({ "Name": name, "Value": value })).collect();
+            body["Headers"] = json!(headers);
+        }
+        let response = self
+            .client
+            .post(EMAIL_URL)
+            .header("X-Postmark-Server-Token", &self.server_token)
+            .header("Accept", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .map_err(BotError::NetworkError)?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        // Rejections come back as HTTP 422 with a non-zero ErrorCode, e.g. 406
+        // for an address that is inactive on the stream.
+        match serde_json::from_str::<SendResponse>(&text) {
+            Ok(result) if status.is_success() && result.error_code == 0 => Ok(()),
+            Ok(result) => Err(BotError::ProviderError {
+                provider: "Postmark".to_string(),
+                status: status.as_u16(),
+                message: format!("error {}: {}", result.error_code, result.message),
+            }),
+            Err(_) => Err(BotError::ProviderError { provider: "Postmark".to_string(), status: status.as_u16(), message: text }),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use lettre::message::Mailbox;
+use crate::config::{Config, TransportKind, WarmupConfig};
+use crate::error::BotError;
+use crate::ratelimit::{account_sent_today, check_update_account_count, count_account_send, warmup_day, RateLimiter};
+use crate::transport::{self, EmailTransport, SmtpMailer};
+
+// A From address and what delivers its mail.
+pub struct SenderAccount {
+    pub name: String,
+    pub mailbox: Mailbox,
+    pub transport: Box<dyn EmailTransport>,
+    // Sends are counted in Redis per account either way; None leaves only the
+    // global daily limit.
+    pub max_per_day: Option<usize>,
+    // Whether [warmup] ramps up its daily cap.
+    pub warmup: bool,
+}
+
+// The accounts a campaign sends from, taken in turn so each email goes out
+// from the next account that still has sends left today.
+pub struct SenderPool {
+    accounts: Vec<SenderAccount>,
+    next: usize,
+    // Sends per account a dry run pretended to make.
+    rehearsed: Vec<usize>,
+    warmup: WarmupConfig,
+}
+
+impl SenderPool {
+    // Every [[accounts]] entry, or just [send] sender through send.transport
+    // when none are configured.
+    pub async fn connect(config: &Config, default_sender: &Mailbox) -> Result<Self, BotError> {
+        let mut accounts = Vec::new();
+        for account in &config.accounts {
+            let mailbox = account.sender_mailbox()?;
+            // The sandbox takes every account's mail, with their own
+            // servers and credentials left alone.
+            let transport = match config.send.transport {
+                TransportKind::Sandbox => SmtpMailer::sandbox(&config.sandbox, &config.dkim, &mailbox).await?,
+                _ => SmtpMailer::connect(&account.smtp(&config.smtp)?, &config.dkim, &mailbox).await?,
+            };
+            accounts.push(SenderAccount {
+                name: account.name.clone(),
+                transport: Box::new(transport),
+                mailbox,
+                max_per_day: account.max_per_day,
+                warmup: account.warmup.unwrap_or(config.warmup.enabled),
+            });
+        }
+        if accounts.is_empty() {
+            accounts.push(SenderAccount {
+                name: default_sender.email.to_string(),
+                mailbox: default_sender.clone(),
+                transport: transport::connect(config, default_sender).await?,
+                max_per_day: None,
+                warmup: config.warmup.enabled,
+            });
+        }
+        let rehearsed = vec![0; accounts.len()];
+        Ok(SenderPool { accounts, next: 0, rehearsed, warmup: config.warmup.clone() })
+    }
+
+    pub fn describe(&self) -> String {
+        self.accounts
+            .iter()
+            .map(|account| {
+                let warmup = if account.warmup { ", warming up" } else { "" };
+                match account.max_per_day {
+                    Some(max_per_day) => format!("{} via {} ({}/day{})", account.mailbox, account.transport.name(), max_per_day, warmup),
+                    None if account.warmup => format!("{} via {}, warming up", account.mailbox, account.transport.name()),
+                    None => format!("{} via {}", account.mailbox, account.transport.name()),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // The account's own cap or, lower while it warms up, what today of the
+    // warm-up allows; `start` starts its warm-up with this send.
+    async fn daily_limit(&self, limiter: &mut dyn RateLimiter, index: usize, start: bool) -> Result<Option<usize>, BotError> {
+        let account = &self.accounts[index];
+        if !account.warmup {
+            return Ok(account.max_per_day);
+        }
+        let warmup = self.warmup.limit(warmup_day(limiter, &account.name, start).await?);
+        Ok(Some(account.max_per_day.map_or(warmup, |max_per_day| max_per_day.min(warmup))))
+    }
+
+    // The next account in turn that is under its daily cap and provider quota,
+    // counting the send against its cap; None once every account is used up.
+    pub async fn next_available(&mut self, limiter: &mut dyn RateLimiter) -> Result<Option<&mut SenderAccount>, BotError> {
+        let count = self.accounts.len();
+        for offset in 0..count {
+            let index = (self.next + offset) % count;
+            let account = &self.accounts[index];
+            if account.transport.quota_left() == Some(0) {
+                continue;
+            }
+            match self.daily_limit(limiter, index, true).await? {
+                Some(max_per_day) => {
+                    if !check_update_account_count(limiter, &account.name, max_per_day).await? {
+                        continue;
+                    }
+                }
+                None => count_account_send(limiter, &account.name).await?,
+            }
+            self.next = (index + 1) % count;
+            return Ok(Some(&mut self.accounts[index]));
+        }
+        Ok(None)
+    }
+
+    // The same pick for a dry run, which counts the send in memory on top of
+    // what each account already sent today, leaving Redis untouched.
+    pub async fn rehearse_next(&mut self, limiter: &mut dyn RateLimiter) -> Result<Option<&mut SenderAccount>, BotError> {
+        let count = self.accounts.len();
+        for offset in 0..count {
+            let index = (self.next + offset) % count;
+            let account = &self.accounts[index];
+            if account.transport.quota_left() == Some(0) {
+                continue;
+            }
+            if let Some(max_per_day) = self.daily_limit(limiter, index, false).await? {
+                if account_sent_today(limiter, &account.name).await? + self.rehearsed[index] >= max_per_day {
+                    continue;
+                }
+            }
+            self.rehearsed[index] += 1;
+            self.next = (index + 1) % count;
+            return Ok(Some(&mut self.accounts[index]));
+        }
+        Ok(None)
+    }
+}
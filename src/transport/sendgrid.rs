@@ -0,0 +1,92 @@
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use serde_json::{json, Value};
+use crate::config::SendgridConfig;
+use crate::error::BotError;
+use crate::http_client;
+use crate::transport::{EmailTransport, OutgoingEmail};
+
+const MAIL_SEND_URL: &str = "https://api.sendgrid.com/v3/mail/send";
+// SendGrid rejects more than 10 categories per message.
+const MAX_CATEGORIES: usize = 10;
+
+fn address(mailbox: &Mailbox) -> Value {
+    match &mailbox.name {
+        Some(name) => json!({ "email": mailbox.email.to_string(), "name": name }),
+        None => json!({ "email": mailbox.email.to_string() }),
+    }
+}
+
+// Sends through the v3 mail/send API with an API key that has the Mail Send
+// permission. Tags become SendGrid categories, so opens, clicks and bounces
+// can be broken down per campaign in its Statistics page.
+pub struct SendgridTransport {
+    client: reqwest::Client,
+    api_key: String,
+}
+
+impl SendgridTransport {
+    pub fn new(config: &SendgridConfig) -> Result<Self, BotError> {
+        Ok(SendgridTransport {
+            client: http_client::client()?,
+            api_key: config.api_key()?.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for SendgridTransport {
+    fn name(&self) -> &str {
+        "SendGrid"
+    }
+
+    async fn send(&self, email: &OutgoingEmail) -> Result<(), BotError> {
+        let mut body = json!({
+            "personalizations": [{ "to": [address(&email.recipient()?)] }],
+            "from": address(&email.from),
+            "subject": email.subject,
+            // SendGrid requires text/plain to come before text/html.
+            "content": [{ "type": "text/plain", "value": email.text }, { "type": "text/html", "value": email.html }],
+        });
+        if !email.tags.is_empty() {
+            let categories: Vec<&String> = email.tags.iter().take(MAX_CATEGORIES).collect();
+            body["categories"] = json!(categories);
+        }
+        let attachments: Vec<_> = email
+            .files()
+            .map(|attachment| {
+                let mut file = json!({ "content": attachment.base64(), "type": attachment.content_type, "filename": attachment.filename });
+                match &attachment.content_id {
+                    Some(content_id) => {
+                        file["disposition"] = json!("inline");
+                        file["content_id"] = json!(content_id);
+                    }
+                    None => file["disposition"] = json!("attachment"),
+                }
+                file
+            })
+            .collect();
+        if !attachments.is_empty() {
+            body["attachments"] = json!(attachments);
+        }
+        let headers = email.extra_headers();
+        if !headers.is_empty() {
+            body["headers"] = headers.into_iter().map(|(name, value)| (name.to_string(), json!(value))).collect();
+        }
+        let response = self
+            .client
+            .post(MAIL_SEND_URL)
+            .bearer_auth(&self.api_key)
+            .json(&body)
+            .send()
+            .await
+            .map_err(BotError::NetworkError)?;
+        let status = response.status();
+        if !status.is_success() {
+            // Errors come back as {"errors": [{"message": ..., "field": ...}]}.
+            let message = response.text().await.unwrap_or_default();
+            return Err(BotError::ProviderError { provider: "SendGrid".to_string(), status: status.as_u16(), message });
+        }
+        Ok(())
+    }
+}
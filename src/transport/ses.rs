@@ -0,0 +1,228 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use crate::config::SesConfig;
+use crate::error::BotError;
+use crate::http_client;
+use crate::transport::{EmailTransport, OutgoingEmail};
+
+const SERVICE: &str = "ses";
+// SES tag values only allow ASCII letters, digits, '_', '-', '.' and '@'.
+const TAG_NAME: &str = "campaign";
+
+#[derive(Deserialize)]
+struct AccountResponse {
+    #[serde(rename = "SendQuota")]
+    send_quota: SendQuota,
+    #[serde(default, rename = "ProductionAccessEnabled")]
+    production_access: bool,
+}
+
+#[derive(Deserialize)]
+struct SendQuota {
+    #[serde(rename = "Max24HourSend")]
+    max_24_hour_send: f64,
+    #[serde(rename = "MaxSendRate")]
+    max_send_rate: f64,
+    #[serde(rename = "SentLast24Hours")]
+    sent_last_24_hours: f64,
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn tag_value(tag: &str) -> String {
+    tag.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "_-.@".contains(c) { c } else { '_' })
+        .collect()
+}
+
+// Sends through the SES v2 API, signing requests with AWS Signature Version 4.
+// The account's sending quota is read when connecting: sends are spaced to
+// stay under its maximum send rate, and the campaign stops before the 24-hour
+// quota runs out.
+pub struct SesTransport {
+    client: reqwest::Client,
+    config: SesConfig,
+    host: String,
+    send_interval: Duration,
+    last_send: Option<Instant>,
+    quota_left: u64,
+    sent: AtomicU64,
+}
+
+impl SesTransport {
+    pub async fn connect(config: &SesConfig) -> Result<Self, BotError> {
+        config.access_key_id()?;
+        config.secret_access_key()?;
+        let mut transport = SesTransport {
+            client: http_client::client()?,
+            config: config.clone(),
+            host: format!("email.{}.amazonaws.com", config.region),
+            send_interval: Duration::ZERO,
+            last_send: None,
+            quota_left: 0,
+            sent: AtomicU64::new(0),
+        };
+
+        let account: AccountResponse = serde_json::from_value(transport.request("GET", "/v2/email/account", None).await?)
+            .map_err(BotError::DataParseError)?;
+        let quota = account.send_quota;
+        if quota.max_send_rate > 0.0 {
+            transport.send_interval = Duration::from_secs_f64(1.0 / quota.max_send_rate);
+        }
+        transport.quota_left = (quota.max_24_hour_send - quota.sent_last_24_hours).max(0.0) as u64;
+        println!(
+            "SES quota: {} of {} emails left in the last 24 hours, at most {} per second",
+            transport.quota_left, quota.max_24_hour_send, quota.max_send_rate
+        );
+        if !account.production_access {
+            println!("The SES account is in the sandbox; only verified recipients get mail");
+        }
+        Ok(transport)
+    }
+
+    // The Authorization header for a request, per
+    // https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_sigv.html
+    fn authorization(&self, method: &str, path: &str, amz_date: &str, payload_hash: &str) -> Result<String, BotError> {
+        let date = &amz_date[..8];
+        let mut headers = vec![("host", self.host.clone()), ("x-amz-date", amz_date.to_string())];
+        if let Some(token) = &self.config.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("{}\n{}\n\n{}\n{}\n{}", method, path, canonical_headers, signed_headers, payload_hash);
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.config.region, SERVICE);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+            amz_date,
+            scope,
+            Sha256::digest(canonical_request.as_bytes())
+        );
+        let secret = format!("AWS4{}", self.config.secret_access_key()?);
+        let key = hmac_sha256(secret.as_bytes(), date);
+        let key = hmac_sha256(&key, &self.config.region);
+        let key = hmac_sha256(&key, SERVICE);
+        let key = hmac_sha256(&key, "aws4_request");
+        let signature: String = hmac_sha256(&key, &string_to_sign).iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.config.access_key_id()?,
+            scope,
+            signed_headers,
+            signature
+        ))
+    }
+
+    async fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value, BotError> {
+        let payload = body.map(Value::to_string).unwrap_or_default();
+        let payload_hash = format!("{:x}", Sha256::digest(payload.as_bytes()));
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let authorization = self.authorization(method, path, &amz_date, &payload_hash)?;
+
+        let url = format!("https://{}{}", self.host, path);
+        let method = reqwest::Method::from_bytes(method.as_bytes()).expect("static HTTP method");
+        let mut request = self
+            .client
+            .request(method, &url)
+            .header("x-amz-date", &amz_date)
+            .header("authorization", authorization);
+        if let Some(token) = &self.config.session_token {
+            request = request.header("x-amz-security-token", token);
+        }
+        if body.is_some() {
+            request = request.header("content-type", "application/json").body(payload);
+        }
+        let response = request.send().await.map_err(BotError::NetworkError)?;
+        let status = response.status();
+        if !status.is_success() {
+            // Errors come back as {"message": ...} with the type in x-amzn-ErrorType.
+            let message = response.text().await.unwrap_or_default();
+            return Err(BotError::ProviderError { provider: "SES".to_string(), status: status.as_u16(), message });
+        }
+        response.json().await.map_err(BotError::NetworkError)
+    }
+}
+
+#[async_trait]
+impl EmailTransport for SesTransport {
+    fn name(&self) -> &str {
+        "Amazon SES"
+    }
+
+    // Waits out the gap the account's max send rate requires since the last send.
+    async fn prepare(&mut self) -> Result<(), BotError> {
+        if let Some(last_send) = self.last_send {
+            let elapsed = last_send.elapsed();
+            if elapsed < self.send_interval {
+                tokio::time::sleep(self.send_interval - elapsed).await;
+            }
+        }
+        self.last_send = Some(Instant::now());
+        Ok(())
+    }
+
+    fn quota_left(&self) -> Option<u64> {
+        Some(self.quota_left.saturating_sub(self.sent.load(Ordering::Relaxed)))
+    }
+
+    async fn send(&self, email: &OutgoingEmail) -> Result<(), BotError> {
+        let mut body = json!({
+            "FromEmailAddress": email.from.to_string(),
+            "Destination": { "ToAddresses": [email.recipient()?.to_string()] },
+            "Content": {
+                "Simple": {
+                    "Subject": { "Data": email.subject, "Charset": "UTF-8" },
+                    "Body": {
+                        "Text": { "Data": email.text, "Charset": "UTF-8" },
+                        "Html": { "Data": email.html, "Charset": "UTF-8" },
+                    },
+                }
+            },
+        });
+        if let Some(tag) = email.tags.first() {
+            body["EmailTags"] = json!([{ "Name": TAG_NAME, "Value": tag_value(tag) }]);
+        }
+        let headers = email.extra_headers();
+        if !headers.is_empty() {
+            let headers: Vec<_> = headers.iter().map(|(name, value)| json!({ "Name": name, "Value": value })).collect();
+            body["Content"]["Simple"]["Headers"] = json!(headers);
+        }
+        let attachments: Vec<_> = email
+            .files()
+            .map(|attachment| {
+                let mut file = json!({
+                    "FileName": attachment.filename,
+                    "RawContent": attachment.base64(),
+                    "ContentType": attachment.content_type,
+                    "ContentDisposition": "ATTACHMENT",
+                    "ContentTransferEncoding": "BASE64",
+                });
+                if let Some(content_id) = &attachment.content_id {
+                    file["ContentDisposition"] = json!("INLINE");
+                    file["ContentId"] = json!(content_id);
+                }
+                file
+            })
+            .collect();
+        if !attachments.is_empty() {
+            body["Content"]["Simple"]["Attachments"] = json!(attachments);
+        }
+        if let Some(configuration_set) = &self.config.configuration_set {
+            body["ConfigurationSetName"] = json!(configuration_set);
+        }
+        self.request("POST", "/v2/email/outbound-emails", Some(&body)).await?;
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use async_trait::async_trait;
+use lettre::message::header::{ContentType, Header, HeaderName, HeaderValue};
+use lettre::message::dkim::{DkimCanonicalization, DkimCanonicalizationType, DkimConfig as DkimSigner, DkimSigningAlgorithm, DkimSigningKey};
+use lettre::message::{Attachment as AttachmentPart, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use crate::attachment::Attachment;
+use crate::config::{DkimAlgorithm, DkimConfig, SandboxConfig, SmtpConfig, SmtpTls};
+use crate::error::BotError;
+use crate::http_client;
+use crate::oauth::{refresh_access_token, AccessToken};
+use crate::transport::{EmailTransport, OutgoingEmail};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+// Sends without blocking the runtime, so a slow server only holds up the
+// send itself and not the other work of the run.
+type SmtpTransport = AsyncSmtpTransport<Tokio1Executor>;
+
+// lettre only takes typed headers.
+#[derive(Clone)]
+struct ListUnsubscribeHeader(String);
+
+impl Header for ListUnsubscribeHeader {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, BoxError> {
+        Ok(ListUnsubscribeHeader(s.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+#[derive(Clone)]
+struct ListUnsubscribePostHeader;
+
+impl Header for ListUnsubscribePostHeader {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+
+    fn parse(_: &str) -> Result<Self, BoxError> {
+        Ok(ListUnsubscribePostHeader)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "List-Unsubscribe=One-Click".to_string())
+    }
+}
+
+fn attachment_part(attachment: &Attachment) -> Result<SinglePart, BotError> {
+    let content_type = ContentType::parse(&attachment.content_type)
+        .map_err(|e| BotError::InvalidData(format!("{}: invalid content type: {}", attachment.filename, e)))?;
+    let part = match &attachment.content_id {
+        Some(content_id) => AttachmentPart::new_inline(content_id.clone()),
+        None => AttachmentPart::new(attachment.filename.clone()),
+    };
+    Ok(part.body(attachment.data.clone(), content_type))
+}
+
+// Gmail only shows its unsubscribe button when List-Unsubscribe is signed,
+// and the threading headers are signed so they can't be swapped in transit.
+// Content-Type can't be: lettre writes it with the MIME body, after signing.
+const DKIM_SIGNED_HEADERS: &[&str] = &[
+    "From",
+    "To",
+    "Subject",
+    "Date",
+    "Message-ID",
+    "In-Reply-To",
+    "References",
+    "MIME-Version",
+    "List-Unsubscribe",
+    "List-Unsubscribe-Post",
+];
+
+// None unless dkim.selector is set. Relaxed canonicalization survives relays
+// that refold headers or trailing whitespace.
+fn dkim_signer(dkim: &DkimConfig, sender: &Mailbox) -> Result<Option<DkimSigner>, BotError> {
+    let Some(selector) = &dkim.selector else {
+        return Ok(None);
+    };
+    let algorithm = match dkim.algorithm {
+        DkimAlgorithm::Rsa => DkimSigningAlgorithm::Rsa,
+        DkimAlgorithm::Ed25519 => DkimSigningAlgorithm::Ed25519,
+    };
+    let pem = dkim.private_key()?;
+    let key = DkimSigningKey::new(pem.trim(), algorithm).map_err(|e| {
+        let hint = if pem.contains("BEGIN PRIVATE KEY") {
+            "; convert the PKCS#8 key with `openssl rsa -in key.pem -traditional`"
+        } else {
+            ""
+        };
+        BotError::ConfigError(format!("dkim.private_key: {}{}", e, hint))
+    })?;
+    let domain = dkim.domain.clone().unwrap_or_else(|| sender.email.domain().to_string());
+    let headers = DKIM_SIGNED_HEADERS.iter().map(|name| HeaderName::new_from_ascii_str(name)).collect();
+    let canonicalization = DkimCanonicalization { header: DkimCanonicalizationType::Relaxed, body: DkimCanonicalizationType::Relaxed };
+    Ok(Some(DkimSigner::new(selector.clone(), domain, key, headers, canonicalization)))
+}
+
+// `mechanisms` of None keeps lettre's default of PLAIN then LOGIN.
+fn build_transport(smtp: &SmtpConfig, credentials: Option<Credentials>, mechanisms: Option<Vec<Mechanism>>) -> Result<SmtpTransport, BotError> {
+    let builder = match smtp.tls {
+        SmtpTls::Tls => SmtpTransport::relay(&smtp.host)?,
+        SmtpTls::Starttls => SmtpTransport::starttls_relay(&smtp.host)?,
+        SmtpTls::None => SmtpTransport::builder_dangerous(&smtp.host),
+    };
+    let mut builder = builder.port(smtp.port());
+    if let Some(credentials) = credentials {
+        builder = builder.credentials(credentials);
+    }
+    if let Some(mechanisms) = mechanisms {
+        builder = builder.authentication(mechanisms);
+    }
+    Ok(builder.build())
+}
+
+// Logs in as smtp.username, or as the sender's address when it isn't set,
+// unless smtp.auth is off. With smtp.oauth2 it logs in with XOAUTH2 and
+// rebuilds the transport whenever the access token is about to expire.
+// With [dkim] set up every email is signed before it is handed over.
+pub struct SmtpMailer {
+    name: String,
+    smtp: SmtpConfig,
+    username: String,
+    transport: SmtpTransport,
+    oauth: Option<(reqwest::Client, AccessToken)>,
+    dkim: Option<DkimSigner>,
+}
+
+impl SmtpMailer {
+    pub async fn connect(smtp: &SmtpConfig, dkim: &DkimConfig, sender: &Mailbox) -> Result<Self, BotError> {
+        let dkim = dkim_signer(dkim, sender)?;
+        let username = smtp.username.clone().unwrap_or_else(|| sender.email.to_string());
+        let mut oauth = None;
+        let transport = match &smtp.oauth2 {
+            Some(config) if smtp.auth => {
+                let client = http_client::client()?;
+                let token = refresh_access_token(&client, config).await?;
+                let credentials = Credentials::new(username.clone(), token.token.clone());
+                oauth = Some((client, token));
+                build_transport(smtp, Some(credentials), Some(vec![Mechanism::Xoauth2]))?
+            }
+            _ if smtp.auth => {
+                let credentials = Credentials::new(username.clone(), smtp.password()?.to_string());
+                build_transport(smtp, Some(credentials), None)?
+            }
+            _ => build_transport(smtp, None, None)?,
+        };
+        let name = if dkim.is_some() { "SMTP (DKIM-signed)" } else { "SMTP" }.to_string();
+        Ok(SmtpMailer { name, smtp: smtp.clone(), username, transport, oauth, dkim })
+    }
+
+    // The [sandbox] instance; mail is still DKIM-signed when [dkim] is set
+    // up, so the signature can be checked in its web UI.
+    pub async fn sandbox(sandbox: &SandboxConfig, dkim: &DkimConfig, sender: &Mailbox) -> Result<Self, BotError> {
+        let mut mailer = SmtpMailer::connect(&sandbox.smtp(), dkim, sender).await?;
+        let signed = if mailer.dkim.is_some() { " (DKIM-signed)" } else { "" };
+        mailer.name = format!("the sandbox at {}:{}{}", sandbox.host, sandbox.port, signed);
+        Ok(mailer)
+    }
+}
+
+// The MIME message as it goes out over SMTP, also what the spam check scores.
+pub fn build_message(email: &OutgoingEmail) -> Result<Message, BotError> {
+    let mut builder = Message::builder()
+        .message_id(Some(email.message_id.clone()))
+        .from(email.from.clone())
+        .to(email.recipient()?)
+        .subject(&email.subject);
+    if let Some(parent) = email.references.last() {
+        builder = builder.in_reply_to(parent.clone()).references(email.references.join(" "));
+    }
+    if let Some(unsubscribe) = &email.list_unsubscribe {
+        builder = builder.header(ListUnsubscribeHeader(unsubscribe.value.clone()));
+        if unsubscribe.one_click {
+            builder = builder.header(ListUnsubscribePostHeader);
+        }
+    }
+    // Inline images sit next to the HTML in multipart/related, and that
+    // goes first in multipart/mixed when there are attachments too.
+    let mut body = MultiPart::alternative_plain_html(email.text.clone(), email.html.clone());
+    if !email.inline_images.is_empty() {
+        let mut related = MultiPart::related().multipart(body);
+        for image in email.inline_images.iter() {
+            related = related.singlepart(attachment_part(image)?);
+        }
+        body = related;
+    }
+    if !email.attachments.is_empty() {
+        let mut mixed = MultiPart::mixed().multipart(body);
+        for attachment in email.attachments.iter() {
+            mixed = mixed.singlepart(attachment_part(attachment)?);
+        }
+        body = mixed;
+    }
+    builder.multipart(body).map_err(BotError::EmailError)
+}
+
+#[async_trait]
+impl EmailTransport for SmtpMailer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    // A no-op unless an OAuth2 access token is close to expiring.
+    async fn prepare(&mut self) -> Result<(), BotError> {
+        let (Some((client, token)), Some(config)) = (&mut self.oauth, &self.smtp.oauth2) else {
+            return Ok(());
+        };
+        if !token.needs_refresh() {
+            return Ok(());
+        }
+        *token = refresh_access_token(client, config).await?;
+        println!("Refreshed the SMTP OAuth2 access token");
+        let credentials = Credentials::new(self.username.clone(), token.token.clone());
+        self.transport = build_transport(&self.smtp, Some(credentials), Some(vec![Mechanism::Xoauth2]))?;
+        Ok(())
+    }
+
+    async fn send(&self, email: &OutgoingEmail) -> Result<(), BotError> {
+        let mut message = build_message(email)?;
+        if let Some(dkim) = &self.dkim {
+            message.sign(dkim);
+        }
+        self.transport.send(message).await.map_err(BotError::SmtpTransportError)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use hmac::{Hmac, Mac};
+use lettre::Address;
+use sha2::Sha256;
+use crate::config::UnsubscribeConfig;
+use crate::email::normalize_email;
+use crate::transport::ListUnsubscribe;
+
+// Hex digits of the HMAC kept in a token; 128 bits can't be guessed.
+const SIGNATURE_LEN: usize = 32;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn signature(secret: &str, email: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(email.as_bytes());
+    let mut signature = hex(&mac.finalize().into_bytes());
+    signature.truncate(SIGNATURE_LEN);
+    signature
+}
+
+// "<hex of the payload>.<signature>": the link carries the payload itself, so
+// nothing has to be stored per email, and the signature stops anyone from
+// forging links for payloads they weren't sent.
+pub(crate) fn sign(secret: &str, payload: &str) -> String {
+    format!("{}.{}", hex(payload.as_bytes()), signature(secret, payload))
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// The payload a token was signed for, or None when it is malformed or its
+// signature doesn't match.
+pub(crate) fn verify(secret: &str, token: &str) -> Option<String> {
+    let (payload, signature) = token.trim().split_once('.')?;
+    let payload = String::from_utf8(unhex(payload)?).ok()?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac.verify_truncated_left(&unhex(signature).filter(|bytes| bytes.len() * 2 == SIGNATURE_LEN)?).ok()?;
+    Some(payload)
+}
+
+// Unsubscribe tokens carry the recipient's address, which stops anyone from
+// unsubscribing addresses they didn't receive a link for.
+pub fn unsubscribe_token(secret: &str, email: &str) -> String {
+    sign(secret, &normalize_email(email))
+}
+
+// Tracking tokens signed with the same secret don't carry a bare address.
+pub fn verify_token(secret: &str, token: &str) -> Option<String> {
+    verify(secret, token).filter(|email| email.parse::<Address>().is_ok())
+}
+
+// How one recipient can opt out.
+#[derive(Debug, Clone)]
+pub struct UnsubscribeLinks {
+    pub url: Option<String>,
+    pub mailto: String,
+}
+
+impl UnsubscribeLinks {
+    // `sender` is the address the email goes out from, used when no mailto
+    // address is configured.
+    pub fn new(config: &UnsubscribeConfig, recipient: &str, sender: &Address) -> Self {
+        let mailbox = config.mailto.clone().unwrap_or_else(|| sender.to_string());
+        // Config::validate makes sure a url comes with a secret.
+        let url = config
+            .url
+            .as_ref()
+            .zip(config.secret.as_ref())
+            .map(|(url, secret)| url.replace("{token}", &unsubscribe_token(secret, recipient)));
+        UnsubscribeLinks { url, mailto: format!("mailto:{}?subject=unsubscribe", mailbox) }
+    }
+
+    // The web page when there is one, since not every mail client opens
+    // mailto links.
+    pub fn footer_url(&self) -> &str {
+        self.url.as_deref().unwrap_or(&self.mailto)
+    }
+
+    // Gmail and Yahoo only offer their unsubscribe button for one-click
+    // (RFC 8058) links, which must be https.
+    pub fn header(&self) -> ListUnsubscribe {
+        let mut targets = vec![format!("<{}>", self.mailto)];
+        if let Some(url) = &self.url {
+            targets.push(format!("<{}>", url));
+        }
+        ListUnsubscribe {
+            value: targets.join(", "),
+            one_click: self.url.as_deref().is_some_and(|url| url.starts_with("https://")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_returns_the_signed_payload() {
+        let token = sign("secret", "1:jane@plumbing.com");
+        assert_eq!(verify("secret", &token).as_deref(), Some("1:jane@plumbing.com"));
+        assert_eq!(verify("secret", &format!(" {}\n", token)).as_deref(), Some("1:jane@plumbing.com"));
+        assert_eq!(verify("other secret", &token), None);
+    }
+
+    #[test]
+    fn verify_rejects_tampered_and_truncated_tokens() {
+        let token = sign("secret", "jane@plumbing.com");
+        let (payload, signature) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", hex(b"bob@plumbing.com"), signature);
+        assert_eq!(verify("secret", &forged), None);
+        let flipped = if signature.starts_with('0') { signature.replacen('0', "1", 1) } else { format!("0{}", &signature[1..]) };
+        assert_eq!(verify("secret", &format!("{}.{}", payload, flipped)), None);
+        assert_eq!(verify("secret", &format!("{}.{}", payload, &signature[..SIGNATURE_LEN - 2])), None);
+        assert_eq!(verify("secret", &format!("{}.{}", &payload[..payload.len() - 1], signature)), None);
+        assert_eq!(verify("secret", &format!("{}.{}00", payload, signature)), None);
+        assert_eq!(verify("secret", payload), None);
+        assert_eq!(verify("secret", ""), None);
+    }
+
+    #[test]
+    fn unsubscribe_tokens_carry_the_normalized_address() {
+        let token = unsubscribe_token("secret", " Jane@Plumbing.com ");
+        assert_eq!(verify_token("secret", &token).as_deref(), Some("jane@plumbing.com"));
+        assert_eq!(verify_token("secret", &sign("secret", "1:jane@plumbing.com")), None);
+    }
+}
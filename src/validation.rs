@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use futures::stream::{self, StreamExt};
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::TokioAsyncResolver;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use crate::scrape::Business;
+
+pub const DEFAULT_DNS_CONCURRENCY: usize = 16;
+pub const DEFAULT_SMTP_TIMEOUT_SECS: u64 = 15;
+pub const DEFAULT_SMTP_CONCURRENCY: usize = 4;
+const SMTP_PORT: u16 = 25;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MxStatus {
+    // Mail exchangers in preference order.
+    Mx(Vec<String>),
+    // No MX record, but the domain has an address record (RFC 5321 implicit MX).
+    Implicit(String),
+    // NXDOMAIN, a null MX (RFC 7505), or no MX and no address record.
+    NoMail,
+    // The lookup itself failed (timeout, SERVFAIL); says nothing about the domain.
+    Unknown(String),
+}
+
+impl MxStatus {
+    pub fn accepts_mail(&self) -> Option<bool> {
+        match self {
+            MxStatus::Mx(_) | MxStatus::Implicit(_) => Some(true),
+            MxStatus::NoMail => Some(false),
+            MxStatus::Unknown(_) => None,
+        }
+    }
+
+    // The host to connect to for delivery, if there is one.
+    pub fn primary_host(&self) -> Option<&str> {
+        match self {
+            MxStatus::Mx(hosts) => hosts.first().map(String::as_str),
+            MxStatus::Implicit(domain) => Some(domain),
+            _ => None,
+        }
+    }
+}
+
+pub fn email_domain(email: &str) -> Option<String> {
+    email.rsplit_once('@').map(|(_, domain)| domain.trim().trim_end_matches('.').to_lowercase())
+}
+
+pub(crate) fn is_no_records(error: &ResolveError) -> bool {
+    matches!(error.kind(), ResolveErrorKind::NoRecordsFound { .. })
+}
+
+// The system resolver, or public DNS when /etc/resolv.conf can't be read.
+pub(crate) fn system_resolver() -> TokioAsyncResolver {
+    TokioAsyncResolver::tokio_from_system_conf()
+        .unwrap_or_else(|_| TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default()))
+}
+
+// Looks up each recipient domain once per run; leads often share a domain.
+pub struct MxValidator {
+    resolver: TokioAsyncResolver,
+    cache: Mutex<HashMap<String, MxStatus>>,
+}
+
+impl MxValidator {
+    pub fn new() -> Self {
+        MxValidator {
+            resolver: system_resolver(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn check_domain(&self, domain: &str) -> MxStatus {
+        if let Some(status) = self.cache.lock().unwrap().get(domain) {
+            return status.clone();
+        }
+        let status = self.lookup(domain).await;
+        self.cache.lock().unwrap().insert(domain.to_string(), status.clone());
+        status
+    }
+
+    async fn lookup(&self, domain: &str) -> MxStatus {
+        // A trailing dot keeps the resolver from appending search domains.
+        let fqdn = format!("{}.", domain);
+        match self.resolver.mx_lookup(fqdn.as_str()).await {
+            Ok(lookup) => {
+                let mut records: Vec<_> = lookup.iter().collect();
+                records.sort_by_key(|mx| mx.preference());
+                if records.iter().all(|mx| mx.exchange().is_root()) {
+                    return MxStatus::NoMail;
+                }
+                let hosts = records
+                    .iter()
+                    .filter(|mx| !mx.exchange().is_root())
+                    .map(|mx| mx.exchange().to_utf8().trim_end_matches('.').to_string())
+                    .collect();
+                MxStatus::Mx(hosts)
+            }
+            Err(e) if is_no_records(&e) => match self.resolver.lookup_ip(fqdn.as_str()).await {
+                Ok(_) => MxStatus::Implicit(domain.to_string()),
+                Err(e) if is_no_records(&e) => MxStatus::NoMail,
+                Err(e) => MxStatus::Unknown(e.to_string()),
+            },
+            Err(e) => MxStatus::Unknown(e.to_string()),
+        }
+    }
+
+    pub async fn check_email(&self, email: &str) -> MxStatus {
+        match email_domain(email) {
+            Some(domain) if !domain.is_empty() => self.check_domain(&domain).await,
+            _ => MxStatus::NoMail,
+        }
+    }
+
+    // Checks every lead and records the result in `mx_valid`, keeping the input order.
+    pub async fn check_businesses(&self, businesses: Vec<Business>, concurrency: usize) -> Vec<(Business, MxStatus)> {
+        stream::iter(businesses)
+            .map(|mut business| async move {
+                let status = self.check_email(&business.email).await;
+                business.mx_valid = status.accepts_mail();
+                (business, status)
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+}
+
+impl Default for MxValidator {
+    fn default() -> Self {
+        MxValidator::new()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpStatus {
+    Deliverable,
+    Undeliverable,
+    // Greylisted, timed out, blocked, or the server accepts any address (catch-all).
+    Unknown,
+}
+
+impl SmtpStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SmtpStatus::Deliverable => "deliverable",
+            SmtpStatus::Undeliverable => "undeliverable",
+            SmtpStatus::Unknown => "unknown",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "deliverable" => Some(SmtpStatus::Deliverable),
+            "undeliverable" => Some(SmtpStatus::Undeliverable),
+            "unknown" => Some(SmtpStatus::Unknown),
+            _ => None,
+        }
+    }
+}
+
+struct SmtpReply {
+    code: u16,
+    text: String,
+}
+
+// Reads one (possibly multi-line) reply, e.g. "250-first\r\n250 last\r\n".
+async fn read_reply<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<SmtpReply> {
+    let mut text = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "connection closed"));
+        }
+        let code = line.get(..3).and_then(|code| code.parse().ok()).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("malformed SMTP reply: {}", line.trim_end()))
+        })?;
+        text.push_str(line.get(4..).unwrap_or("").trim_end());
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(SmtpReply { code, text });
+        }
+        text.push(' ');
+    }
+}
+
+async fn send_line<W: AsyncWrite + Unpin>(writer: &mut W, line: &str) -> std::io::Result<()> {
+    writer.write_all(format!("{}\r\n", line).as_bytes()).await
+}
+
+// Asks the recipient's mail server whether it would accept a message (RCPT TO)
+// and hangs up before DATA, so nothing is delivered.
+pub struct SmtpVerifier {
+    pub helo_name: String,
+    pub mail_from: String,
+    pub timeout: Duration,
+}
+
+impl SmtpVerifier {
+    pub fn new(helo_name: &str, mail_from: &str) -> Self {
+        SmtpVerifier {
+            helo_name: helo_name.to_string(),
+            mail_from: mail_from.to_string(),
+            timeout: Duration::from_secs(DEFAULT_SMTP_TIMEOUT_SECS),
+        }
+    }
+
+    pub async fn verify(&self, email: &str, mx_host: &str) -> SmtpStatus {
+        match tokio::time::timeout(self.timeout, self.callout(email, mx_host)).await {
+            Ok(Ok(status)) => status,
+            Ok(Err(e)) => {
+                eprintln!("SMTP check of {} via {} failed: {}", email, mx_host, e);
+                SmtpStatus::Unknown
+            }
+            Err(_) => {
+                eprintln!("SMTP check of {} via {} timed out", email, mx_host);
+                SmtpStatus::Unknown
+            }
+        }
+    }
+
+    async fn callout(&self, email: &str, mx_host: &str) -> std::io::Result<SmtpStatus> {
+        let stream = TcpStream::connect((mx_host, SMTP_PORT)).await?;
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        if read_reply(&mut reader).await?.code != 220 {
+            return Ok(SmtpStatus::Unknown);
+        }
+        send_line(&mut writer, &format!("EHLO {}", self.helo_name)).await?;
+        if read_reply(&mut reader).await?.code != 250 {
+            send_line(&mut writer, &format!("HELO {}", self.helo_name)).await?;
+            if read_reply(&mut reader).await?.code != 250 {
+                return Ok(SmtpStatus::Unknown);
+            }
+        }
+        send_line(&mut writer, &format!("MAIL FROM:<{}>", self.mail_from)).await?;
+        if read_reply(&mut reader).await?.code != 250 {
+            return Ok(SmtpStatus::Unknown);
+        }
+
+        send_line(&mut writer, &format!("RCPT TO:<{}>", email)).await?;
+        let reply = read_reply(&mut reader).await?;
+        let status = match reply.code {
+            250 | 251 => SmtpStatus::Deliverable,
+            550 | 551 | 553 => SmtpStatus::Undeliverable,
+            _ => SmtpStatus::Unknown,
+        };
+        if status == SmtpStatus::Undeliverable {
+            println!("{} rejected by {}: {} {}", email, mx_host, reply.code, reply.text);
+        }
+
+        // A server that also accepts a made-up mailbox tells us nothing about this one.
+        if status == SmtpStatus::Deliverable {
+            let domain = email_domain(email).unwrap_or_default();
+            let probe: u64 = rand::random();
+            send_line(&mut writer, &format!("RCPT TO:<no-such-user-{:x}@{}>", probe, domain)).await?;
+            if matches!(read_reply(&mut reader).await?.code, 250 | 251) {
+                println!("{} accepts any address, marking {} as unknown", mx_host, email);
+                send_line(&mut writer, "QUIT").await?;
+                return Ok(SmtpStatus::Unknown);
+            }
+        }
+
+        send_line(&mut writer, "QUIT").await?;
+        Ok(status)
+    }
+}
+
+impl SmtpVerifier {
+    // Verifies the leads that have a mail server and records the result in
+    // `smtp_status`; leads without one are passed through unchanged.
+    pub async fn verify_businesses(&self, checked: Vec<(Business, MxStatus)>, concurrency: usize) -> Vec<(Business, MxStatus)> {
+        stream::iter(checked)
+            .map(|(mut business, status)| async move {
+                if let Some(host) = status.primary_host() {
+                    business.smtp_status = Some(self.verify(&business.email, host).await);
+                }
+                (business, status)
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+}